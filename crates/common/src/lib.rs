@@ -3,6 +3,7 @@ use chrono::{DateTime, Utc};
 use multiaddr::Multiaddr;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+use std::time::Duration;
 use thiserror::Error;
 
 /// Common error types used throughout the stealth sidecar
@@ -32,6 +33,39 @@ pub struct EpochInfo {
 }
 
 impl EpochInfo {
+    /// Mainnet genesis, seconds since the Unix epoch
+    pub const MAINNET_GENESIS_TIME: u64 = 1606824023;
+
+    /// Mainnet slot length
+    pub const SECONDS_PER_SLOT: u64 = 12;
+    /// Mainnet epoch length
+    pub const SLOTS_PER_EPOCH: u64 = 32;
+
+    /// Slot and epoch at `now` (time since the Unix epoch) with mainnet timing
+    pub fn at(genesis_time: u64, now: Duration) -> Self {
+        Self::of_slot(now.as_secs().saturating_sub(genesis_time) / Self::SECONDS_PER_SLOT)
+    }
+
+    /// `slot` and its epoch with mainnet timing
+    pub fn of_slot(slot: u64) -> Self {
+        Self {
+            epoch: Self::epoch_from_slot(slot, Self::SLOTS_PER_EPOCH),
+            slot,
+            slots_per_epoch: Self::SLOTS_PER_EPOCH,
+            seconds_per_slot: Self::SECONDS_PER_SLOT,
+        }
+    }
+
+    /// When the slot starts, as time since the Unix epoch
+    pub fn slot_start(&self, genesis_time: u64) -> Duration {
+        Duration::from_secs(genesis_time + self.slot * self.seconds_per_slot)
+    }
+
+    /// Attestations for the slot are due a third of the way into it
+    pub fn attestation_deadline(&self, genesis_time: u64) -> Duration {
+        self.slot_start(genesis_time) + Duration::from_secs(self.seconds_per_slot) / 3
+    }
+
     /// Calculate the current epoch from a slot
     pub fn epoch_from_slot(slot: u64, slots_per_epoch: u64) -> u64 {
         slot / slots_per_epoch
@@ -95,6 +129,12 @@ pub struct StealthConfig {
     
     /// Friend nodes for the privacy mesh
     pub friend_nodes: Vec<FriendNodeConfig>,
+
+    /// Friends that must have answered our heartbeats for our attestation to be relayed (defaults to 3)
+    pub min_anonymity_set: Option<usize>,
+
+    /// What happens to our attestation when fewer friends are reachable (defaults to `drop`)
+    pub anonymity_fallback: Option<AnonymityFallback>,
     
     /// Waku node configuration
     pub waku_config: WakuConfig,
@@ -106,23 +146,114 @@ pub struct StealthConfig {
     pub network: NetworkConfig,
 }
 
+/// Minimum anonymity set when `min_anonymity_set` is unset
+pub const DEFAULT_MIN_ANONYMITY_SET: usize = 3;
+
+/// What to do with our attestation when the friend mesh is too small to hide in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnonymityFallback {
+    /// Publish it on gossip ourselves, as its visible origin
+    PublishDirect,
+    /// Hold it until enough friends are back, dropping it at the slot's propagation deadline
+    Delay,
+    /// Drop it and alert
+    #[default]
+    Drop,
+}
+
+impl StealthConfig {
+    pub fn min_anonymity_set(&self) -> usize {
+        self.min_anonymity_set.unwrap_or(DEFAULT_MIN_ANONYMITY_SET)
+    }
+
+    pub fn genesis_time(&self) -> u64 {
+        self.network.genesis_time.unwrap_or(EpochInfo::MAINNET_GENESIS_TIME)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FriendNodeConfig {
     pub peer_id: String,
     pub multiaddr: Multiaddr,
+    /// Hex X25519 key relay payloads to this friend are sealed to
     pub public_key: String,
+    /// Expected fingerprint of `public_key`, compared out of band
+    pub key_fingerprint: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WakuConfig {
     /// Local nwaku node RPC endpoint
     pub nwaku_rpc_url: String,
+
+    /// Local nwaku node REST endpoint; when set it is used instead of JSON-RPC, and
+    /// friends' RLN proofs go unchecked unless `native_rln` is set too
+    pub nwaku_rest_url: Option<String>,
     
     /// RLN contract address
     pub rln_contract_address: Option<String>,
     
-    /// Rate limit (messages per epoch)
+    /// Rate limit (messages per RLN epoch, a beacon slot with the REST or native provider)
     pub rate_limit_per_epoch: u32,
+
+    /// X25519 key friends seal relay payloads to (defaults to `stealth-relay.key`)
+    pub relay_key_path: Option<String>,
+
+    /// Dandelion++ routing: each hop passes an attestation along the stem with this
+    /// probability and fluffs it otherwise; unset sends to every friend at once
+    pub stem_probability: Option<f64>,
+
+    /// Seconds a stem hop waits to see its attestation on gossip before fluffing it
+    /// itself (defaults to 6, randomized up to double)
+    pub embargo_secs: Option<u64>,
+
+    /// Hold attestations for a random delay before relaying or publishing them;
+    /// unset sends them right away
+    pub mix_delay: Option<MixDelay>,
+
+    /// Held attestations are released in shuffled batches this often (defaults to 500ms)
+    pub mix_window_ms: Option<u64>,
+
+    /// Send friends dummy messages on a schedule, so the ones carrying our
+    /// attestations do not stand out; unset sends none
+    pub cover_traffic: Option<CoverTraffic>,
+
+    /// Seconds between liveness pings to each friend (defaults to 10, 0 disables);
+    /// friends that miss three in a row are not relayed through
+    pub heartbeat_secs: Option<u64>,
+
+    /// Prove and verify RLN ourselves with zerokit, so nwaku is only a transport;
+    /// needs friend-relay's `native-rln` feature. Unset leaves RLN to nwaku
+    pub native_rln: Option<NativeRlnConfig>,
+}
+
+/// Files of a native RLN group; every member must share the membership list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NativeRlnConfig {
+    /// Identity commitments of the group, one hex field element per line
+    pub membership_path: String,
+    /// Our identity secret, created on first use
+    pub identity_path: String,
+}
+
+/// Distribution of the mixing delay, capped by the slot's propagation deadline
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "distribution", rename_all = "snake_case")]
+pub enum MixDelay {
+    Uniform { min_ms: u64, max_ms: u64 },
+    Exponential { mean_ms: u64 },
+}
+
+/// Cover messages per beacon epoch (384 s), capped at half the RLN rate limit of
+/// each slot-long RLN epoch in it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "schedule", rename_all = "snake_case")]
+pub enum CoverTraffic {
+    /// Evenly spaced
+    Constant { per_epoch: u32 },
+    /// Exponentially distributed gaps with this mean rate
+    Poisson { per_epoch: f64 },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -134,9 +265,74 @@ pub struct MetricsConfig {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkConfig {
+    /// TCP port for libp2p, on IPv4 and IPv6
     pub listen_port: u16,
+    /// UDP port for libp2p QUIC (defaults to `listen_port + 1`)
+    pub quic_port: Option<u16>,
+    /// IP to advertise, or `"auto"`; either way it is advertised only once peers confirm it
     pub external_ip: Option<String>,
     pub bootstrap_peers: Option<Vec<String>>,
+    /// UDP port for discv5 (defaults to `listen_port`)
+    pub discovery_port: Option<u16>,
+    /// Base64 ENRs of discv5 bootnodes
+    pub boot_enrs: Option<Vec<String>>,
+    /// Beacon node REST API the req/resp `Status` head is sourced from
+    pub beacon_api_url: Option<String>,
+    /// Static head for `Status` when no beacon API is configured
+    pub chain_head: Option<ChainHeadConfig>,
+    /// File holding the secp256k1 node key, created on first start
+    pub node_key_path: Option<String>,
+    /// Switch to a fresh node identity every this many epochs, at a subnet reshuffle
+    pub identity_rotation_epochs: Option<u64>,
+    /// Peer count to reach through discovery and prune back down to
+    pub target_peers: Option<usize>,
+    /// Peer count above which inbound connections are turned away
+    pub max_peers: Option<usize>,
+    /// `"full"` or `"light"` to also subscribe to the non-attestation core topics
+    pub cover_topics: Option<String>,
+    /// Peer IDs, IPs or CIDRs exempt from IP caps, suspicion and pruning
+    pub peer_allowlist: Option<Vec<String>>,
+    /// Peer IDs, IPs or CIDRs we never connect to
+    pub peer_denylist: Option<Vec<String>>,
+    /// Distinct peers allowed behind one IP
+    pub max_peers_per_ip: Option<usize>,
+    /// Distinct peers allowed per /24 (IPv4) or /64 (IPv6)
+    pub max_peers_per_ip_block: Option<usize>,
+    /// Unix time of the chain's genesis, which slots are counted from (defaults to mainnet)
+    pub genesis_time: Option<u64>,
+    /// Active validators, which set the committees per slot attestations are checked
+    /// against (defaults to mainnet's; followed from `beacon_api_url` when set)
+    pub active_validators: Option<u64>,
+    /// Hex fork version in the signing domain and gossip topic digest (defaults to
+    /// mainnet Electra; followed from `beacon_api_url` when set, which other
+    /// networks need to name the fork)
+    pub fork_version: Option<String>,
+    /// Hex `genesis_validators_root` in the signing domain (defaults to mainnet;
+    /// read from `beacon_api_url` when set)
+    pub genesis_validators_root: Option<String>,
+    /// Beacon API validators response saved to disk; enables BLS checks of gossip attestations
+    pub validator_pubkeys_path: Option<String>,
+    /// Friends' relay nodes (`/ip4/../tcp/9100/p2p/<id>`) that publish our own attestations on their mesh
+    pub publish_relays: Option<Vec<String>>,
+    /// Peer IDs of friends' relay nodes we publish attestations for; only they may
+    /// connect to our relay node, which runs apart from the beacon swarm
+    pub relay_clients: Option<Vec<String>>,
+    /// TCP and QUIC port relay clients reach our relay node on (defaults to 9100)
+    pub relay_port: Option<u16>,
+    /// File holding the relay node's secp256k1 key, whose peer ID friends configure
+    /// (defaults to `stealth-relay-node.key`)
+    pub relay_node_key_path: Option<String>,
+}
+
+/// Chain head advertised in the req/resp `Status` handshake
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainHeadConfig {
+    /// Hex encoded finalized checkpoint root
+    pub finalized_root: String,
+    pub finalized_epoch: u64,
+    /// Hex encoded head block root
+    pub head_root: String,
+    pub head_slot: u64,
 }
 
 impl Default for StealthConfig {
@@ -144,10 +340,21 @@ impl Default for StealthConfig {
         Self {
             extra_subnets_per_epoch: 8,
             friend_nodes: Vec::new(),
+            min_anonymity_set: None,
+            anonymity_fallback: None,
             waku_config: WakuConfig {
                 nwaku_rpc_url: "http://localhost:8545".to_string(),
+                nwaku_rest_url: None,
                 rln_contract_address: None,
                 rate_limit_per_epoch: 100,
+                relay_key_path: None,
+                stem_probability: None,
+                embargo_secs: None,
+                mix_delay: None,
+                mix_window_ms: None,
+                cover_traffic: None,
+                heartbeat_secs: None,
+                native_rln: None,
             },
             metrics: MetricsConfig {
                 enabled: true,
@@ -156,8 +363,31 @@ impl Default for StealthConfig {
             },
             network: NetworkConfig {
                 listen_port: 9000,
+                quic_port: None,
                 external_ip: None,
                 bootstrap_peers: None,
+                discovery_port: None,
+                boot_enrs: None,
+                beacon_api_url: None,
+                chain_head: None,
+                node_key_path: None,
+                identity_rotation_epochs: None,
+                target_peers: None,
+                max_peers: None,
+                cover_topics: None,
+                peer_allowlist: None,
+                peer_denylist: None,
+                max_peers_per_ip: None,
+                max_peers_per_ip_block: None,
+                genesis_time: None,
+                active_validators: None,
+                fork_version: None,
+                genesis_validators_root: None,
+                validator_pubkeys_path: None,
+                publish_relays: None,
+                relay_clients: None,
+                relay_port: None,
+                relay_node_key_path: None,
             },
         }
     }
@@ -222,10 +452,30 @@ pub mod utils {
             ));
         }
 
-        if config.friend_nodes.len() < 3 {
-            return Err(StealthError::Config(
-                "At least 3 friend nodes required for k-anonymity.".to_string()
-            ));
+        if let Some(stem_probability) = config.waku_config.stem_probability {
+            if !(0.0..=1.0).contains(&stem_probability) {
+                return Err(StealthError::Config(format!(
+                    "stem_probability {} is not between 0 and 1",
+                    stem_probability
+                )));
+            }
+        }
+
+        if let Some(MixDelay::Uniform { min_ms, max_ms }) = config.waku_config.mix_delay {
+            if min_ms > max_ms {
+                return Err(StealthError::Config(format!(
+                    "mix_delay min_ms {} is above max_ms {}",
+                    min_ms, max_ms
+                )));
+            }
+        }
+
+        if config.friend_nodes.len() < config.min_anonymity_set() {
+            return Err(StealthError::Config(format!(
+                "At least {} friend nodes required for k-anonymity, {} configured.",
+                config.min_anonymity_set(),
+                config.friend_nodes.len()
+            )));
         }
 
         Ok(())
@@ -254,6 +504,44 @@ mod tests {
 
         assert_eq!(epoch_info.slots_remaining_in_epoch(), 32);
         assert_eq!(epoch_info.seconds_until_next_epoch(), 384);
+
+        let genesis = EpochInfo::MAINNET_GENESIS_TIME;
+        let now = Duration::from_secs(genesis + 320 * 12 + 7);
+        assert_eq!(EpochInfo::at(genesis, now), epoch_info);
+        assert_eq!(EpochInfo::of_slot(320), epoch_info);
+        assert_eq!(epoch_info.slot_start(genesis), now - Duration::from_secs(7));
+        assert_eq!(epoch_info.attestation_deadline(genesis), now - Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_validate_config_checks_the_anonymity_set() {
+        let friend = |peer_id: &str| FriendNodeConfig {
+            peer_id: peer_id.to_string(),
+            multiaddr: "/ip4/127.0.0.1/tcp/60001".parse().unwrap(),
+            public_key: String::new(),
+            key_fingerprint: None,
+        };
+        let mut config = StealthConfig {
+            friend_nodes: vec![friend("a"), friend("b")],
+            ..StealthConfig::default()
+        };
+        let error = utils::validate_config(&config).unwrap_err();
+        assert!(error.to_string().contains("At least 3 friend nodes"), "{}", error);
+
+        config.min_anonymity_set = Some(2);
+        assert!(utils::validate_config(&config).is_ok());
+
+        config.waku_config.mix_delay = Some(MixDelay::Uniform { min_ms: 3000, max_ms: 200 });
+        let error = utils::validate_config(&config).unwrap_err();
+        assert!(error.to_string().contains("mix_delay"), "{}", error);
+        config.waku_config.mix_delay = None;
+        config.waku_config.stem_probability = Some(1.5);
+        let error = utils::validate_config(&config).unwrap_err();
+        assert!(error.to_string().contains("stem_probability"), "{}", error);
+
+        let parsed: AnonymityFallback = serde_json::from_str("\"publish_direct\"").unwrap();
+        assert_eq!(parsed, AnonymityFallback::PublishDirect);
+        assert_eq!(AnonymityFallback::default(), AnonymityFallback::Drop);
     }
 
     #[test]
@@ -0,0 +1,134 @@
+use crate::mixer::propagation_deadline;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// What became of our attestation when too few friends were reachable to hide it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnonymityDecision {
+    PublishedDirect,
+    Delayed,
+    /// Held earlier, and relayed once enough friends were back
+    RelayedAfterDelay,
+    Dropped,
+}
+
+impl AnonymityDecision {
+    /// Label in `privacy_events_total`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnonymityDecision::PublishedDirect => "anonymity_published_direct",
+            AnonymityDecision::Delayed => "anonymity_delayed",
+            AnonymityDecision::RelayedAfterDelay => "anonymity_relayed_after_delay",
+            AnonymityDecision::Dropped => "anonymity_dropped",
+        }
+    }
+}
+
+/// When an attestation for `slot` held at `now`, which is `unix_now` since the
+/// Unix epoch, stops being worth relaying; `None` if its deadline already passed
+pub fn hold_deadline(genesis_time: u64, slot: u64, unix_now: Duration, now: Instant) -> Option<Instant> {
+    let remaining = propagation_deadline(genesis_time, slot).saturating_sub(unix_now);
+    (!remaining.is_zero()).then(|| now + remaining)
+}
+
+/// Our attestation waiting for the anonymity set to recover
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Held {
+    pub attestation_data: Vec<u8>,
+    pub subnet_id: u8,
+    pub slot: u64,
+    deadline: Instant,
+}
+
+/// Attestations held under the `delay` fallback
+#[derive(Debug, Default)]
+pub struct HeldAttestations {
+    held: Vec<Held>,
+}
+
+impl HeldAttestations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hold(&mut self, attestation_data: Vec<u8>, subnet_id: u8, slot: u64, deadline: Instant) {
+        self.held.push(Held {
+            attestation_data,
+            subnet_id,
+            slot,
+            deadline,
+        });
+    }
+
+    /// Remove and return what is past its deadline
+    pub fn expire(&mut self, now: Instant) -> Vec<Held> {
+        let (expired, held) = self.held.drain(..).partition(|held| held.deadline <= now);
+        self.held = held;
+        expired
+    }
+
+    pub fn take_all(&mut self) -> Vec<Held> {
+        std::mem::take(&mut self.held)
+    }
+
+    pub fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use stealth_common::EpochInfo;
+
+    #[test]
+    fn test_holds_last_until_the_propagation_deadline() {
+        let genesis = EpochInfo::MAINNET_GENESIS_TIME;
+        let slot_start = Duration::from_secs(genesis + 1000 * 12);
+        let now = Instant::now();
+
+        // Attestations are due 4s in, and propagate for 4s more
+        let at = |secs| slot_start + Duration::from_secs(secs);
+        assert_eq!(hold_deadline(genesis, 1000, at(1), now), Some(now + Duration::from_secs(7)));
+        assert_eq!(hold_deadline(genesis, 1000, at(8), now), None);
+        assert_eq!(hold_deadline(genesis, 1000, at(11), now), None);
+        // The deadline is the attestation's slot's, not the one it is held in
+        assert_eq!(hold_deadline(genesis, 999, at(1), now), None);
+        assert_eq!(hold_deadline(genesis, 1001, at(11), now), Some(now + Duration::from_secs(9)));
+    }
+
+    #[test]
+    fn test_held_attestations_expire_or_are_taken() {
+        let mut held = HeldAttestations::new();
+        let now = Instant::now();
+        held.hold(vec![1], 1, 1000, now + Duration::from_secs(1));
+        held.hold(vec![2], 2, 1000, now + Duration::from_secs(5));
+        held.hold(vec![3], 3, 1001, now + Duration::from_secs(5));
+
+        assert!(held.expire(now).is_empty());
+        let expired = held.expire(now + Duration::from_secs(2));
+        assert_eq!((expired.len(), expired[0].subnet_id), (1, 1));
+        assert_eq!(held.len(), 2);
+        let taken: Vec<(u8, u64)> = held.take_all().iter().map(|held| (held.subnet_id, held.slot)).collect();
+        assert_eq!(taken, vec![(2, 1000), (3, 1001)]);
+        assert!(held.is_empty());
+    }
+
+    #[test]
+    fn test_decisions_have_distinct_labels() {
+        let decisions = [
+            AnonymityDecision::PublishedDirect,
+            AnonymityDecision::Delayed,
+            AnonymityDecision::RelayedAfterDelay,
+            AnonymityDecision::Dropped,
+        ];
+        let labels: HashSet<&str> = decisions.iter().map(AnonymityDecision::as_str).collect();
+        assert_eq!(labels.len(), decisions.len());
+        assert!(labels.iter().all(|label| label.starts_with("anonymity_")));
+    }
+}
@@ -0,0 +1,125 @@
+use rand::{thread_rng, Rng};
+use std::time::Duration;
+use stealth_common::{CoverTraffic, WakuConfig};
+use tracing::warn;
+
+/// Beacon epoch the cover rate is spread over
+pub const COVER_EPOCH: Duration = Duration::from_secs(12 * 32);
+/// RLN epoch `rate_limit_per_epoch` counts over: a slot with the REST and native providers
+pub const RLN_EPOCH: Duration = Duration::from_secs(12);
+/// Length of a dummy attestation, about that of an SSZ `SingleAttestation`
+pub const COVER_ATTESTATION_SIZE: usize = 240;
+
+/// Messages per RLN epoch cover may use; the other half is left for attestations
+pub fn cover_budget(rate_limit_per_epoch: u32) -> u32 {
+    rate_limit_per_epoch / 2
+}
+
+/// The cover budget over a whole [`COVER_EPOCH`], the unit cover rates are given in
+fn cover_budget_per_cover_epoch(rate_limit_per_epoch: u32) -> u32 {
+    let rln_epochs = (COVER_EPOCH.as_secs() / RLN_EPOCH.as_secs()) as u32;
+    cover_budget(rate_limit_per_epoch).saturating_mul(rln_epochs)
+}
+
+/// Cover traffic schedule from `waku_config`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoverConfig {
+    pub traffic: CoverTraffic,
+}
+
+impl CoverConfig {
+    /// `None` unless `cover_traffic` has a positive rate; rates above the cover
+    /// budget, converted from RLN epochs to beacon epochs, are lowered to it
+    pub fn from_config(config: &WakuConfig) -> Option<Self> {
+        let traffic = config.cover_traffic?;
+        let budget = cover_budget_per_cover_epoch(config.rate_limit_per_epoch);
+        let traffic = match traffic {
+            CoverTraffic::Constant { per_epoch } if per_epoch > budget => {
+                warn!("🎭 Cover rate {} is above half the RLN rate limit, using {}", per_epoch, budget);
+                CoverTraffic::Constant { per_epoch: budget }
+            }
+            CoverTraffic::Poisson { per_epoch } if per_epoch > budget as f64 => {
+                warn!("🎭 Cover rate {} is above half the RLN rate limit, using {}", per_epoch, budget);
+                CoverTraffic::Poisson { per_epoch: budget as f64 }
+            }
+            traffic => traffic,
+        };
+        let rate = match traffic {
+            CoverTraffic::Constant { per_epoch } => per_epoch as f64,
+            CoverTraffic::Poisson { per_epoch } => per_epoch,
+        };
+        if rate.is_nan() || rate <= 0.0 {
+            warn!("🎭 Cover rate {} leaves nothing to send, cover traffic disabled", rate);
+            return None;
+        }
+        Some(Self { traffic })
+    }
+
+    /// Wait before the next cover message
+    pub fn next_delay(&self) -> Duration {
+        match self.traffic {
+            CoverTraffic::Constant { per_epoch } => COVER_EPOCH / per_epoch,
+            CoverTraffic::Poisson { per_epoch } => {
+                let uniform: f64 = thread_rng().gen();
+                COVER_EPOCH.mul_f64(-(1.0 - uniform).ln() / per_epoch)
+            }
+        }
+    }
+}
+
+/// Random bytes standing in for an attestation
+pub fn dummy_attestation() -> Vec<u8> {
+    let mut attestation = vec![0u8; COVER_ATTESTATION_SIZE];
+    thread_rng().fill(attestation.as_mut_slice());
+    attestation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waku(cover_traffic: Option<CoverTraffic>, rate_limit_per_epoch: u32) -> WakuConfig {
+        WakuConfig {
+            cover_traffic,
+            rate_limit_per_epoch,
+            ..stealth_common::StealthConfig::default().waku_config
+        }
+    }
+
+    #[test]
+    fn test_constant_schedule_is_evenly_spaced() {
+        let cover = CoverConfig::from_config(&waku(Some(CoverTraffic::Constant { per_epoch: 4 }), 100)).unwrap();
+        for _ in 0..10 {
+            assert_eq!(cover.next_delay(), Duration::from_secs(96));
+        }
+        assert_eq!(dummy_attestation().len(), COVER_ATTESTATION_SIZE);
+        assert_ne!(dummy_attestation(), dummy_attestation());
+    }
+
+    #[test]
+    fn test_poisson_gaps_average_the_rate() {
+        let cover = CoverConfig::from_config(&waku(Some(CoverTraffic::Poisson { per_epoch: 8.0 }), 100)).unwrap();
+        let gaps: Vec<Duration> = (0..2000).map(|_| cover.next_delay()).collect();
+        let mean = gaps.iter().sum::<Duration>() / gaps.len() as u32;
+        assert!(mean > Duration::from_secs(40) && mean < Duration::from_secs(56), "{:?}", mean);
+        assert!(gaps.iter().any(|gap| *gap < Duration::from_secs(10)));
+        assert!(gaps.iter().any(|gap| *gap > Duration::from_secs(100)));
+    }
+
+    #[test]
+    fn test_rate_is_kept_within_the_rln_budget() {
+        assert_eq!(CoverConfig::from_config(&waku(None, 100)), None);
+        assert_eq!(CoverConfig::from_config(&waku(Some(CoverTraffic::Constant { per_epoch: 0 }), 100)), None);
+        assert_eq!(CoverConfig::from_config(&waku(Some(CoverTraffic::Poisson { per_epoch: f64::NAN }), 100)), None);
+        // A limit of one leaves no budget for cover at all
+        assert_eq!(CoverConfig::from_config(&waku(Some(CoverTraffic::Constant { per_epoch: 3 }), 1)), None);
+
+        // Half of 10 per slot-long RLN epoch is 160 per beacon epoch
+        let clamped = CoverConfig::from_config(&waku(Some(CoverTraffic::Constant { per_epoch: 500 }), 10)).unwrap();
+        assert_eq!(clamped.traffic, CoverTraffic::Constant { per_epoch: 160 });
+        let kept = CoverConfig::from_config(&waku(Some(CoverTraffic::Poisson { per_epoch: 80.5 }), 10)).unwrap();
+        assert_eq!(kept.traffic, CoverTraffic::Poisson { per_epoch: 80.5 });
+        let clamped = CoverConfig::from_config(&waku(Some(CoverTraffic::Poisson { per_epoch: 200.0 }), 10)).unwrap();
+        assert_eq!(clamped.traffic, CoverTraffic::Poisson { per_epoch: 160.0 });
+    }
+}
@@ -0,0 +1,409 @@
+use anyhow::{anyhow, Context};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use stealth_common::FriendNodeConfig;
+use thiserror::Error;
+use tracing::{info, warn};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// One-way Noise handshake: we know the friend's static key, send ours encrypted,
+/// and the static-static DH authenticates us to that friend and nobody else
+const NOISE_PARAMS: &str = "Noise_X_25519_ChaChaPoly_SHA256";
+/// Binds envelopes to the friend relay so they are useless in any other protocol
+const PROLOGUE: &[u8] = b"stealth-relay/1";
+/// Largest Noise message
+const MAX_ENVELOPE_SIZE: usize = 65535;
+/// Ephemeral key, encrypted static key and the payload tag
+const ENVELOPE_OVERHEAD: usize = KEY_SIZE + KEY_SIZE + 16 + 16;
+/// Plaintexts are padded to a multiple of this before sealing, so attestations
+/// of any size and cover messages all look alike on the wire
+const PADDING_BLOCK: usize = 4096;
+/// Big-endian length of the real plaintext, in front of the padding
+const LENGTH_PREFIX_SIZE: usize = 4;
+/// X25519 key length
+pub const KEY_SIZE: usize = 32;
+/// Bytes of the SHA-256 of a key shown as its fingerprint
+const FINGERPRINT_SIZE: usize = 16;
+/// Domain separation for content topics derived from a friend's shared secret
+const TOPIC_LABEL: &[u8] = b"stealth-relay/topic";
+/// Bytes of the derived hash used in a content topic
+const TOPIC_TAG_SIZE: usize = 16;
+
+/// Key file used when `waku_config.relay_key_path` is not configured
+pub const DEFAULT_RELAY_KEY_PATH: &str = "stealth-relay.key";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CryptoError {
+    #[error("Friend {peer_id} has a malformed public key: {reason}")]
+    MalformedKey { peer_id: String, reason: String },
+    #[error("Friend {peer_id} key has fingerprint {actual}, but {expected} is pinned")]
+    FingerprintMismatch {
+        peer_id: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("Payload of {0} bytes is too large to seal")]
+    TooLarge(usize),
+    /// Sealed to someone else, or tampered with
+    #[error("Envelope could not be opened: {0}")]
+    Open(String),
+    #[error("Noise error: {0}")]
+    Noise(String),
+}
+
+/// Hex SHA-256 prefix friends compare out of band before pinning a key
+pub fn fingerprint(public_key: &[u8; KEY_SIZE]) -> String {
+    hex::encode(&Sha256::digest(public_key)[..FINGERPRINT_SIZE])
+}
+
+/// A friend's X25519 static key, checked against its pinned fingerprint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FriendKey {
+    pub peer_id: String,
+    pub public_key: [u8; KEY_SIZE],
+}
+
+impl FriendKey {
+    pub fn from_config(friend: &FriendNodeConfig) -> Result<Self, CryptoError> {
+        let malformed = |reason: String| CryptoError::MalformedKey {
+            peer_id: friend.peer_id.clone(),
+            reason,
+        };
+        let bytes = hex::decode(friend.public_key.trim().trim_start_matches("0x"))
+            .map_err(|e| malformed(format!("not hex: {}", e)))?;
+        let public_key: [u8; KEY_SIZE] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| malformed(format!("expected a {}-byte X25519 key, found {} bytes", KEY_SIZE, bytes.len())))?;
+
+        if let Some(expected) = &friend.key_fingerprint {
+            let expected = expected.trim().trim_start_matches("0x").to_lowercase();
+            let actual = fingerprint(&public_key);
+            if expected != actual {
+                return Err(CryptoError::FingerprintMismatch {
+                    peer_id: friend.peer_id.clone(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        Ok(Self {
+            peer_id: friend.peer_id.clone(),
+            public_key,
+        })
+    }
+
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.public_key)
+    }
+}
+
+/// Where we send to and listen for one friend. Only the two of us can derive
+/// the topics, and each direction has its own, so we never receive our own sends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FriendChannel {
+    pub key: FriendKey,
+    pub outbound_topic: String,
+    pub inbound_topic: String,
+}
+
+impl FriendChannel {
+    pub fn peer_id(&self) -> &str {
+        &self.key.peer_id
+    }
+}
+
+/// Content topic for envelopes from `sender` to `recipient`
+fn channel_topic(shared_secret: &[u8; KEY_SIZE], sender: &[u8; KEY_SIZE], recipient: &[u8; KEY_SIZE]) -> String {
+    let tag = Sha256::new()
+        .chain_update(TOPIC_LABEL)
+        .chain_update(shared_secret)
+        .chain_update(sender)
+        .chain_update(recipient)
+        .finalize();
+    crate::relay_topic(&hex::encode(&tag[..TOPIC_TAG_SIZE]))
+}
+
+/// Our static X25519 key: friends seal to it, and it authenticates what we send them
+pub struct RelayIdentity {
+    secret: StaticSecret,
+    public_key: [u8; KEY_SIZE],
+}
+
+impl RelayIdentity {
+    pub fn generate() -> Self {
+        Self::from_secret(rand::random())
+    }
+
+    fn from_secret(secret: [u8; KEY_SIZE]) -> Self {
+        let secret = StaticSecret::from(secret);
+        let public_key = PublicKey::from(&secret).to_bytes();
+        Self { secret, public_key }
+    }
+
+    /// Load the key from `path`, creating it (mode 0600) if it does not exist.
+    /// The file holds the raw 32-byte secret.
+    pub fn load_or_create(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            let identity = Self::generate();
+            identity.write(path)?;
+            info!("🔑 Generated new relay key at {} (fingerprint {})", path.display(), identity.fingerprint());
+            return Ok(identity);
+        }
+
+        enforce_permissions(path)?;
+        let bytes = fs::read(path).with_context(|| format!("Failed to read relay key {}", path.display()))?;
+        let secret: [u8; KEY_SIZE] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| anyhow!("Relay key {} must be 32 bytes, found {}", path.display(), bytes.len()))?;
+        let identity = Self::from_secret(secret);
+        info!("🔑 Loaded relay key from {} (fingerprint {})", path.display(), identity.fingerprint());
+        Ok(identity)
+    }
+
+    fn write(&self, path: &Path) -> anyhow::Result<()> {
+        write_secret(path, self.secret.as_bytes())
+    }
+
+    /// What friends put in their `public_key` for us
+    pub fn public_key_hex(&self) -> String {
+        format!("0x{}", hex::encode(self.public_key))
+    }
+
+    pub fn public_key(&self) -> &[u8; KEY_SIZE] {
+        &self.public_key
+    }
+
+    pub fn fingerprint(&self) -> String {
+        fingerprint(&self.public_key)
+    }
+
+    /// Derive the topics we share with `friend` from our static-static DH
+    pub fn channel(&self, friend: &FriendKey) -> Result<FriendChannel, CryptoError> {
+        let shared = self.secret.diffie_hellman(&PublicKey::from(friend.public_key));
+        // A low-order point gives everyone the same secret, and so the same topics
+        if !shared.was_contributory() {
+            return Err(CryptoError::MalformedKey {
+                peer_id: friend.peer_id.clone(),
+                reason: "low-order X25519 point".to_string(),
+            });
+        }
+        Ok(FriendChannel {
+            key: friend.clone(),
+            outbound_topic: channel_topic(shared.as_bytes(), &self.public_key, &friend.public_key),
+            inbound_topic: channel_topic(shared.as_bytes(), &friend.public_key, &self.public_key),
+        })
+    }
+
+    /// Encrypt `plaintext` so only `recipient` can read it and tell it came from us
+    pub fn seal(&self, recipient: &FriendKey, plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let padded = pad(plaintext)?;
+        let mut handshake = snow::Builder::new(noise_params())
+            .local_private_key(self.secret.as_bytes())
+            .remote_public_key(&recipient.public_key)
+            .prologue(PROLOGUE)
+            .build_initiator()
+            .map_err(|e| CryptoError::Noise(e.to_string()))?;
+        let mut envelope = vec![0u8; padded.len() + ENVELOPE_OVERHEAD];
+        let len = handshake
+            .write_message(&padded, &mut envelope)
+            .map_err(|e| CryptoError::Noise(e.to_string()))?;
+        envelope.truncate(len);
+        Ok(envelope)
+    }
+
+    /// Decrypt an envelope sealed to us, returning the plaintext and the sender's
+    /// static key; the caller decides whether that key belongs to a friend
+    pub fn open(&self, envelope: &[u8]) -> Result<(Vec<u8>, [u8; KEY_SIZE]), CryptoError> {
+        if envelope.len() < ENVELOPE_OVERHEAD || envelope.len() > MAX_ENVELOPE_SIZE {
+            return Err(CryptoError::Open(format!("{} bytes is not a valid envelope size", envelope.len())));
+        }
+        let mut handshake = snow::Builder::new(noise_params())
+            .local_private_key(self.secret.as_bytes())
+            .prologue(PROLOGUE)
+            .build_responder()
+            .map_err(|e| CryptoError::Noise(e.to_string()))?;
+        let mut plaintext = vec![0u8; envelope.len()];
+        let len = handshake
+            .read_message(envelope, &mut plaintext)
+            .map_err(|e| CryptoError::Open(e.to_string()))?;
+        plaintext.truncate(len);
+        let sender: [u8; KEY_SIZE] = handshake
+            .get_remote_static()
+            .and_then(|key| key.try_into().ok())
+            .ok_or_else(|| CryptoError::Open("No sender key in envelope".to_string()))?;
+        Ok((unpad(&plaintext)?, sender))
+    }
+}
+
+/// Length prefix, `plaintext`, then zeros up to the next padding block
+fn pad(plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let max_padded = MAX_ENVELOPE_SIZE - ENVELOPE_OVERHEAD;
+    let len = plaintext.len() + LENGTH_PREFIX_SIZE;
+    if len > max_padded {
+        return Err(CryptoError::TooLarge(plaintext.len()));
+    }
+    let padded_len = (len.div_ceil(PADDING_BLOCK) * PADDING_BLOCK).min(max_padded);
+    let mut padded = Vec::with_capacity(padded_len);
+    padded.extend_from_slice(&(plaintext.len() as u32).to_be_bytes());
+    padded.extend_from_slice(plaintext);
+    padded.resize(padded_len, 0);
+    Ok(padded)
+}
+
+fn unpad(padded: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let (prefix, rest) = padded
+        .split_first_chunk::<LENGTH_PREFIX_SIZE>()
+        .ok_or_else(|| CryptoError::Open("Missing length prefix".to_string()))?;
+    let len = u32::from_be_bytes(*prefix) as usize;
+    rest.get(..len)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| CryptoError::Open(format!("Length prefix {} exceeds the {} padded bytes", len, rest.len())))
+}
+
+fn noise_params() -> snow::params::NoiseParams {
+    NOISE_PARAMS.parse().expect("valid Noise parameters")
+}
+
+/// Atomically write a key file readable only by us
+pub(crate) fn write_secret(path: &Path, secret: &[u8]) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&tmp_path)
+        .with_context(|| format!("Failed to create key file {}", tmp_path.display()))?;
+    file.write_all(secret)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Tighten a key file that is readable by group or others back to 0600
+#[cfg(unix)]
+pub(crate) fn enforce_permissions(path: &Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        warn!("🔑 Key file {} had mode {:o}, restricting to 600", path.display(), mode & 0o777);
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn enforce_permissions(_path: &Path) -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn friend_config(peer_id: &str, identity: &RelayIdentity) -> FriendNodeConfig {
+        FriendNodeConfig {
+            peer_id: peer_id.to_string(),
+            multiaddr: "/ip4/127.0.0.1/tcp/60001".parse().unwrap(),
+            public_key: identity.public_key_hex(),
+            key_fingerprint: None,
+        }
+    }
+
+    #[test]
+    fn test_sealed_payload_opens_only_for_its_recipient() {
+        let alice = RelayIdentity::generate();
+        let bob = RelayIdentity::generate();
+        let carol = RelayIdentity::generate();
+        let to_bob = FriendKey::from_config(&friend_config("bob", &bob)).unwrap();
+
+        let envelope = alice.seal(&to_bob, b"attestation").unwrap();
+        assert!(!envelope.windows(11).any(|window| window == b"attestation"));
+
+        let (plaintext, sender) = bob.open(&envelope).unwrap();
+        assert_eq!(plaintext, b"attestation");
+        assert_eq!(&sender, alice.public_key());
+
+        assert!(matches!(carol.open(&envelope), Err(CryptoError::Open(_))));
+        let mut tampered = envelope.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(bob.open(&tampered), Err(CryptoError::Open(_))));
+    }
+
+    #[test]
+    fn test_friend_keys_are_validated_and_pinned() {
+        let bob = RelayIdentity::generate();
+        let mut config = friend_config("bob", &bob);
+        config.key_fingerprint = Some(bob.fingerprint().to_uppercase());
+        assert_eq!(FriendKey::from_config(&config).unwrap().public_key, *bob.public_key());
+
+        config.key_fingerprint = Some(RelayIdentity::generate().fingerprint());
+        assert!(matches!(FriendKey::from_config(&config), Err(CryptoError::FingerprintMismatch { .. })));
+
+        config.public_key = "pub1".to_string();
+        assert!(matches!(FriendKey::from_config(&config), Err(CryptoError::MalformedKey { .. })));
+        config.public_key = "0x1234".to_string();
+        let error = FriendKey::from_config(&config).unwrap_err();
+        assert!(error.to_string().contains("found 2 bytes"), "{}", error);
+    }
+
+    #[test]
+    fn test_channel_topics_are_private_and_directional() {
+        let (alice, bob, carol) = (RelayIdentity::generate(), RelayIdentity::generate(), RelayIdentity::generate());
+        let key = |peer_id, identity: &RelayIdentity| FriendKey::from_config(&friend_config(peer_id, identity)).unwrap();
+
+        let alice_to_bob = alice.channel(&key("bob", &bob)).unwrap();
+        let bob_to_alice = bob.channel(&key("alice", &alice)).unwrap();
+        assert_eq!(alice_to_bob.outbound_topic, bob_to_alice.inbound_topic);
+        assert_eq!(alice_to_bob.inbound_topic, bob_to_alice.outbound_topic);
+        assert_ne!(alice_to_bob.outbound_topic, alice_to_bob.inbound_topic);
+
+        let alice_to_carol = alice.channel(&key("carol", &carol)).unwrap();
+        assert_ne!(alice_to_bob.outbound_topic, alice_to_carol.outbound_topic);
+
+        let low_order = FriendKey {
+            peer_id: "zero".to_string(),
+            public_key: [0; KEY_SIZE],
+        };
+        assert!(matches!(alice.channel(&low_order), Err(CryptoError::MalformedKey { .. })));
+    }
+
+    #[test]
+    fn test_envelopes_are_padded_to_blocks() {
+        let (alice, bob) = (RelayIdentity::generate(), RelayIdentity::generate());
+        let to_bob = FriendKey::from_config(&friend_config("bob", &bob)).unwrap();
+
+        let short = alice.seal(&to_bob, &[7; 10]).unwrap();
+        let long = alice.seal(&to_bob, &[7; 3000]).unwrap();
+        assert_eq!(short.len(), long.len());
+        assert_eq!(short.len(), PADDING_BLOCK + ENVELOPE_OVERHEAD);
+        assert_eq!(alice.seal(&to_bob, &[7; PADDING_BLOCK]).unwrap().len(), 2 * PADDING_BLOCK + ENVELOPE_OVERHEAD);
+        assert_eq!(bob.open(&long).unwrap().0, vec![7; 3000]);
+
+        // The last block is cut short at the Noise message limit
+        let largest = MAX_ENVELOPE_SIZE - ENVELOPE_OVERHEAD - LENGTH_PREFIX_SIZE;
+        assert_eq!(alice.seal(&to_bob, &vec![7; largest]).unwrap().len(), MAX_ENVELOPE_SIZE);
+        assert_eq!(alice.seal(&to_bob, &vec![7; largest - 100]).unwrap().len(), MAX_ENVELOPE_SIZE);
+        assert_eq!(alice.seal(&to_bob, &vec![7; largest + 1]), Err(CryptoError::TooLarge(largest + 1)));
+        assert!(unpad(&[0, 0, 1, 0, 7]).is_err());
+    }
+
+    #[test]
+    fn test_relay_key_persists() {
+        let path = std::env::temp_dir().join(format!("stealth-relay-test-{}.key", rand::random::<u64>()));
+        let created = RelayIdentity::load_or_create(&path).unwrap();
+        let loaded = RelayIdentity::load_or_create(&path).unwrap();
+        assert_eq!(created.public_key(), loaded.public_key());
+        fs::remove_file(&path).unwrap();
+    }
+}
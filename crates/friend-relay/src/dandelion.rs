@@ -0,0 +1,234 @@
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+use std::collections::HashMap;
+use std::time::Duration;
+use stealth_common::WakuConfig;
+use tokio::time::Instant;
+
+/// How long a stem graph lasts before new relays are picked
+pub const STEM_EPOCH: Duration = Duration::from_secs(600);
+/// Friends each node passes stem messages to during an epoch
+const STEM_RELAYS: usize = 2;
+/// Embargo before randomization when `embargo_secs` is unset, half a slot
+const DEFAULT_EMBARGO: Duration = Duration::from_secs(6);
+
+/// Dandelion++ parameters from `waku_config`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DandelionConfig {
+    pub stem_probability: f64,
+    pub embargo: Duration,
+}
+
+impl DandelionConfig {
+    /// `None` unless `stem_probability` is set; `validate_config` rejects values
+    /// that are not probabilities
+    pub fn from_config(config: &WakuConfig) -> Option<Self> {
+        let stem_probability = config.stem_probability?;
+        Some(Self {
+            stem_probability,
+            embargo: config.embargo_secs.map(Duration::from_secs).unwrap_or(DEFAULT_EMBARGO),
+        })
+    }
+
+    /// Whether this hop passes a stem message on rather than fluffing it
+    pub fn keep_stem(&self) -> bool {
+        thread_rng().gen::<f64>() < self.stem_probability
+    }
+
+    /// Between one and two embargoes from `now`, so the timers of the nodes on
+    /// a stem do not all fire at once, but never past `latest`, the attestation's
+    /// propagation deadline
+    pub fn embargo_deadline(&self, now: Instant, latest: Instant) -> Instant {
+        (now + self.embargo.mul_f64(1.0 + thread_rng().gen::<f64>())).min(latest)
+    }
+}
+
+/// Our part of the Dandelion++ stem graph.
+///
+/// Each epoch we pick up to two friends as stem relays. Our own attestations
+/// always take the same one, and everything from a given friend is passed to the
+/// same one, so repeated messages reveal no more of the graph than the first.
+#[derive(Debug, Default)]
+pub struct StemGraph {
+    epoch_started: Option<Instant>,
+    relays: Vec<String>,
+    own: Option<String>,
+    /// Stem relay for messages from each friend
+    routes: HashMap<String, String>,
+}
+
+impl StemGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pick new relays from `friends` and forget the old routes
+    pub fn rotate(&mut self, friends: &[String], now: Instant) {
+        let mut rng = thread_rng();
+        self.relays = friends.choose_multiple(&mut rng, STEM_RELAYS).cloned().collect();
+        self.own = self.relays.choose(&mut rng).cloned();
+        self.routes.clear();
+        self.epoch_started = Some(now);
+    }
+
+    /// Stem relay our own attestations take this epoch
+    pub fn own_relay(&self) -> Option<&str> {
+        self.own.as_deref()
+    }
+
+    /// Next hop for a stem message from `from`, or for our own with `None`.
+    /// Rotates when the epoch is over or a relay is no longer a friend.
+    pub fn next_hop(&mut self, from: Option<&str>, friends: &[String], now: Instant) -> Option<String> {
+        let stale = self.epoch_started.is_none_or(|started| now.duration_since(started) >= STEM_EPOCH)
+            || self.relays.iter().any(|relay| !friends.contains(relay));
+        if stale {
+            self.rotate(friends, now);
+        }
+
+        let Some(from) = from else {
+            return self.own.clone();
+        };
+        if let Some(to) = self.routes.get(from) {
+            return Some(to.clone());
+        }
+        // Never straight back to the friend it came from
+        let candidates: Vec<&String> = self.relays.iter().filter(|relay| relay.as_str() != from).collect();
+        let to = candidates.choose(&mut thread_rng())?.to_string();
+        self.routes.insert(from.to_string(), to.clone());
+        Some(to)
+    }
+}
+
+/// An attestation we passed along the stem, to fluff if it never shows up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Embargoed {
+    pub message_id: String,
+    pub subnet_id: u8,
+    pub attestation_data: Vec<u8>,
+    pub slot: u64,
+    /// Ours, so never published from our own identity but fluffed through a friend
+    pub own: bool,
+    pub deadline: Instant,
+}
+
+/// Embargo timers of the attestations we stemmed
+#[derive(Debug, Default)]
+pub struct Embargoes {
+    pending: HashMap<String, Embargoed>,
+}
+
+impl Embargoes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, embargoed: Embargoed) {
+        self.pending.insert(embargoed.message_id.clone(), embargoed);
+    }
+
+    /// Remove and return the attestations whose embargo is over
+    pub fn expire(&mut self, now: Instant) -> Vec<Embargoed> {
+        let expired: Vec<String> = self
+            .pending
+            .values()
+            .filter(|embargoed| embargoed.deadline <= now)
+            .map(|embargoed| embargoed.message_id.clone())
+            .collect();
+        expired.iter().filter_map(|message_id| self.pending.remove(message_id)).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn friends(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_routes_are_stable_within_an_epoch() {
+        let friends = friends(&["a", "b", "c", "d"]);
+        let mut graph = StemGraph::new();
+        let now = Instant::now();
+
+        let own = graph.next_hop(None, &friends, now).unwrap();
+        let from_a = graph.next_hop(Some("a"), &friends, now).unwrap();
+        for _ in 0..20 {
+            assert_eq!(graph.next_hop(None, &friends, now).as_ref(), Some(&own));
+            assert_eq!(graph.next_hop(Some("a"), &friends, now).as_ref(), Some(&from_a));
+        }
+        assert_ne!(from_a, "a");
+        assert!(graph.relays.contains(&own) && graph.relays.contains(&from_a));
+        assert_eq!(graph.relays.len(), STEM_RELAYS);
+
+        // With only the sender as a friend there is nowhere to stem to
+        let mut lonely = StemGraph::new();
+        assert_eq!(lonely.next_hop(Some("a"), &friends[..1], now), None);
+    }
+
+    #[test]
+    fn test_graph_rotates_each_epoch_and_when_a_relay_leaves() {
+        let all = friends(&["a", "b", "c", "d", "e", "f", "g", "h"]);
+        let mut graph = StemGraph::new();
+        let start = Instant::now();
+        graph.next_hop(None, &all, start);
+        let first = graph.epoch_started;
+
+        graph.next_hop(None, &all, start + STEM_EPOCH - Duration::from_secs(1));
+        assert_eq!(graph.epoch_started, first);
+        graph.next_hop(None, &all, start + STEM_EPOCH);
+        assert_ne!(graph.epoch_started, first);
+
+        // Dropping a chosen relay forces new ones from the remaining friends
+        let gone = graph.relays[0].clone();
+        let remaining: Vec<String> = all.into_iter().filter(|friend| *friend != gone).collect();
+        let own = graph.next_hop(None, &remaining, start + STEM_EPOCH).unwrap();
+        assert_ne!(own, gone);
+        assert!(graph.relays.iter().all(|relay| remaining.contains(relay)));
+    }
+
+    #[test]
+    fn test_embargoes_expire_and_config_is_checked() {
+        let config = |stem_probability, embargo_secs| WakuConfig {
+            stem_probability,
+            embargo_secs,
+            ..stealth_common::StealthConfig::default().waku_config
+        };
+        assert_eq!(DandelionConfig::from_config(&config(None, Some(3))), None);
+        let dandelion = DandelionConfig::from_config(&config(Some(0.9), None)).unwrap();
+        assert_eq!(dandelion.embargo, DEFAULT_EMBARGO);
+
+        let now = Instant::now();
+        let deadline = dandelion.embargo_deadline(now, now + DEFAULT_EMBARGO * 3);
+        assert!(deadline >= now + DEFAULT_EMBARGO && deadline <= now + DEFAULT_EMBARGO * 2);
+        // Never past the propagation deadline
+        let latest = now + Duration::from_secs(2);
+        assert_eq!(dandelion.embargo_deadline(now, latest), latest);
+
+        let embargoed = |message_id: &str, subnet_id, secs| Embargoed {
+            message_id: message_id.to_string(),
+            subnet_id,
+            attestation_data: vec![subnet_id],
+            slot: 1000,
+            own: false,
+            deadline: now + Duration::from_secs(secs),
+        };
+        let mut embargoes = Embargoes::new();
+        embargoes.insert(embargoed("early", 1, 1));
+        embargoes.insert(embargoed("late", 2, 5));
+        assert!(embargoes.expire(now).is_empty());
+        let expired = embargoes.expire(now + Duration::from_secs(1));
+        assert_eq!(expired.len(), 1);
+        assert_eq!((expired[0].message_id.as_str(), expired[0].subnet_id), ("early", 1));
+        assert_eq!(embargoes.len(), 1);
+    }
+}
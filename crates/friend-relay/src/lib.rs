@@ -5,16 +5,51 @@ use chrono::{DateTime, Utc};
 use futures::stream::StreamExt;
 use libp2p::PeerId;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
-use stealth_common::{FriendNodeConfig, StealthConfig, StealthError, StealthResult, WakuConfig};
+use std::time::{Duration, Instant, SystemTime};
+use stealth_common::{AnonymityFallback, EpochInfo, FriendNodeConfig, StealthConfig, StealthError, StealthResult, WakuConfig};
 use tokio::sync::{mpsc, watch, RwLock};
+use tokio::task::JoinHandle;
 use tokio::time::interval;
 use tracing::{debug, error, info, warn};
 
+pub mod anonymity;
+pub mod cover;
+pub mod crypto;
+pub mod dandelion;
+pub mod liveness;
+pub mod mixer;
+pub mod rest;
+#[cfg(feature = "native-rln")]
+pub mod rln;
+
+use anonymity::{AnonymityDecision, HeldAttestations};
+use cover::CoverConfig;
+use crypto::{FriendChannel, FriendKey, RelayIdentity};
+use dandelion::{DandelionConfig, Embargoed, Embargoes, StemGraph};
+use liveness::{Heartbeat, HeartbeatLimiter, Liveness, Transition};
+use mixer::{MixConfig, Mixer};
+
+/// Attestation subnets a relayed message may be for
+const ATTESTATION_SUBNET_COUNT: u8 = 64;
+/// How often nwaku is polled for messages on a subscribed topic
+const RELAY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often stemmed attestations are checked for an expired embargo
+const EMBARGO_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How often attestations held for a small anonymity set are retried
+const HELD_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// RLN epochs a friend's proof may be off from ours, for clock skew and transit
+const MAX_RLN_EPOCH_DRIFT: u64 = 1;
+
+/// Waku content topic for one direction of a friend channel. The tag is derived
+/// from the secret we share with the friend, and the subnet travels encrypted.
+pub fn relay_topic(tag: &str) -> String {
+    format!("/stealth-relay/1/{}/noise", tag)
+}
+
 /// A message to be relayed through the friend mesh
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RelayMessage {
@@ -28,6 +63,15 @@ pub struct RelayMessage {
     pub timestamp: DateTime<Utc>,
     /// Origin peer (encrypted or anonymized)
     pub origin_hint: Option<String>,
+    /// In the Dandelion++ stem phase: the receiver may pass it on instead of publishing
+    #[serde(default)]
+    pub stem: bool,
+    /// Cover traffic: the receiving friend verifies and drops it
+    #[serde(default)]
+    pub cover: bool,
+    /// Slot of the attestation, whose propagation deadline bounds how long hops hold it
+    #[serde(default)]
+    pub slot: Option<u64>,
 }
 
 /// RLN (Rate Limiting Nullifier) proof for spam protection
@@ -58,6 +102,7 @@ pub enum RelayCommand {
     RelayAttestation {
         attestation_data: Vec<u8>,
         subnet_id: u8,
+        slot: u64,
     },
     /// Add a new friend node
     AddFriend(FriendNodeConfig),
@@ -83,15 +128,44 @@ pub enum RelayEvent {
         friends_count: usize,
         latency_ms: u64,
     },
-    /// Message was received from a friend
+    /// One friend's copy of a relayed message was or was not pushed
+    FriendMessageSent {
+        friend_id: String,
+        success: bool,
+    },
+    /// Message was received from a friend, verified, and fluffed or passed along the stem
     MessageReceived {
         message_id: String,
         from_friend: String,
+        subnet_id: u8,
+    },
+    /// A stemmed attestation did not show up on gossip in time, so we published it
+    EmbargoExpired {
+        message_id: String,
+        subnet_id: u8,
+    },
+    /// Message from a friend was not published
+    MessageDropped {
+        from_friend: Option<String>,
+        reason: DropReason,
     },
-    /// Friend node connected
+    /// A friend we had not heard from, or had given up on, sent us something
     FriendConnected(String),
-    /// Friend node disconnected
+    /// A live friend missed too many pings in a row, or was removed
     FriendDisconnected(String),
+    /// Too few friends were reachable to hide our attestation in, so the fallback policy applied
+    AnonymityFallback {
+        subnet_id: u8,
+        reachable: usize,
+        decision: AnonymityDecision,
+    },
+    /// A friend answered a ping
+    FriendHeartbeat {
+        friend_id: String,
+        rtt: Duration,
+        /// Share of recent pings it did not answer in time
+        loss_rate: f64,
+    },
     /// RLN rate limit exceeded
     RateLimitExceeded {
         epoch: u64,
@@ -102,6 +176,55 @@ pub enum RelayEvent {
     Error(String),
 }
 
+/// Why an inbound friend message was dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// Not a `ProvenMessage`, or for a subnet that does not exist
+    Malformed,
+    /// Not sealed to us, or corrupted on the way
+    Undecryptable,
+    /// Sealed by a key that belongs to none of our friends
+    UnknownSender,
+    /// Already seen, including our own messages coming back
+    Duplicate,
+    /// Proof fails to verify or is for an RLN epoch more than one from ours
+    InvalidProof,
+    /// Nullifier reused, the epoch's message budget exhausted, or too many heartbeats
+    RateLimited,
+    PublishFailed,
+}
+
+impl DropReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DropReason::Malformed => "malformed",
+            DropReason::Undecryptable => "undecryptable",
+            DropReason::UnknownSender => "unknown_sender",
+            DropReason::Duplicate => "duplicate",
+            DropReason::InvalidProof => "invalid_proof",
+            DropReason::RateLimited => "rate_limited",
+            DropReason::PublishFailed => "publish_failed",
+        }
+    }
+}
+
+/// A verified message from a friend, ready to fluff or pass along the stem
+struct Inbound {
+    from_friend: String,
+    message_id: String,
+    proven: ProvenMessage,
+    /// The decrypted `ProvenMessage`, forwarded as is
+    payload: Vec<u8>,
+}
+
+/// What the mixer holds
+enum Mixed {
+    Outbound { attestation_data: Vec<u8>, subnet_id: u8, slot: u64 },
+    Inbound(Box<Inbound>),
+    /// Cover is mixed too, or it would be the only thing leaving between windows
+    Cover,
+}
+
 /// Statistics for the friend relay
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RelayStats {
@@ -111,6 +234,21 @@ pub struct RelayStats {
     pub average_latency_ms: f64,
     pub rate_limit_violations: u64,
     pub bandwidth_bytes_per_second: f64,
+    /// Cover messages, counted apart from the real ones above and never exported
+    pub cover_sent: u64,
+    pub cover_received: u64,
+}
+
+impl RelayStats {
+    /// Share of real messages in what we sent and what we received, for local
+    /// inspection only; `None` before any traffic
+    pub fn real_fraction(&self) -> (Option<f64>, Option<f64>) {
+        let fraction = |real: u64, cover: u64| (real + cover > 0).then(|| real as f64 / (real + cover) as f64);
+        (
+            fraction(self.messages_sent, self.cover_sent),
+            fraction(self.messages_received, self.cover_received),
+        )
+    }
 }
 
 /// Interface to interact with Waku node for RLN proofs and message relay
@@ -148,7 +286,75 @@ pub trait WakuProvider: Send + Sync {
     async fn get_rln_epoch(&self) -> StealthResult<u64>;
 }
 
+/// The nwaku API `config` points at: REST when `nwaku_rest_url` is set, JSON-RPC
+/// otherwise, with RLN done locally when `native_rln` is set, which needs the
+/// `native-rln` feature
+pub fn nwaku_provider(stealth_config: &StealthConfig) -> StealthResult<Box<dyn WakuProvider>> {
+    let config = &stealth_config.waku_config;
+    let transport: Box<dyn WakuProvider> = match &config.nwaku_rest_url {
+        Some(rest_url) => {
+            if config.native_rln.is_none() {
+                warn!("⚠️ nwaku REST API has no RLN to check friends' proofs with, only per-friend rate limits apply");
+            }
+            Box::new(rest::NwakuRestProvider::new(rest_url, stealth_config.genesis_time())?)
+        }
+        None => Box::new(NwakuProvider::new(config)),
+    };
+    Ok(match &config.native_rln {
+        #[cfg(not(feature = "native-rln"))]
+        Some(_) => {
+            return Err(StealthError::Config(
+                "Native RLN needs friend-relay's `native-rln` feature".to_string(),
+            ))
+        }
+        #[cfg(feature = "native-rln")]
+        Some(native) => Box::new(
+            rln::NativeRln::from_config(transport, config, native, stealth_config.genesis_time())
+                .map_err(|e| StealthError::WakuRln(format!("Native RLN setup failed: {:#}", e)))?,
+        ),
+        None => transport,
+    })
+}
+
+/// Lets the node pick its nwaku API at startup
+#[async_trait::async_trait]
+impl WakuProvider for Box<dyn WakuProvider> {
+    async fn generate_rln_proof(&self, message: &[u8], epoch: u64) -> StealthResult<RlnProof> {
+        (**self).generate_rln_proof(message, epoch).await
+    }
+
+    async fn verify_rln_proof(&self, proof: &RlnProof, message: &[u8]) -> StealthResult<bool> {
+        (**self).verify_rln_proof(proof, message).await
+    }
+
+    async fn light_push(&self, topic: &str, message: &[u8], timestamp: Option<DateTime<Utc>>) -> StealthResult<String> {
+        (**self).light_push(topic, message, timestamp).await
+    }
+
+    async fn subscribe_relay(&self, topic: &str) -> StealthResult<mpsc::UnboundedReceiver<Vec<u8>>> {
+        (**self).subscribe_relay(topic).await
+    }
+
+    async fn get_rln_epoch(&self) -> StealthResult<u64> {
+        (**self).get_rln_epoch().await
+    }
+}
+
+/// Where attestations received from friends are published on the beacon gossip network
+#[async_trait::async_trait]
+pub trait BeaconPublisher: Send + Sync {
+    /// Publish an SSZ-encoded attestation on its subnet
+    async fn publish_attestation(&self, subnet_id: u8, attestation_data: Vec<u8>) -> StealthResult<()>;
+
+    /// Whether gossip already carried the attestation, so an expired embargo
+    /// need not publish it again
+    async fn seen_on_gossip(&self, _subnet_id: u8, _attestation_data: &[u8]) -> bool {
+        false
+    }
+}
+
 /// Implementation of WakuProvider that talks to nwaku via JSON-RPC
+#[derive(Clone)]
 pub struct NwakuProvider {
     client: reqwest::Client,
     base_url: String,
@@ -309,15 +515,48 @@ impl WakuProvider for NwakuProvider {
         &self,
         topic: &str,
     ) -> StealthResult<mpsc::UnboundedReceiver<Vec<u8>>> {
-        // In a real implementation, this would set up a WebSocket or SSE connection
-        // to receive real-time messages. For this demo, we'll simulate it.
-        let (tx, rx) = mpsc::unbounded_channel();
-        
+        let subscribed: bool = self
+            .rpc_call("post_waku_v2_relay_v1_subscriptions", serde_json::json!([[topic]]))
+            .await?;
+        if !subscribed {
+            return Err(StealthError::WakuRln(format!("nwaku refused subscription to {}", topic)));
+        }
         debug!("Subscribed to Waku topic: {}", topic);
-        
-        // In practice, you'd start a background task that listens for messages
-        // and forwards them to the channel
-        
+
+        #[derive(Deserialize)]
+        struct WakuMessage {
+            payload: String,
+        }
+
+        // nwaku buffers messages per topic; poll them until the receiver goes away
+        let (tx, rx) = mpsc::unbounded_channel();
+        let provider = self.clone();
+        let topic = topic.to_string();
+        tokio::spawn(async move {
+            let mut poll_timer = interval(RELAY_POLL_INTERVAL);
+            while !tx.is_closed() {
+                poll_timer.tick().await;
+                let messages: Vec<WakuMessage> = match provider
+                    .rpc_call("get_waku_v2_relay_v1_messages", serde_json::json!([topic]))
+                    .await
+                {
+                    Ok(messages) => messages,
+                    Err(e) => {
+                        debug!("Failed to poll Waku topic {}: {}", topic, e);
+                        continue;
+                    }
+                };
+                for message in messages {
+                    match general_purpose::STANDARD.decode(&message.payload) {
+                        Ok(payload) => {
+                            let _ = tx.send(payload);
+                        }
+                        Err(e) => debug!("Invalid payload base64 on {}: {}", topic, e),
+                    }
+                }
+            }
+        });
+
         Ok(rx)
     }
 
@@ -350,7 +589,14 @@ impl RateLimiter {
         }
     }
 
+    /// Count a message for `epoch`, which becomes the current one if it is newer;
+    /// callers bound it first, or one far-future proof would lock out the present
     pub fn check_and_update(&mut self, epoch: u64, nullifier: B256) -> Result<(), String> {
+        // Nullifiers of older epochs are forgotten, so their proofs could be replayed
+        if epoch < self.current_epoch.saturating_sub(3) {
+            return Err(format!("Epoch {} is too old (current {})", epoch, self.current_epoch));
+        }
+
         // Update epoch if necessary
         if epoch > self.current_epoch {
             self.current_epoch = epoch;
@@ -380,6 +626,20 @@ impl RateLimiter {
         Ok(())
     }
 
+    /// Change the per-epoch budget, keeping the nullifiers already seen
+    pub fn set_rate_limit(&mut self, rate_limit_per_epoch: u32) {
+        self.rate_limit_per_epoch = rate_limit_per_epoch;
+    }
+
+    /// Messages counted so far in `epoch`
+    pub fn used(&self, epoch: u64) -> u32 {
+        if epoch == self.current_epoch {
+            self.message_count
+        } else {
+            0
+        }
+    }
+
     pub fn get_stats(&self) -> (u64, u32, u32) {
         (self.current_epoch, self.message_count, self.rate_limit_per_epoch)
     }
@@ -427,8 +687,33 @@ pub struct FriendRelay<W: WakuProvider> {
     config: StealthConfig,
     waku_provider: W,
     friends: HashMap<String, FriendNodeConfig>,
+    /// Topics and keys of the friends whose `public_key` is valid
+    channels: HashMap<String, FriendChannel>,
+    identity: Arc<RelayIdentity>,
+    /// Inbound topic forwarders, by friend
+    subscriptions: HashMap<String, JoinHandle<()>>,
+    inbound_tx: mpsc::UnboundedSender<(String, Vec<u8>)>,
+    inbound_rx: mpsc::UnboundedReceiver<(String, Vec<u8>)>,
     rate_limiter: Arc<RwLock<RateLimiter>>,
+    /// Nullifiers and message budget of what each friend sends us, kept apart from our own
+    inbound_limiters: HashMap<String, RateLimiter>,
     message_queue: Arc<RwLock<MessageQueue>>,
+    publisher: Option<Arc<dyn BeaconPublisher>>,
+    /// Dandelion++ routing, when configured
+    dandelion: Option<DandelionConfig>,
+    stem_graph: StemGraph,
+    embargoes: Embargoes,
+    /// Random delay and batching before anything leaves, when configured
+    mixer: Option<Mixer<Mixed>>,
+    /// Dummy message schedule, when configured
+    cover: Option<CoverConfig>,
+    /// Pings and their answers, unless heartbeats are disabled
+    liveness: Option<Liveness>,
+    heartbeat_limiter: HeartbeatLimiter,
+    /// Our attestations waiting for enough friends, under the `delay` fallback
+    held: HeldAttestations,
+    /// For slot deadlines
+    genesis_time: u64,
     stats: Arc<RwLock<RelayStats>>,
     command_tx: mpsc::UnboundedSender<RelayCommand>,
     command_rx: mpsc::UnboundedReceiver<RelayCommand>,
@@ -444,21 +729,54 @@ impl<W: WakuProvider> FriendRelay<W> {
     ) -> (Self, FriendRelayHandle) {
         let (command_tx, command_rx) = mpsc::unbounded_channel();
         let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (inbound_tx, inbound_rx) = mpsc::unbounded_channel();
 
         let friends: HashMap<String, FriendNodeConfig> = config
             .friend_nodes
             .iter()
             .map(|f| (f.peer_id.clone(), f.clone()))
             .collect();
+        let friend_keys: Vec<FriendKey> = friends
+            .values()
+            .filter_map(|friend| match FriendKey::from_config(friend) {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    error!("🔐 Not relaying through {}: {}", friend.peer_id, e);
+                    None
+                }
+            })
+            .collect();
+        let identity = Arc::new(RelayIdentity::generate());
+        let channels = Self::open_channels(&identity, friend_keys);
+        let mut liveness = Liveness::from_config(&config.waku_config);
+        if let Some(liveness) = &mut liveness {
+            channels.keys().for_each(|peer_id| liveness.add(peer_id));
+        }
 
         let relay = Self {
             config: config.clone(),
             waku_provider,
             friends,
+            channels,
+            identity,
+            subscriptions: HashMap::new(),
+            inbound_tx,
+            inbound_rx,
             rate_limiter: Arc::new(RwLock::new(RateLimiter::new(
                 config.waku_config.rate_limit_per_epoch,
             ))),
+            inbound_limiters: HashMap::new(),
             message_queue: Arc::new(RwLock::new(MessageQueue::new(1000))),
+            publisher: None,
+            dandelion: DandelionConfig::from_config(&config.waku_config),
+            stem_graph: StemGraph::new(),
+            embargoes: Embargoes::new(),
+            mixer: MixConfig::from_config(&config.waku_config, config.genesis_time()).map(Mixer::new),
+            cover: CoverConfig::from_config(&config.waku_config),
+            liveness,
+            heartbeat_limiter: HeartbeatLimiter::new(),
+            held: HeldAttestations::new(),
+            genesis_time: config.genesis_time(),
             stats: Arc::new(RwLock::new(RelayStats {
                 messages_sent: 0,
                 messages_received: 0,
@@ -466,6 +784,8 @@ impl<W: WakuProvider> FriendRelay<W> {
                 average_latency_ms: 0.0,
                 rate_limit_violations: 0,
                 bandwidth_bytes_per_second: 0.0,
+                cover_sent: 0,
+                cover_received: 0,
             })),
             command_tx: command_tx.clone(),
             command_rx,
@@ -475,18 +795,71 @@ impl<W: WakuProvider> FriendRelay<W> {
 
         let handle = FriendRelayHandle {
             command_tx,
-            event_rx,
+            event_rx: Some(event_rx),
         };
 
         (relay, handle)
     }
 
+    /// Publish what friends relay through us; without a publisher inbound
+    /// messages are verified but go nowhere
+    pub fn with_publisher(mut self, publisher: Arc<dyn BeaconPublisher>) -> Self {
+        self.publisher = Some(publisher);
+        self
+    }
+
+    /// Seal and open relay payloads with a persistent key friends know; the
+    /// default is ephemeral, so friends cannot reach us across restarts
+    pub fn with_identity(mut self, identity: RelayIdentity) -> Self {
+        self.identity = Arc::new(identity);
+        let keys = self.channels.drain().map(|(_, channel)| channel.key).collect();
+        self.channels = Self::open_channels(&self.identity, keys);
+        self
+    }
+
+    fn open_channels(identity: &RelayIdentity, keys: Vec<FriendKey>) -> HashMap<String, FriendChannel> {
+        keys.into_iter()
+            .filter_map(|key| match identity.channel(&key) {
+                Ok(channel) => Some((key.peer_id, channel)),
+                Err(e) => {
+                    error!("🔐 Not relaying through {}: {}", key.peer_id, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
     /// Main event loop for the friend relay
     pub async fn run(&mut self) -> StealthResult<()> {
         info!("Starting FriendRelay with {} friends...", self.friends.len());
 
         // Set up periodic tasks
         let mut stats_timer = interval(Duration::from_secs(60));
+        let mut embargo_timer = interval(EMBARGO_CHECK_INTERVAL);
+        let mut mix_timer = interval(self.mixer.as_ref().map_or(EMBARGO_CHECK_INTERVAL, |mixer| mixer.config().window));
+        let mut heartbeat_timer = interval(self.liveness.as_ref().map_or(EMBARGO_CHECK_INTERVAL, Liveness::interval));
+        let mut held_timer = interval(HELD_CHECK_INTERVAL);
+        if let Some(mixer) = &self.mixer {
+            info!("🎲 Mixing attestations with {:?}, released every {:?}", mixer.config().delay, mixer.config().window);
+        }
+        if let Some(dandelion) = &self.dandelion {
+            info!("🌼 Dandelion++ routing: stem probability {}, embargo {:?}", dandelion.stem_probability, dandelion.embargo);
+        }
+        let cover_timer = tokio::time::sleep(self.cover.map_or(Duration::ZERO, |cover| cover.next_delay()));
+        tokio::pin!(cover_timer);
+        if let Some(cover) = &self.cover {
+            info!("🎭 Sending cover traffic: {:?}", cover.traffic);
+        }
+
+        // Each friend sends to us on its own topic; funnel them all into one channel
+        let channels: Vec<FriendChannel> = self.channels.values().cloned().collect();
+        let mut subscribed = 0;
+        for channel in &channels {
+            if self.subscribe_inbound(channel).await {
+                subscribed += 1;
+            }
+        }
+        info!("📥 Relaying for {}/{} friends", subscribed, channels.len());
 
         loop {
             tokio::select! {
@@ -503,6 +876,40 @@ impl<W: WakuProvider> FriendRelay<W> {
                     self.update_stats().await;
                 }
 
+                // Release what the mixer held, in one shuffled batch
+                _ = mix_timer.tick(), if self.mixer.as_ref().is_some_and(|mixer| !mixer.is_empty()) => {
+                    self.release_mixed().await;
+                }
+
+                // Relay held attestations once enough friends are back, or drop them at the deadline
+                _ = held_timer.tick(), if !self.held.is_empty() => {
+                    self.retry_held().await;
+                }
+
+                // Ping every friend, and give up on those that stopped answering
+                _ = heartbeat_timer.tick(), if self.liveness.is_some() => {
+                    self.send_heartbeats().await;
+                }
+
+                // Send the next dummy message, through the mixer if there is one
+                _ = &mut cover_timer, if self.cover.is_some() => {
+                    if let Some(cover) = self.cover {
+                        cover_timer.as_mut().reset(tokio::time::Instant::now() + cover.next_delay());
+                    }
+                    match &mut self.mixer {
+                        Some(mixer) => {
+                            let slot = EpochInfo::at(self.genesis_time, unix_now()).slot;
+                            mixer.hold(Mixed::Cover, slot, tokio::time::Instant::now(), unix_now());
+                        }
+                        None => self.send_cover().await,
+                    }
+                }
+
+                // Fluff stemmed attestations that never reached gossip
+                _ = embargo_timer.tick(), if !self.embargoes.is_empty() => {
+                    self.fluff_expired_embargoes().await;
+                }
+
                 // Handle commands
                 Some(command) = self.command_rx.recv() => {
                     if let Err(e) = self.handle_command(command).await {
@@ -510,26 +917,436 @@ impl<W: WakuProvider> FriendRelay<W> {
                         let _ = self.event_tx.send(RelayEvent::Error(e.to_string()));
                     }
                 }
+
+                // Relay for friends
+                Some((peer_id, envelope)) = self.inbound_rx.recv() => {
+                    self.handle_inbound(peer_id, &envelope).await;
+                }
             }
         }
 
+        for (_, subscription) in self.subscriptions.drain() {
+            subscription.abort();
+        }
         Ok(())
     }
 
+    /// Forward what `channel`'s friend sends us into the inbound channel
+    async fn subscribe_inbound(&mut self, channel: &FriendChannel) -> bool {
+        match self.waku_provider.subscribe_relay(&channel.inbound_topic).await {
+            Ok(mut rx) => {
+                let peer_id = channel.peer_id().to_string();
+                let inbound_tx = self.inbound_tx.clone();
+                let forwarder = tokio::spawn({
+                    let peer_id = peer_id.clone();
+                    async move {
+                        while let Some(envelope) = rx.recv().await {
+                            if inbound_tx.send((peer_id.clone(), envelope)).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                });
+                if let Some(previous) = self.subscriptions.insert(peer_id, forwarder) {
+                    previous.abort();
+                }
+                true
+            }
+            Err(e) => {
+                warn!("Failed to subscribe to the relay topic of {}: {}", channel.peer_id(), e);
+                false
+            }
+        }
+    }
+
+    /// Verify a message a friend relayed to us and publish its attestation
+    async fn handle_inbound(&mut self, topic_friend: String, envelope: &[u8]) {
+        let (payload, sender) = match self.identity.open(envelope) {
+            Ok(opened) => opened,
+            Err(_) => return self.drop_inbound(None, DropReason::Undecryptable),
+        };
+        // Only the sender key authenticates the friend, never the topic or the claimed
+        // `sender_id`; a removed friend's topic may still deliver for a moment
+        let Some(from_friend) = self
+            .channels
+            .get(&topic_friend)
+            .filter(|channel| channel.key.public_key == sender)
+            .map(|channel| channel.peer_id().to_string())
+        else {
+            debug!("Message on {}'s topic sealed by key {}", topic_friend, crypto::fingerprint(&sender));
+            return self.drop_inbound(None, DropReason::UnknownSender);
+        };
+        self.friend_seen(&from_friend).await;
+        if let Ok(heartbeat) = serde_json::from_slice::<Heartbeat>(&payload) {
+            if !self.heartbeat_limiter.allow(&from_friend, tokio::time::Instant::now()) {
+                return self.drop_inbound(Some(from_friend), DropReason::RateLimited);
+            }
+            return self.handle_heartbeat(from_friend, heartbeat).await;
+        }
+        let proven = match serde_json::from_slice::<ProvenMessage>(&payload) {
+            Ok(proven) if proven.message.subnet_id < ATTESTATION_SUBNET_COUNT => proven,
+            _ => return self.drop_inbound(Some(from_friend), DropReason::Malformed),
+        };
+        let subnet_id = proven.message.subnet_id;
+        let message_id = proven.message.message_id.clone();
+
+        if self.message_queue.write().await.has_seen(&message_id) {
+            return self.drop_inbound(Some(from_friend), DropReason::Duplicate);
+        }
+
+        // The proof covers the same serialization the sender generated it over
+        let Ok(message_bytes) = serde_json::to_vec(&proven.message) else {
+            return self.drop_inbound(Some(from_friend), DropReason::Malformed);
+        };
+        match self.waku_provider.get_rln_epoch().await {
+            Ok(epoch) if proven.rln_proof.epoch.abs_diff(epoch) <= MAX_RLN_EPOCH_DRIFT => {}
+            Ok(epoch) => {
+                debug!("Friend message {} from {} for RLN epoch {}, ours is {}", message_id, from_friend, proven.rln_proof.epoch, epoch);
+                return self.drop_inbound(Some(from_friend), DropReason::InvalidProof);
+            }
+            Err(e) => {
+                warn!("Failed to read the RLN epoch for a message from {}: {}", from_friend, e);
+                return self.drop_inbound(Some(from_friend), DropReason::InvalidProof);
+            }
+        }
+        match self.waku_provider.verify_rln_proof(&proven.rln_proof, &message_bytes).await {
+            Ok(true) => {}
+            Ok(false) => return self.drop_inbound(Some(from_friend), DropReason::InvalidProof),
+            Err(e) => {
+                warn!("Failed to verify RLN proof from {}: {}", from_friend, e);
+                return self.drop_inbound(Some(from_friend), DropReason::InvalidProof);
+            }
+        }
+        // Every friend may use the full per-epoch budget, but only its own
+        let rate_limit = self.config.waku_config.rate_limit_per_epoch;
+        let limiter = self.inbound_limiters.entry(from_friend.clone()).or_insert_with(|| RateLimiter::new(rate_limit));
+        if let Err(e) = limiter.check_and_update(proven.rln_proof.epoch, proven.rln_proof.nullifier) {
+            debug!("Friend message {} from {} over the rate limit: {}", message_id, from_friend, e);
+            let (epoch, attempts, limit) = limiter.get_stats();
+            let _ = self.event_tx.send(RelayEvent::RateLimitExceeded { epoch, attempts, limit });
+            return self.drop_inbound(Some(from_friend), DropReason::RateLimited);
+        }
+
+        self.message_queue.write().await.add_message(message_id.clone());
+        // Verified and counted against the friend's budget like a real message, then dropped
+        if proven.message.cover {
+            self.stats.write().await.cover_received += 1;
+            debug!("🎭 Dropped cover message from {}", from_friend);
+            return;
+        }
+        self.stats.write().await.messages_received += 1;
+        let _ = self.event_tx.send(RelayEvent::MessageReceived {
+            message_id: message_id.clone(),
+            from_friend: from_friend.clone(),
+            subnet_id,
+        });
+
+        // A message without a slot is taken to be for the current one
+        let slot = proven.message.slot.unwrap_or_else(|| EpochInfo::at(self.genesis_time, unix_now()).slot);
+        let inbound = Inbound {
+            from_friend,
+            message_id,
+            proven,
+            payload,
+        };
+        match &mut self.mixer {
+            Some(mixer) => {
+                let delay = mixer.hold(Mixed::Inbound(Box::new(inbound)), slot, tokio::time::Instant::now(), unix_now());
+                debug!("🎲 Holding a friend's attestation for {:?}", delay);
+            }
+            None => self.deliver_inbound(inbound).await,
+        }
+    }
+
+    /// Pass a verified message along the stem, or publish it
+    async fn deliver_inbound(&mut self, inbound: Inbound) {
+        let Inbound { from_friend, message_id, proven, payload } = inbound;
+        let subnet_id = proven.message.subnet_id;
+        if proven.message.stem && self.dandelion.is_some_and(|dandelion| dandelion.keep_stem()) {
+            let embargoed = Embargoed {
+                message_id,
+                subnet_id,
+                attestation_data: proven.message.attestation_data.clone(),
+                slot: proven.message.slot.unwrap_or_else(|| EpochInfo::at(self.genesis_time, unix_now()).slot),
+                own: false,
+                deadline: tokio::time::Instant::now(),
+            };
+            if self.forward_stem(&from_friend, embargoed, &payload).await {
+                return;
+            }
+        }
+
+        let Some(publisher) = &self.publisher else {
+            debug!("No beacon publisher, not publishing attestation relayed by {}", from_friend);
+            return;
+        };
+        match publisher.publish_attestation(subnet_id, proven.message.attestation_data).await {
+            Ok(()) => debug!("📤 Published attestation relayed by {} on subnet {}", from_friend, subnet_id),
+            Err(e) => {
+                warn!("Failed to publish attestation relayed by {}: {}", from_friend, e);
+                self.drop_inbound(Some(from_friend), DropReason::PublishFailed);
+            }
+        }
+    }
+
+    /// Pass a friend's stem message on to our relay for that friend, under embargo.
+    /// Returns false when there is no one to pass it to, so the caller fluffs it.
+    async fn forward_stem(&mut self, from_friend: &str, mut embargoed: Embargoed, proven_message_bytes: &[u8]) -> bool {
+        let Some(dandelion) = self.dandelion else {
+            return false;
+        };
+        let now = tokio::time::Instant::now();
+        let friends = self.reachable_friends();
+        let Some(channel) = self
+            .stem_graph
+            .next_hop(Some(from_friend), &friends, now)
+            .and_then(|next_hop| self.channels.get(&next_hop))
+        else {
+            return false;
+        };
+
+        let result = match self.identity.seal(&channel.key, proven_message_bytes) {
+            Ok(envelope) => self.waku_provider.light_push(&channel.outbound_topic, &envelope, Some(Utc::now())).await,
+            Err(e) => Err(StealthError::WakuRln(e.to_string())),
+        };
+        let _ = self.event_tx.send(RelayEvent::FriendMessageSent {
+            friend_id: channel.peer_id().to_string(),
+            success: result.is_ok(),
+        });
+        if let Err(e) = result {
+            warn!("Failed to pass {} along the stem to {}: {}", embargoed.message_id, channel.peer_id(), e);
+            return false;
+        }
+
+        debug!("🌱 Passed {} from {} along the stem to {}", embargoed.message_id, from_friend, channel.peer_id());
+        embargoed.deadline = dandelion.embargo_deadline(now, self.propagation_instant(embargoed.slot, now));
+        self.embargoes.insert(embargoed);
+        true
+    }
+
+    /// Send out everything in the mixer whose delay is over
+    async fn release_mixed(&mut self) {
+        let Some(mixer) = &mut self.mixer else {
+            return;
+        };
+        let batch = mixer.release(tokio::time::Instant::now());
+        if batch.len() > 1 {
+            debug!("🎲 Releasing a batch of {} attestations", batch.len());
+        }
+        for mixed in batch {
+            match mixed {
+                Mixed::Outbound { attestation_data, subnet_id, slot } => {
+                    if let Err(e) = self.relay_attestation(attestation_data, subnet_id, slot).await {
+                        error!("Error relaying mixed attestation: {}", e);
+                        let _ = self.event_tx.send(RelayEvent::Error(e.to_string()));
+                    }
+                }
+                Mixed::Inbound(inbound) => self.deliver_inbound(*inbound).await,
+                Mixed::Cover => self.send_cover().await,
+            }
+        }
+    }
+
+    /// Fluff the stemmed attestations whose embargo ran out before gossip saw them:
+    /// a friend's ourselves, and ours through a friend off the stem, since
+    /// publishing it from our own identity would give us away
+    async fn fluff_expired_embargoes(&mut self) {
+        let expired = self.embargoes.expire(tokio::time::Instant::now());
+        let publisher = self.publisher.clone();
+        for embargoed in expired {
+            if let Some(publisher) = &publisher {
+                if publisher.seen_on_gossip(embargoed.subnet_id, &embargoed.attestation_data).await {
+                    debug!("🌼 {} reached gossip before its embargo ran out", embargoed.message_id);
+                    continue;
+                }
+            }
+            let (message_id, subnet_id) = (embargoed.message_id.clone(), embargoed.subnet_id);
+            let result = match (&publisher, embargoed.own) {
+                (_, true) => {
+                    warn!("⏰ Embargo expired for our {} on subnet {}, fluffing it through a friend", message_id, subnet_id);
+                    self.fluff_own(embargoed).await
+                }
+                (Some(publisher), false) => {
+                    warn!("⏰ Embargo expired for {} on subnet {}, fluffing it ourselves", message_id, subnet_id);
+                    publisher.publish_attestation(subnet_id, embargoed.attestation_data).await
+                }
+                (None, false) => {
+                    warn!("⏰ Embargo expired for {} but there is no beacon publisher", message_id);
+                    continue;
+                }
+            };
+            if let Err(e) = result {
+                warn!("Failed to fluff {} after its embargo: {}", message_id, e);
+                continue;
+            }
+            let _ = self.event_tx.send(RelayEvent::EmbargoExpired { message_id, subnet_id });
+        }
+    }
+
+    /// Have a friend off the stem publish our attestation, as a fresh message
+    async fn fluff_own(&mut self, embargoed: Embargoed) -> StealthResult<()> {
+        let relay_message = RelayMessage {
+            message_id: format!("{}_{}", Utc::now().timestamp_nanos_opt().unwrap_or(0), embargoed.subnet_id),
+            attestation_data: embargoed.attestation_data,
+            subnet_id: embargoed.subnet_id,
+            timestamp: Utc::now(),
+            origin_hint: None,
+            stem: false,
+            cover: false,
+            slot: Some(embargoed.slot),
+        };
+        self.send_to_friends(relay_message).await.map(|_| ())
+    }
+
+    /// A reachable friend other than our own stem relay, or the stem relay if it is the only one
+    fn fluff_relay(&self) -> Option<FriendNodeConfig> {
+        let friends = self.reachable_friends();
+        let off_stem: Vec<&String> = friends
+            .iter()
+            .filter(|friend| Some(friend.as_str()) != self.stem_graph.own_relay())
+            .collect();
+        let chosen = off_stem.choose(&mut thread_rng()).copied().or_else(|| friends.choose(&mut thread_rng()))?;
+        self.friends.get(chosen).cloned()
+    }
+
+    /// `slot`'s propagation deadline as an instant on the clock `now` is from
+    fn propagation_instant(&self, slot: u64, now: tokio::time::Instant) -> tokio::time::Instant {
+        now + mixer::propagation_deadline(self.genesis_time, slot).saturating_sub(unix_now())
+    }
+
+    /// Whether `peer_id` has answered us; every friend is when heartbeats are off
+    fn is_live(&self, peer_id: &str) -> bool {
+        self.liveness.as_ref().is_none_or(|liveness| liveness.is_live(peer_id))
+    }
+
+    /// Friends with a channel that have shown they are live
+    fn reachable_friends(&self) -> Vec<String> {
+        self.channels.keys().filter(|peer_id| self.is_live(peer_id)).cloned().collect()
+    }
+
+    /// Ping every friend over its channel
+    async fn send_heartbeats(&mut self) {
+        let now = tokio::time::Instant::now();
+        let peers: Vec<String> = self.channels.keys().cloned().collect();
+        for peer_id in peers {
+            let Some((nonce, transition)) = self.liveness.as_mut().and_then(|liveness| liveness.ping(&peer_id, now)) else {
+                continue;
+            };
+            if let Some(transition) = transition {
+                self.report_transition(peer_id.clone(), transition).await;
+            }
+            if let Err(e) = self.send_heartbeat(&peer_id, Heartbeat::Ping { nonce }).await {
+                debug!("💓 Failed to ping {}: {}", peer_id, e);
+            }
+        }
+    }
+
+    async fn send_heartbeat(&self, peer_id: &str, heartbeat: Heartbeat) -> StealthResult<()> {
+        let channel = self
+            .channels
+            .get(peer_id)
+            .ok_or_else(|| StealthError::Config(format!("No valid public key for {}", peer_id)))?;
+        let payload = serde_json::to_vec(&heartbeat)
+            .map_err(|e| StealthError::WakuRln(format!("Heartbeat serialization failed: {}", e)))?;
+        let envelope = self
+            .identity
+            .seal(&channel.key, &payload)
+            .map_err(|e| StealthError::WakuRln(e.to_string()))?;
+        self.waku_provider.light_push(&channel.outbound_topic, &envelope, Some(Utc::now())).await?;
+        Ok(())
+    }
+
+    async fn handle_heartbeat(&mut self, from_friend: String, heartbeat: Heartbeat) {
+        match heartbeat {
+            Heartbeat::Ping { nonce } => {
+                if let Err(e) = self.send_heartbeat(&from_friend, Heartbeat::Pong { nonce }).await {
+                    debug!("💓 Failed to answer {}'s ping: {}", from_friend, e);
+                }
+            }
+            Heartbeat::Pong { nonce } => {
+                let Some(liveness) = &mut self.liveness else {
+                    return;
+                };
+                let Some(rtt) = liveness.pong(&from_friend, nonce, tokio::time::Instant::now()) else {
+                    debug!("💓 Late or unexpected pong from {}", from_friend);
+                    return;
+                };
+                let loss_rate = liveness.get(&from_friend).map_or(0.0, |friend| friend.loss_rate());
+                let _ = self.event_tx.send(RelayEvent::FriendHeartbeat {
+                    friend_id: from_friend,
+                    rtt,
+                    loss_rate,
+                });
+            }
+        }
+    }
+
+    /// Anything authenticated from a friend shows it is live
+    async fn friend_seen(&mut self, peer_id: &str) {
+        let transition = self
+            .liveness
+            .as_mut()
+            .and_then(|liveness| liveness.seen(peer_id, tokio::time::Instant::now()));
+        if let Some(transition) = transition {
+            self.report_transition(peer_id.to_string(), transition).await;
+        }
+    }
+
+    async fn report_transition(&mut self, peer_id: String, transition: Transition) {
+        self.stats.write().await.friends_connected = self.liveness.as_ref().map_or(0, Liveness::live_count);
+        let event = match transition {
+            Transition::Connected => {
+                info!("💓 Friend {} is live", peer_id);
+                RelayEvent::FriendConnected(peer_id)
+            }
+            Transition::Disconnected => {
+                warn!("💔 Lost friend {}", peer_id);
+                RelayEvent::FriendDisconnected(peer_id)
+            }
+        };
+        let _ = self.event_tx.send(event);
+    }
+
+    fn drop_inbound(&self, from_friend: Option<String>, reason: DropReason) {
+        debug!("Dropped friend message from {:?}: {}", from_friend, reason.as_str());
+        let _ = self.event_tx.send(RelayEvent::MessageDropped { from_friend, reason });
+    }
+
     async fn handle_command(&mut self, command: RelayCommand) -> StealthResult<()> {
         match command {
-            RelayCommand::RelayAttestation { attestation_data, subnet_id } => {
-                self.relay_attestation(attestation_data, subnet_id).await?;
-            }
+            RelayCommand::RelayAttestation { attestation_data, subnet_id, slot } => match &mut self.mixer {
+                Some(mixer) => {
+                    let outbound = Mixed::Outbound { attestation_data, subnet_id, slot };
+                    let delay = mixer.hold(outbound, slot, tokio::time::Instant::now(), unix_now());
+                    debug!("🎲 Holding our attestation for subnet {} for {:?}", subnet_id, delay);
+                }
+                None => self.relay_attestation(attestation_data, subnet_id, slot).await?,
+            },
             RelayCommand::AddFriend(friend_config) => {
-                info!("Adding friend: {}", friend_config.peer_id);
+                let channel = FriendKey::from_config(&friend_config)
+                    .and_then(|key| self.identity.channel(&key))
+                    .map_err(|e| StealthError::Config(e.to_string()))?;
+                info!("Adding friend: {} (key fingerprint {})", friend_config.peer_id, channel.key.fingerprint());
+                self.subscribe_inbound(&channel).await;
+                if let Some(liveness) = &mut self.liveness {
+                    liveness.add(&friend_config.peer_id);
+                }
+                self.channels.insert(friend_config.peer_id.clone(), channel);
                 self.friends.insert(friend_config.peer_id.clone(), friend_config.clone());
-                let _ = self.event_tx.send(RelayEvent::FriendConnected(friend_config.peer_id));
             }
             RelayCommand::RemoveFriend(peer_id) => {
                 info!("Removing friend: {}", peer_id);
                 self.friends.remove(&peer_id);
-                let _ = self.event_tx.send(RelayEvent::FriendDisconnected(peer_id));
+                self.channels.remove(&peer_id);
+                if let Some(subscription) = self.subscriptions.remove(&peer_id) {
+                    subscription.abort();
+                }
+                self.inbound_limiters.remove(&peer_id);
+                self.heartbeat_limiter.remove(&peer_id);
+                let removed = self.liveness.as_mut().and_then(|liveness| liveness.remove(&peer_id));
+                if removed.is_some_and(|friend| friend.status == liveness::Status::Live) {
+                    self.report_transition(peer_id, Transition::Disconnected).await;
+                }
             }
             RelayCommand::GetStats => {
                 let stats = self.stats.read().await.clone();
@@ -539,6 +1356,10 @@ impl<W: WakuProvider> FriendRelay<W> {
                 info!("Updating RLN parameters: rate_limit={}, epoch={}", rate_limit, epoch);
                 let mut limiter = self.rate_limiter.write().await;
                 *limiter = RateLimiter::new(rate_limit);
+                self.config.waku_config.rate_limit_per_epoch = rate_limit;
+                for limiter in self.inbound_limiters.values_mut() {
+                    limiter.set_rate_limit(rate_limit);
+                }
             }
             RelayCommand::Stop => {
                 info!("Received stop command");
@@ -548,19 +1369,172 @@ impl<W: WakuProvider> FriendRelay<W> {
         Ok(())
     }
 
-    async fn relay_attestation(&mut self, attestation_data: Vec<u8>, subnet_id: u8) -> StealthResult<()> {
+    async fn relay_attestation(&mut self, attestation_data: Vec<u8>, subnet_id: u8, slot: u64) -> StealthResult<()> {
+        let reachable = self.reachable_friends().len();
+        if reachable < self.config.min_anonymity_set() {
+            return self.anonymity_fallback(attestation_data, subnet_id, slot, reachable).await;
+        }
         let start_time = Instant::now();
         
         // Create relay message
         let message_id = format!("{}_{}", Utc::now().timestamp_nanos_opt().unwrap_or(0), subnet_id);
         let relay_message = RelayMessage {
             message_id: message_id.clone(),
-            attestation_data: attestation_data.clone(),
+            attestation_data,
             subnet_id,
             timestamp: Utc::now(),
             origin_hint: None, // We don't reveal origin for privacy
+            stem: self.dandelion.is_some(),
+            cover: false,
+            slot: Some(slot),
         };
 
+        let (successful_relays, friends_count) = self.send_to_friends(relay_message).await?;
+
+        if successful_relays < friends_count {
+            warn!("Only {}/{} friends received the message", successful_relays, friends_count);
+        }
+
+        // Update stats
+        {
+            let mut stats = self.stats.write().await;
+            stats.messages_sent += 1;
+            let latency_ms = start_time.elapsed().as_millis() as u64;
+            stats.average_latency_ms = 
+                (stats.average_latency_ms * (stats.messages_sent - 1) as f64 + latency_ms as f64) 
+                / stats.messages_sent as f64;
+        }
+
+        // Emit success event
+        let _ = self.event_tx.send(RelayEvent::MessageRelayed {
+            message_id,
+            friends_count: successful_relays,
+            latency_ms: start_time.elapsed().as_millis() as u64,
+        });
+
+        info!("Relayed attestation to {}/{} friends in {}ms", 
+              successful_relays, friends_count, start_time.elapsed().as_millis());
+
+        Ok(())
+    }
+
+    /// Apply `anonymity_fallback` to an attestation too few friends are reachable to hide
+    async fn anonymity_fallback(
+        &mut self,
+        attestation_data: Vec<u8>,
+        subnet_id: u8,
+        slot: u64,
+        reachable: usize,
+    ) -> StealthResult<()> {
+        let min = self.config.min_anonymity_set();
+        let decision = match self.config.anonymity_fallback.unwrap_or_default() {
+            AnonymityFallback::PublishDirect => match &self.publisher {
+                Some(publisher) => {
+                    warn!("🕵️ Only {}/{} friends reachable, publishing our attestation for subnet {} directly", reachable, min, subnet_id);
+                    publisher.publish_attestation(subnet_id, attestation_data).await?;
+                    AnonymityDecision::PublishedDirect
+                }
+                None => {
+                    error!("🚨 Only {}/{} friends reachable and no beacon publisher, dropping our attestation for subnet {}", reachable, min, subnet_id);
+                    AnonymityDecision::Dropped
+                }
+            },
+            AnonymityFallback::Delay => {
+                match anonymity::hold_deadline(self.genesis_time, slot, unix_now(), tokio::time::Instant::now()) {
+                    Some(deadline) => {
+                        warn!("🕵️ Only {}/{} friends reachable, holding our attestation for subnet {}", reachable, min, subnet_id);
+                        self.held.hold(attestation_data, subnet_id, slot, deadline);
+                        AnonymityDecision::Delayed
+                    }
+                    None => {
+                        error!("🚨 Only {}/{} friends reachable past the slot deadline, dropping our attestation for subnet {}", reachable, min, subnet_id);
+                        AnonymityDecision::Dropped
+                    }
+                }
+            }
+            AnonymityFallback::Drop => {
+                error!("🚨 Only {}/{} friends reachable, dropping our attestation for subnet {}", reachable, min, subnet_id);
+                AnonymityDecision::Dropped
+            }
+        };
+        let _ = self.event_tx.send(RelayEvent::AnonymityFallback { subnet_id, reachable, decision });
+        Ok(())
+    }
+
+    /// Drop held attestations past their deadline, and relay the rest if enough friends are back
+    async fn retry_held(&mut self) {
+        let reachable = self.reachable_friends().len();
+        let min = self.config.min_anonymity_set();
+        for held in self.held.expire(tokio::time::Instant::now()) {
+            error!("🚨 Still only {}/{} friends reachable at the slot deadline, dropping our attestation for subnet {}", reachable, min, held.subnet_id);
+            let _ = self.event_tx.send(RelayEvent::AnonymityFallback {
+                subnet_id: held.subnet_id,
+                reachable,
+                decision: AnonymityDecision::Dropped,
+            });
+        }
+        if reachable < min {
+            return;
+        }
+        for held in self.held.take_all() {
+            info!("🕵️ {} friends reachable again, relaying our held attestation for subnet {}", reachable, held.subnet_id);
+            let _ = self.event_tx.send(RelayEvent::AnonymityFallback {
+                subnet_id: held.subnet_id,
+                reachable,
+                decision: AnonymityDecision::RelayedAfterDelay,
+            });
+            if let Err(e) = self.relay_attestation(held.attestation_data, held.subnet_id, held.slot).await {
+                error!("Error relaying held attestation: {}", e);
+                let _ = self.event_tx.send(RelayEvent::Error(e.to_string()));
+            }
+        }
+    }
+
+    /// Send a dummy message exactly as an attestation would go out, unless it
+    /// would eat into the half of the RLN budget kept for attestations
+    async fn send_cover(&mut self) {
+        if self.channels.is_empty() {
+            return;
+        }
+        let epoch = match self.waku_provider.get_rln_epoch().await {
+            Ok(epoch) => epoch,
+            Err(e) => {
+                debug!("🎭 Skipping cover message, no RLN epoch: {}", e);
+                return;
+            }
+        };
+        let (used, limit) = {
+            let limiter = self.rate_limiter.read().await;
+            (limiter.used(epoch), limiter.get_stats().2)
+        };
+        if used >= cover::cover_budget(limit) {
+            debug!("🎭 Skipping cover message, {}/{} of RLN epoch {} used", used, limit, epoch);
+            return;
+        }
+
+        let subnet_id = thread_rng().gen_range(0..ATTESTATION_SUBNET_COUNT);
+        let relay_message = RelayMessage {
+            message_id: format!("{}_{}", Utc::now().timestamp_nanos_opt().unwrap_or(0), subnet_id),
+            attestation_data: cover::dummy_attestation(),
+            subnet_id,
+            timestamp: Utc::now(),
+            origin_hint: None,
+            stem: self.dandelion.is_some(),
+            cover: true,
+            slot: Some(EpochInfo::at(self.genesis_time, unix_now()).slot),
+        };
+        match self.send_to_friends(relay_message).await {
+            Ok((successful, selected)) => {
+                self.stats.write().await.cover_sent += 1;
+                debug!("🎭 Sent cover message to {}/{} friends", successful, selected);
+            }
+            Err(e) => debug!("🎭 Cover message failed: {}", e),
+        }
+    }
+
+    /// Prove, seal and push `relay_message` to the friends it goes to, returning
+    /// how many of them got it out of how many were selected
+    async fn send_to_friends(&mut self, relay_message: RelayMessage) -> StealthResult<(usize, usize)> {
         // Get current RLN epoch
         let rln_epoch = self.waku_provider.get_rln_epoch().await?;
 
@@ -585,6 +1559,16 @@ impl<W: WakuProvider> FriendRelay<W> {
             }
         }
 
+        let message_id = relay_message.message_id.clone();
+        let embargoed = (!relay_message.cover).then(|| Embargoed {
+            message_id: message_id.clone(),
+            subnet_id: relay_message.subnet_id,
+            attestation_data: relay_message.attestation_data.clone(),
+            slot: relay_message.slot.unwrap_or_else(|| EpochInfo::at(self.genesis_time, unix_now()).slot),
+            own: true,
+            deadline: tokio::time::Instant::now(),
+        });
+
         // Create proven message
         let proven_message = ProvenMessage {
             message: relay_message,
@@ -595,22 +1579,52 @@ impl<W: WakuProvider> FriendRelay<W> {
         let proven_message_bytes = serde_json::to_vec(&proven_message)
             .map_err(|e| StealthError::WakuRln(format!("Proven message serialization failed: {}", e)))?;
 
+        // Our own message comes back to us on the topic
+        self.message_queue.write().await.add_message(message_id.clone());
+
         // Select friends to relay through (randomize for better privacy)
-        let mut selected_friends: Vec<_> = self.friends.values().cloned().collect();
-        selected_friends.shuffle(&mut thread_rng());
-        
-        // Use all friends for maximum k-anonymity
+        let selected_friends: Vec<_> = match self.dandelion {
+            // Our own attestation whose stem went quiet, for one friend to publish
+            Some(_) if !proven_message.message.stem => self.fluff_relay().into_iter().collect(),
+            // One stem relay, and a fluff through a friend if the attestation never comes back on gossip
+            Some(dandelion) => {
+                let now = tokio::time::Instant::now();
+                let friends = self.reachable_friends();
+                if let Some(mut embargoed) = embargoed {
+                    embargoed.deadline = dandelion.embargo_deadline(now, self.propagation_instant(embargoed.slot, now));
+                    self.embargoes.insert(embargoed);
+                }
+                self.stem_graph
+                    .next_hop(None, &friends, now)
+                    .and_then(|next_hop| self.friends.get(&next_hop).cloned())
+                    .into_iter()
+                    .collect()
+            }
+            // Use all friends for maximum k-anonymity
+            None => {
+                let mut friends: Vec<_> = self.friends.values().filter(|friend| self.is_live(&friend.peer_id)).cloned().collect();
+                friends.shuffle(&mut thread_rng());
+                friends
+            }
+        };
         let friends_count = selected_friends.len();
         
-        // Relay to all friends simultaneously
+        // Relay to all friends simultaneously, each sealed to that friend on its own topic
         let relay_futures: Vec<_> = selected_friends
             .iter()
             .map(|friend| {
-                let topic = format!("/stealth-relay/{}/ssz", subnet_id);
                 let waku_provider = &self.waku_provider;
-                let message_bytes = proven_message_bytes.clone();
+                let sealed = match self.channels.get(&friend.peer_id) {
+                    Some(channel) => self
+                        .identity
+                        .seal(&channel.key, &proven_message_bytes)
+                        .map(|envelope| (channel.outbound_topic.clone(), envelope))
+                        .map_err(|e| StealthError::WakuRln(e.to_string())),
+                    None => Err(StealthError::Config(format!("No valid public key for {}", friend.peer_id))),
+                };
                 async move {
-                    waku_provider.light_push(&topic, &message_bytes, Some(Utc::now())).await
+                    let (topic, envelope) = sealed?;
+                    waku_provider.light_push(&topic, &envelope, Some(Utc::now())).await
                 }
             })
             .collect();
@@ -618,38 +1632,24 @@ impl<W: WakuProvider> FriendRelay<W> {
         // Wait for all relays to complete
         let results = futures::future::join_all(relay_futures).await;
         
-        // Count successful relays
-        let successful_relays = results.iter().filter(|r| r.is_ok()).count();
+        // Count successful relays, and report each friend's
+        let mut successful_relays = 0;
+        for (friend, result) in selected_friends.iter().zip(&results) {
+            if let Err(e) = result {
+                debug!("Relay through {} failed: {}", friend.peer_id, e);
+            }
+            successful_relays += usize::from(result.is_ok());
+            let _ = self.event_tx.send(RelayEvent::FriendMessageSent {
+                friend_id: friend.peer_id.clone(),
+                success: result.is_ok(),
+            });
+        }
         
         if successful_relays == 0 {
             return Err(StealthError::WakuRln("Failed to relay to any friends".to_string()));
         }
 
-        if successful_relays < friends_count {
-            warn!("Only {}/{} friends received the message", successful_relays, friends_count);
-        }
-
-        // Update stats
-        {
-            let mut stats = self.stats.write().await;
-            stats.messages_sent += 1;
-            let latency_ms = start_time.elapsed().as_millis() as u64;
-            stats.average_latency_ms = 
-                (stats.average_latency_ms * (stats.messages_sent - 1) as f64 + latency_ms as f64) 
-                / stats.messages_sent as f64;
-        }
-
-        // Emit success event
-        let _ = self.event_tx.send(RelayEvent::MessageRelayed {
-            message_id,
-            friends_count: successful_relays,
-            latency_ms: start_time.elapsed().as_millis() as u64,
-        });
-
-        info!("Relayed attestation to {}/{} friends in {}ms", 
-              successful_relays, friends_count, start_time.elapsed().as_millis());
-
-        Ok(())
+        Ok((successful_relays, friends_count))
     }
 
     async fn update_stats(&self) {
@@ -657,6 +1657,11 @@ impl<W: WakuProvider> FriendRelay<W> {
         debug!("Friend relay stats: sent={}, received={}, friends={}, avg_latency={}ms",
                stats.messages_sent, stats.messages_received, 
                stats.friends_connected, stats.average_latency_ms);
+        if self.cover.is_some() || stats.cover_received > 0 {
+            let (sent, received) = stats.real_fraction();
+            debug!("🎭 Real share: sent {:?} ({} cover), received {:?} ({} cover)",
+                   sent, stats.cover_sent, received, stats.cover_received);
+        }
     }
 
     pub async fn get_stats(&self) -> RelayStats {
@@ -664,10 +1669,15 @@ impl<W: WakuProvider> FriendRelay<W> {
     }
 }
 
+/// Time since the Unix epoch, for slot deadlines
+fn unix_now() -> Duration {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default()
+}
+
 /// Handle to interact with the FriendRelay
 pub struct FriendRelayHandle {
     command_tx: mpsc::UnboundedSender<RelayCommand>,
-    event_rx: mpsc::UnboundedReceiver<RelayEvent>,
+    event_rx: Option<mpsc::UnboundedReceiver<RelayEvent>>,
 }
 
 impl FriendRelayHandle {
@@ -678,14 +1688,19 @@ impl FriendRelayHandle {
             .map_err(|_| StealthError::WakuRln("Channel closed".to_string()))
     }
 
-    /// Receive events from the FriendRelay
+    /// Receive events from the FriendRelay; `None` once the receiver was taken
     pub async fn recv_event(&mut self) -> Option<RelayEvent> {
-        self.event_rx.recv().await
+        self.event_rx.as_mut()?.recv().await
+    }
+
+    /// Move the events to another task, keeping this handle for commands
+    pub fn take_events(&mut self) -> Option<mpsc::UnboundedReceiver<RelayEvent>> {
+        self.event_rx.take()
     }
 
-    /// Relay an attestation through the friend mesh
-    pub fn relay_attestation(&self, attestation_data: Vec<u8>, subnet_id: u8) -> StealthResult<()> {
-        self.send_command(RelayCommand::RelayAttestation { attestation_data, subnet_id })
+    /// Relay an attestation for `slot` through the friend mesh
+    pub fn relay_attestation(&self, attestation_data: Vec<u8>, subnet_id: u8, slot: u64) -> StealthResult<()> {
+        self.send_command(RelayCommand::RelayAttestation { attestation_data, subnet_id, slot })
     }
 
     /// Add a new friend node
@@ -707,15 +1722,210 @@ impl FriendRelayHandle {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use stealth_common::{CoverTraffic, MixDelay};
     use tokio::sync::watch;
 
+    /// `B256::random` needs alloy's getrandom feature
+    fn slot_now() -> u64 {
+        EpochInfo::at(EpochInfo::MAINNET_GENESIS_TIME, unix_now()).slot
+    }
+
+    fn random_b256() -> B256 {
+        B256::from(rand::random::<[u8; 32]>())
+    }
+
+    /// Sender side of each subscribed topic
+    type Subscriptions = Arc<std::sync::Mutex<HashMap<String, mpsc::UnboundedSender<Vec<u8>>>>>;
+
+    /// Topic and payload of one light push
+    type Pushed = (String, Vec<u8>);
+
     struct MockWakuProvider {
         current_epoch: u64,
+        /// Senders of every subscribed topic, so tests can play the friend
+        subscriptions: Subscriptions,
+        /// Everything light pushed, in order
+        pushed: Arc<std::sync::Mutex<Vec<Pushed>>>,
     }
 
     impl MockWakuProvider {
         fn new() -> Self {
-            Self { current_epoch: 100 }
+            Self {
+                current_epoch: 100,
+                subscriptions: Arc::default(),
+                pushed: Arc::default(),
+            }
+        }
+
+        /// Another node on the same in-process Waku: pushes reach every subscriber
+        fn connected(&self) -> Self {
+            Self {
+                current_epoch: self.current_epoch,
+                subscriptions: self.subscriptions.clone(),
+                pushed: Arc::default(),
+            }
+        }
+    }
+
+    fn friend_config(peer_id: &str, identity: &RelayIdentity) -> FriendNodeConfig {
+        FriendNodeConfig {
+            peer_id: peer_id.to_string(),
+            multiaddr: "/ip4/127.0.0.1/tcp/60001".parse().unwrap(),
+            public_key: identity.public_key_hex(),
+            key_fingerprint: Some(identity.fingerprint()),
+        }
+    }
+
+    struct MockPublisher {
+        published: mpsc::UnboundedSender<(u8, Vec<u8>)>,
+        /// What `seen_on_gossip` answers
+        seen: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl BeaconPublisher for MockPublisher {
+        async fn publish_attestation(&self, subnet_id: u8, attestation_data: Vec<u8>) -> StealthResult<()> {
+            let _ = self.published.send((subnet_id, attestation_data));
+            Ok(())
+        }
+
+        async fn seen_on_gossip(&self, _subnet_id: u8, _attestation_data: &[u8]) -> bool {
+            self.seen
+        }
+    }
+
+    fn proven_message(message_id: &str, subnet_id: u8, nullifier: B256, proof: Vec<u8>) -> Vec<u8> {
+        serde_json::to_vec(&ProvenMessage {
+            message: RelayMessage {
+                message_id: message_id.to_string(),
+                attestation_data: vec![subnet_id; 8],
+                subnet_id,
+                timestamp: Utc::now(),
+                origin_hint: None,
+                stem: false,
+                cover: false,
+                slot: None,
+            },
+            rln_proof: RlnProof {
+                nullifier,
+                proof,
+                epoch: 100,
+                signal_hash: B256::ZERO,
+            },
+            sender_id: "someone_else".to_string(),
+        })
+        .unwrap()
+    }
+
+    /// A running relay with a mock publisher, and `friend1`'s side of its subscriptions
+    struct InboundRelay {
+        subscriptions: Subscriptions,
+        published: mpsc::UnboundedReceiver<(u8, Vec<u8>)>,
+        handle: FriendRelayHandle,
+        friend: RelayIdentity,
+        relay_key: FriendKey,
+        _shutdown: watch::Sender<bool>,
+    }
+
+    impl InboundRelay {
+        async fn start() -> Self {
+            let friend = RelayIdentity::generate();
+            let identity = RelayIdentity::generate();
+            let relay_key = FriendKey::from_config(&friend_config("relay", &identity)).unwrap();
+            let mut config = StealthConfig {
+                friend_nodes: vec![friend_config("friend1", &friend)],
+                ..StealthConfig::default()
+            };
+            // Without heartbeats, every event is about the messages sent
+            config.waku_config.heartbeat_secs = Some(0);
+
+            let waku_provider = MockWakuProvider::new();
+            let subscriptions = waku_provider.subscriptions.clone();
+            let (published_tx, published) = mpsc::unbounded_channel();
+            let (shutdown_tx, shutdown_rx) = watch::channel(false);
+            let (relay, handle) = FriendRelay::new(config, waku_provider, shutdown_rx);
+            let mut relay = relay
+                .with_identity(identity)
+                .with_publisher(Arc::new(MockPublisher { published: published_tx, seen: false }));
+            tokio::spawn(async move { relay.run().await });
+            while subscriptions.lock().unwrap().is_empty() {
+                tokio::task::yield_now().await;
+            }
+            Self { subscriptions, published, handle, friend, relay_key, _shutdown: shutdown_tx }
+        }
+
+        /// Deliver `envelope` on the topic `friend1` sends to the relay on
+        fn deliver(&self, envelope: Vec<u8>) {
+            let topic = self.friend.channel(&self.relay_key).unwrap().outbound_topic;
+            self.subscriptions.lock().unwrap()[&topic].send(envelope).unwrap();
+        }
+
+        /// Seal `payload` as `friend1` and deliver it
+        fn send(&self, payload: Vec<u8>) {
+            self.deliver(self.friend.seal(&self.relay_key, &payload).unwrap());
+        }
+    }
+
+    /// A relay on a shared in-process Waku
+    struct Node {
+        relay: Option<FriendRelay<MockWakuProvider>>,
+        handle: FriendRelayHandle,
+        pushed: Arc<std::sync::Mutex<Vec<Pushed>>>,
+        published: mpsc::UnboundedReceiver<(u8, Vec<u8>)>,
+        shutdown: watch::Sender<bool>,
+    }
+
+    impl Node {
+        /// With Dandelion++ enabled, and without heartbeats so every friend counts as live
+        fn new(waku: &MockWakuProvider, identity: RelayIdentity, friends: Vec<FriendNodeConfig>, stem_probability: f64, seen: bool) -> Self {
+            let mut config = StealthConfig {
+                friend_nodes: friends,
+                min_anonymity_set: Some(1),
+                ..StealthConfig::default()
+            };
+            config.waku_config.stem_probability = Some(stem_probability);
+            config.waku_config.heartbeat_secs = Some(0);
+            Self::with_config(waku, identity, config, seen)
+        }
+
+        fn with_config(waku: &MockWakuProvider, identity: RelayIdentity, config: StealthConfig, seen: bool) -> Self {
+            let waku_provider = waku.connected();
+            let pushed = waku_provider.pushed.clone();
+            let (published_tx, published) = mpsc::unbounded_channel();
+            let (shutdown_tx, shutdown_rx) = watch::channel(false);
+            let (relay, handle) = FriendRelay::new(config, waku_provider, shutdown_rx);
+            let relay = relay
+                .with_identity(identity)
+                .with_publisher(Arc::new(MockPublisher { published: published_tx, seen }));
+            Self { relay: Some(relay), handle, pushed, published, shutdown: shutdown_tx }
+        }
+
+        fn start(&mut self) {
+            let mut relay = self.relay.take().unwrap();
+            tokio::spawn(async move { relay.run().await });
+        }
+    }
+
+    async fn wait_for_subscriptions(subscriptions: &Subscriptions, count: usize) {
+        while subscriptions.lock().unwrap().len() < count {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    async fn next_event(handle: &mut FriendRelayHandle) -> RelayEvent {
+        next_event_within(handle, Duration::from_secs(5)).await
+    }
+
+    async fn next_event_within(handle: &mut FriendRelayHandle, wait: Duration) -> RelayEvent {
+        tokio::time::timeout(wait, handle.recv_event()).await.unwrap().unwrap()
+    }
+
+    /// Reachable friends and decision of the next `AnonymityFallback` event
+    async fn next_fallback(handle: &mut FriendRelayHandle) -> (usize, AnonymityDecision) {
+        loop {
+            if let RelayEvent::AnonymityFallback { reachable, decision, .. } = next_event(handle).await {
+                return (reachable, decision);
+            }
         }
     }
 
@@ -723,23 +1933,28 @@ mod tests {
     impl WakuProvider for MockWakuProvider {
         async fn generate_rln_proof(&self, _message: &[u8], epoch: u64) -> StealthResult<RlnProof> {
             Ok(RlnProof {
-                nullifier: B256::random(),
+                nullifier: random_b256(),
                 proof: vec![1, 2, 3, 4],
                 epoch,
-                signal_hash: B256::random(),
+                signal_hash: random_b256(),
             })
         }
 
-        async fn verify_rln_proof(&self, _proof: &RlnProof, _message: &[u8]) -> StealthResult<bool> {
-            Ok(true)
+        async fn verify_rln_proof(&self, proof: &RlnProof, _message: &[u8]) -> StealthResult<bool> {
+            Ok(!proof.proof.is_empty())
         }
 
-        async fn light_push(&self, _topic: &str, _message: &[u8], _timestamp: Option<DateTime<Utc>>) -> StealthResult<String> {
+        async fn light_push(&self, topic: &str, message: &[u8], _timestamp: Option<DateTime<Utc>>) -> StealthResult<String> {
+            self.pushed.lock().unwrap().push((topic.to_string(), message.to_vec()));
+            if let Some(subscriber) = self.subscriptions.lock().unwrap().get(topic) {
+                let _ = subscriber.send(message.to_vec());
+            }
             Ok("message_123".to_string())
         }
 
-        async fn subscribe_relay(&self, _topic: &str) -> StealthResult<mpsc::UnboundedReceiver<Vec<u8>>> {
-            let (_tx, rx) = mpsc::unbounded_channel();
+        async fn subscribe_relay(&self, topic: &str) -> StealthResult<mpsc::UnboundedReceiver<Vec<u8>>> {
+            let (tx, rx) = mpsc::unbounded_channel();
+            self.subscriptions.lock().unwrap().insert(topic.to_string(), tx);
             Ok(rx)
         }
 
@@ -754,16 +1969,16 @@ mod tests {
         
         // Should allow messages up to the limit
         for i in 0..10 {
-            let nullifier = B256::random();
+            let nullifier = random_b256();
             assert!(limiter.check_and_update(100, nullifier).is_ok(), "Message {} should be allowed", i);
         }
         
         // Should reject after limit
-        let nullifier = B256::random();
+        let nullifier = random_b256();
         assert!(limiter.check_and_update(100, nullifier).is_err());
         
         // Should reset for new epoch
-        let nullifier = B256::random();
+        let nullifier = random_b256();
         assert!(limiter.check_and_update(101, nullifier).is_ok());
     }
 
@@ -790,11 +2005,13 @@ mod tests {
                 peer_id: "friend1".to_string(),
                 multiaddr: "/ip4/127.0.0.1/tcp/60001".parse().unwrap(),
                 public_key: "pub1".to_string(),
+                key_fingerprint: None,
             },
             FriendNodeConfig {
                 peer_id: "friend2".to_string(),
                 multiaddr: "/ip4/127.0.0.1/tcp/60002".parse().unwrap(),
                 public_key: "pub2".to_string(),
+                key_fingerprint: None,
             },
         ];
 
@@ -806,5 +2023,478 @@ mod tests {
         assert_eq!(relay.friends.len(), 2);
         assert!(relay.friends.contains_key("friend1"));
         assert!(relay.friends.contains_key("friend2"));
+        // Neither key is valid X25519, so nothing can be sealed to them
+        assert!(relay.channels.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_outbound_messages_are_sealed_per_friend() {
+        let (alice, bob) = (RelayIdentity::generate(), RelayIdentity::generate());
+        let mut broken = friend_config("carol", &alice);
+        broken.public_key = "0xabcd".to_string();
+        let mut config = StealthConfig {
+            friend_nodes: vec![friend_config("alice", &alice), friend_config("bob", &bob), broken.clone()],
+            min_anonymity_set: Some(2),
+            ..StealthConfig::default()
+        };
+        // Without heartbeats every friend counts as live
+        config.waku_config.heartbeat_secs = Some(0);
+        let waku_provider = MockWakuProvider::new();
+        let pushed = waku_provider.pushed.clone();
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let (relay, mut handle) = FriendRelay::new(config, waku_provider, shutdown_rx);
+        let identity = RelayIdentity::generate();
+        let relay_key = FriendKey::from_config(&friend_config("relay", &identity)).unwrap();
+        let mut relay = relay.with_identity(identity);
+
+        relay.relay_attestation(vec![9; 8], 2, slot_now()).await.unwrap();
+        let pushed = pushed.lock().unwrap().clone();
+        assert_eq!(pushed.len(), 2);
+        for friend in [&alice, &bob] {
+            // Each friend's copy is on the topic only it listens on, and only it can open it
+            let topic = friend.channel(&relay_key).unwrap().inbound_topic;
+            let (_, envelope) = pushed.iter().find(|(pushed_topic, _)| *pushed_topic == topic).unwrap();
+            let (payload, sender) = friend.open(envelope).unwrap();
+            assert_eq!(sender, relay_key.public_key);
+            let proven: ProvenMessage = serde_json::from_slice(&payload).unwrap();
+            assert_eq!((proven.message.subnet_id, proven.message.attestation_data), (2, vec![9; 8]));
+        }
+        assert_ne!(pushed[0].1, pushed[1].1);
+
+        let mut sent = HashMap::new();
+        for _ in 0..3 {
+            if let RelayEvent::FriendMessageSent { friend_id, success } = next_event(&mut handle).await {
+                sent.insert(friend_id, success);
+            }
+        }
+        assert_eq!(sent, HashMap::from([("alice".to_string(), true), ("bob".to_string(), true), ("carol".to_string(), false)]));
+
+        // A friend with a malformed key is refused
+        broken.peer_id = "dave".to_string();
+        assert!(matches!(
+            relay.handle_command(RelayCommand::AddFriend(broken)).await,
+            Err(StealthError::Config(_))
+        ));
+        assert!(!relay.friends.contains_key("dave"));
+    }
+
+    #[tokio::test]
+    async fn test_inbound_messages_are_verified_and_published() {
+        let mut relay = InboundRelay::start().await;
+        relay.send(proven_message("m1", 5, B256::repeat_byte(1), vec![1, 2, 3]));
+
+        match next_event(&mut relay.handle).await {
+            RelayEvent::MessageReceived { message_id, from_friend, subnet_id } => {
+                // Attributed by the sealing key, not the claimed sender_id
+                assert_eq!((message_id.as_str(), from_friend.as_str(), subnet_id), ("m1", "friend1", 5));
+            }
+            other => panic!("Unexpected event {:?}", other),
+        }
+        assert_eq!(relay.published.recv().await.unwrap(), (5, vec![5; 8]));
+    }
+
+    #[tokio::test]
+    async fn test_inbound_duplicates_and_replayed_nullifiers_are_dropped() {
+        let mut relay = InboundRelay::start().await;
+        let nullifier = B256::repeat_byte(2);
+
+        relay.send(proven_message("m1", 7, nullifier, vec![1]));
+        assert!(matches!(next_event(&mut relay.handle).await, RelayEvent::MessageReceived { .. }));
+        relay.send(proven_message("m1", 7, nullifier, vec![1]));
+        assert!(matches!(
+            next_event(&mut relay.handle).await,
+            RelayEvent::MessageDropped { reason: DropReason::Duplicate, .. }
+        ));
+        // A new message id does not make a spent nullifier usable again
+        relay.send(proven_message("m2", 7, nullifier, vec![1]));
+        assert!(matches!(next_event(&mut relay.handle).await, RelayEvent::RateLimitExceeded { .. }));
+        assert!(matches!(
+            next_event(&mut relay.handle).await,
+            RelayEvent::MessageDropped { reason: DropReason::RateLimited, .. }
+        ));
+
+        assert_eq!(relay.published.recv().await.unwrap().0, 7);
+        assert!(relay.published.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_inbound_invalid_messages_are_dropped() {
+        let mut relay = InboundRelay::start().await;
+
+        // Empty proof fails verification
+        relay.send(proven_message("m1", 3, B256::repeat_byte(3), Vec::new()));
+        assert!(matches!(
+            next_event(&mut relay.handle).await,
+            RelayEvent::MessageDropped { reason: DropReason::InvalidProof, .. }
+        ));
+        // Proofs for an RLN epoch two away from ours (100) never reach the limiter
+        let mut future: ProvenMessage = serde_json::from_slice(&proven_message("m4", 3, B256::repeat_byte(6), vec![1])).unwrap();
+        future.rln_proof.epoch = 102;
+        relay.send(serde_json::to_vec(&future).unwrap());
+        assert!(matches!(
+            next_event(&mut relay.handle).await,
+            RelayEvent::MessageDropped { reason: DropReason::InvalidProof, .. }
+        ));
+        // A message for a subnet that does not exist
+        relay.send(proven_message("m2", ATTESTATION_SUBNET_COUNT, B256::repeat_byte(4), vec![1]));
+        assert!(matches!(
+            next_event(&mut relay.handle).await,
+            RelayEvent::MessageDropped { from_friend: Some(_), reason: DropReason::Malformed }
+        ));
+        // Plain garbage, and a valid envelope sealed to someone else
+        relay.deliver(b"not an envelope".to_vec());
+        let someone_else = FriendKey::from_config(&friend_config("other", &RelayIdentity::generate())).unwrap();
+        let payload = proven_message("m3", 4, B256::repeat_byte(5), vec![1]);
+        relay.deliver(relay.friend.seal(&someone_else, &payload).unwrap());
+        for _ in 0..2 {
+            assert!(matches!(
+                next_event(&mut relay.handle).await,
+                RelayEvent::MessageDropped { from_friend: None, reason: DropReason::Undecryptable }
+            ));
+        }
+        // Sealed to us by a key that is not a friend's
+        let stranger = RelayIdentity::generate();
+        relay.deliver(stranger.seal(&relay.relay_key, &payload).unwrap());
+        assert!(matches!(
+            next_event(&mut relay.handle).await,
+            RelayEvent::MessageDropped { from_friend: None, reason: DropReason::UnknownSender }
+        ));
+        assert!(relay.published.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dandelion_origin_stems_to_one_friend() {
+        let (a, b, c) = (RelayIdentity::generate(), RelayIdentity::generate(), RelayIdentity::generate());
+        let (to_a, to_b, to_c) = (friend_config("a", &a), friend_config("b", &b), friend_config("c", &c));
+        let waku = MockWakuProvider::new();
+        let mut origin = Node::new(&waku, a, vec![to_b, to_c], 1.0, false);
+        let mut hop_b = Node::new(&waku, b, vec![to_a.clone()], 0.0, false);
+        let mut hop_c = Node::new(&waku, c, vec![to_a], 0.0, false);
+        hop_b.start();
+        hop_c.start();
+        wait_for_subscriptions(&waku.subscriptions, 2).await;
+
+        let relay = origin.relay.as_mut().unwrap();
+        relay.relay_attestation(vec![4; 8], 4, slot_now()).await.unwrap();
+        relay.relay_attestation(vec![5; 8], 5, slot_now()).await.unwrap();
+        // Both took the same single stem relay, and wait under embargo
+        let pushed = origin.pushed.lock().unwrap().clone();
+        assert_eq!(pushed.len(), 2);
+        assert_eq!(pushed[0].0, pushed[1].0);
+        assert_eq!(relay.embargoes.len(), 2);
+
+        // The hop that got them fluffs them, the other sees nothing
+        let (fluffed, idle) = tokio::select! {
+            Some(first) = hop_b.published.recv() => (vec![first, hop_b.published.recv().await.unwrap()], &mut hop_c),
+            Some(first) = hop_c.published.recv() => (vec![first, hop_c.published.recv().await.unwrap()], &mut hop_b),
+        };
+        assert_eq!(fluffed, vec![(4, vec![4; 8]), (5, vec![5; 8])]);
+        assert!(idle.published.try_recv().is_err());
+        assert!(origin.published.try_recv().is_err());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_dandelion_hops_pass_the_stem_on() {
+        let (a, b, c) = (RelayIdentity::generate(), RelayIdentity::generate(), RelayIdentity::generate());
+        let (to_a, to_b, to_c) = (friend_config("a", &a), friend_config("b", &b), friend_config("c", &c));
+        let waku = MockWakuProvider::new();
+        let mut origin = Node::new(&waku, a, vec![to_b.clone()], 1.0, false);
+        let mut hop_b = Node::new(&waku, b, vec![to_a, to_c], 1.0, false);
+        let mut hop_c = Node::new(&waku, c, vec![to_b], 0.0, false);
+        hop_b.start();
+        hop_c.start();
+        wait_for_subscriptions(&waku.subscriptions, 3).await;
+
+        origin.relay.as_mut().unwrap().relay_attestation(vec![6; 8], 6, slot_now()).await.unwrap();
+        // B never sends it back to A, so C is the only way on
+        assert_eq!(hop_c.published.recv().await.unwrap(), (6, vec![6; 8]));
+        assert!(hop_b.published.try_recv().is_err());
+        loop {
+            match next_event(&mut hop_b.handle).await {
+                RelayEvent::FriendMessageSent { friend_id, success } => {
+                    assert_eq!((friend_id.as_str(), success), ("c", true));
+                    break;
+                }
+                RelayEvent::MessageReceived { .. } | RelayEvent::FriendConnected(_) | RelayEvent::FriendHeartbeat { .. } => {}
+                other => panic!("Unexpected event {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dandelion_embargo_fluffs_what_gossip_never_saw() {
+        for seen in [false, true] {
+            let (a, b, c) = (RelayIdentity::generate(), RelayIdentity::generate(), RelayIdentity::generate());
+            let waku = MockWakuProvider::new();
+            // B is not running, so a stem through it ends there; C publishes whatever reaches it
+            let (to_a, friends) = (friend_config("a", &a), vec![friend_config("b", &b), friend_config("c", &c)]);
+            let mut origin = Node::new(&waku, a, friends, 1.0, seen);
+            let mut hop_c = Node::new(&waku, c, vec![to_a], 0.0, false);
+            origin.start();
+            hop_c.start();
+            wait_for_subscriptions(&waku.subscriptions, 2).await;
+
+            origin.handle.relay_attestation(vec![7; 8], 7, slot_now()).unwrap();
+            if seen {
+                let until = tokio::time::Instant::now() + Duration::from_secs(30);
+                while let Ok(Some(event)) = tokio::time::timeout_at(until, origin.handle.recv_event()).await {
+                    assert!(!matches!(event, RelayEvent::EmbargoExpired { .. }), "Fluffed an attestation gossip already carried");
+                }
+            } else {
+                // Whichever way the stem went, C publishes it and the origin never does
+                assert_eq!(hop_c.published.recv().await.unwrap(), (7, vec![7; 8]));
+                loop {
+                    if let RelayEvent::EmbargoExpired { subnet_id, .. } = next_event_within(&mut origin.handle, Duration::from_secs(30)).await {
+                        assert_eq!(subnet_id, 7);
+                        break;
+                    }
+                }
+            }
+            assert!(origin.published.try_recv().is_err(), "The origin published its own attestation");
+            origin.shutdown.send(true).unwrap();
+            hop_c.shutdown.send(true).unwrap();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mixer_holds_and_batches_both_directions() {
+        let (friend, identity) = (RelayIdentity::generate(), RelayIdentity::generate());
+        let relay_key = FriendKey::from_config(&friend_config("relay", &identity)).unwrap();
+        let mut config = StealthConfig {
+            friend_nodes: vec![friend_config("friend1", &friend)],
+            min_anonymity_set: Some(1),
+            ..StealthConfig::default()
+        };
+        // Only the attestation is pushed
+        config.waku_config.heartbeat_secs = Some(0);
+        // A second into slot 0, so the deadline leaves room for the whole delay
+        config.network.genesis_time = Some(unix_now().as_secs() - 1);
+        let waku_provider = MockWakuProvider::new();
+        let (subscriptions, pushed) = (waku_provider.subscriptions.clone(), waku_provider.pushed.clone());
+        let (published_tx, mut published) = mpsc::unbounded_channel();
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let (relay, mut handle) = FriendRelay::new(config, waku_provider, shutdown_rx);
+        let mut relay = relay
+            .with_identity(identity)
+            .with_publisher(Arc::new(MockPublisher { published: published_tx, seen: false }));
+        relay.mixer = Some(Mixer::new(MixConfig {
+            delay: MixDelay::Uniform { min_ms: 2000, max_ms: 2000 },
+            window: Duration::from_millis(500),
+            genesis_time: unix_now().as_secs() - 1,
+        }));
+        tokio::spawn(async move { relay.run().await });
+        wait_for_subscriptions(&subscriptions, 1).await;
+
+        let topic = friend.channel(&relay_key).unwrap().outbound_topic;
+        for (message_id, nullifier) in [("m1", 1), ("m2", 2)] {
+            let payload = proven_message(message_id, 9, B256::repeat_byte(nullifier), vec![1]);
+            subscriptions.lock().unwrap()[&topic].send(friend.seal(&relay_key, &payload).unwrap()).unwrap();
+        }
+        handle.relay_attestation(vec![3; 8], 3, 0).unwrap();
+        for _ in 0..2 {
+            assert!(matches!(next_event(&mut handle).await, RelayEvent::MessageReceived { .. }));
+        }
+
+        // Nothing leaves before the delay
+        assert!(tokio::time::timeout(Duration::from_millis(1900), published.recv()).await.is_err());
+        assert!(pushed.lock().unwrap().is_empty());
+
+        // Then both friends' attestations and ours go out in the same window
+        let first = tokio::time::timeout(Duration::from_millis(600), published.recv()).await.unwrap().unwrap();
+        let second = tokio::time::timeout(Duration::from_millis(100), published.recv()).await.unwrap().unwrap();
+        assert_eq!((first.0, second.0), (9, 9));
+        assert_eq!(pushed.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cover_traffic_looks_real_and_is_dropped_by_friends() {
+        let (a, b) = (RelayIdentity::generate(), RelayIdentity::generate());
+        let (to_a, to_b) = (friend_config("a", &a), friend_config("b", &b));
+        let waku = MockWakuProvider::new();
+        let mut config = StealthConfig {
+            friend_nodes: vec![to_b.clone()],
+            min_anonymity_set: Some(1),
+            ..StealthConfig::default()
+        };
+        // Every 8 seconds, with no pings to tell apart
+        config.waku_config.cover_traffic = Some(CoverTraffic::Constant { per_epoch: 48 });
+        config.waku_config.heartbeat_secs = Some(0);
+        let mut sender = Node::with_config(&waku, a, config, false);
+        let mut friend_config = StealthConfig {
+            friend_nodes: vec![to_a.clone()],
+            ..StealthConfig::default()
+        };
+        friend_config.waku_config.heartbeat_secs = Some(0);
+        let mut friend = Node::with_config(&waku, b, friend_config, false);
+        let mut friend_events = friend.handle.take_events().unwrap();
+        friend.start();
+        wait_for_subscriptions(&waku.subscriptions, 1).await;
+
+        sender.relay.as_mut().unwrap().relay_attestation(vec![5; 240], 5, slot_now()).await.unwrap();
+        sender.start();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+
+        // Three cover messages joined the real one, alike on the wire
+        let pushed = sender.pushed.lock().unwrap().clone();
+        assert_eq!(pushed.len(), 4);
+        assert!(pushed.iter().all(|(topic, envelope)| *topic == pushed[0].0 && envelope.len() == pushed[0].1.len()));
+
+        // The friend published and reported only the real one
+        assert_eq!(friend.published.recv().await.unwrap(), (5, vec![5; 240]));
+        assert!(friend.published.try_recv().is_err());
+        let mut received = 0;
+        while let Ok(event) = friend_events.try_recv() {
+            match event {
+                RelayEvent::MessageReceived { .. } => received += 1,
+                RelayEvent::MessageDropped { reason, .. } => panic!("Dropped as {}", reason.as_str()),
+                _ => {}
+            }
+        }
+        assert_eq!(received, 1);
+        sender.shutdown.send(true).unwrap();
+        friend.shutdown.send(true).unwrap();
+
+        // Cover stops at half the RLN budget, leaving the rest for attestations
+        let mut config = StealthConfig {
+            friend_nodes: vec![to_b],
+            min_anonymity_set: Some(1),
+            ..StealthConfig::default()
+        };
+        config.waku_config.rate_limit_per_epoch = 4;
+        config.waku_config.heartbeat_secs = Some(0);
+        let mut limited = Node::with_config(&waku, RelayIdentity::generate(), config, false);
+        let relay = limited.relay.as_mut().unwrap();
+        for _ in 0..3 {
+            relay.send_cover().await;
+        }
+        assert_eq!(limited.pushed.lock().unwrap().len(), 2);
+        assert_eq!(relay.get_stats().await.cover_sent, 2);
+        relay.relay_attestation(vec![6; 240], 6, slot_now()).await.unwrap();
+        relay.relay_attestation(vec![7; 240], 7, slot_now()).await.unwrap();
+        assert!(relay.relay_attestation(vec![8; 240], 8, slot_now()).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_liveness_follows_heartbeats_and_gates_relays() {
+        let (a, b, c) = (RelayIdentity::generate(), RelayIdentity::generate(), RelayIdentity::generate());
+        let to_a = friend_config("a", &a);
+        let waku = MockWakuProvider::new();
+        let config = |friend_nodes| StealthConfig {
+            friend_nodes,
+            min_anonymity_set: Some(1),
+            ..StealthConfig::default()
+        };
+        let mut origin = Node::with_config(&waku, a, config(vec![friend_config("b", &b), friend_config("c", &c)]), false);
+        let mut live = Node::with_config(&waku, b, config(vec![to_a]), false);
+        live.start();
+        origin.start();
+
+        // B answers pings and turns up live; C never runs, so it quietly goes down
+        let mut heartbeats = 0;
+        let mut connected = Vec::new();
+        while heartbeats < 4 {
+            match next_event_within(&mut origin.handle, Duration::from_secs(60)).await {
+                RelayEvent::FriendConnected(peer_id) => connected.push(peer_id),
+                RelayEvent::FriendHeartbeat { friend_id, loss_rate, .. } => {
+                    assert_eq!((friend_id.as_str(), loss_rate), ("b", 0.0));
+                    heartbeats += 1;
+                }
+                other => panic!("Unexpected event {:?}", other),
+            }
+        }
+        assert_eq!(connected, vec!["b".to_string()]);
+
+        origin.handle.relay_attestation(vec![3; 8], 3, slot_now()).unwrap();
+        assert_eq!(live.published.recv().await.unwrap(), (3, vec![3; 8]));
+        loop {
+            match next_event_within(&mut origin.handle, Duration::from_secs(60)).await {
+                RelayEvent::FriendMessageSent { friend_id, .. } => assert_eq!(friend_id, "b"),
+                RelayEvent::MessageRelayed { friends_count, .. } => {
+                    assert_eq!(friends_count, 1);
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        // Once B stops, it misses three pings and is reported lost
+        live.shutdown.send(true).unwrap();
+        loop {
+            if let RelayEvent::FriendDisconnected(peer_id) = next_event_within(&mut origin.handle, Duration::from_secs(60)).await {
+                assert_eq!(peer_id, "b");
+                break;
+            }
+        }
+        origin.handle.relay_attestation(vec![4; 8], 4, slot_now()).unwrap();
+        loop {
+            match next_event_within(&mut origin.handle, Duration::from_secs(60)).await {
+                RelayEvent::AnonymityFallback { reachable, decision, .. } => {
+                    assert_eq!((reachable, decision), (0, AnonymityDecision::Dropped));
+                    break;
+                }
+                RelayEvent::FriendMessageSent { .. } => panic!("Relayed to a friend that is down"),
+                _ => {}
+            }
+        }
+        origin.shutdown.send(true).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_anonymity_fallback_policies() {
+        let (b, c) = (RelayIdentity::generate(), RelayIdentity::generate());
+        let config = StealthConfig {
+            friend_nodes: vec![friend_config("b", &b)],
+            min_anonymity_set: Some(2),
+            ..StealthConfig::default()
+        };
+        let waku_provider = MockWakuProvider::new();
+        let pushed = waku_provider.pushed.clone();
+        let (published_tx, mut published) = mpsc::unbounded_channel();
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let (relay, mut handle) = FriendRelay::new(config, waku_provider, shutdown_rx);
+        let mut relay = relay.with_publisher(Arc::new(MockPublisher { published: published_tx, seen: false }));
+        // A second into slot 0, so a held attestation has 7 seconds
+        relay.genesis_time = unix_now().as_secs() - 1;
+        let seen = |relay: &mut FriendRelay<MockWakuProvider>, peer_id| {
+            relay.liveness.as_mut().unwrap().seen(peer_id, tokio::time::Instant::now());
+        };
+        // Friends that never answered do not count
+        relay.relay_attestation(vec![1; 8], 1, 0).await.unwrap();
+        assert_eq!(next_fallback(&mut handle).await, (0, AnonymityDecision::Dropped));
+        seen(&mut relay, "b");
+        // Unset means drop, and nothing leaves
+        relay.relay_attestation(vec![1; 8], 1, 0).await.unwrap();
+        assert_eq!(next_fallback(&mut handle).await, (1, AnonymityDecision::Dropped));
+        assert!(pushed.lock().unwrap().is_empty());
+        assert!(published.try_recv().is_err());
+
+        relay.config.anonymity_fallback = Some(AnonymityFallback::PublishDirect);
+        relay.relay_attestation(vec![2; 8], 2, 0).await.unwrap();
+        assert_eq!(next_fallback(&mut handle).await, (1, AnonymityDecision::PublishedDirect));
+        assert_eq!(published.try_recv().unwrap(), (2, vec![2; 8]));
+
+        // Held until a second friend turns up
+        relay.config.anonymity_fallback = Some(AnonymityFallback::Delay);
+        relay.relay_attestation(vec![3; 8], 3, 0).await.unwrap();
+        assert_eq!(next_fallback(&mut handle).await, (1, AnonymityDecision::Delayed));
+        relay.retry_held().await;
+        assert_eq!(relay.held.len(), 1);
+        relay.handle_command(RelayCommand::AddFriend(friend_config("c", &c))).await.unwrap();
+        relay.retry_held().await;
+        assert_eq!(relay.held.len(), 1);
+        seen(&mut relay, "c");
+        relay.retry_held().await;
+        assert_eq!(next_fallback(&mut handle).await, (2, AnonymityDecision::RelayedAfterDelay));
+        assert_eq!(pushed.lock().unwrap().len(), 2);
+
+        // Or dropped at the slot's propagation deadline
+        relay.handle_command(RelayCommand::RemoveFriend("c".to_string())).await.unwrap();
+        relay.relay_attestation(vec![4; 8], 4, 0).await.unwrap();
+        assert_eq!(next_fallback(&mut handle).await, (1, AnonymityDecision::Delayed));
+        tokio::time::advance(Duration::from_secs(7)).await;
+        relay.retry_held().await;
+        assert_eq!(next_fallback(&mut handle).await, (1, AnonymityDecision::Dropped));
+        assert!(relay.held.is_empty());
+        assert!(published.try_recv().is_err());
+    }
+}
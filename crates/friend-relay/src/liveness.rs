@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use stealth_common::WakuConfig;
+use tokio::time::Instant;
+
+/// Ping interval when `heartbeat_secs` is unset
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Unanswered pings in a row after which a friend is down
+const MISSED_PINGS_DOWN: u32 = 3;
+/// Recent pings the loss rate is measured over
+const LOSS_WINDOW: usize = 20;
+/// Weight of a new sample in the smoothed round-trip time
+const RTT_SMOOTHING: f64 = 0.125;
+/// Heartbeats, pings and pongs together, a friend may send us per window; a
+/// friend pinging every second still fits
+const MAX_HEARTBEATS_PER_WINDOW: u32 = 120;
+const HEARTBEAT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Liveness probe, sealed to a friend on its topic like a relay message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Heartbeat {
+    Ping { nonce: u64 },
+    Pong { nonce: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Not heard from yet, and not enough pings missed to call it down
+    Unknown,
+    Live,
+    Down,
+}
+
+/// A change worth reporting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Connected,
+    Disconnected,
+}
+
+/// What we know of one friend's liveness
+#[derive(Debug)]
+pub struct FriendLiveness {
+    pub status: Status,
+    pub last_seen: Option<Instant>,
+    /// Smoothed round-trip time of our pings
+    pub rtt: Option<Duration>,
+    missed: u32,
+    /// Pings awaiting a pong, by nonce
+    outstanding: HashMap<u64, Instant>,
+    /// Whether each recent ping was answered in time
+    answered: VecDeque<bool>,
+}
+
+impl FriendLiveness {
+    fn new() -> Self {
+        Self {
+            status: Status::Unknown,
+            last_seen: None,
+            rtt: None,
+            missed: 0,
+            outstanding: HashMap::new(),
+            answered: VecDeque::new(),
+        }
+    }
+
+    /// Share of recent pings that went unanswered
+    pub fn loss_rate(&self) -> f64 {
+        if self.answered.is_empty() {
+            return 0.0;
+        }
+        self.answered.iter().filter(|answered| !**answered).count() as f64 / self.answered.len() as f64
+    }
+
+    fn record(&mut self, answered: bool) {
+        if self.answered.len() == LOSS_WINDOW {
+            self.answered.pop_front();
+        }
+        self.answered.push_back(answered);
+    }
+}
+
+/// Liveness of every friend, from pings and whatever else they send us.
+///
+/// Friends start out unknown and are live from the first message they send
+/// us; only live friends are relayed through or count towards the anonymity
+/// set. Three pings missed in a row take a friend down.
+#[derive(Debug)]
+pub struct Liveness {
+    interval: Duration,
+    friends: HashMap<String, FriendLiveness>,
+    next_nonce: u64,
+}
+
+impl Liveness {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            friends: HashMap::new(),
+            next_nonce: rand::random(),
+        }
+    }
+
+    /// `None` when `heartbeat_secs` is 0
+    pub fn from_config(config: &WakuConfig) -> Option<Self> {
+        let interval = config
+            .heartbeat_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL);
+        (!interval.is_zero()).then(|| Self::new(interval))
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn add(&mut self, peer_id: &str) {
+        self.friends.entry(peer_id.to_string()).or_insert_with(FriendLiveness::new);
+    }
+
+    pub fn remove(&mut self, peer_id: &str) -> Option<FriendLiveness> {
+        self.friends.remove(peer_id)
+    }
+
+    pub fn get(&self, peer_id: &str) -> Option<&FriendLiveness> {
+        self.friends.get(peer_id)
+    }
+
+    pub fn is_down(&self, peer_id: &str) -> bool {
+        self.friends.get(peer_id).is_some_and(|friend| friend.status == Status::Down)
+    }
+
+    pub fn is_live(&self, peer_id: &str) -> bool {
+        self.friends.get(peer_id).is_some_and(|friend| friend.status == Status::Live)
+    }
+
+    pub fn live_count(&self) -> usize {
+        self.friends.values().filter(|friend| friend.status == Status::Live).count()
+    }
+
+    /// An authenticated message from `peer_id` arrived at `now`
+    pub fn seen(&mut self, peer_id: &str, now: Instant) -> Option<Transition> {
+        let friend = self.friends.get_mut(peer_id)?;
+        friend.last_seen = Some(now);
+        friend.missed = 0;
+        let was = std::mem::replace(&mut friend.status, Status::Live);
+        (was != Status::Live).then_some(Transition::Connected)
+    }
+
+    /// The round trip of the ping `nonce` answers, if it is still outstanding
+    pub fn pong(&mut self, peer_id: &str, nonce: u64, now: Instant) -> Option<Duration> {
+        let friend = self.friends.get_mut(peer_id)?;
+        let sent = friend.outstanding.remove(&nonce)?;
+        let sample = now.duration_since(sent);
+        friend.rtt = Some(match friend.rtt {
+            Some(rtt) => rtt.mul_f64(1.0 - RTT_SMOOTHING) + sample.mul_f64(RTT_SMOOTHING),
+            None => sample,
+        });
+        friend.record(true);
+        Some(sample)
+    }
+
+    /// Count pings to `peer_id` older than an interval as lost, then record a new
+    /// one sent at `now`. Returns its nonce, and `Disconnected` if the friend was
+    /// live and has now missed too many.
+    pub fn ping(&mut self, peer_id: &str, now: Instant) -> Option<(u64, Option<Transition>)> {
+        let interval = self.interval;
+        let friend = self.friends.get_mut(peer_id)?;
+        let lost: Vec<u64> = friend
+            .outstanding
+            .iter()
+            .filter(|(_, sent)| now.duration_since(**sent) >= interval)
+            .map(|(nonce, _)| *nonce)
+            .collect();
+        for nonce in lost {
+            friend.outstanding.remove(&nonce);
+            friend.missed += 1;
+            friend.record(false);
+        }
+
+        let mut transition = None;
+        if friend.missed >= MISSED_PINGS_DOWN && friend.status != Status::Down {
+            if friend.status == Status::Live {
+                transition = Some(Transition::Disconnected);
+            }
+            friend.status = Status::Down;
+        }
+
+        let nonce = self.next_nonce;
+        self.next_nonce = self.next_nonce.wrapping_add(1);
+        friend.outstanding.insert(nonce, now);
+        Some((nonce, transition))
+    }
+}
+
+/// Caps the heartbeats taken from each friend. They skip RLN and each ping
+/// costs us a push, so nothing else bounds them.
+#[derive(Debug, Default)]
+pub struct HeartbeatLimiter {
+    /// Start of each friend's window and its heartbeats so far
+    windows: HashMap<String, (Instant, u32)>,
+}
+
+impl HeartbeatLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a heartbeat from `peer_id` at `now`, and whether it is within budget
+    pub fn allow(&mut self, peer_id: &str, now: Instant) -> bool {
+        let (start, count) = self.windows.entry(peer_id.to_string()).or_insert((now, 0));
+        if now.duration_since(*start) >= HEARTBEAT_WINDOW {
+            *start = now;
+            *count = 0;
+        }
+        *count = count.saturating_add(1);
+        *count <= MAX_HEARTBEATS_PER_WINDOW
+    }
+
+    pub fn remove(&mut self, peer_id: &str) {
+        self.windows.remove(peer_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pongs_measure_round_trips_and_loss() {
+        let mut liveness = Liveness::new(Duration::from_secs(10));
+        liveness.add("a");
+        let start = Instant::now();
+
+        let (nonce, transition) = liveness.ping("a", start).unwrap();
+        assert_eq!(transition, None);
+        assert_eq!(liveness.pong("a", nonce, start + Duration::from_millis(80)), Some(Duration::from_millis(80)));
+        assert_eq!(liveness.pong("a", nonce, start + Duration::from_millis(90)), None);
+        assert_eq!(liveness.get("a").unwrap().rtt, Some(Duration::from_millis(80)));
+
+        let (nonce, _) = liveness.ping("a", start + Duration::from_secs(10)).unwrap();
+        liveness.pong("a", nonce, start + Duration::from_secs(10) + Duration::from_millis(160));
+        assert_eq!(liveness.get("a").unwrap().rtt, Some(Duration::from_millis(90)));
+
+        // The third ping goes unanswered until the fourth is sent
+        liveness.ping("a", start + Duration::from_secs(20));
+        liveness.ping("a", start + Duration::from_secs(30));
+        let friend = liveness.get("a").unwrap();
+        assert!((friend.loss_rate() - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(liveness.ping("b", start), None);
+    }
+
+    #[test]
+    fn test_transitions_fire_on_state_changes_only() {
+        let mut liveness = Liveness::new(Duration::from_secs(10));
+        liveness.add("a");
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(!liveness.is_live("a"));
+        assert_eq!(liveness.seen("a", at(1)), Some(Transition::Connected));
+        assert!(liveness.is_live("a"));
+        assert_eq!(liveness.seen("a", at(2)), None);
+        assert_eq!(liveness.live_count(), 1);
+
+        // Three pings lost in a row take it down, once
+        let transitions: Vec<_> = (0..5).map(|n| liveness.ping("a", at(10 * n)).unwrap().1).collect();
+        assert_eq!(transitions, vec![None, None, None, Some(Transition::Disconnected), None]);
+        assert!(liveness.is_down("a"));
+        assert_eq!(liveness.live_count(), 0);
+
+        // Any message brings it back
+        assert_eq!(liveness.seen("a", at(50)), Some(Transition::Connected));
+        assert!(!liveness.is_down("a"));
+    }
+
+    #[test]
+    fn test_silent_friends_go_down_without_ever_connecting() {
+        let mut liveness = Liveness::new(Duration::from_secs(10));
+        liveness.add("a");
+        let start = Instant::now();
+        for n in 0..4 {
+            assert_eq!(liveness.ping("a", start + Duration::from_secs(10 * n)).unwrap().1, None);
+        }
+        assert!(liveness.is_down("a"));
+        assert!(liveness.remove("a").is_some());
+        assert!(!liveness.is_down("a"));
+
+        let config = |heartbeat_secs| WakuConfig {
+            heartbeat_secs,
+            ..stealth_common::StealthConfig::default().waku_config
+        };
+        assert!(Liveness::from_config(&config(Some(0))).is_none());
+        assert_eq!(Liveness::from_config(&config(None)).unwrap().interval(), DEFAULT_HEARTBEAT_INTERVAL);
+    }
+
+    #[test]
+    fn test_heartbeats_are_limited_per_friend() {
+        let mut limiter = HeartbeatLimiter::new();
+        let start = Instant::now();
+        assert!((0..MAX_HEARTBEATS_PER_WINDOW).all(|_| limiter.allow("a", start)));
+        assert!(!limiter.allow("a", start + Duration::from_secs(59)));
+        assert!(limiter.allow("b", start + Duration::from_secs(59)));
+
+        // A new window starts with a full budget
+        assert!(limiter.allow("a", start + HEARTBEAT_WINDOW));
+        limiter.remove("a");
+        assert!(!limiter.windows.contains_key("a"));
+    }
+}
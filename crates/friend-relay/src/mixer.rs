@@ -0,0 +1,190 @@
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+use std::time::Duration;
+use stealth_common::{EpochInfo, MixDelay, WakuConfig};
+use tokio::time::Instant;
+
+/// Attestations still count if they propagate this long after the attestation deadline
+pub const PROPAGATION_ALLOWANCE: Duration = Duration::from_secs(4);
+/// Release window when `mix_window_ms` is unset
+const DEFAULT_MIX_WINDOW: Duration = Duration::from_millis(500);
+
+/// Last moment an attestation for `slot` can still propagate in time, as time
+/// since the Unix epoch
+pub fn propagation_deadline(genesis_time: u64, slot: u64) -> Duration {
+    EpochInfo::of_slot(slot).attestation_deadline(genesis_time) + PROPAGATION_ALLOWANCE
+}
+
+/// Mixing parameters from `waku_config`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MixConfig {
+    pub delay: MixDelay,
+    pub window: Duration,
+    pub genesis_time: u64,
+}
+
+impl MixConfig {
+    /// `None` unless `mix_delay` is configured; `validate_config` rejects a
+    /// uniform range with `min_ms` above `max_ms`
+    pub fn from_config(config: &WakuConfig, genesis_time: u64) -> Option<Self> {
+        let delay = config.mix_delay?;
+        Some(Self {
+            delay,
+            window: config
+                .mix_window_ms
+                .filter(|ms| *ms > 0)
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_MIX_WINDOW),
+            genesis_time,
+        })
+    }
+
+    fn sample(&self) -> Duration {
+        let mut rng = thread_rng();
+        match self.delay {
+            MixDelay::Uniform { min_ms, max_ms } => Duration::from_millis(rng.gen_range(min_ms..=max_ms.max(min_ms))),
+            MixDelay::Exponential { mean_ms } => {
+                let uniform: f64 = rng.gen();
+                Duration::from_secs_f64(-(1.0 - uniform).ln() * mean_ms as f64 / 1000.0)
+            }
+        }
+    }
+
+    /// Random delay for an attestation for `slot` entering the mixer at `now` (time
+    /// since the Unix epoch), leaving a window to spare before its propagation deadline
+    pub fn delay(&self, slot: u64, now: Duration) -> Duration {
+        let available = propagation_deadline(self.genesis_time, slot)
+            .saturating_sub(now)
+            .saturating_sub(self.window);
+        self.sample().min(available)
+    }
+}
+
+/// Holds attestations until their delay is over. The relay drains it once per
+/// window, so whatever came due in the meantime, from us or any friend, leaves
+/// together in random order.
+#[derive(Debug)]
+pub struct Mixer<T> {
+    config: MixConfig,
+    held: Vec<(Instant, T)>,
+}
+
+impl<T> Mixer<T> {
+    pub fn new(config: MixConfig) -> Self {
+        Self {
+            config,
+            held: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &MixConfig {
+        &self.config
+    }
+
+    /// Hold `item`, an attestation for `slot`, entering at `now`, with `unix_now`
+    /// the same moment as time since the Unix epoch
+    pub fn hold(&mut self, item: T, slot: u64, now: Instant, unix_now: Duration) -> Duration {
+        let delay = self.config.delay(slot, unix_now);
+        self.held.push((now + delay, item));
+        delay
+    }
+
+    /// Everything due by `now`, shuffled
+    pub fn release(&mut self, now: Instant) -> Vec<T> {
+        let (due, held): (Vec<_>, Vec<_>) = self.held.drain(..).partition(|(release_at, _)| *release_at <= now);
+        self.held = held;
+        let mut batch: Vec<T> = due.into_iter().map(|(_, item)| item).collect();
+        batch.shuffle(&mut thread_rng());
+        batch
+    }
+
+    pub fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(delay: MixDelay) -> MixConfig {
+        MixConfig {
+            delay,
+            window: DEFAULT_MIX_WINDOW,
+            genesis_time: EpochInfo::MAINNET_GENESIS_TIME,
+        }
+    }
+
+    const SLOT: u64 = 1000;
+
+    /// `offset` into `SLOT`, as time since the Unix epoch
+    fn into_slot(offset: Duration) -> Duration {
+        Duration::from_secs(EpochInfo::MAINNET_GENESIS_TIME + SLOT * 12) + offset
+    }
+
+    #[test]
+    fn test_delays_follow_the_distribution_within_the_deadline() {
+        let uniform = config(MixDelay::Uniform { min_ms: 1000, max_ms: 2000 });
+        for _ in 0..100 {
+            let delay = uniform.delay(SLOT, into_slot(Duration::from_secs(4)));
+            assert!(delay >= Duration::from_millis(1000) && delay <= Duration::from_millis(2000));
+        }
+
+        // Produced at the attestation deadline, there are 4s minus one window left
+        let exponential = config(MixDelay::Exponential { mean_ms: 60_000 });
+        let cap = PROPAGATION_ALLOWANCE - DEFAULT_MIX_WINDOW;
+        let delays: Vec<Duration> = (0..100).map(|_| exponential.delay(SLOT, into_slot(Duration::from_secs(4)))).collect();
+        assert!(delays.iter().all(|delay| *delay <= cap));
+        assert!(delays.contains(&cap));
+        // Already past the deadline, it goes out at the next window
+        assert_eq!(exponential.delay(SLOT, into_slot(Duration::from_secs(10))), Duration::ZERO);
+        // The deadline is the attestation's slot's, not the one it arrives in
+        assert_eq!(exponential.delay(SLOT - 1, into_slot(Duration::from_secs(1))), Duration::ZERO);
+        let fixed = config(MixDelay::Uniform { min_ms: 6000, max_ms: 6000 });
+        assert_eq!(fixed.delay(SLOT + 1, into_slot(Duration::from_secs(11))), Duration::from_secs(6));
+    }
+
+    #[test]
+    fn test_release_returns_what_is_due() {
+        let mut mixer = Mixer::new(config(MixDelay::Uniform { min_ms: 1000, max_ms: 1000 }));
+        let start = Instant::now();
+        let unix = into_slot(Duration::from_secs(1));
+        mixer.hold("a", SLOT, start, unix);
+        mixer.hold("b", SLOT, start + Duration::from_millis(500), unix + Duration::from_millis(500));
+        mixer.hold("c", SLOT, start + Duration::from_millis(900), unix + Duration::from_millis(900));
+
+        assert!(mixer.release(start + Duration::from_millis(999)).is_empty());
+        assert_eq!(mixer.release(start + Duration::from_millis(1500)).len(), 2);
+        assert_eq!(mixer.len(), 1);
+        assert_eq!(mixer.release(start + Duration::from_secs(2)), vec!["c"]);
+        assert!(mixer.is_empty());
+    }
+
+    #[test]
+    fn test_batches_are_shuffled_and_config_is_checked() {
+        let mut mixer = Mixer::new(config(MixDelay::Uniform { min_ms: 0, max_ms: 0 }));
+        let (start, unix) = (Instant::now(), into_slot(Duration::ZERO));
+        let orders: Vec<Vec<u32>> = (0..20)
+            .map(|_| {
+                for item in 0..8 {
+                    mixer.hold(item, SLOT, start, unix);
+                }
+                mixer.release(start)
+            })
+            .collect();
+        assert!(orders.iter().any(|order| *order != (0..8).collect::<Vec<_>>()));
+
+        let waku = |mix_delay| WakuConfig {
+            mix_delay,
+            ..stealth_common::StealthConfig::default().waku_config
+        };
+        assert_eq!(MixConfig::from_config(&waku(None), EpochInfo::MAINNET_GENESIS_TIME), None);
+        let mix = MixConfig::from_config(&waku(Some(MixDelay::Exponential { mean_ms: 800 })), 1_700_000_000).unwrap();
+        assert_eq!(mix.window, DEFAULT_MIX_WINDOW);
+        assert_eq!(mix.genesis_time, 1_700_000_000);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::{unix_now, RlnProof, WakuProvider, MAX_RLN_EPOCH_DRIFT, RELAY_POLL_INTERVAL};
+use alloy_primitives::B256;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use stealth_common::{EpochInfo, StealthError, StealthResult};
+use tokio::sync::mpsc;
+use tokio::time::interval;
+use tracing::debug;
+
+/// Domain separation for the nullifiers of node-proven messages
+const NULLIFIER_LABEL: &[u8] = b"stealth-relay/nullifier";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A message as nwaku's REST API carries it
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RestMessage {
+    payload: String,
+    content_topic: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct LightPushRequest {
+    message: RestMessage,
+}
+
+/// Implementation of WakuProvider that talks to nwaku via its REST API.
+///
+/// Topics are content topics, routed by autosharding. nwaku attaches RLN proofs
+/// to what we push and drops relayed messages whose proofs fail, but those stay
+/// inside the node. The proof in our envelopes is no proof at all: it carries a
+/// nullifier to dedupe on and a beacon slot as its epoch, and a friend can put
+/// anything in it. Only the epoch is checked, and friends are held to
+/// `rate_limit_per_epoch` by their per-friend limits alone.
+#[derive(Clone)]
+pub struct NwakuRestProvider {
+    client: reqwest::Client,
+    base_url: Url,
+    genesis_time: u64,
+}
+
+impl NwakuRestProvider {
+    pub fn new(rest_url: &str, genesis_time: u64) -> StealthResult<Self> {
+        let base_url = Url::parse(rest_url)
+            .map_err(|e| StealthError::Config(format!("Invalid nwaku REST URL {}: {}", rest_url, e)))?;
+        if base_url.cannot_be_a_base() {
+            return Err(StealthError::Config(format!("Invalid nwaku REST URL {}", rest_url)));
+        }
+        Ok(Self {
+            client: reqwest::Client::new(),
+            base_url,
+            genesis_time,
+        })
+    }
+
+    /// `segments` under the base URL, each percent-encoded, so content topics fit in one
+    fn endpoint(&self, segments: &[&str]) -> Url {
+        let mut url = self.base_url.clone();
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(segments);
+        }
+        url
+    }
+
+    async fn check(response: reqwest::Result<reqwest::Response>, what: &str) -> StealthResult<reqwest::Response> {
+        let response = response.map_err(|e| StealthError::WakuRln(format!("{} failed: {}", what, e)))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(StealthError::WakuRln(format!("{} failed with {}: {}", what, status, body)));
+        }
+        Ok(response)
+    }
+
+    async fn messages(&self, topic: &str) -> StealthResult<Vec<RestMessage>> {
+        let request = self
+            .client
+            .get(self.endpoint(&["relay", "v1", "auto", "messages", topic]))
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await;
+        Self::check(request, "Relay poll")
+            .await?
+            .json()
+            .await
+            .map_err(|e| StealthError::WakuRln(format!("JSON decode failed: {}", e)))
+    }
+}
+
+/// The stand-in proof for `message` in `epoch`, a nullifier with nothing behind it
+fn node_proof(message: &[u8], epoch: u64) -> RlnProof {
+    let signal_hash: [u8; 32] = Sha256::digest(message).into();
+    let nullifier: [u8; 32] = Sha256::new()
+        .chain_update(NULLIFIER_LABEL)
+        .chain_update(epoch.to_be_bytes())
+        .chain_update(signal_hash)
+        .finalize()
+        .into();
+    RlnProof {
+        nullifier: B256::from(nullifier),
+        proof: Vec::new(),
+        epoch,
+        signal_hash: B256::from(signal_hash),
+    }
+}
+
+#[async_trait::async_trait]
+impl WakuProvider for NwakuRestProvider {
+    async fn generate_rln_proof(&self, message: &[u8], epoch: u64) -> StealthResult<RlnProof> {
+        Ok(node_proof(message, epoch))
+    }
+
+    /// Nothing here can be verified: nwaku checks RLN on the Waku message and
+    /// keeps the result to itself. This only bounds the epoch to the current
+    /// slot, so the per-friend limits see messages in the slot they arrive in.
+    async fn verify_rln_proof(&self, proof: &RlnProof, _message: &[u8]) -> StealthResult<bool> {
+        let epoch = self.get_rln_epoch().await?;
+        Ok(proof.epoch.abs_diff(epoch) <= MAX_RLN_EPOCH_DRIFT)
+    }
+
+    async fn light_push(&self, topic: &str, message: &[u8], timestamp: Option<DateTime<Utc>>) -> StealthResult<String> {
+        let timestamp_ns = timestamp.unwrap_or_else(Utc::now).timestamp_nanos_opt().unwrap_or(0);
+        let request = LightPushRequest {
+            message: RestMessage {
+                payload: general_purpose::STANDARD.encode(message),
+                content_topic: topic.to_string(),
+                timestamp: Some(timestamp_ns),
+            },
+        };
+        let response = self
+            .client
+            .post(self.endpoint(&["lightpush", "v1", "message"]))
+            .json(&request)
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await;
+        Self::check(response, "LightPush").await?;
+
+        // The v1 endpoint answers with a bare OK, so name the message ourselves
+        let message_id = Sha256::new()
+            .chain_update(topic.as_bytes())
+            .chain_update(message)
+            .chain_update(timestamp_ns.to_be_bytes())
+            .finalize();
+        Ok(hex::encode(message_id))
+    }
+
+    async fn subscribe_relay(&self, topic: &str) -> StealthResult<mpsc::UnboundedReceiver<Vec<u8>>> {
+        let response = self
+            .client
+            .post(self.endpoint(&["relay", "v1", "auto", "subscriptions"]))
+            .json(&[topic])
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await;
+        Self::check(response, "Relay subscription").await?;
+        debug!("Subscribed to Waku topic over REST: {}", topic);
+
+        // nwaku buffers messages per topic; poll them until the receiver goes away
+        let (tx, rx) = mpsc::unbounded_channel();
+        let provider = self.clone();
+        let topic = topic.to_string();
+        tokio::spawn(async move {
+            let mut poll_timer = interval(RELAY_POLL_INTERVAL);
+            while !tx.is_closed() {
+                poll_timer.tick().await;
+                let messages = match provider.messages(&topic).await {
+                    Ok(messages) => messages,
+                    Err(e) => {
+                        debug!("Failed to poll Waku topic {}: {}", topic, e);
+                        continue;
+                    }
+                };
+                for message in messages {
+                    match general_purpose::STANDARD.decode(&message.payload) {
+                        Ok(payload) => {
+                            let _ = tx.send(payload);
+                        }
+                        Err(e) => debug!("Invalid payload base64 on {}: {}", topic, e),
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+
+    /// The current beacon slot; nwaku's own RLN epochs stay inside the node
+    async fn get_rln_epoch(&self) -> StealthResult<u64> {
+        Ok(EpochInfo::at(self.genesis_time, unix_now()).slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use warp::Filter;
+
+    /// Messages waiting on each topic, keyed by its percent-encoded form as the
+    /// path carries it
+    type Buffers = Arc<Mutex<HashMap<String, Vec<Value>>>>;
+
+    fn encoded(topic: &str) -> String {
+        let mut url = Url::parse("http://localhost/").unwrap();
+        url.path_segments_mut().unwrap().pop_if_empty().push(topic);
+        url.path().trim_start_matches('/').to_string()
+    }
+
+    /// Enough of nwaku's REST API to push, subscribe and poll; pushes to
+    /// `/reject` fail like a node without lightpush peers
+    async fn mock_nwaku() -> (String, Buffers) {
+        let buffers: Buffers = Arc::default();
+        let subscribe = {
+            let buffers = buffers.clone();
+            warp::post()
+                .and(warp::path!("relay" / "v1" / "auto" / "subscriptions"))
+                .and(warp::body::json())
+                .map(move |topics: Vec<String>| {
+                    for topic in topics {
+                        buffers.lock().unwrap().entry(encoded(&topic)).or_default();
+                    }
+                    "OK"
+                })
+        };
+        let poll = {
+            let buffers = buffers.clone();
+            warp::get()
+                .and(warp::path!("relay" / "v1" / "auto" / "messages" / String))
+                .map(move |topic: String| match buffers.lock().unwrap().get_mut(&topic) {
+                    Some(messages) => warp::reply::with_status(warp::reply::json(&std::mem::take(messages)), warp::http::StatusCode::OK),
+                    None => warp::reply::with_status(warp::reply::json(&"Not subscribed"), warp::http::StatusCode::NOT_FOUND),
+                })
+        };
+        let push = {
+            let buffers = buffers.clone();
+            warp::post()
+                .and(warp::path!("lightpush" / "v1" / "message"))
+                .and(warp::body::json())
+                .map(move |request: Value| {
+                    let message = request["message"].clone();
+                    let topic = message["contentTopic"].as_str().unwrap_or_default().to_string();
+                    if topic == "/reject" {
+                        return warp::reply::with_status("No peers for lightpush", warp::http::StatusCode::SERVICE_UNAVAILABLE);
+                    }
+                    if let Some(messages) = buffers.lock().unwrap().get_mut(&encoded(&topic)) {
+                        messages.push(message);
+                    }
+                    warp::reply::with_status("OK", warp::http::StatusCode::OK)
+                })
+        };
+        let (addr, server) = warp::serve(subscribe.or(poll).or(push)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{}", addr), buffers)
+    }
+
+    #[tokio::test]
+    async fn test_pushes_arrive_on_polled_subscriptions() {
+        let (url, buffers) = mock_nwaku().await;
+        let provider = NwakuRestProvider::new(&url, EpochInfo::MAINNET_GENESIS_TIME).unwrap();
+        let topic = crate::relay_topic("00ff");
+
+        let mut messages = provider.subscribe_relay(&topic).await.unwrap();
+        assert!(buffers.lock().unwrap().contains_key(&encoded(&topic)));
+        let first = provider.light_push(&topic, b"first", None).await.unwrap();
+        let second = provider.light_push(&topic, b"second", None).await.unwrap();
+        assert_ne!(first, second);
+
+        for expected in [b"first".to_vec(), b"second".to_vec()] {
+            let payload = tokio::time::timeout(Duration::from_secs(5), messages.recv()).await.unwrap().unwrap();
+            assert_eq!(payload, expected);
+        }
+        // Other topics are kept apart
+        provider.subscribe_relay(&crate::relay_topic("ff00")).await.unwrap();
+        provider.light_push(&crate::relay_topic("ff00"), b"other", None).await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(1500), messages.recv()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_node_errors_surface() {
+        let (url, _buffers) = mock_nwaku().await;
+        let provider = NwakuRestProvider::new(&format!("{}/", url), EpochInfo::MAINNET_GENESIS_TIME).unwrap();
+        let error = provider.light_push("/reject", b"lost", None).await.unwrap_err();
+        assert!(error.to_string().contains("503") && error.to_string().contains("No peers"), "{}", error);
+        let error = provider.messages("/never-subscribed").await.unwrap_err();
+        assert!(error.to_string().contains("404"), "{}", error);
+
+        assert!(NwakuRestProvider::new("not a url", EpochInfo::MAINNET_GENESIS_TIME).is_err());
+        assert!(NwakuRestProvider::new("mailto:node@example.com", EpochInfo::MAINNET_GENESIS_TIME).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_node_proofs_are_only_bound_to_the_slot() {
+        let genesis_time = unix_now().as_secs() - 1200;
+        let provider = NwakuRestProvider::new("http://127.0.0.1:8645", genesis_time).unwrap();
+        let epoch = provider.get_rln_epoch().await.unwrap();
+        assert!((100..=101).contains(&epoch));
+
+        let proof = provider.generate_rln_proof(b"attestation", epoch).await.unwrap();
+        assert!(provider.verify_rln_proof(&proof, b"attestation").await.unwrap());
+        // A message resent in the same epoch reuses its nullifier, and the next epoch gets a new one
+        assert_eq!(provider.generate_rln_proof(b"attestation", epoch).await.unwrap().nullifier, proof.nullifier);
+        assert_ne!(provider.generate_rln_proof(b"attestation", epoch + 1).await.unwrap().nullifier, proof.nullifier);
+
+        // Anything in the current slot passes, since there is nothing to check it against
+        let forged = RlnProof { nullifier: B256::repeat_byte(7), ..proof.clone() };
+        assert!(provider.verify_rln_proof(&forged, b"forged").await.unwrap());
+        let stale = RlnProof { epoch: epoch - 5, ..proof };
+        assert!(!provider.verify_rln_proof(&stale, b"attestation").await.unwrap());
+    }
+}
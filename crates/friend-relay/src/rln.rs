@@ -0,0 +1,421 @@
+use crate::crypto::{enforce_permissions, write_secret};
+use crate::{unix_now, RlnProof, WakuProvider};
+use ::rln::circuit::Fr;
+use ::rln::hashers::{hash_to_field, poseidon_hash};
+use ::rln::protocol::keygen;
+use ::rln::public::RLN;
+use ::rln::utils::{bytes_le_to_fr, fr_to_bytes_le};
+use alloy_primitives::B256;
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use stealth_common::{EpochInfo, NativeRlnConfig, StealthError, StealthResult, WakuConfig};
+use tokio::sync::mpsc;
+use tracing::info;
+
+/// Depth of the membership tree zerokit publishes parameters for
+pub const TREE_DEPTH: usize = 20;
+/// Bytes of a serialized field element
+const FIELD_SIZE: usize = 32;
+/// Bytes of a compressed Groth16 proof on BN254
+const PROOF_SIZE: usize = 128;
+/// zerokit's proof output: the proof, then the root, external nullifier, `x`,
+/// `y` and nullifier
+const PROOF_VALUES_SIZE: usize = PROOF_SIZE + 5 * FIELD_SIZE;
+const EXTERNAL_NULLIFIER_OFFSET: usize = PROOF_SIZE + FIELD_SIZE;
+const X_OFFSET: usize = PROOF_SIZE + 2 * FIELD_SIZE;
+const NULLIFIER_OFFSET: usize = PROOF_SIZE + 4 * FIELD_SIZE;
+/// Hashed with the epoch into the external nullifier, so our nullifiers mean
+/// nothing in any other RLN application
+const RLN_IDENTIFIER: &[u8] = b"stealth-relay/rln";
+
+/// Binds nullifiers to an epoch of this application
+pub fn external_nullifier(epoch: u64) -> Fr {
+    poseidon_hash(&[Fr::from(epoch), hash_to_field(RLN_IDENTIFIER)])
+}
+
+fn field_bytes(element: &Fr) -> Vec<u8> {
+    fr_to_bytes_le(element)
+}
+
+/// `0x`-prefixed little-endian hex, as zerokit serializes field elements
+pub fn field_hex(element: &Fr) -> String {
+    format!("0x{}", hex::encode(field_bytes(element)))
+}
+
+/// Parse `field_hex`, rejecting values that are not below the modulus
+pub fn parse_field(hex_str: &str) -> anyhow::Result<Fr> {
+    read_field(&hex::decode(hex_str.trim().trim_start_matches("0x"))?)
+}
+
+fn read_field(bytes: &[u8]) -> anyhow::Result<Fr> {
+    if bytes.len() != FIELD_SIZE {
+        bail!("expected {} bytes, found {}", FIELD_SIZE, bytes.len());
+    }
+    let (element, _) = bytes_le_to_fr(bytes);
+    if field_bytes(&element) != bytes {
+        bail!("not a field element");
+    }
+    Ok(element)
+}
+
+/// `len` as zerokit prefixes variable-length inputs
+fn length_prefix(len: usize) -> [u8; 8] {
+    (len as u64).to_le_bytes()
+}
+
+/// Read commitments from `path`, one per line; blank lines and `#` comments are skipped
+pub fn load_members(path: &Path) -> anyhow::Result<Vec<Fr>> {
+    let contents = fs::read_to_string(path).with_context(|| format!("Failed to read RLN members {}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number, line.split('#').next().unwrap_or_default().trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(number, line)| parse_field(line).with_context(|| format!("Invalid RLN member on line {} of {}", number + 1, path.display())))
+        .collect()
+}
+
+/// Our RLN identity secret; the group knows us by its commitment
+pub struct RlnIdentity {
+    secret: Fr,
+    commitment: Fr,
+}
+
+impl RlnIdentity {
+    pub fn generate() -> Self {
+        let (secret, commitment) = keygen();
+        Self { secret, commitment }
+    }
+
+    /// Load the secret at `path`, creating it with mode 0600 if it does not exist
+    pub fn load_or_create(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            let identity = Self::generate();
+            write_secret(path, &field_bytes(&identity.secret))?;
+            info!("🪪 Generated new RLN identity at {} (commitment {})", path.display(), field_hex(&identity.commitment));
+            return Ok(identity);
+        }
+
+        enforce_permissions(path)?;
+        let bytes = fs::read(path).with_context(|| format!("Failed to read RLN identity {}", path.display()))?;
+        let secret = read_field(&bytes).with_context(|| format!("Invalid RLN identity {}", path.display()))?;
+        Ok(Self {
+            secret,
+            commitment: poseidon_hash(&[secret]),
+        })
+    }
+
+    /// What the group lists in its membership file for us
+    pub fn commitment(&self) -> Fr {
+        self.commitment
+    }
+}
+
+/// Proves our messages and verifies the group's with zerokit's RLN, whose
+/// tree holds each member's rate commitment `poseidon(commitment, message_limit)`
+pub struct RlnProver {
+    rln: Mutex<RLN>,
+    identity: RlnIdentity,
+    index: usize,
+    members: usize,
+    message_limit: u32,
+}
+
+impl RlnProver {
+    pub fn new(identity: RlnIdentity, members: &[Fr], message_limit: u32) -> anyhow::Result<Self> {
+        if members.len() > 1 << TREE_DEPTH {
+            bail!("{} members do not fit a tree of depth {}", members.len(), TREE_DEPTH);
+        }
+        let index = members
+            .iter()
+            .position(|member| *member == identity.commitment)
+            .ok_or_else(|| anyhow!("Our RLN commitment {} is not in the membership list", field_hex(&identity.commitment)))?;
+
+        let limit = Fr::from(message_limit as u64);
+        let mut leaves = length_prefix(members.len()).to_vec();
+        for member in members {
+            leaves.extend(field_bytes(&poseidon_hash(&[*member, limit])));
+        }
+        let mut rln = RLN::new(TREE_DEPTH, Cursor::new("{}")).map_err(|e| anyhow!("RLN setup failed: {}", e))?;
+        rln.init_tree_with_leaves(Cursor::new(leaves))
+            .map_err(|e| anyhow!("RLN membership tree failed: {}", e))?;
+        Ok(Self {
+            rln: Mutex::new(rln),
+            identity,
+            index,
+            members: members.len(),
+            message_limit,
+        })
+    }
+
+    pub fn message_limit(&self) -> u32 {
+        self.message_limit
+    }
+
+    /// Prove `message` as our `message_id`th in `epoch`; the proof bytes are
+    /// zerokit's proof output as it verifies it
+    pub fn prove(&self, message: &[u8], epoch: u64, message_id: u32) -> anyhow::Result<RlnProof> {
+        if message_id >= self.message_limit {
+            bail!("Message {} is over the limit of {} per epoch", message_id, self.message_limit);
+        }
+        let mut input = field_bytes(&self.identity.secret);
+        input.extend(length_prefix(self.index));
+        input.extend(field_bytes(&Fr::from(self.message_limit as u64)));
+        input.extend(field_bytes(&Fr::from(message_id as u64)));
+        input.extend(field_bytes(&external_nullifier(epoch)));
+        input.extend(length_prefix(message.len()));
+        input.extend_from_slice(message);
+
+        let mut output = Vec::with_capacity(PROOF_VALUES_SIZE);
+        self.rln
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .generate_rln_proof(Cursor::new(input), &mut output)
+            .map_err(|e| anyhow!("RLN proof failed: {}", e))?;
+        if output.len() != PROOF_VALUES_SIZE {
+            bail!("RLN proof has {} bytes, expected {}", output.len(), PROOF_VALUES_SIZE);
+        }
+        Ok(RlnProof {
+            nullifier: B256::from_slice(&output[NULLIFIER_OFFSET..]),
+            signal_hash: B256::from_slice(&output[X_OFFSET..X_OFFSET + FIELD_SIZE]),
+            proof: output,
+            epoch,
+        })
+    }
+
+    /// Whether a member of our tree proved `message` in its epoch within the limit
+    pub fn verify(&self, proof: &RlnProof, message: &[u8]) -> bool {
+        let values = &proof.proof;
+        if values.len() != PROOF_VALUES_SIZE
+            || values[EXTERNAL_NULLIFIER_OFFSET..X_OFFSET] != field_bytes(&external_nullifier(proof.epoch))[..]
+            || values[X_OFFSET..X_OFFSET + FIELD_SIZE] != field_bytes(&hash_to_field(message))[..]
+            || values[X_OFFSET..X_OFFSET + FIELD_SIZE] != proof.signal_hash[..]
+            || values[NULLIFIER_OFFSET..] != proof.nullifier[..]
+        {
+            return false;
+        }
+        let mut input = values.clone();
+        input.extend(length_prefix(message.len()));
+        input.extend_from_slice(message);
+        self.rln
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .verify_rln_proof(Cursor::new(input))
+            .unwrap_or(false)
+    }
+}
+
+/// WakuProvider that proves and verifies RLN itself and uses `transport` only
+/// to move messages, so any node that can push and relay will do. Epochs are
+/// beacon slots, and `rate_limit_per_epoch` is per slot and the same for the group.
+pub struct NativeRln<T: WakuProvider> {
+    transport: T,
+    prover: Arc<RlnProver>,
+    /// The epoch we are sending in, and the next message id free in it
+    next_message: Mutex<(u64, u32)>,
+    /// Ids spent before a restart are unknown, so nothing is proved until the slot after this
+    started_in: u64,
+    genesis_time: u64,
+}
+
+impl<T: WakuProvider> NativeRln<T> {
+    pub fn new(transport: T, prover: RlnProver, genesis_time: u64) -> Self {
+        let started_in = EpochInfo::at(genesis_time, unix_now()).slot;
+        Self {
+            transport,
+            prover: Arc::new(prover),
+            next_message: Mutex::new((started_in, 0)),
+            started_in,
+            genesis_time,
+        }
+    }
+
+    pub fn from_config(
+        transport: T,
+        config: &WakuConfig,
+        native: &NativeRlnConfig,
+        genesis_time: u64,
+    ) -> anyhow::Result<Self> {
+        let identity = RlnIdentity::load_or_create(Path::new(&native.identity_path))?;
+        let members = load_members(Path::new(&native.membership_path))?;
+        let prover = RlnProver::new(identity, &members, config.rate_limit_per_epoch)
+            .with_context(|| format!("Add it to {} and share the list with the group", native.membership_path))?;
+        info!(
+            "🪪 Native RLN member {} of {} (commitment {})",
+            prover.index,
+            prover.members,
+            field_hex(&prover.identity.commitment)
+        );
+        Ok(Self::new(transport, prover, genesis_time))
+    }
+
+    /// Take the next message id in `epoch`; ids are never reused, since two
+    /// shares on one nullifier give our secret away
+    fn next_message_id(&self, epoch: u64) -> StealthResult<u32> {
+        if epoch <= self.started_in {
+            return Err(StealthError::WakuRln(format!(
+                "RLN epoch {} began before we started, its message ids may be spent",
+                epoch
+            )));
+        }
+        let mut next = self.next_message.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match epoch.cmp(&next.0) {
+            Ordering::Greater => *next = (epoch, 0),
+            Ordering::Less => return Err(StealthError::WakuRln(format!("RLN epoch {} is over (now {})", epoch, next.0))),
+            Ordering::Equal => {}
+        }
+        if next.1 >= self.prover.message_limit() {
+            return Err(StealthError::WakuRln(format!(
+                "All {} RLN messages of epoch {} are used",
+                self.prover.message_limit(),
+                epoch
+            )));
+        }
+        next.1 += 1;
+        Ok(next.1 - 1)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: WakuProvider> WakuProvider for NativeRln<T> {
+    async fn generate_rln_proof(&self, message: &[u8], epoch: u64) -> StealthResult<RlnProof> {
+        let message_id = self.next_message_id(epoch)?;
+        let prover = self.prover.clone();
+        let message = message.to_vec();
+        tokio::task::spawn_blocking(move || prover.prove(&message, epoch, message_id))
+            .await
+            .map_err(|e| StealthError::WakuRln(format!("RLN prover stopped: {}", e)))?
+            .map_err(|e| StealthError::WakuRln(e.to_string()))
+    }
+
+    async fn verify_rln_proof(&self, proof: &RlnProof, message: &[u8]) -> StealthResult<bool> {
+        let prover = self.prover.clone();
+        let (proof, message) = (proof.clone(), message.to_vec());
+        tokio::task::spawn_blocking(move || prover.verify(&proof, &message))
+            .await
+            .map_err(|e| StealthError::WakuRln(format!("RLN verifier stopped: {}", e)))
+    }
+
+    async fn light_push(&self, topic: &str, message: &[u8], timestamp: Option<DateTime<Utc>>) -> StealthResult<String> {
+        self.transport.light_push(topic, message, timestamp).await
+    }
+
+    async fn subscribe_relay(&self, topic: &str) -> StealthResult<mpsc::UnboundedReceiver<Vec<u8>>> {
+        self.transport.subscribe_relay(topic).await
+    }
+
+    /// The current beacon slot
+    async fn get_rln_epoch(&self) -> StealthResult<u64> {
+        Ok(EpochInfo::at(self.genesis_time, unix_now()).slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rest::NwakuRestProvider;
+    use std::sync::OnceLock;
+
+    /// Building a depth 20 tree is slow, so tests share one group
+    struct Group {
+        alice: RlnProver,
+        bob: RlnProver,
+    }
+
+    fn group() -> &'static Group {
+        static GROUP: OnceLock<Group> = OnceLock::new();
+        GROUP.get_or_init(|| {
+            let (alice, bob) = (RlnIdentity::generate(), RlnIdentity::generate());
+            let members = vec![RlnIdentity::generate().commitment(), alice.commitment(), bob.commitment()];
+            Group {
+                alice: RlnProver::new(alice, &members, 2).unwrap(),
+                bob: RlnProver::new(bob, &members, 2).unwrap(),
+            }
+        })
+    }
+
+    #[test]
+    fn test_members_and_identities_round_trip() {
+        let dir = std::env::temp_dir().join(format!("stealth-rln-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // The identity is created once and its commitment survives a reload
+        let path = dir.join("rln.key");
+        let identity = RlnIdentity::load_or_create(&path).unwrap();
+        assert_eq!(RlnIdentity::load_or_create(&path).unwrap().commitment(), identity.commitment());
+
+        // The membership file takes comments and rejects anything else
+        let members = dir.join("members.txt");
+        let listed: Vec<Fr> = (0..3).map(|_| RlnIdentity::generate().commitment()).collect();
+        let hex: Vec<String> = listed.iter().map(field_hex).collect();
+        fs::write(&members, format!("# group\n{}\n\n{}  # carol\n", hex[..2].join("\n"), hex[2])).unwrap();
+        assert_eq!(load_members(&members).unwrap(), listed);
+        fs::write(&members, format!("{}\n0x{}\n", hex[0], "ff".repeat(FIELD_SIZE))).unwrap();
+        let error = load_members(&members).unwrap_err();
+        assert!(format!("{:#}", error).contains("line 2"), "{:#}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_proofs_verify_for_members_within_the_limit() {
+        let Group { alice, bob } = group();
+        let proof = alice.prove(b"attestation", 7, 0).unwrap();
+        assert!(bob.verify(&proof, b"attestation"));
+        assert!(!bob.verify(&proof, b"forged"));
+        assert!(!bob.verify(&RlnProof { epoch: 8, ..proof.clone() }, b"attestation"));
+        let mut tampered = proof.clone();
+        tampered.proof[0] ^= 1;
+        assert!(!bob.verify(&tampered, b"attestation"));
+
+        // Each message id has its own nullifier, which the epoch changes
+        assert_eq!(alice.prove(b"other", 7, 0).unwrap().nullifier, proof.nullifier);
+        assert_ne!(alice.prove(b"attestation", 7, 1).unwrap().nullifier, proof.nullifier);
+        assert_ne!(alice.prove(b"attestation", 8, 0).unwrap().nullifier, proof.nullifier);
+        assert!(alice.prove(b"attestation", 7, 2).is_err());
+
+        // Only listed members can prove
+        assert!(RlnProver::new(RlnIdentity::generate(), &[RlnIdentity::generate().commitment()], 2).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_native_provider_spends_message_ids_per_slot() {
+        let dir = std::env::temp_dir().join(format!("stealth-rln-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let native = NativeRlnConfig {
+            membership_path: dir.join("members.txt").display().to_string(),
+            identity_path: dir.join("rln.key").display().to_string(),
+        };
+        let config = WakuConfig {
+            rate_limit_per_epoch: 2,
+            ..stealth_common::StealthConfig::default().waku_config
+        };
+        let genesis_time = unix_now().as_secs() - 1200;
+        let transport = || NwakuRestProvider::new("http://127.0.0.1:8645", genesis_time).unwrap();
+
+        // Not a member until our commitment is listed
+        let identity = RlnIdentity::load_or_create(Path::new(&native.identity_path)).unwrap();
+        fs::write(&native.membership_path, format!("{}\n", field_hex(&RlnIdentity::generate().commitment()))).unwrap();
+        assert!(NativeRln::from_config(transport(), &config, &native, genesis_time).is_err());
+        fs::write(&native.membership_path, format!("{}\n", field_hex(&identity.commitment()))).unwrap();
+        let provider = NativeRln::from_config(transport(), &config, &native, genesis_time).unwrap();
+
+        // Slots count from the configured genesis, and the one we started in is skipped
+        let slot = provider.started_in;
+        assert!((slot..=slot + 1).contains(&provider.get_rln_epoch().await.unwrap()));
+        assert!(slot >= 100);
+        assert!(provider.generate_rln_proof(b"attestation", slot).await.is_err());
+        let first = provider.generate_rln_proof(b"attestation", slot + 1).await.unwrap();
+        let second = provider.generate_rln_proof(b"attestation", slot + 1).await.unwrap();
+        assert_ne!(first.nullifier, second.nullifier);
+        assert!(provider.verify_rln_proof(&second, b"attestation").await.unwrap());
+        assert!(provider.generate_rln_proof(b"attestation", slot + 1).await.is_err());
+        assert!(provider.generate_rln_proof(b"attestation", slot + 2).await.is_ok());
+        assert!(provider.generate_rln_proof(b"attestation", slot + 1).await.is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -30,6 +30,8 @@ pub struct StealthMetricsCollector {
     pub friend_relay_latency: Histogram,
     pub friend_messages_sent_total: IntCounterVec,
     pub friend_messages_received_total: IntCounterVec,
+    pub friend_rtt_seconds: HistogramVec,
+    pub friend_ping_loss_rate: GaugeVec,
     pub rln_proofs_generated_total: IntCounter,
     pub rln_proofs_verified_total: IntCounterVec,
     pub rate_limit_violations_total: IntCounter,
@@ -47,6 +49,8 @@ pub struct StealthMetricsCollector {
     // Attack defense metrics
     pub rainbow_attack_attempts_detected: IntCounter,
     pub privacy_events_total: IntCounterVec,
+    pub validator_modelled_exposure: GaugeVec,
+    pub subnet_modelled_exposure: GaugeVec,
     
     start_time: DateTime<Utc>,
 }
@@ -134,6 +138,22 @@ impl StealthMetricsCollector {
             &["friend_id"]
         ).map_err(|e| StealthError::Metrics(format!("Failed to create friend_messages_received_total: {}", e)))?;
         
+        let friend_rtt_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "stealth_sidecar_friend_rtt_seconds",
+                "Round-trip time of liveness pings to friend nodes"
+            ).buckets(prometheus::exponential_buckets(0.005, 2.0, 12).map_err(|e| StealthError::Metrics(format!("Invalid buckets: {}", e)))?),
+            &["friend_id"]
+        ).map_err(|e| StealthError::Metrics(format!("Failed to create friend_rtt_seconds: {}", e)))?;
+        
+        let friend_ping_loss_rate = GaugeVec::new(
+            Opts::new(
+                "stealth_sidecar_friend_ping_loss_rate",
+                "Share of recent liveness pings a friend did not answer"
+            ),
+            &["friend_id"]
+        ).map_err(|e| StealthError::Metrics(format!("Failed to create friend_ping_loss_rate: {}", e)))?;
+        
         let rln_proofs_generated_total = IntCounter::new(
             "stealth_sidecar_rln_proofs_generated_total",
             "Total number of RLN proofs generated"
@@ -158,7 +178,7 @@ impl StealthMetricsCollector {
                 "stealth_sidecar_bandwidth_bytes_total",
                 "Total bandwidth usage in bytes"
             ),
-            &["direction", "protocol"] // "inbound"/"outbound", "reth"/"waku"/"gossip"
+            &["direction", "protocol", "topic"] // "inbound"/"outbound", "reth"/"waku"/"gossip"/"libp2p", gossip topic or "none"
         ).map_err(|e| StealthError::Metrics(format!("Failed to create bandwidth_bytes_total: {}", e)))?;
         
         let peer_connections = GaugeVec::new(
@@ -207,6 +227,22 @@ impl StealthMetricsCollector {
             &["event_type"] // "subnet_shuffle", "friend_relay", "anonymity_preserved"
         ).map_err(|e| StealthError::Metrics(format!("Failed to create privacy_events_total: {}", e)))?;
         
+        let validator_modelled_exposure = GaugeVec::new(
+            Opts::new(
+                "stealth_sidecar_validator_modelled_exposure_ratio",
+                "Modelled share of a validator's attestations a single gossip observer would attribute to our peer ID, estimated from who we sent them to"
+            ),
+            &["validator"]
+        ).map_err(|e| StealthError::Metrics(format!("Failed to create validator_modelled_exposure: {}", e)))?;
+        
+        let subnet_modelled_exposure = GaugeVec::new(
+            Opts::new(
+                "stealth_sidecar_subnet_modelled_exposure_ratio",
+                "Modelled share of attestations sent on a subnet a single gossip observer would attribute to our peer ID, estimated from who we sent them to"
+            ),
+            &["subnet"]
+        ).map_err(|e| StealthError::Metrics(format!("Failed to create subnet_modelled_exposure: {}", e)))?;
+        
         // Register all metrics
         registry.register(Box::new(subnets_joined_total.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
         registry.register(Box::new(subnets_left_total.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
@@ -219,6 +255,8 @@ impl StealthMetricsCollector {
         registry.register(Box::new(friend_relay_latency.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
         registry.register(Box::new(friend_messages_sent_total.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
         registry.register(Box::new(friend_messages_received_total.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
+        registry.register(Box::new(friend_rtt_seconds.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
+        registry.register(Box::new(friend_ping_loss_rate.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
         registry.register(Box::new(rln_proofs_generated_total.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
         registry.register(Box::new(rln_proofs_verified_total.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
         registry.register(Box::new(rate_limit_violations_total.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
@@ -230,6 +268,8 @@ impl StealthMetricsCollector {
         registry.register(Box::new(cpu_usage_percent.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
         registry.register(Box::new(rainbow_attack_attempts_detected.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
         registry.register(Box::new(privacy_events_total.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
+        registry.register(Box::new(validator_modelled_exposure.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
+        registry.register(Box::new(subnet_modelled_exposure.clone())).map_err(|e| StealthError::Metrics(format!("Registry error: {}", e)))?;
         
         Ok(Self {
             registry,
@@ -244,6 +284,8 @@ impl StealthMetricsCollector {
             friend_relay_latency,
             friend_messages_sent_total,
             friend_messages_received_total,
+            friend_rtt_seconds,
+            friend_ping_loss_rate,
             rln_proofs_generated_total,
             rln_proofs_verified_total,
             rate_limit_violations_total,
@@ -255,6 +297,8 @@ impl StealthMetricsCollector {
             cpu_usage_percent,
             rainbow_attack_attempts_detected,
             privacy_events_total,
+            validator_modelled_exposure,
+            subnet_modelled_exposure,
             start_time: Utc::now(),
         })
     }
@@ -309,6 +353,23 @@ impl StealthMetricsCollector {
         self.friend_messages_sent_total.with_label_values(&[friend_id, status]).inc();
     }
     
+    /// Record an attestation a friend relayed through us
+    pub fn record_friend_message_received(&self, friend_id: &str) {
+        self.attestations_received_total.inc();
+        self.friend_messages_received_total.with_label_values(&[friend_id]).inc();
+    }
+    
+    /// Record a friend's answer to a liveness ping
+    pub fn record_friend_heartbeat(&self, friend_id: &str, rtt_seconds: f64, loss_rate: f64) {
+        self.friend_rtt_seconds.with_label_values(&[friend_id]).observe(rtt_seconds);
+        self.friend_ping_loss_rate.with_label_values(&[friend_id]).set(loss_rate);
+    }
+    
+    /// Record what happened to our attestation when too few friends were reachable
+    pub fn record_anonymity_fallback(&self, decision: &str) {
+        self.privacy_events_total.with_label_values(&[decision]).inc();
+    }
+    
     /// Record RLN proof generation
     pub fn record_rln_proof_generated(&self) {
         self.rln_proofs_generated_total.inc();
@@ -327,7 +388,12 @@ impl StealthMetricsCollector {
     
     /// Record bandwidth usage
     pub fn record_bandwidth(&self, bytes: u64, direction: &str, protocol: &str) {
-        self.bandwidth_bytes_total.with_label_values(&[direction, protocol]).inc_by(bytes as f64);
+        self.bandwidth_bytes_total.with_label_values(&[direction, protocol, "none"]).inc_by(bytes as f64);
+    }
+
+    /// Record libp2p bandwidth attributed to a gossip topic (`beacon_attestation_5`, `other`, ...)
+    pub fn record_topic_bandwidth(&self, bytes: u64, direction: &str, topic: &str) {
+        self.bandwidth_bytes_total.with_label_values(&[direction, "libp2p", topic]).inc_by(bytes as f64);
     }
     
     /// Record message size
@@ -346,6 +412,18 @@ impl StealthMetricsCollector {
         self.privacy_events_total.with_label_values(&["anonymity_preserved"]).inc();
     }
     
+    /// Replace the modelled exposure gauges, so validators and subnets we stopped sending on drop out
+    pub fn update_modelled_exposure(&self, per_validator: &HashMap<u64, f64>, per_subnet: &HashMap<u8, f64>) {
+        self.validator_modelled_exposure.reset();
+        for (validator, ratio) in per_validator {
+            self.validator_modelled_exposure.with_label_values(&[&validator.to_string()]).set(*ratio);
+        }
+        self.subnet_modelled_exposure.reset();
+        for (subnet, ratio) in per_subnet {
+            self.subnet_modelled_exposure.with_label_values(&[&subnet.to_string()]).set(*ratio);
+        }
+    }
+    
     /// Get the Prometheus registry for HTTP endpoint
     pub fn registry(&self) -> &Registry {
         &self.registry
@@ -450,7 +528,7 @@ impl DashboardStats {
             uptime_seconds: collector.uptime_seconds.get(),
             subnets_subscribed: collector.current_subscribed_subnets.get(),
             attestations_relayed: collector.attestations_relayed_total.get(),
-            friends_connected: 3, // Would come from peer_connections metric
+            friends_connected: collector.peer_connections.with_label_values(&["friend"]).get() as i64,
             average_latency_ms: 36.0, // Would come from friend_relay_latency metric
             bandwidth_mbps: 0.8, // Would be calculated from bandwidth_bytes_total
             memory_usage_mb: collector.memory_usage_bytes.get() / 1_000_000.0,
@@ -480,6 +558,20 @@ mod tests {
         assert_eq!(collector.rln_proofs_generated_total.get(), 1);
     }
     
+    #[test]
+    fn test_friend_heartbeat_metrics() {
+        let collector = StealthMetricsCollector::new().unwrap();
+        collector.record_friend_heartbeat("friend_1", 0.04, 0.0);
+        collector.record_friend_heartbeat("friend_1", 0.12, 0.5);
+        collector.update_peer_connections("friend", 2);
+        
+        let rtt = collector.friend_rtt_seconds.with_label_values(&["friend_1"]);
+        assert_eq!(rtt.get_sample_count(), 2);
+        assert!((rtt.get_sample_sum() - 0.16).abs() < 1e-9);
+        assert_eq!(collector.friend_ping_loss_rate.with_label_values(&["friend_1"]).get(), 0.5);
+        assert_eq!(DashboardStats::from_collector(&collector).friends_connected, 2);
+    }
+    
     #[test]
     fn test_dashboard_stats() {
         let collector = StealthMetricsCollector::new().unwrap();
@@ -0,0 +1,267 @@
+use anyhow::{anyhow, Result};
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use stealth_common::NetworkConfig;
+
+/// Distinct peers that must observe the same IP before we advertise it
+pub const EXTERNAL_IP_CONFIRMATIONS: usize = 2;
+
+/// Ports the swarm and discv5 listen on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenPorts {
+    pub tcp: u16,
+    pub quic: u16,
+    /// discv5 UDP port, distinct from the QUIC one
+    pub discovery: u16,
+}
+
+impl ListenPorts {
+    /// Ports from the config: QUIC defaults to `listen_port + 1`, discv5 to `listen_port`
+    pub fn from_config(network_config: &NetworkConfig) -> Self {
+        let tcp = network_config.listen_port;
+        Self {
+            tcp,
+            quic: network_config.quic_port.unwrap_or_else(|| if tcp == 0 { 0 } else { tcp.saturating_add(1) }),
+            discovery: network_config.discovery_port.unwrap_or(tcp),
+        }
+    }
+
+    /// Ports the OS picks when we listen, for a second identity running next to
+    /// the configured one
+    pub fn ephemeral() -> Self {
+        Self { tcp: 0, quic: 0, discovery: 0 }
+    }
+
+    /// Dual-stack TCP and QUIC listen addresses
+    pub fn listen_addresses(&self) -> Vec<Multiaddr> {
+        [IpAddr::V4(Ipv4Addr::UNSPECIFIED), IpAddr::V6(Ipv6Addr::UNSPECIFIED)]
+            .into_iter()
+            .flat_map(|ip| self.listen_addresses_on(ip))
+            .collect()
+    }
+
+    /// TCP and QUIC listen addresses on one IP
+    pub fn listen_addresses_on(&self, ip: IpAddr) -> Vec<Multiaddr> {
+        vec![tcp_address(ip, self.tcp), quic_address(ip, self.quic)]
+    }
+
+    /// Fill in a TCP or QUIC port of 0 from an address the swarm is listening on
+    pub fn bound(self, address: &Multiaddr) -> Self {
+        let mut ports = self;
+        let mut protocols = address.iter().skip_while(|p| !matches!(p, Protocol::Tcp(_) | Protocol::Udp(_)));
+        match (protocols.next(), protocols.next()) {
+            (Some(Protocol::Tcp(port)), _) if ports.tcp == 0 => ports.tcp = port,
+            (Some(Protocol::Udp(port)), Some(Protocol::QuicV1)) if ports.quic == 0 => ports.quic = port,
+            _ => {}
+        }
+        ports
+    }
+
+    /// Whether the OS has yet to pick a TCP or QUIC port
+    pub fn unbound(&self) -> bool {
+        self.tcp == 0 || self.quic == 0
+    }
+
+    /// Addresses to advertise once `ip` is confirmed as ours
+    pub fn external_addresses(&self, ip: IpAddr) -> Vec<Multiaddr> {
+        vec![quic_address(ip, self.quic), tcp_address(ip, self.tcp)]
+    }
+}
+
+/// How `network.external_ip` is interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalIp {
+    /// Nothing configured, never advertise an address
+    Disabled,
+    /// `"auto"`: advertise the public IP our peers agree on
+    Auto,
+    /// Advertise this IP once peers confirm they see us there
+    Explicit(IpAddr),
+}
+
+impl ExternalIp {
+    pub fn from_config(external_ip: Option<&str>) -> Result<Self> {
+        match external_ip.map(str::trim) {
+            None | Some("") => Ok(Self::Disabled),
+            Some(value) if value.eq_ignore_ascii_case("auto") => Ok(Self::Auto),
+            Some(value) => value
+                .parse()
+                .map(Self::Explicit)
+                .map_err(|_| anyhow!("Invalid external_ip {:?}, expected an IP or \"auto\"", value)),
+        }
+    }
+}
+
+/// Confirms our external IP from the addresses peers report through identify
+#[derive(Debug)]
+pub struct ExternalAddressConfirmer {
+    setting: ExternalIp,
+    ports: ListenPorts,
+    observers: HashMap<IpAddr, HashSet<PeerId>>,
+    confirmed: Option<IpAddr>,
+}
+
+impl ExternalAddressConfirmer {
+    pub fn new(setting: ExternalIp, ports: ListenPorts) -> Self {
+        Self {
+            setting,
+            ports,
+            observers: HashMap::new(),
+            confirmed: None,
+        }
+    }
+
+    /// Record the address `peer_id` observed us at; returns the IP when this
+    /// observation is the one that confirms it
+    pub fn observe(&mut self, peer_id: PeerId, observed: &Multiaddr) -> Option<IpAddr> {
+        let ip = observed.iter().find_map(|protocol| match protocol {
+            Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+            Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+            _ => None,
+        })?;
+        let acceptable = match self.setting {
+            ExternalIp::Disabled => false,
+            ExternalIp::Auto => is_public(ip),
+            ExternalIp::Explicit(expected) => ip == expected,
+        };
+        if !acceptable || self.confirmed == Some(ip) {
+            return None;
+        }
+
+        let observers = self.observers.entry(ip).or_default();
+        observers.insert(peer_id);
+        if observers.len() < EXTERNAL_IP_CONFIRMATIONS {
+            return None;
+        }
+        self.confirmed = Some(ip);
+        Some(ip)
+    }
+
+    pub fn confirmed(&self) -> Option<IpAddr> {
+        self.confirmed
+    }
+
+    pub fn ports(&self) -> ListenPorts {
+        self.ports
+    }
+}
+
+fn tcp_address(ip: IpAddr, port: u16) -> Multiaddr {
+    Multiaddr::from(ip).with(Protocol::Tcp(port))
+}
+
+fn quic_address(ip: IpAddr, port: u16) -> Multiaddr {
+    Multiaddr::from(ip).with(Protocol::Udp(port)).with(Protocol::QuicV1)
+}
+
+/// Whether other nodes on the internet could reach us at `ip`
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || shared)
+        }
+        IpAddr::V6(ip) => {
+            let unique_local = (ip.segments()[0] & 0xfe00) == 0xfc00;
+            let link_local = (ip.segments()[0] & 0xffc0) == 0xfe80;
+            !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_identity::Keypair;
+
+    fn peer() -> PeerId {
+        PeerId::from(Keypair::generate_ed25519().public())
+    }
+
+    #[test]
+    fn test_listen_addresses_are_dual_stack() {
+        let config = NetworkConfig {
+            listen_port: 9000,
+            ..stealth_common::StealthConfig::default().network
+        };
+        let ports = ListenPorts::from_config(&config);
+        assert_eq!(ports, ListenPorts { tcp: 9000, quic: 9001, discovery: 9000 });
+
+        let addresses: Vec<String> = ports.listen_addresses().iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            addresses,
+            vec![
+                "/ip4/0.0.0.0/tcp/9000",
+                "/ip4/0.0.0.0/udp/9001/quic-v1",
+                "/ip6/::/tcp/9000",
+                "/ip6/::/udp/9001/quic-v1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ephemeral_ports_are_read_back() {
+        let ports = ListenPorts::ephemeral();
+        assert!(ports.unbound());
+        let ports = ports
+            .bound(&"/ip4/0.0.0.0/tcp/41234".parse().unwrap())
+            .bound(&"/ip4/127.0.0.1/udp/41235/quic-v1".parse().unwrap())
+            // Later listen addresses do not move a port that is already bound
+            .bound(&"/ip4/10.0.0.1/tcp/9".parse().unwrap());
+        assert!(!ports.unbound());
+        assert_eq!(ports, ListenPorts { tcp: 41234, quic: 41235, discovery: 0 });
+    }
+
+    #[test]
+    fn test_external_ip_setting() {
+        assert_eq!(ExternalIp::from_config(None).unwrap(), ExternalIp::Disabled);
+        assert_eq!(ExternalIp::from_config(Some("auto")).unwrap(), ExternalIp::Auto);
+        assert_eq!(
+            ExternalIp::from_config(Some("127.0.0.1")).unwrap(),
+            ExternalIp::Explicit(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        );
+        assert!(ExternalIp::from_config(Some("my.host")).is_err());
+    }
+
+    #[test]
+    fn test_external_address_needs_confirmation() {
+        let observed: Multiaddr = "/ip4/81.2.69.160/tcp/41234".parse().unwrap();
+        let private: Multiaddr = "/ip4/192.168.1.10/tcp/41234".parse().unwrap();
+        let ports = ListenPorts { tcp: 9000, quic: 9001, discovery: 9000 };
+
+        let mut auto = ExternalAddressConfirmer::new(ExternalIp::Auto, ports);
+        let first = peer();
+        assert_eq!(auto.observe(first, &private), None);
+        assert_eq!(auto.observe(peer(), &private), None);
+        assert_eq!(auto.observe(first, &observed), None);
+        // The same peer reporting again does not count twice
+        assert_eq!(auto.observe(first, &observed), None);
+        assert_eq!(auto.observe(peer(), &observed), Some("81.2.69.160".parse().unwrap()));
+        assert_eq!(auto.observe(peer(), &observed), None);
+        assert_eq!(auto.confirmed(), Some("81.2.69.160".parse().unwrap()));
+        let advertised: Vec<String> = ports
+            .external_addresses(auto.confirmed().unwrap())
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        assert_eq!(advertised, vec!["/ip4/81.2.69.160/udp/9001/quic-v1", "/ip4/81.2.69.160/tcp/9000"]);
+
+        // An explicit IP is only confirmed by matching observations
+        let mut explicit = ExternalAddressConfirmer::new(ExternalIp::Explicit("192.168.1.10".parse().unwrap()), ports);
+        assert_eq!(explicit.observe(peer(), &observed), None);
+        assert_eq!(explicit.observe(peer(), &observed), None);
+        assert_eq!(explicit.observe(peer(), &private), None);
+        assert_eq!(explicit.observe(peer(), &private), Some("192.168.1.10".parse().unwrap()));
+
+        let mut disabled = ExternalAddressConfirmer::new(ExternalIp::Disabled, ports);
+        assert_eq!(disabled.observe(peer(), &observed), None);
+        assert_eq!(disabled.observe(peer(), &observed), None);
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::gossip;
+
+/// SSZ size of `AttestationData`
+pub const ATTESTATION_DATA_SIZE: usize = 128;
+/// BLS signature size
+pub const SIGNATURE_SIZE: usize = 96;
+/// Fixed part of a phase0 `Attestation`: bitlist offset, data, signature
+pub const PHASE0_FIXED_SIZE: usize = 4 + ATTESTATION_DATA_SIZE + SIGNATURE_SIZE;
+/// Fixed part of an Electra `Attestation`: phase0 fields plus `committee_bits`
+pub const ELECTRA_FIXED_SIZE: usize = PHASE0_FIXED_SIZE + COMMITTEE_BITS_SIZE;
+/// SSZ size of an Electra `SingleAttestation`
+pub const SINGLE_ATTESTATION_SIZE: usize = 8 + 8 + ATTESTATION_DATA_SIZE + SIGNATURE_SIZE;
+/// `Bitvector[MAX_COMMITTEES_PER_SLOT]` size
+const COMMITTEE_BITS_SIZE: usize = 8;
+/// `MAX_VALIDATORS_PER_COMMITTEE * MAX_COMMITTEES_PER_SLOT` bits, the largest Electra bitlist
+const MAX_AGGREGATION_BITS: usize = 2048 * 64;
+
+/// Which container a gossip attestation was encoded as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttestationFormat {
+    /// Pre-Electra `Attestation`
+    Phase0,
+    /// Electra `Attestation` with `committee_bits`
+    Electra,
+    /// Electra `SingleAttestation` carrying the attester index
+    Single,
+}
+
+/// FFG checkpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub epoch: u64,
+    pub root: [u8; 32],
+}
+
+/// `AttestationData` shared by all attestation containers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttestationData {
+    pub slot: u64,
+    /// Committee index pre-Electra, always zero from Electra on
+    pub index: u64,
+    pub beacon_block_root: [u8; 32],
+    pub source: Checkpoint,
+    pub target: Checkpoint,
+}
+
+/// An attestation decoded from a `beacon_attestation_{subnet}` gossip message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedAttestation {
+    pub format: AttestationFormat,
+    pub data: AttestationData,
+    /// Committee the attestation is for, wherever the container keeps it
+    pub committee_index: u64,
+    /// Participation bits; empty for `SingleAttestation`
+    pub aggregation_bits: Vec<bool>,
+    /// Only known for `SingleAttestation`
+    pub attester_index: Option<u64>,
+    pub signature: [u8; SIGNATURE_SIZE],
+}
+
+impl DecodedAttestation {
+    /// Snappy-decompress and SSZ-decode a gossip payload
+    pub fn from_gossip(data: &[u8]) -> Result<Self> {
+        let ssz = gossip::decompress_snappy(data).ok_or_else(|| anyhow!("Payload is not valid snappy"))?;
+        Self::from_ssz(&ssz)
+    }
+
+    /// Decode any of the attestation containers, told apart by their fixed layout
+    pub fn from_ssz(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < 4 {
+            bail!("Attestation too short: {} bytes", bytes.len());
+        }
+        // Attestation starts with the aggregation_bits offset, SingleAttestation with a
+        // committee index (< 64), so the first four bytes say which one we have
+        let offset = u32::from_le_bytes(bytes[0..4].try_into().expect("length checked")) as usize;
+        match offset {
+            PHASE0_FIXED_SIZE if bytes.len() > PHASE0_FIXED_SIZE => Self::decode_phase0(bytes),
+            ELECTRA_FIXED_SIZE if bytes.len() > ELECTRA_FIXED_SIZE => Self::decode_electra(bytes),
+            _ if bytes.len() == SINGLE_ATTESTATION_SIZE => Self::decode_single(bytes),
+            _ => bail!("Unrecognised attestation layout: {} bytes, offset {}", bytes.len(), offset),
+        }
+    }
+
+    /// Positions in the committee that signed this attestation
+    pub fn attesting_positions(&self) -> Vec<usize> {
+        self.aggregation_bits
+            .iter()
+            .enumerate()
+            .filter_map(|(position, bit)| bit.then_some(position))
+            .collect()
+    }
+
+    /// Whether exactly one validator signed, i.e. it has not been aggregated
+    pub fn is_unaggregated(&self) -> bool {
+        self.attester_index.is_some() || self.attesting_positions().len() == 1
+    }
+
+    fn decode_phase0(bytes: &[u8]) -> Result<Self> {
+        let data = decode_attestation_data(&bytes[4..4 + ATTESTATION_DATA_SIZE]);
+        Ok(Self {
+            format: AttestationFormat::Phase0,
+            committee_index: data.index,
+            data,
+            aggregation_bits: decode_bitlist(&bytes[PHASE0_FIXED_SIZE..])?,
+            attester_index: None,
+            signature: bytes[4 + ATTESTATION_DATA_SIZE..PHASE0_FIXED_SIZE].try_into().expect("length checked"),
+        })
+    }
+
+    fn decode_electra(bytes: &[u8]) -> Result<Self> {
+        let data = decode_attestation_data(&bytes[4..4 + ATTESTATION_DATA_SIZE]);
+        let committee_bits = &bytes[PHASE0_FIXED_SIZE..ELECTRA_FIXED_SIZE];
+        // Gossip attestations are for exactly one committee
+        let committees: Vec<u64> = (0..COMMITTEE_BITS_SIZE as u64 * 8)
+            .filter(|i| committee_bits[*i as usize / 8] & (1 << (i % 8)) != 0)
+            .collect();
+        let [committee_index] = committees[..] else {
+            bail!("Expected one committee bit, found {}", committees.len());
+        };
+        Ok(Self {
+            format: AttestationFormat::Electra,
+            data,
+            committee_index,
+            aggregation_bits: decode_bitlist(&bytes[ELECTRA_FIXED_SIZE..])?,
+            attester_index: None,
+            signature: bytes[4 + ATTESTATION_DATA_SIZE..PHASE0_FIXED_SIZE].try_into().expect("length checked"),
+        })
+    }
+
+    fn decode_single(bytes: &[u8]) -> Result<Self> {
+        Ok(Self {
+            format: AttestationFormat::Single,
+            committee_index: read_u64(bytes, 0),
+            attester_index: Some(read_u64(bytes, 8)),
+            data: decode_attestation_data(&bytes[16..16 + ATTESTATION_DATA_SIZE]),
+            aggregation_bits: Vec::new(),
+            signature: bytes[16 + ATTESTATION_DATA_SIZE..].try_into().expect("length checked"),
+        })
+    }
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().expect("caller checked length"))
+}
+
+fn decode_attestation_data(bytes: &[u8]) -> AttestationData {
+    AttestationData {
+        slot: read_u64(bytes, 0),
+        index: read_u64(bytes, 8),
+        beacon_block_root: bytes[16..48].try_into().expect("caller checked length"),
+        source: Checkpoint {
+            epoch: read_u64(bytes, 48),
+            root: bytes[56..88].try_into().expect("caller checked length"),
+        },
+        target: Checkpoint {
+            epoch: read_u64(bytes, 88),
+            root: bytes[96..128].try_into().expect("caller checked length"),
+        },
+    }
+}
+
+/// Decode an SSZ `Bitlist`, whose highest set bit marks the length
+fn decode_bitlist(bytes: &[u8]) -> Result<Vec<bool>> {
+    let last = *bytes.last().ok_or_else(|| anyhow!("Empty bitlist"))?;
+    if last == 0 {
+        bail!("Bitlist is missing its delimiter bit");
+    }
+    let len = (bytes.len() - 1) * 8 + (7 - last.leading_zeros() as usize);
+    if len > MAX_AGGREGATION_BITS {
+        bail!("Bitlist of {} bits is too long", len);
+    }
+    Ok((0..len).map(|i| bytes[i / 8] & (1 << (i % 8)) != 0).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snap::raw::Encoder;
+
+    fn sample_data() -> Vec<u8> {
+        let mut data = Vec::with_capacity(ATTESTATION_DATA_SIZE);
+        data.extend_from_slice(&9_600_123u64.to_le_bytes()); // slot
+        data.extend_from_slice(&0u64.to_le_bytes()); // index
+        data.extend_from_slice(&[0xaa; 32]); // beacon_block_root
+        data.extend_from_slice(&300_002u64.to_le_bytes());
+        data.extend_from_slice(&[0xbb; 32]);
+        data.extend_from_slice(&300_003u64.to_le_bytes());
+        data.extend_from_slice(&[0xcc; 32]);
+        data
+    }
+
+    #[test]
+    fn test_decode_single_attestation() {
+        let mut ssz = Vec::new();
+        ssz.extend_from_slice(&17u64.to_le_bytes()); // committee_index
+        ssz.extend_from_slice(&123_456u64.to_le_bytes()); // attester_index
+        ssz.extend_from_slice(&sample_data());
+        ssz.extend_from_slice(&[0x11; SIGNATURE_SIZE]);
+        let compressed = Encoder::new().compress_vec(&ssz).unwrap();
+
+        let attestation = DecodedAttestation::from_gossip(&compressed).unwrap();
+        assert_eq!(attestation.format, AttestationFormat::Single);
+        assert_eq!(attestation.committee_index, 17);
+        assert_eq!(attestation.attester_index, Some(123_456));
+        assert_eq!(attestation.data.slot, 9_600_123);
+        assert_eq!(attestation.data.beacon_block_root, [0xaa; 32]);
+        assert_eq!(attestation.data.target.epoch, 300_003);
+        assert_eq!(attestation.signature, [0x11; SIGNATURE_SIZE]);
+        assert!(attestation.is_unaggregated());
+    }
+
+    #[test]
+    fn test_decode_phase0_and_electra_attestations() {
+        // 10 committee members, member 3 signed: bits 0b0000_1000, then delimiter at bit 10
+        let bitlist = [0b0000_1000u8, 0b0000_0100];
+
+        let mut phase0 = (PHASE0_FIXED_SIZE as u32).to_le_bytes().to_vec();
+        let mut data = sample_data();
+        data[8..16].copy_from_slice(&5u64.to_le_bytes());
+        phase0.extend_from_slice(&data);
+        phase0.extend_from_slice(&[0x22; SIGNATURE_SIZE]);
+        phase0.extend_from_slice(&bitlist);
+
+        let attestation = DecodedAttestation::from_ssz(&phase0).unwrap();
+        assert_eq!(attestation.format, AttestationFormat::Phase0);
+        assert_eq!(attestation.committee_index, 5);
+        assert_eq!(attestation.aggregation_bits.len(), 10);
+        assert_eq!(attestation.attesting_positions(), vec![3]);
+        assert_eq!(attestation.signature, [0x22; SIGNATURE_SIZE]);
+
+        let mut electra = (ELECTRA_FIXED_SIZE as u32).to_le_bytes().to_vec();
+        electra.extend_from_slice(&sample_data());
+        electra.extend_from_slice(&[0x33; SIGNATURE_SIZE]);
+        let mut committee_bits = [0u8; COMMITTEE_BITS_SIZE];
+        committee_bits[5] = 0b0000_0010; // committee 41
+        electra.extend_from_slice(&committee_bits);
+        electra.extend_from_slice(&bitlist);
+
+        let attestation = DecodedAttestation::from_ssz(&electra).unwrap();
+        assert_eq!(attestation.format, AttestationFormat::Electra);
+        assert_eq!(attestation.committee_index, 41);
+        assert_eq!(attestation.attesting_positions(), vec![3]);
+        assert!(attestation.is_unaggregated());
+    }
+
+    #[test]
+    fn test_rejects_malformed_attestations() {
+        assert!(DecodedAttestation::from_ssz(&[0u8; 100]).is_err());
+
+        // Bitlist without its delimiter bit
+        let mut phase0 = (PHASE0_FIXED_SIZE as u32).to_le_bytes().to_vec();
+        phase0.extend_from_slice(&sample_data());
+        phase0.extend_from_slice(&[0u8; SIGNATURE_SIZE]);
+        phase0.push(0);
+        assert!(DecodedAttestation::from_ssz(&phase0).is_err());
+
+        assert!(DecodedAttestation::from_gossip(&[0xff; 16]).is_err());
+    }
+}
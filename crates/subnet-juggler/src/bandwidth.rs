@@ -0,0 +1,380 @@
+use futures::{ready, AsyncRead, AsyncWrite};
+use libp2p::core::muxing::{StreamMuxer, StreamMuxerBox, StreamMuxerEvent, SubstreamBox};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Topic label for traffic that is not a gossip payload: control messages, req/resp,
+/// identify and transport framing
+pub const OTHER_TOPIC: &str = "other";
+/// Length of the rolling window behind `last_hour`
+pub const BANDWIDTH_WINDOW: Duration = Duration::from_secs(3600);
+/// Granularity of the rolling window
+const BUCKET_DURATION: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+impl Direction {
+    /// Label value, matching `StealthMetricsCollector::bandwidth_bytes_total`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Inbound => "inbound",
+            Direction::Outbound => "outbound",
+        }
+    }
+}
+
+/// Bytes in each direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ByteCounts {
+    pub inbound: u64,
+    pub outbound: u64,
+}
+
+impl ByteCounts {
+    pub fn add(&mut self, direction: Direction, bytes: u64) {
+        match direction {
+            Direction::Inbound => self.inbound += bytes,
+            Direction::Outbound => self.outbound += bytes,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.inbound + self.outbound
+    }
+
+    fn merge(&mut self, other: &ByteCounts) {
+        self.inbound += other.inbound;
+        self.outbound += other.outbound;
+    }
+}
+
+/// Bandwidth as measured by the network layer
+#[derive(Debug, Clone, Default)]
+pub struct BandwidthReport {
+    /// Everything that crossed the transport since start
+    pub transport: ByteCounts,
+    /// Since start, by topic label (`beacon_attestation_5`, ..., [`OTHER_TOPIC`])
+    pub per_topic: HashMap<String, ByteCounts>,
+    /// Rolling total over the last hour, by topic label
+    pub last_hour: HashMap<String, ByteCounts>,
+}
+
+impl BandwidthReport {
+    /// Bytes used over the last hour across all topics
+    pub fn last_hour_total(&self) -> ByteCounts {
+        let mut total = ByteCounts::default();
+        for counts in self.last_hour.values() {
+            total.merge(counts);
+        }
+        total
+    }
+
+    /// Hourly cost of one attestation subnet
+    pub fn subnet_last_hour(&self, subnet_id: u8) -> ByteCounts {
+        self.last_hour
+            .get(&format!("beacon_attestation_{}", subnet_id))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Per-topic increments since `previous`, for feeding monotonic counters
+    pub fn increments_since(&self, previous: &BandwidthReport) -> Vec<(String, Direction, u64)> {
+        let mut increments = Vec::new();
+        for (topic, counts) in &self.per_topic {
+            let before = previous.per_topic.get(topic).copied().unwrap_or_default();
+            for (direction, now, then) in [
+                (Direction::Inbound, counts.inbound, before.inbound),
+                (Direction::Outbound, counts.outbound, before.outbound),
+            ] {
+                if now > then {
+                    increments.push((topic.clone(), direction, now - then));
+                }
+            }
+        }
+        increments
+    }
+}
+
+/// Bytes carried by the streams of every connection, shared by the muxers that count them
+#[derive(Debug, Clone, Default)]
+pub struct TransportBandwidth {
+    inbound: Arc<AtomicU64>,
+    outbound: Arc<AtomicU64>,
+}
+
+impl TransportBandwidth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wrap a connection's muxer so the bytes of all its streams are counted here
+    pub fn count(&self, muxer: StreamMuxerBox) -> StreamMuxerBox {
+        StreamMuxerBox::new(CountingMuxer {
+            inner: muxer,
+            counters: self.clone(),
+        })
+    }
+
+    /// Bytes across all connections and transports so far
+    pub fn totals(&self) -> ByteCounts {
+        ByteCounts {
+            inbound: self.inbound.load(Ordering::Relaxed),
+            outbound: self.outbound.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct CountingMuxer {
+    inner: StreamMuxerBox,
+    counters: TransportBandwidth,
+}
+
+impl StreamMuxer for CountingMuxer {
+    type Substream = CountingStream;
+    type Error = io::Error;
+
+    fn poll_inbound(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let inner = ready!(Pin::new(&mut this.inner).poll_inbound(cx))?;
+        Poll::Ready(Ok(CountingStream::new(inner, &this.counters)))
+    }
+
+    fn poll_outbound(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Self::Substream, Self::Error>> {
+        let this = self.get_mut();
+        let inner = ready!(Pin::new(&mut this.inner).poll_outbound(cx))?;
+        Poll::Ready(Ok(CountingStream::new(inner, &this.counters)))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<StreamMuxerEvent, Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll(cx)
+    }
+}
+
+/// A stream that adds what is read and written to its connection's counters
+pub struct CountingStream {
+    inner: SubstreamBox,
+    counters: TransportBandwidth,
+}
+
+impl CountingStream {
+    fn new(inner: SubstreamBox, counters: &TransportBandwidth) -> Self {
+        Self {
+            inner,
+            counters: counters.clone(),
+        }
+    }
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let read = ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.counters.inbound.fetch_add(read as u64, Ordering::Relaxed);
+        Poll::Ready(Ok(read))
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        this.counters.outbound.fetch_add(written as u64, Ordering::Relaxed);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+/// Per-topic byte counters with a rolling hourly window
+#[derive(Debug, Default)]
+pub struct BandwidthTracker {
+    source: Option<TransportBandwidth>,
+    per_topic: HashMap<String, ByteCounts>,
+    buckets: VecDeque<(Instant, HashMap<String, ByteCounts>)>,
+    transport: ByteCounts,
+    /// Topic bytes recorded since the transport was last sampled
+    attributed: ByteCounts,
+}
+
+impl BandwidthTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracker that samples the swarm's transport counters
+    pub fn with_transport(source: TransportBandwidth) -> Self {
+        Self {
+            source: Some(source),
+            ..Self::default()
+        }
+    }
+
+    /// Read the transport counters, see [`Self::sample_transport`]
+    pub fn sample(&mut self, now: Instant) {
+        if let Some(totals) = self.source.as_ref().map(TransportBandwidth::totals) {
+            self.sample_transport(totals, now);
+        }
+    }
+
+    /// Count a gossip payload sent, forwarded or received on `topic`
+    pub fn record(&mut self, topic: &str, direction: Direction, bytes: u64, now: Instant) {
+        self.attributed.add(direction, bytes);
+        self.add(topic_label(topic), direction, bytes, now);
+    }
+
+    /// Take new transport totals; whatever the topics do not account for is [`OTHER_TOPIC`]
+    pub fn sample_transport(&mut self, transport: ByteCounts, now: Instant) {
+        let inbound = transport.inbound.saturating_sub(self.transport.inbound);
+        let outbound = transport.outbound.saturating_sub(self.transport.outbound);
+        self.transport = transport;
+
+        let other_inbound = inbound.saturating_sub(self.attributed.inbound);
+        let other_outbound = outbound.saturating_sub(self.attributed.outbound);
+        self.attributed = ByteCounts::default();
+        if other_inbound > 0 {
+            self.add(OTHER_TOPIC.to_string(), Direction::Inbound, other_inbound, now);
+        }
+        if other_outbound > 0 {
+            self.add(OTHER_TOPIC.to_string(), Direction::Outbound, other_outbound, now);
+        }
+    }
+
+    pub fn report(&mut self, now: Instant) -> BandwidthReport {
+        self.expire(now);
+        let mut last_hour: HashMap<String, ByteCounts> = HashMap::new();
+        for (_, bucket) in &self.buckets {
+            for (topic, counts) in bucket {
+                last_hour.entry(topic.clone()).or_default().merge(counts);
+            }
+        }
+        BandwidthReport {
+            transport: self.transport,
+            per_topic: self.per_topic.clone(),
+            last_hour,
+        }
+    }
+
+    fn add(&mut self, topic: String, direction: Direction, bytes: u64, now: Instant) {
+        self.expire(now);
+        let current = matches!(self.buckets.back(), Some((start, _)) if now < *start + BUCKET_DURATION);
+        if !current {
+            self.buckets.push_back((now, HashMap::new()));
+        }
+        let (_, bucket) = self.buckets.back_mut().expect("bucket pushed above");
+        bucket.entry(topic.clone()).or_default().add(direction, bytes);
+        self.per_topic.entry(topic).or_default().add(direction, bytes);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while matches!(self.buckets.front(), Some((start, _)) if *start + BANDWIDTH_WINDOW <= now) {
+            self.buckets.pop_front();
+        }
+    }
+}
+
+/// Short label for a gossip topic: `/eth2/7a7b8b7f/beacon_attestation_5/ssz_snappy`
+/// becomes `beacon_attestation_5`
+pub fn topic_label(topic: &str) -> String {
+    let parts: Vec<&str> = topic.split('/').collect();
+    match parts.as_slice() {
+        ["", "eth2", _, name, _] => name.to_string(),
+        _ => topic.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUBNET_5: &str = "/eth2/7a7b8b7f/beacon_attestation_5/ssz_snappy";
+
+    #[test]
+    fn test_topic_labels() {
+        assert_eq!(topic_label(SUBNET_5), "beacon_attestation_5");
+        assert_eq!(topic_label("/eth2/7a7b8b7f/beacon_block/ssz_snappy"), "beacon_block");
+        assert_eq!(topic_label(OTHER_TOPIC), OTHER_TOPIC);
+    }
+
+    #[test]
+    fn test_unattributed_transport_bytes_count_as_other() {
+        let start = Instant::now();
+        let mut tracker = BandwidthTracker::new();
+        tracker.record(SUBNET_5, Direction::Inbound, 600, start);
+        tracker.record(SUBNET_5, Direction::Outbound, 200, start);
+        tracker.sample_transport(ByteCounts { inbound: 1000, outbound: 150 }, start);
+
+        let report = tracker.report(start);
+        assert_eq!(report.transport, ByteCounts { inbound: 1000, outbound: 150 });
+        assert_eq!(report.per_topic[OTHER_TOPIC], ByteCounts { inbound: 400, outbound: 0 });
+        assert_eq!(report.subnet_last_hour(5), ByteCounts { inbound: 600, outbound: 200 });
+
+        // Only the growth since the last sample is attributed
+        tracker.sample_transport(ByteCounts { inbound: 1100, outbound: 200 }, start);
+        let next = tracker.report(start);
+        assert_eq!(next.per_topic[OTHER_TOPIC], ByteCounts { inbound: 500, outbound: 50 });
+
+        let mut increments = next.increments_since(&report);
+        increments.sort_by_key(|(_, direction, _)| direction.as_str());
+        assert_eq!(
+            increments,
+            vec![
+                (OTHER_TOPIC.to_string(), Direction::Inbound, 100),
+                (OTHER_TOPIC.to_string(), Direction::Outbound, 50),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_streams_are_counted() {
+        use futures::io::{AsyncReadExt, AsyncWriteExt, Cursor};
+
+        let counters = TransportBandwidth::new();
+        let mut stream = CountingStream::new(SubstreamBox::new(Cursor::new(vec![7u8; 16])), &counters);
+        futures::executor::block_on(async {
+            let mut buf = [0u8; 10];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(&[1, 2, 3]).await.unwrap();
+        });
+        assert_eq!(counters.totals(), ByteCounts { inbound: 10, outbound: 3 });
+        // Clones share the counters, as every connection's muxer does
+        assert_eq!(counters.clone().totals(), counters.totals());
+    }
+
+    #[test]
+    fn test_hourly_window_rolls_over() {
+        let start = Instant::now();
+        let mut tracker = BandwidthTracker::new();
+        tracker.record(SUBNET_5, Direction::Inbound, 1000, start);
+        tracker.record(SUBNET_5, Direction::Inbound, 500, start + Duration::from_secs(1800));
+
+        let report = tracker.report(start + Duration::from_secs(1801));
+        assert_eq!(report.last_hour_total().inbound, 1500);
+
+        let report = tracker.report(start + Duration::from_secs(3700));
+        assert_eq!(report.subnet_last_hour(5).inbound, 500);
+        assert_eq!(report.per_topic["beacon_attestation_5"].inbound, 1500);
+
+        let report = tracker.report(start + Duration::from_secs(7200));
+        assert_eq!(report.last_hour_total(), ByteCounts::default());
+    }
+}
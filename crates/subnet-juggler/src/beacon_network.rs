@@ -1,27 +1,82 @@
 use anyhow::Result;
+use futures::future::Either;
+use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use libp2p::{
+    connection_limits::{self, ConnectionLimits},
+    core::{muxing::StreamMuxerBox, upgrade, Transport},
     gossipsub::{self, IdentTopic, MessageId},
     identify,
-    noise,
-    swarm::{NetworkBehaviour, SwarmEvent},
-    tcp, yamux, Multiaddr, PeerId, SwarmBuilder,
+    request_response::{self, OutboundRequestId},
+    swarm::{dial_opts::DialOpts, NetworkBehaviour, SwarmEvent},
+    quic, tcp, yamux, Multiaddr, PeerId, SwarmBuilder,
 };
 use libp2p_identity as identity;
-use sha2::{Digest, Sha256};
-use std::collections::HashMap;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime};
-use stealth_common::{EpochInfo, StealthError, StealthResult, SubnetId};
+use stealth_common::{EpochInfo, NetworkConfig, StealthError, StealthResult, SubnetId};
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, info, warn};
 
+use crate::addresses::{ExternalAddressConfirmer, ExternalIp, ListenPorts};
+use crate::attestation::DecodedAttestation;
+use crate::bandwidth::{BandwidthReport, BandwidthTracker, Direction, TransportBandwidth};
+use crate::connection_manager::{
+    ConnectionManager, ConnectionManagerConfig, ConnectionStats, PeerSnapshot, DEFAULT_MAX_PEERS, DEFAULT_TARGET_PEERS,
+};
+use crate::discovery::{Discovery, DiscoveryConfig, QueryFuture};
+use crate::exposure::{ExposureReport, ExposureTracker};
+use crate::fork::Fork;
+use crate::gossip::{self, MeshStability};
+use crate::node_identity;
+use crate::peer_filter::{PeerFilter, PeerFilterConfig, PeerFilterUpdate};
+use crate::relay_publisher::{self, PublishRequest, PublishResponse, RelayPublisher, RelayPublisherConfig};
+use crate::rpc::{self, MetaData, RpcBehaviour, RpcRequest, RpcResponse, StatusMessage};
+use crate::topics::{CoreTopic, CoverTopics};
+use crate::validation::{self, AttestationValidator, ChainState, Outcome, PubkeyCache, ValidationConfig, ValidationStats};
 use crate::NetworkingProvider;
 
+/// Mesh size below which a newly joined subnet triggers a targeted discovery query
+const TARGET_PEERS_PER_SUBNET: usize = 6;
+/// How often to check whether we need more peers or have too many
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(30);
+/// How often scheduled dials are checked
+const DIAL_INTERVAL: Duration = Duration::from_millis(250);
+/// How often transport byte counters are folded into the per-topic accounting
+const BANDWIDTH_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+/// How often our own first-seen exposure is modelled (one epoch)
+const EXPOSURE_INTERVAL: Duration = Duration::from_secs(384);
+/// Subnets we stay subscribed to regardless of the juggler
+const BACKBONE_SUBNETS: [u8; 2] = [0, 1];
+/// How often we ping connected peers (and learn of their MetaData changes)
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the Status head is refreshed from the beacon API (one epoch)
+const STATUS_REFRESH_INTERVAL: Duration = Duration::from_secs(384);
+/// How long shutdown waits for Goodbye messages to be delivered
+const GOODBYE_GRACE: Duration = Duration::from_secs(2);
+/// How often mesh membership is sampled for stability (one slot)
+const MESH_SAMPLE_INTERVAL: Duration = Duration::from_secs(12);
+/// How long the previous identity stays connected after a rotation (eight slots)
+const IDENTITY_OVERLAP: Duration = Duration::from_secs(96);
+/// Connections per peer, enough for TCP and QUIC side by side
+const MAX_CONNECTIONS_PER_PEER: u32 = 2;
+/// Inbound handshakes in flight at once
+const MAX_PENDING_INBOUND: u32 = 32;
+/// How long to wait for the OS to report the ports it picked
+const LISTEN_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Network behaviour for beacon chain gossipsub
 #[derive(NetworkBehaviour)]
 pub struct BeaconNetworkBehaviour {
+    /// Connection gates go first, so refused connections never reach the protocols
+    pub limits: connection_limits::Behaviour,
+    pub peer_filter: PeerFilter,
     pub gossipsub: gossipsub::Behaviour,
     pub identify: identify::Behaviour,
+    pub rpc: RpcBehaviour,
 }
 
 /// Command sent to the beacon network manager
@@ -44,19 +99,77 @@ pub enum NetworkCommand {
     GetGossipMetrics {
         response: oneshot::Sender<GossipMetrics>,
     },
+    GetBandwidth {
+        response: oneshot::Sender<BandwidthReport>,
+    },
+    /// Latest estimate of how attributable our attestations are to our peer ID
+    GetExposure {
+        response: oneshot::Sender<ExposureReport>,
+    },
+    /// Snappy-compress an SSZ attestation and publish it on its subnet topic.
+    /// On subnets we are subscribed to it goes to our mesh. With `fanout` we may
+    /// also publish to other subnets, where each publish goes to a fresh random
+    /// set of `D` topic peers.
+    Publish {
+        subnet_id: SubnetId,
+        ssz_data: Vec<u8>,
+        fanout: bool,
+        response: oneshot::Sender<Result<MessageId>>,
+    },
+    /// Publish an attestation a relay client handed our relay node, once it
+    /// passes the checks gossip gets
+    PublishForFriend {
+        request: PublishRequest,
+        response: oneshot::Sender<PublishResponse>,
+    },
+    /// Whether gossip already delivered an attestation by the same attester for
+    /// the same target epoch; only subnets we are subscribed to are seen
+    SeenOnGossip {
+        ssz_data: Vec<u8>,
+        response: oneshot::Sender<bool>,
+    },
+    /// Replace the head we advertise in `Status`
+    UpdateStatus(StatusMessage),
+    /// Follow a fork or a change in committees per slot when validating attestations
+    UpdateChain(ChainState),
+    /// Change the allow or deny lists or a peer's suspicion
+    UpdatePeerFilter(PeerFilterUpdate),
+    /// Sent before a subnet reshuffle; switches to a fresh node identity when
+    /// rotation is due, carrying over only the `retained` subnets
+    PrepareReshuffle {
+        epoch: u64,
+        retained: Vec<SubnetId>,
+        response: oneshot::Sender<Result<()>>,
+    },
     Shutdown,
 }
 
 /// Metrics about gossip citizenship
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct GossipMetrics {
+    /// Mean gossipsub score of connected peers
     pub average_peer_score: f64,
+    /// Lowest gossipsub score among connected peers
+    pub lowest_peer_score: f64,
+    /// Peers in any of our topic meshes
+    pub mesh_peers: usize,
+    /// Share of mesh peers that keep us grafted between samples, our standing as seen by others
+    pub mesh_stability: f64,
     pub connected_peers: usize,
     pub active_subnets: usize,
     pub messages_published: u64,
     pub messages_received: u64,
+    /// Transport-level totals, including control traffic and req/resp
     pub bytes_sent: u64,
     pub bytes_received: u64,
+    /// Per-topic and rolling hourly byte counts
+    pub bandwidth: BandwidthReport,
+    /// Peer targets, pending dials and pruning
+    pub connections: ConnectionStats,
+    /// Gossip attestation validation results
+    pub validation: ValidationStats,
+    /// Our modelled first-seen exposure as of the last estimate
+    pub exposure: ExposureReport,
 }
 
 /// Events from the beacon network
@@ -65,12 +178,75 @@ pub enum NetworkEvent {
     AttestationReceived {
         subnet_id: u8,
         peer_id: PeerId,
+        attestation: Box<DecodedAttestation>,
+        /// Snappy-compressed payload as received, for forwarding
         message_data: Vec<u8>,
     },
     PeerConnected(PeerId),
     PeerDisconnected(PeerId),
 }
 
+/// Our side of the req/resp protocols and what we know of each peer
+struct RpcState {
+    status: StatusMessage,
+    metadata: MetaData,
+    /// Last MetaData `seq_number` seen from each peer
+    peer_seq_numbers: HashMap<PeerId, u64>,
+    /// Outbound Goodbyes, the peer is disconnected once each completes
+    goodbyes: HashMap<OutboundRequestId, PeerId>,
+}
+
+/// Per-identity bookkeeping the network loop drives alongside the swarm
+struct NetworkServices {
+    discovery: Option<Discovery>,
+    connections: ConnectionManager,
+    addresses: ExternalAddressConfirmer,
+    bandwidth: BandwidthTracker,
+    /// Hex digest of the fork our topics are on
+    fork_digest: String,
+    cover_topics: CoverTopics,
+    validator: AttestationValidator,
+    exposure: ExposureTracker,
+}
+
+/// What is needed to bring up a node identity, kept current for the next rotation
+struct IdentityRotation {
+    every_epochs: Option<u64>,
+    key_path: PathBuf,
+    bootstrap_peers: Vec<String>,
+    network_config: NetworkConfig,
+    /// Latest head, so the new identity's Status is current
+    status: StatusMessage,
+    /// Lists and suspicion including runtime updates
+    peer_filter: PeerFilterConfig,
+    /// Chain parameters for attestation validation, including the latest fork
+    validation: ValidationConfig,
+    /// Fork at startup, whose digest every identity's topics, Status and ENR carry
+    fork: Fork,
+    fork_digest: [u8; 4],
+    event_tx: mpsc::UnboundedSender<NetworkEvent>,
+}
+
+impl IdentityRotation {
+    /// Start a swarm under a fresh key, join the retained subnets and persist the key
+    async fn rotate(&self, retained: &[SubnetId]) -> Result<mpsc::UnboundedSender<NetworkCommand>> {
+        let key = identity::Keypair::generate_secp256k1();
+        // The current identity still holds the configured ports during the overlap
+        let instance = BeaconNetworkProvider::spawn_instance(key.clone(), ListenPorts::ephemeral(), self).await?;
+
+        for subnet_id in retained {
+            let (tx, rx) = oneshot::channel();
+            instance
+                .send(NetworkCommand::Subscribe { subnet_id: *subnet_id, response: tx })
+                .map_err(|_| anyhow::anyhow!("New identity stopped before subscribing"))?;
+            rx.await.map_err(|_| anyhow::anyhow!("Response channel closed"))??;
+        }
+
+        node_identity::write_keypair(&self.key_path, &key)?;
+        Ok(instance)
+    }
+}
+
 /// Beacon chain network provider using real libp2p gossipsub
 pub struct BeaconNetworkProvider {
     command_tx: mpsc::UnboundedSender<NetworkCommand>,
@@ -80,171 +256,1066 @@ pub struct BeaconNetworkProvider {
 
 impl BeaconNetworkProvider {
     /// Create a new beacon network provider
-    pub async fn new(bootstrap_peers: Vec<String>) -> Result<Self> {
+    pub async fn new(bootstrap_peers: Vec<String>, network_config: &NetworkConfig) -> Result<Self> {
         info!("🌐 Initializing beacon chain libp2p network");
         
         // Create channels for communication
-        let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
         let (event_tx, event_rx) = mpsc::unbounded_channel();
         
-        // Set up libp2p identity
-        let local_key = identity::Keypair::generate_ed25519();
+        // Eth2 peers expect a stable secp256k1 identity
+        let key_path = PathBuf::from(
+            network_config
+                .node_key_path
+                .as_deref()
+                .unwrap_or(node_identity::DEFAULT_NODE_KEY_PATH),
+        );
+        let local_key = node_identity::load_or_create_keypair(&key_path)?;
+
+        // Topics, Status and the ENR carry the digest of the active fork
+        let validation = Self::validation_config(network_config).await?;
+        let fork_digest = validation.fork_digest();
+        info!("🍴 On fork {:?} with digest {}", validation.fork, hex::encode(fork_digest));
+
+        // Status starts from the configured head and follows the beacon API if there is one
+        let status = Self::configured_status(network_config, fork_digest);
+        if let Some(url) = network_config.beacon_api_url.clone() {
+            let command_tx = command_tx.clone();
+            tokio::spawn(Self::run_status_refresh(url, fork_digest, command_tx));
+        }
+
+        let rotation = IdentityRotation {
+            every_epochs: network_config.identity_rotation_epochs,
+            key_path,
+            bootstrap_peers,
+            network_config: network_config.clone(),
+            status,
+            peer_filter: PeerFilterConfig::from_config(network_config)?,
+            fork: validation.fork,
+            fork_digest,
+            validation,
+            event_tx,
+        };
+        let active = Self::spawn_instance(local_key, ListenPorts::from_config(network_config), &rotation).await?;
+        if let Some(every) = rotation.every_epochs {
+            info!("🪪 Node identity rotates every {} epochs", every);
+        }
+        // Friends' relay traffic runs on a separate friend-only swarm under its own identity
+        let (relay_node, publish_via_relays) = match RelayPublisherConfig::from_config(network_config)? {
+            Some(config) => {
+                if !config.relays.is_empty() {
+                    info!("🔁 Publishing our attestations through {} friend relays", config.relays.len());
+                }
+                if !config.clients.is_empty() {
+                    info!("🔁 Publishing attestations for {} relay clients", config.clients.len());
+                }
+                let key_path = network_config
+                    .relay_node_key_path
+                    .as_deref()
+                    .unwrap_or(relay_publisher::DEFAULT_RELAY_NODE_KEY_PATH);
+                let local_key = node_identity::load_or_create_keypair(Path::new(key_path))?;
+                let publish_via_relays = !config.relays.is_empty();
+                let relay_node = RelayPublisher::new(local_key, config, command_tx.clone())?.spawn();
+                (Some(relay_node), publish_via_relays)
+            }
+            None => (None, false),
+        };
+        tokio::spawn(Self::run_command_router(command_rx, active, relay_node, publish_via_relays, rotation));
+
+        Ok(Self {
+            command_tx,
+            event_rx,
+            peer_count: 0,
+        })
+    }
+
+    /// Build a swarm for one node identity from the `shared` settings and spawn its event loop
+    async fn spawn_instance(
+        local_key: identity::Keypair,
+        ports: ListenPorts,
+        shared: &IdentityRotation,
+    ) -> Result<mpsc::UnboundedSender<NetworkCommand>> {
+        let IdentityRotation { bootstrap_peers, network_config, status, fork, event_tx, .. } = shared;
+        let (status, event_tx) = (*status, event_tx.clone());
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
         let local_peer_id = PeerId::from(local_key.public());
         info!("Local peer id: {local_peer_id}");
 
-        // Configure gossipsub for Ethereum beacon chain
-        let gossipsub_config = gossipsub::ConfigBuilder::default()
-            .heartbeat_interval(Duration::from_secs(1))
-            .validation_mode(gossipsub::ValidationMode::Permissive)
-            .message_id_fn(|message| {
-                MessageId::from(&Sha256::digest(&message.data)[..20])
-            })
-            .build()
-            .expect("Valid gossipsub config");
-
-        // Create gossipsub behaviour
-        let mut gossipsub = gossipsub::Behaviour::new(
-            gossipsub::MessageAuthenticity::Signed(local_key.clone()),
-            gossipsub_config,
-        )
-        .expect("Correct configuration");
+        // Anonymous gossipsub with spec parameters and message ids
+        let mut gossipsub = gossip::build_gossipsub()?;
 
         // Subscribe to backbone subnets (0 and 1 for demo)
-        let fork_digest = "7a7b8b7f"; // Ethereum mainnet fork digest
-        for subnet_id in 0..2 {
-            let topic = IdentTopic::new(format!(
-                "/eth2/{}/beacon_attestation_{}/ssz_snappy",
-                fork_digest, subnet_id
-            ));
-            gossipsub.subscribe(&topic)?;
+        let fork_digest = hex::encode(shared.fork_digest);
+        for subnet_id in BACKBONE_SUBNETS {
+            let topic = attestation_topic(&fork_digest, subnet_id);
+            gossip::subscribe_attestation_topic(&mut gossipsub, &topic)?;
             info!("✅ Subscribed to attestation subnet {}", subnet_id);
         }
 
+        // Core topics make our subscriptions look like a regular beacon node's
+        let cover_topics = CoverTopics::from_config(network_config.cover_topics.as_deref())?;
+        for core in cover_topics.topics(*fork) {
+            gossipsub.subscribe(&core.topic(&fork_digest))?;
+        }
+        if cover_topics != CoverTopics::Disabled {
+            info!("✅ Subscribed to {} core topics ({:?})", cover_topics.topics(*fork).len(), cover_topics);
+        }
+
         // Create identify behaviour
         let identify = identify::Behaviour::new(
             identify::Config::new("/eth2/1.0.0".into(), local_key.public())
         );
 
         // Build network behaviour
+        let limits = ConnectionLimits::default()
+            .with_max_established_per_peer(Some(MAX_CONNECTIONS_PER_PEER))
+            .with_max_pending_incoming(Some(MAX_PENDING_INBOUND));
         let behaviour = BeaconNetworkBehaviour {
+            limits: connection_limits::Behaviour::new(limits),
+            peer_filter: PeerFilter::new(shared.peer_filter.clone()),
             gossipsub,
             identify,
+            rpc: RpcBehaviour::new(),
         };
 
-        // Create swarm
-        let mut swarm = SwarmBuilder::with_existing_identity(local_key)
+        // Create swarm with QUIC next to noise-secured TCP, counting every byte either carries
+        let transport_bandwidth = TransportBandwidth::new();
+        let counter = transport_bandwidth.clone();
+        let mut swarm = SwarmBuilder::with_existing_identity(local_key.clone())
             .with_tokio()
-            .with_tcp(
-                tcp::Config::default(),
-                (libp2p::tls::Config::new, libp2p::noise::Config::new),
-                yamux::Config::default,
-            )?
+            .with_other_transport(|key| -> Result<_, Box<dyn std::error::Error + Send + Sync>> {
+                let tcp = tcp::tokio::Transport::new(tcp::Config::default())
+                    .upgrade(upgrade::Version::V1Lazy)
+                    .authenticate(libp2p::noise::Config::new(key)?)
+                    .multiplex(yamux::Config::default());
+                let quic = quic::tokio::Transport::new(quic::Config::new(key));
+                Ok(tcp.or_transport(quic).map(move |output, _| {
+                    let (peer_id, muxer) = match output {
+                        Either::Left((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+                        Either::Right((peer_id, muxer)) => (peer_id, StreamMuxerBox::new(muxer)),
+                    };
+                    (peer_id, counter.count(muxer))
+                }))
+            })?
             .with_behaviour(|_key| behaviour)?
             .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
             .build();
 
-        // Listen on local port
-        swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
-
-        // Connect to bootstrap peers with backoff and jitter for good network citizenship
-        let validated_peers = Self::get_validated_bootstrap_peers(&bootstrap_peers);
-        for (i, peer_addr) in validated_peers.iter().enumerate() {
-            match peer_addr.parse::<Multiaddr>() {
-                Ok(addr) => {
-                    // Add jitter to avoid coordinated connection storms
-                    let jitter_ms = (i * 100) + ((i * 73) % 500); // Deterministic jitter
-                    
-                    info!("🔗 Dialing bootstrap peer: {} (delay: {}ms)", addr, jitter_ms);
-                    let swarm_ref = &mut swarm;
-                    let addr_clone = addr.clone();
-                    tokio::spawn(async move {
-                        tokio::time::sleep(Duration::from_millis(jitter_ms as u64)).await;
-                        // Note: We can't easily dial here due to ownership, so we dial immediately
-                        // In production, this would use a more sophisticated connection manager
-                    });
-                    
-                    if let Err(e) = swarm.dial(addr) {
-                        warn!("Failed to dial {}: {}", peer_addr, e);
-                    }
-                }
-                Err(e) => {
-                    warn!("Invalid bootstrap peer address {}: {}", peer_addr, e);
-                }
+        let ports = Self::listen(&mut swarm, ports).await?;
+
+        // Start discv5 so we can find peers beyond the static bootstrap list
+        let discovery = Self::start_discovery(&local_key, network_config, shared.fork_digest, ports).await;
+        let backbone: HashSet<u8> = BACKBONE_SUBNETS.into_iter().collect();
+        if let Some(discovery) = &discovery {
+            if let Err(e) = discovery.update_attnets(&backbone) {
+                warn!("Failed to advertise backbone subnets in ENR: {}", e);
+            }
+        }
+        let validator = AttestationValidator::new(shared.validation.clone())?;
+        let external_ip = ExternalIp::from_config(network_config.external_ip.as_deref())?;
+        let addresses = ExternalAddressConfirmer::new(external_ip, ports);
+
+        // Bootstrap peers are dialled with jitter by the connection manager and kept connected
+        let mut connections = ConnectionManager::new(ConnectionManagerConfig {
+            target_peers: network_config.target_peers.unwrap_or(DEFAULT_TARGET_PEERS),
+            max_peers: network_config.max_peers.unwrap_or(DEFAULT_MAX_PEERS),
+            min_peers_per_subnet: TARGET_PEERS_PER_SUBNET,
+            ..Default::default()
+        });
+        for peer_addr in Self::get_validated_bootstrap_peers(bootstrap_peers) {
+            let result = peer_addr
+                .parse::<Multiaddr>()
+                .map_err(anyhow::Error::from)
+                .and_then(|addr| connections.add_persistent_peer(addr));
+            match result {
+                Ok(peer_id) => debug!("🔗 Scheduled bootstrap peer {}", peer_id),
+                Err(e) => warn!("Invalid bootstrap peer address {}: {}", peer_addr, e),
             }
         }
 
         // Spawn the network event loop
-        let command_tx_clone = command_tx.clone();
         tokio::spawn(async move {
-            Self::run_network_loop(swarm, command_rx, event_tx, fork_digest.to_string()).await;
+            let services = NetworkServices {
+                discovery,
+                connections,
+                addresses,
+                bandwidth: BandwidthTracker::with_transport(transport_bandwidth),
+                fork_digest,
+                cover_topics,
+                validator,
+                exposure: ExposureTracker::new(),
+            };
+            Self::run_network_loop(swarm, services, command_rx, event_tx, status).await;
         });
 
-        Ok(Self {
-            command_tx: command_tx_clone,
-            event_rx,
-            peer_count: 0,
+        Ok(command_tx)
+    }
+
+    /// Route commands to the active identity; during a rotation overlap the retiring
+    /// identity still follows unsubscribes and status updates until it says Goodbye
+    async fn run_command_router(
+        mut command_rx: mpsc::UnboundedReceiver<NetworkCommand>,
+        mut active: mpsc::UnboundedSender<NetworkCommand>,
+        relay_node: Option<mpsc::UnboundedSender<NetworkCommand>>,
+        publish_via_relays: bool,
+        mut rotation: IdentityRotation,
+    ) {
+        let mut retiring: Option<mpsc::UnboundedSender<NetworkCommand>> = None;
+        let overlap = tokio::time::sleep(Duration::ZERO);
+        tokio::pin!(overlap);
+
+        loop {
+            tokio::select! {
+                _ = &mut overlap, if retiring.is_some() => {
+                    if let Some(old) = retiring.take() {
+                        info!("🪪 Retiring previous node identity");
+                        let _ = old.send(NetworkCommand::Shutdown);
+                    }
+                }
+
+                command = command_rx.recv() => {
+                    let Some(command) = command else {
+                        break;
+                    };
+                    match command {
+                        NetworkCommand::Unsubscribe { subnet_id, response } => {
+                            if let Some(old) = &retiring {
+                                let (tx, _rx) = oneshot::channel();
+                                let _ = old.send(NetworkCommand::Unsubscribe { subnet_id, response: tx });
+                            }
+                            let _ = active.send(NetworkCommand::Unsubscribe { subnet_id, response });
+                        }
+                        NetworkCommand::UpdateStatus(status) => {
+                            rotation.status = status;
+                            if let Some(old) = &retiring {
+                                let _ = old.send(NetworkCommand::UpdateStatus(status));
+                            }
+                            let _ = active.send(NetworkCommand::UpdateStatus(status));
+                        }
+                        NetworkCommand::UpdateChain(chain) => {
+                            if chain.fork != rotation.fork {
+                                warn!("🍴 Chain moved to fork {:?}, restart to follow its gossip topics", chain.fork);
+                            }
+                            rotation.validation.fork_version = chain.fork_version;
+                            rotation.validation.fork = chain.fork;
+                            rotation.validation.committees_per_slot = chain.committees_per_slot;
+                            if let Some(old) = &retiring {
+                                let _ = old.send(NetworkCommand::UpdateChain(chain));
+                            }
+                            let _ = active.send(NetworkCommand::UpdateChain(chain));
+                        }
+                        NetworkCommand::UpdatePeerFilter(update) => {
+                            rotation.peer_filter.apply(update.clone());
+                            if let Some(old) = &retiring {
+                                let _ = old.send(NetworkCommand::UpdatePeerFilter(update.clone()));
+                            }
+                            let _ = active.send(NetworkCommand::UpdatePeerFilter(update));
+                        }
+                        NetworkCommand::PrepareReshuffle { epoch, retained, response } => {
+                            let result = if retiring.is_none()
+                                && node_identity::rotation_due(epoch, rotation.every_epochs)
+                            {
+                                rotation.rotate(&retained).await.map(|new_active| {
+                                    retiring = Some(std::mem::replace(&mut active, new_active));
+                                    overlap.as_mut().reset(tokio::time::Instant::now() + IDENTITY_OVERLAP);
+                                    info!("🪪 Rotated node identity at epoch {}", epoch);
+                                })
+                            } else {
+                                Ok(())
+                            };
+                            let _ = response.send(result);
+                        }
+                        command @ NetworkCommand::Publish { .. } => match &relay_node {
+                            Some(relay_node) if publish_via_relays => {
+                                let _ = relay_node.send(command);
+                            }
+                            _ => {
+                                let _ = active.send(command);
+                            }
+                        },
+                        NetworkCommand::Shutdown => {
+                            if let Some(relay_node) = &relay_node {
+                                let _ = relay_node.send(NetworkCommand::Shutdown);
+                            }
+                            if let Some(old) = retiring.take() {
+                                let _ = old.send(NetworkCommand::Shutdown);
+                            }
+                            let _ = active.send(NetworkCommand::Shutdown);
+                            break;
+                        }
+                        command => {
+                            let _ = active.send(command);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Listen dual-stack on `ports`; IPv6 may be unavailable on the host. Ports of
+    /// 0 are picked by the OS on IPv4, read back and reused for IPv6.
+    async fn listen(swarm: &mut libp2p::Swarm<BeaconNetworkBehaviour>, mut ports: ListenPorts) -> Result<ListenPorts> {
+        let mut listening = 0;
+        for ip in [IpAddr::V4(Ipv4Addr::UNSPECIFIED), IpAddr::V6(Ipv6Addr::UNSPECIFIED)] {
+            for address in ports.listen_addresses_on(ip) {
+                match swarm.listen_on(address.clone()) {
+                    Ok(_) => listening += 1,
+                    Err(e) => warn!("Failed to listen on {}: {}", address, e),
+                }
+            }
+            let deadline = tokio::time::Instant::now() + LISTEN_TIMEOUT;
+            while ports.unbound() && listening > 0 {
+                match tokio::time::timeout_at(deadline, swarm.select_next_some()).await {
+                    Ok(SwarmEvent::NewListenAddr { address, .. }) => {
+                        info!("👂 Listening on {address}");
+                        ports = ports.bound(&address);
+                    }
+                    Ok(SwarmEvent::ListenerError { error, .. }) => warn!("Listener failed: {}", error),
+                    Ok(_) => {}
+                    Err(_) => return Err(anyhow::anyhow!("No listen address within {:?}", LISTEN_TIMEOUT)),
+                }
+            }
+        }
+        if listening == 0 {
+            return Err(anyhow::anyhow!("Could not listen on any address"));
+        }
+        Ok(ports)
+    }
+
+    /// Start discv5 on the discovery port, or run without it if that fails. The ENR
+    /// gets an address only once peers have confirmed our external IP.
+    async fn start_discovery(
+        local_key: &identity::Keypair,
+        network_config: &NetworkConfig,
+        fork_digest: [u8; 4],
+        ports: ListenPorts,
+    ) -> Option<Discovery> {
+        let config = DiscoveryConfig {
+            listen_port: ports.discovery,
+            enr_address: None,
+            enr_tcp_port: Some(ports.tcp),
+            enr_quic_port: Some(ports.quic),
+            boot_enrs: network_config.boot_enrs.clone().unwrap_or_default(),
+            fork_digest,
+            peers_per_subnet_query: TARGET_PEERS_PER_SUBNET,
+        };
+
+        match Discovery::new(local_key, config).await {
+            Ok(discovery) => Some(discovery),
+            Err(e) => {
+                warn!("🔭 discv5 unavailable, relying on static bootstrap peers: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Add a peer-confirmed external IP to the swarm and ENR
+    fn advertise_external_ip(
+        swarm: &mut libp2p::Swarm<BeaconNetworkBehaviour>,
+        discovery: Option<&Discovery>,
+        ports: ListenPorts,
+        ip: IpAddr,
+    ) {
+        info!("🌍 External IP {} confirmed by peers", ip);
+        for address in ports.external_addresses(ip) {
+            swarm.add_external_address(address);
+        }
+        if let Some(discovery) = discovery {
+            if let Err(e) = discovery.set_external_address(ip, ports.tcp, ports.quic, ports.discovery) {
+                warn!("Failed to advertise external IP in ENR: {}", e);
+            }
+        }
+    }
+
+    /// Dial peers returned by a discovery query that the connection manager lets us dial
+    fn dial_discovered_peers(
+        swarm: &mut libp2p::Swarm<BeaconNetworkBehaviour>,
+        connections: &ConnectionManager,
+        subnet_id: Option<u8>,
+        peers: Vec<crate::discovery::DiscoveredPeer>,
+    ) {
+        for peer in peers {
+            if swarm.is_connected(&peer.peer_id) || !connections.can_dial(&peer.peer_id) {
+                continue;
+            }
+            match subnet_id {
+                Some(subnet) => debug!("🔭 Dialing peer {} for subnet {}", peer.peer_id, subnet),
+                None => debug!("🔭 Dialing discovered peer {}", peer.peer_id),
+            }
+            let opts = DialOpts::peer_id(peer.peer_id).addresses(peer.addresses).build();
+            if let Err(e) = swarm.dial(opts) {
+                debug!("Failed to dial discovered peer: {}", e);
+            }
+        }
+    }
+
+    /// Chain parameters for gossip attestation validation: from the config, then
+    /// from the beacon API if there is one. Signatures are verified when a pubkey
+    /// cache is configured.
+    async fn validation_config(network_config: &NetworkConfig) -> Result<ValidationConfig> {
+        let pubkeys = match &network_config.validator_pubkeys_path {
+            Some(path) => {
+                let pubkeys = PubkeyCache::load(Path::new(path))?;
+                info!("🔏 Verifying attestation signatures against {} cached pubkeys", pubkeys.len());
+                Some(pubkeys)
+            }
+            None => None,
+        };
+        let defaults = ValidationConfig::default();
+        let mut config = ValidationConfig {
+            genesis_time: network_config.genesis_time.unwrap_or(defaults.genesis_time),
+            committees_per_slot: network_config
+                .active_validators
+                .map(validation::committees_per_slot)
+                .unwrap_or(defaults.committees_per_slot),
+            pubkeys,
+            fork_version: match &network_config.fork_version {
+                Some(version) => parse_fork_digest(version.trim_start_matches("0x"))
+                    .ok_or_else(|| anyhow::anyhow!("Invalid fork_version {}", version))?,
+                None => defaults.fork_version,
+            },
+            fork: defaults.fork,
+            genesis_validators_root: match &network_config.genesis_validators_root {
+                Some(root) => parse_root(root).ok_or_else(|| anyhow::anyhow!("Invalid genesis_validators_root {}", root))?,
+                None => defaults.genesis_validators_root,
+            },
+        };
+        // A configured version names its fork only on mainnet; the beacon API knows the schedule
+        let mut fork = network_config.fork_version.as_ref().map(|_| Fork::from_mainnet_version(config.fork_version));
+
+        if let Some(url) = &network_config.beacon_api_url {
+            let client = reqwest::Client::new();
+            match Self::fetch_genesis(&client, url).await {
+                Ok((genesis_time, root)) => {
+                    if genesis_time != config.genesis_time {
+                        warn!("Beacon API genesis {} differs from the configured {}", genesis_time, config.genesis_time);
+                    }
+                    config.genesis_validators_root = root;
+                }
+                Err(e) => warn!("Failed to fetch genesis from beacon API, using the configured root: {}", e),
+            }
+            let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+            let head_slot = EpochInfo::at(config.genesis_time, now).slot;
+            match Self::fetch_chain_state(&client, url, head_slot).await {
+                Ok(chain) => {
+                    config.fork_version = chain.fork_version;
+                    fork = Some(Ok(chain.fork));
+                    config.committees_per_slot = chain.committees_per_slot;
+                }
+                Err(e) => warn!("Failed to fetch fork and committees from beacon API: {}", e),
+            }
+        }
+        if let Some(fork) = fork {
+            config.fork = fork?;
+        }
+        debug!("🔏 Validating attestations for fork {} with {} committees per slot",
+               hex::encode(config.fork_version), config.committees_per_slot);
+        Ok(config)
+    }
+
+    /// Status from the configured chain head, or a genesis status if none is set
+    fn configured_status(network_config: &NetworkConfig, fork_digest: [u8; 4]) -> StatusMessage {
+        let Some(head) = &network_config.chain_head else {
+            return StatusMessage::genesis(fork_digest);
+        };
+        match (parse_root(&head.finalized_root), parse_root(&head.head_root)) {
+            (Some(finalized_root), Some(head_root)) => StatusMessage {
+                fork_digest,
+                finalized_root,
+                finalized_epoch: head.finalized_epoch,
+                head_root,
+                head_slot: head.head_slot,
+            },
+            _ => {
+                warn!("Invalid chain_head roots in config, advertising genesis status");
+                StatusMessage::genesis(fork_digest)
+            }
+        }
+    }
+
+    /// Poll the beacon API for the head, fork and committee count once per epoch and
+    /// push them into the network loop
+    async fn run_status_refresh(
+        beacon_api_url: String,
+        fork_digest: [u8; 4],
+        command_tx: mpsc::UnboundedSender<NetworkCommand>,
+    ) {
+        let client = reqwest::Client::new();
+        let mut refresh = tokio::time::interval(STATUS_REFRESH_INTERVAL);
+        loop {
+            refresh.tick().await;
+            match Self::fetch_head_status(&client, &beacon_api_url, fork_digest).await {
+                Ok(status) => {
+                    debug!("📋 Status head from beacon API: slot {}", status.head_slot);
+                    if command_tx.send(NetworkCommand::UpdateStatus(status)).is_err() {
+                        break;
+                    }
+                    match Self::fetch_chain_state(&client, &beacon_api_url, status.head_slot).await {
+                        Ok(chain) => {
+                            if command_tx.send(NetworkCommand::UpdateChain(chain)).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => warn!("Failed to fetch fork and committees from beacon API: {}", e),
+                    }
+                }
+                Err(e) => warn!("Failed to fetch head from beacon API: {}", e),
+            }
+        }
+    }
+
+    /// Build a `Status` from the beacon API head header and finality checkpoints
+    async fn fetch_head_status(
+        client: &reqwest::Client,
+        beacon_api_url: &str,
+        fork_digest: [u8; 4],
+    ) -> Result<StatusMessage> {
+        #[derive(Deserialize)]
+        struct HeaderResponse {
+            data: HeaderData,
+        }
+        #[derive(Deserialize)]
+        struct HeaderData {
+            root: String,
+            header: SignedHeader,
+        }
+        #[derive(Deserialize)]
+        struct SignedHeader {
+            message: HeaderMessage,
+        }
+        #[derive(Deserialize)]
+        struct HeaderMessage {
+            slot: String,
+        }
+        #[derive(Deserialize)]
+        struct FinalityResponse {
+            data: FinalityData,
+        }
+        #[derive(Deserialize)]
+        struct FinalityData {
+            finalized: Checkpoint,
+        }
+        #[derive(Deserialize)]
+        struct Checkpoint {
+            epoch: String,
+            root: String,
+        }
+
+        let base = beacon_api_url.trim_end_matches('/');
+        let header: HeaderResponse = client
+            .get(format!("{}/eth/v1/beacon/headers/head", base))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let finality: FinalityResponse = client
+            .get(format!("{}/eth/v1/beacon/states/head/finality_checkpoints", base))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(StatusMessage {
+            fork_digest,
+            finalized_root: parse_root(&finality.data.finalized.root)
+                .ok_or_else(|| anyhow::anyhow!("Invalid finalized root"))?,
+            finalized_epoch: finality.data.finalized.epoch.parse()?,
+            head_root: parse_root(&header.data.root).ok_or_else(|| anyhow::anyhow!("Invalid head root"))?,
+            head_slot: header.data.header.message.slot.parse()?,
+        })
+    }
+
+    /// Genesis time and `genesis_validators_root` from the beacon API
+    async fn fetch_genesis(client: &reqwest::Client, beacon_api_url: &str) -> Result<(u64, [u8; 32])> {
+        #[derive(Deserialize)]
+        struct GenesisResponse {
+            data: GenesisData,
+        }
+        #[derive(Deserialize)]
+        struct GenesisData {
+            genesis_time: String,
+            genesis_validators_root: String,
+        }
+
+        let base = beacon_api_url.trim_end_matches('/');
+        let genesis: GenesisResponse = client
+            .get(format!("{}/eth/v1/beacon/genesis", base))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let root = parse_root(&genesis.data.genesis_validators_root)
+            .ok_or_else(|| anyhow::anyhow!("Invalid genesis_validators_root"))?;
+        Ok((genesis.data.genesis_time.parse()?, root))
+    }
+
+    /// Current fork version and its position in the fork schedule, and the committees
+    /// of `slot`, whose count the beacon node derived from the active validator count
+    async fn fetch_chain_state(client: &reqwest::Client, beacon_api_url: &str, slot: u64) -> Result<ChainState> {
+        #[derive(Deserialize)]
+        struct ForkResponse {
+            data: ForkData,
+        }
+        #[derive(Deserialize)]
+        struct ForkData {
+            current_version: String,
+        }
+        #[derive(Deserialize)]
+        struct ScheduleResponse {
+            data: Vec<ForkData>,
+        }
+        #[derive(Deserialize)]
+        struct CommitteesResponse {
+            data: Vec<serde::de::IgnoredAny>,
+        }
+
+        let base = beacon_api_url.trim_end_matches('/');
+        let fork: ForkResponse = client
+            .get(format!("{}/eth/v1/beacon/states/head/fork", base))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let schedule: ScheduleResponse = client
+            .get(format!("{}/eth/v1/config/fork_schedule", base))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let committees: CommitteesResponse = client
+            .get(format!("{}/eth/v1/beacon/states/head/committees?slot={}", base, slot))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let position = schedule
+            .data
+            .iter()
+            .position(|scheduled| scheduled.current_version == fork.data.current_version)
+            .ok_or_else(|| anyhow::anyhow!("Fork {} is not in the fork schedule", fork.data.current_version))?;
+        let version = fork.data.current_version.trim_start_matches("0x");
+        Ok(ChainState {
+            fork_version: parse_fork_digest(version).ok_or_else(|| anyhow::anyhow!("Invalid fork version"))?,
+            fork: Fork::from_schedule_position(position)?,
+            committees_per_slot: committees.data.len() as u64,
         })
     }
 
+    /// Update MetaData and the ENR after the set of subscribed subnets changed
+    fn advertise_subnets(rpc_state: &mut RpcState, discovery: Option<&Discovery>, subnets: &HashSet<u8>) {
+        if !rpc_state.metadata.set_attnets(subnets) {
+            return;
+        }
+        if let Some(discovery) = discovery {
+            if let Err(e) = discovery.update_attnets(subnets) {
+                warn!("Failed to update ENR attnets: {}", e);
+            }
+        }
+        debug!("📇 MetaData seq {} advertises {} subnets", rpc_state.metadata.seq_number, subnets.len());
+    }
+
+    /// Send a Goodbye; the peer is disconnected once it is delivered
+    fn send_goodbye(swarm: &mut libp2p::Swarm<BeaconNetworkBehaviour>, rpc_state: &mut RpcState, peer_id: PeerId, reason: u64) {
+        debug!("👋 Sending Goodbye ({}) to {}", rpc::goodbye_reason(reason), peer_id);
+        let request_id = swarm.behaviour_mut().rpc.send_request(&peer_id, RpcRequest::Goodbye(reason));
+        rpc_state.goodbyes.insert(request_id, peer_id);
+    }
+
+    /// Drop peers on another fork, they are of no use for attestation gossip
+    fn check_peer_status(
+        swarm: &mut libp2p::Swarm<BeaconNetworkBehaviour>,
+        rpc_state: &mut RpcState,
+        peer_id: PeerId,
+        status: &StatusMessage,
+    ) {
+        if status.fork_digest != rpc_state.status.fork_digest {
+            debug!("🚫 Peer {} is on fork {}", peer_id, hex::encode(status.fork_digest));
+            Self::send_goodbye(swarm, rpc_state, peer_id, rpc::GOODBYE_IRRELEVANT_NETWORK);
+        } else {
+            debug!("📋 Peer {} status: head slot {}, finalized epoch {}", peer_id, status.head_slot, status.finalized_epoch);
+        }
+    }
+
+    /// Request a peer's MetaData if its Ping/Pong sequence number is newer than what we have
+    fn check_peer_seq_number(
+        swarm: &mut libp2p::Swarm<BeaconNetworkBehaviour>,
+        rpc_state: &mut RpcState,
+        peer_id: PeerId,
+        seq_number: u64,
+    ) {
+        let known = rpc_state.peer_seq_numbers.get(&peer_id).copied();
+        if !matches!(known, Some(known) if known >= seq_number) {
+            swarm.behaviour_mut().rpc.send_request(&peer_id, RpcRequest::MetaData);
+        }
+    }
+
+    /// Publish a friend's attestation on our own mesh once it passes the checks
+    /// gossip gets; fanning out is fine since it is not ours
+    fn publish_for_friend(
+        swarm: &mut libp2p::Swarm<BeaconNetworkBehaviour>,
+        validator: &mut AttestationValidator,
+        exposure: &mut ExposureTracker,
+        bandwidth: &mut BandwidthTracker,
+        fork_digest: &str,
+        request: PublishRequest,
+    ) -> PublishResponse {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        match validator.validate(request.subnet_id, &request.data, now) {
+            Outcome::Accept(_) => {}
+            Outcome::Reject(reason) | Outcome::Ignore(reason) => return Err(reason),
+        }
+        let topic = attestation_topic(fork_digest, request.subnet_id);
+        let topic_hash = topic.hash();
+        let len = request.data.len() as u64;
+        let message_id = swarm.behaviour_mut().gossipsub.publish(topic, request.data).map_err(|e| e.to_string())?;
+        // Our mesh if we are in the subnet, otherwise `D` fresh fanout peers
+        let mut recipients: Vec<PeerId> = swarm.behaviour().gossipsub.mesh_peers(&topic_hash).copied().collect();
+        if recipients.is_empty() {
+            recipients = Self::topic_peers(swarm, &topic_hash);
+            recipients.truncate(gossip::MESH_N);
+        }
+        bandwidth.record(topic_hash.as_str(), Direction::Outbound, len * recipients.len() as u64, tokio::time::Instant::now());
+        exposure.record_relay(request.subnet_id, recipients, tokio::time::Instant::now());
+        debug!("📤 Published a friend's attestation on subnet {}", request.subnet_id);
+        Ok(message_id.0)
+    }
+
+    /// Peers subscribed to `topic_hash`, the pool fanout peers are drawn from
+    fn topic_peers(swarm: &libp2p::Swarm<BeaconNetworkBehaviour>, topic_hash: &gossipsub::TopicHash) -> Vec<PeerId> {
+        swarm.behaviour().gossipsub
+            .all_peers()
+            .filter(|(_, topics)| topics.contains(&topic_hash))
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+
+    /// Answer inbound req/resp requests and act on responses to ours
+    fn handle_rpc_event(
+        swarm: &mut libp2p::Swarm<BeaconNetworkBehaviour>,
+        rpc_state: &mut RpcState,
+        event: request_response::Event<RpcRequest, RpcResponse>,
+    ) {
+        match event {
+            request_response::Event::Message { peer, message } => match message {
+                request_response::Message::Request { request, channel, .. } => {
+                    let response = match request {
+                        RpcRequest::Status(status) => {
+                            Self::check_peer_status(swarm, rpc_state, peer, &status);
+                            RpcResponse::Status(rpc_state.status)
+                        }
+                        RpcRequest::Ping(seq_number) => {
+                            Self::check_peer_seq_number(swarm, rpc_state, peer, seq_number);
+                            RpcResponse::Pong(rpc_state.metadata.seq_number)
+                        }
+                        RpcRequest::MetaData => RpcResponse::MetaData(rpc_state.metadata),
+                        RpcRequest::Goodbye(reason) => {
+                            debug!("👋 Peer {} said goodbye: {}", peer, rpc::goodbye_reason(reason));
+                            RpcResponse::Goodbye
+                        }
+                    };
+                    if swarm.behaviour_mut().rpc.send_response(channel, response).is_err() {
+                        debug!("Peer {} closed the stream before our response", peer);
+                    }
+                }
+                request_response::Message::Response { request_id, response } => match response {
+                    RpcResponse::Status(status) => Self::check_peer_status(swarm, rpc_state, peer, &status),
+                    RpcResponse::Pong(seq_number) => Self::check_peer_seq_number(swarm, rpc_state, peer, seq_number),
+                    RpcResponse::MetaData(metadata) => {
+                        debug!("📇 Peer {} MetaData seq {}: subnets {:?}", peer, metadata.seq_number, metadata.subnets());
+                        rpc_state.peer_seq_numbers.insert(peer, metadata.seq_number);
+                    }
+                    RpcResponse::Goodbye => {
+                        if let Some(peer_id) = rpc_state.goodbyes.remove(&request_id) {
+                            let _ = swarm.disconnect_peer_id(peer_id);
+                        }
+                    }
+                },
+            },
+            request_response::Event::OutboundFailure { peer, request_id, error } => {
+                debug!("RPC request to {} failed: {}", peer, error);
+                if rpc_state.goodbyes.remove(&request_id).is_some() {
+                    let _ = swarm.disconnect_peer_id(peer);
+                }
+            }
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                debug!("RPC request from {} failed: {}", peer, error);
+            }
+            request_response::Event::ResponseSent { .. } => {}
+        }
+    }
+
+    /// Connected peers with their score and the subnet meshes they are in
+    fn peer_snapshots(swarm: &libp2p::Swarm<BeaconNetworkBehaviour>) -> Vec<PeerSnapshot> {
+        let gossipsub = &swarm.behaviour().gossipsub;
+        let peer_filter = &swarm.behaviour().peer_filter;
+        let mut mesh_subnets: HashMap<PeerId, Vec<u8>> = HashMap::new();
+        for topic in gossipsub.topics() {
+            if let Some(subnet_id) = Self::parse_subnet_from_topic(topic.as_str()) {
+                for peer_id in gossipsub.mesh_peers(topic) {
+                    mesh_subnets.entry(*peer_id).or_default().push(subnet_id);
+                }
+            }
+        }
+        swarm
+            .connected_peers()
+            .map(|peer_id| PeerSnapshot {
+                peer_id: *peer_id,
+                score: gossipsub.peer_score(peer_id).unwrap_or(0.0),
+                mesh_subnets: mesh_subnets.remove(peer_id).unwrap_or_default(),
+                protected: peer_filter.is_protected(peer_id),
+            })
+            .collect()
+    }
+
+    /// Say Goodbye to every connected peer and wait briefly for delivery
+    async fn say_goodbye_to_all(swarm: &mut libp2p::Swarm<BeaconNetworkBehaviour>, rpc_state: &mut RpcState) {
+        let peers: Vec<PeerId> = swarm.connected_peers().copied().collect();
+        for peer_id in peers {
+            Self::send_goodbye(swarm, rpc_state, peer_id, rpc::GOODBYE_CLIENT_SHUTDOWN);
+        }
+
+        let grace = tokio::time::sleep(GOODBYE_GRACE);
+        tokio::pin!(grace);
+        while !rpc_state.goodbyes.is_empty() {
+            tokio::select! {
+                _ = &mut grace => break,
+                event = swarm.select_next_some() => {
+                    if let SwarmEvent::Behaviour(BeaconNetworkBehaviourEvent::Rpc(event)) = event {
+                        Self::handle_rpc_event(swarm, rpc_state, event.into_inner());
+                    }
+                }
+            }
+        }
+    }
+
     /// Main network event loop
     async fn run_network_loop(
         mut swarm: libp2p::Swarm<BeaconNetworkBehaviour>,
+        services: NetworkServices,
         mut command_rx: mpsc::UnboundedReceiver<NetworkCommand>,
         event_tx: mpsc::UnboundedSender<NetworkEvent>,
-        fork_digest: String,
+        status: StatusMessage,
     ) {
+        let NetworkServices {
+            discovery,
+            mut connections,
+            mut addresses,
+            mut bandwidth,
+            fork_digest,
+            cover_topics,
+            mut validator,
+            mut exposure,
+        } = services;
         let mut subscribed_subnets = HashMap::new();
-        let mut gossip_metrics = GossipMetrics {
-            average_peer_score: 0.0,
-            connected_peers: 0,
-            active_subnets: 0,
-            messages_published: 0,
-            messages_received: 0,
-            bytes_sent: 0,
-            bytes_received: 0,
+        let mut advertised_subnets: HashSet<u8> = BACKBONE_SUBNETS.into_iter().collect();
+        let mut rpc_state = RpcState {
+            status,
+            metadata: MetaData::new(&advertised_subnets),
+            peer_seq_numbers: HashMap::new(),
+            goodbyes: HashMap::new(),
         };
+        let mut gossip_metrics = GossipMetrics::default();
+        let mut mesh_stability = MeshStability::new();
 
         // Conservative limits for good gossip citizenship
         const MAX_CONCURRENT_SUBNETS: usize = 10; // Never overwhelm the network
         const PEER_SCORE_THRESHOLD: f64 = -5.0;   // Drop peers with very bad scores
 
+        let mut discovery_queries: FuturesUnordered<QueryFuture> = FuturesUnordered::new();
+        let mut discovery_timer = tokio::time::interval(DISCOVERY_INTERVAL);
+        let mut ping_timer = tokio::time::interval(PING_INTERVAL);
+        let mut mesh_sample_timer = tokio::time::interval(MESH_SAMPLE_INTERVAL);
+        let mut dial_timer = tokio::time::interval(DIAL_INTERVAL);
+        let mut bandwidth_timer = tokio::time::interval(BANDWIDTH_SAMPLE_INTERVAL);
+        let mut exposure_timer = tokio::time::interval(EXPOSURE_INTERVAL);
+
         loop {
             tokio::select! {
+                _ = bandwidth_timer.tick() => {
+                    bandwidth.sample(tokio::time::Instant::now());
+                }
+
+                // Estimate how often a single observer would pin our attestations on us
+                _ = exposure_timer.tick() => {
+                    gossip_metrics.exposure = exposure.estimate(tokio::time::Instant::now());
+                    if gossip_metrics.exposure.published > 0 {
+                        info!("🕵️ Modelled first-seen exposure: {:.1}% ({} published, {} relayed)",
+                              gossip_metrics.exposure.overall * 100.0,
+                              gossip_metrics.exposure.published,
+                              gossip_metrics.exposure.relayed);
+                    }
+                }
+
+                // Watch whether peers keep us in their meshes while we juggle subnets
+                _ = mesh_sample_timer.tick() => {
+                    mesh_stability.sample(&swarm.behaviour().gossipsub);
+                }
+
+                // Keep connections alive and learn about MetaData changes
+                _ = ping_timer.tick() => {
+                    let peers: Vec<PeerId> = swarm.connected_peers().copied().collect();
+                    for peer_id in peers {
+                        swarm.behaviour_mut().rpc.send_request(&peer_id, RpcRequest::Ping(rpc_state.metadata.seq_number));
+                    }
+                }
+
+                // Start scheduled bootstrap dials and redials once their jitter or backoff elapses
+                _ = dial_timer.tick() => {
+                    for (peer_id, address) in connections.due_dials(tokio::time::Instant::now()) {
+                        debug!("🔗 Dialing persistent peer {} at {}", peer_id, address);
+                        let opts = DialOpts::peer_id(peer_id).addresses(vec![address]).build();
+                        if let Err(e) = swarm.dial(opts) {
+                            debug!("Failed to dial {}: {}", peer_id, e);
+                            connections.on_dial_failure(peer_id);
+                        }
+                    }
+                }
+
+                // Periodically top up our peer set from the DHT, or prune back to the target
+                _ = discovery_timer.tick() => {
+                    if connections.needs_peers() {
+                        if let Some(discovery) = &discovery {
+                            if discovery_queries.is_empty() {
+                                discovery_queries.push(discovery.find_peers());
+                            }
+                        }
+                    } else {
+                        let prunes = connections.select_prunes(&Self::peer_snapshots(&swarm));
+                        if !prunes.is_empty() {
+                            info!("✂️ Pruning {} peers above the target", prunes.len());
+                        }
+                        for peer_id in prunes {
+                            Self::send_goodbye(&mut swarm, &mut rpc_state, peer_id, rpc::GOODBYE_TOO_MANY_PEERS);
+                        }
+                    }
+                }
+
+                // Hand discovery results to the swarm
+                Some((subnet_id, peers)) = discovery_queries.next(), if !discovery_queries.is_empty() => {
+                    Self::dial_discovered_peers(&mut swarm, &connections, subnet_id, peers);
+                }
+
                 // Handle swarm events
                 event = swarm.select_next_some() => {
                     match event {
                         SwarmEvent::Behaviour(event) => match event {
                             BeaconNetworkBehaviourEvent::Gossipsub(gossipsub::Event::Message {
                                 propagation_source: peer_id,
+                                message_id,
                                 message,
-                                ..
                             }) => {
                                 // Update metrics for received messages
                                 gossip_metrics.messages_received += 1;
-                                gossip_metrics.bytes_received += message.data.len() as u64;
-                                
-                                // Parse subnet from topic
-                                if let Some(subnet_id) = Self::parse_subnet_from_topic(&message.topic.as_str()) {
+                                bandwidth.record(message.topic.as_str(), Direction::Inbound, message.data.len() as u64, tokio::time::Instant::now());
+
+                                // Attestations are checked against the spec, core topics follow their policy
+                                let (acceptance, accepted) = match Self::parse_subnet_from_topic(message.topic.as_str()) {
+                                    Some(subnet_id) => {
+                                        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+                                        let outcome = validator.validate(subnet_id, &message.data, now);
+                                        let acceptance = outcome.acceptance();
+                                        match outcome {
+                                            Outcome::Accept(attestation) => (acceptance, Some((subnet_id, attestation))),
+                                            Outcome::Reject(reason) => {
+                                                debug!("🚫 Rejected attestation on subnet {} from {}: {}", subnet_id, peer_id, reason);
+                                                (acceptance, None)
+                                            }
+                                            Outcome::Ignore(reason) => {
+                                                debug!("Ignored attestation on subnet {} from {}: {}", subnet_id, peer_id, reason);
+                                                (acceptance, None)
+                                            }
+                                        }
+                                    }
+                                    None => match CoreTopic::from_topic(message.topic.as_str()) {
+                                        Some(core) => (cover_topics.policy(core).validate(&message.data), None),
+                                        None => (gossipsub::MessageAcceptance::Ignore, None),
+                                    },
+                                };
+                                let forwarding = matches!(acceptance, gossipsub::MessageAcceptance::Accept);
+                                match swarm.behaviour_mut().gossipsub.report_message_validation_result(&message_id, &peer_id, acceptance) {
+                                    // Accepted messages go on to the rest of the mesh, and count against their topic
+                                    Ok(true) if forwarding => {
+                                        let forwarded = swarm.behaviour().gossipsub
+                                            .mesh_peers(&message.topic)
+                                            .filter(|mesh_peer| **mesh_peer != peer_id)
+                                            .count() as u64;
+                                        bandwidth.record(message.topic.as_str(), Direction::Outbound, message.data.len() as u64 * forwarded, tokio::time::Instant::now());
+                                    }
+                                    Ok(_) => {}
+                                    Err(e) => debug!("Failed to report validation of {}: {}", message_id, e),
+                                }
+
+                                if let Some((subnet_id, attestation)) = accepted {
+                                    // Accepting forwards it to the rest of the mesh
+                                    let recipients = swarm.behaviour().gossipsub
+                                        .mesh_peers(&message.topic)
+                                        .filter(|mesh_peer| **mesh_peer != peer_id)
+                                        .copied()
+                                        .collect();
+                                    exposure.record_relay(subnet_id, recipients, tokio::time::Instant::now());
                                     let _ = event_tx.send(NetworkEvent::AttestationReceived {
                                         subnet_id,
                                         peer_id,
+                                        attestation,
                                         message_data: message.data,
                                     });
                                 }
                             }
-                            BeaconNetworkBehaviourEvent::Identify(identify::Event::Received { peer_id, info }) => {
+                            BeaconNetworkBehaviourEvent::Identify(identify::Event::Received { peer_id, info, .. }) => {
                                 debug!("🆔 Identified peer {}: {}", peer_id, info.protocol_version);
+                                if let Some(ip) = addresses.observe(peer_id, &info.observed_addr) {
+                                    Self::advertise_external_ip(&mut swarm, discovery.as_ref(), addresses.ports(), ip);
+                                }
+                            }
+                            BeaconNetworkBehaviourEvent::Rpc(event) => {
+                                Self::handle_rpc_event(&mut swarm, &mut rpc_state, event.into_inner());
                             }
                             _ => {}
                         },
+                        SwarmEvent::OutgoingConnectionError { peer_id: Some(peer_id), error, .. } => {
+                            debug!("Failed to connect to {}: {}", peer_id, error);
+                            connections.on_dial_failure(peer_id);
+                        }
                         SwarmEvent::NewListenAddr { address, .. } => {
                             info!("👂 Listening on {address}");
                         }
-                        SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                        SwarmEvent::ConnectionEstablished { peer_id, endpoint, num_established, .. } => {
                             info!("🤝 Connected to peer: {peer_id}");
+                            if num_established.get() == 1 {
+                                connections.on_connected(peer_id);
+                                let penalty = swarm.behaviour().peer_filter.gossip_penalty(&peer_id);
+                                if penalty < 0.0 {
+                                    swarm.behaviour_mut().gossipsub.set_application_score(&peer_id, penalty);
+                                }
+                                // Turn away inbound peers beyond the maximum
+                                if !endpoint.is_dialer() && connections.over_limit() {
+                                    Self::send_goodbye(&mut swarm, &mut rpc_state, peer_id, rpc::GOODBYE_TOO_MANY_PEERS);
+                                }
+                            }
+                            // The dialing side opens with a Status handshake
+                            if endpoint.is_dialer() && num_established.get() == 1 {
+                                swarm.behaviour_mut().rpc.send_request(&peer_id, RpcRequest::Status(rpc_state.status));
+                            }
                             let _ = event_tx.send(NetworkEvent::PeerConnected(peer_id));
                         }
-                        SwarmEvent::ConnectionClosed { peer_id, .. } => {
+                        SwarmEvent::ConnectionClosed { peer_id, num_established, .. } => {
+                            if num_established == 0 {
+                                rpc_state.peer_seq_numbers.remove(&peer_id);
+                                connections.on_disconnected(peer_id);
+                            }
                             debug!("👋 Disconnected from peer: {peer_id}");
                             let _ = event_tx.send(NetworkEvent::PeerDisconnected(peer_id));
                         }
@@ -266,15 +1337,22 @@ impl BeaconNetworkProvider {
                                 continue;
                             }
                             
-                            let topic = IdentTopic::new(format!(
-                                "/eth2/{}/beacon_attestation_{}/ssz_snappy",
-                                fork_digest, subnet_id.0
-                            ));
+                            let topic = attestation_topic(&fork_digest, subnet_id.0);
                             
-                            let result = swarm.behaviour_mut().gossipsub.subscribe(&topic);
+                            let result = gossip::subscribe_attestation_topic(&mut swarm.behaviour_mut().gossipsub, &topic);
                             if result.is_ok() {
+                                // A fresh subscription is only signalling until we have mesh peers on it
+                                let mesh_peers = swarm.behaviour().gossipsub.mesh_peers(&topic.hash()).count();
+                                if mesh_peers < TARGET_PEERS_PER_SUBNET {
+                                    if let Some(discovery) = &discovery {
+                                        discovery_queries.push(discovery.find_subnet_peers(subnet_id.0));
+                                    }
+                                }
+
                                 subscribed_subnets.insert(subnet_id.0, topic);
                                 gossip_metrics.active_subnets = subscribed_subnets.len();
+                                advertised_subnets.insert(subnet_id.0);
+                                Self::advertise_subnets(&mut rpc_state, discovery.as_ref(), &advertised_subnets);
                                 info!("✅ Subscribed to attestation subnet {} ({}/{})", 
                                       subnet_id.0, subscribed_subnets.len(), MAX_CONCURRENT_SUBNETS);
                             }
@@ -285,6 +1363,8 @@ impl BeaconNetworkProvider {
                                 let result = swarm.behaviour_mut().gossipsub.unsubscribe(&topic);
                                 if result.is_ok() {
                                     info!("✅ Unsubscribed from attestation subnet {}", subnet_id.0);
+                                    advertised_subnets.remove(&subnet_id.0);
+                                    Self::advertise_subnets(&mut rpc_state, discovery.as_ref(), &advertised_subnets);
                                 }
                                 let _ = response.send(result.map(|_| ()).map_err(|e| anyhow::anyhow!("{}", e)));
                             } else {
@@ -296,28 +1376,118 @@ impl BeaconNetworkProvider {
                             let _ = response.send(count);
                         }
                         NetworkCommand::GetEpochInfo { response } => {
-                            let epoch_info = Self::calculate_current_epoch();
+                            let epoch_info = Self::calculate_current_epoch(validator.genesis_time());
                             let _ = response.send(epoch_info);
                         }
                         NetworkCommand::GetGossipMetrics { response } => {
-                            // Update peer count and calculate average peer score
+                            // Update peer count and read back gossipsub peer scores
                             gossip_metrics.connected_peers = swarm.connected_peers().count();
                             gossip_metrics.active_subnets = subscribed_subnets.len();
-                            
-                            // TODO: Calculate actual peer scores when libp2p exposes them
-                            // For now, simulate based on connected peers and message success
-                            let peer_count = gossip_metrics.connected_peers as f64;
-                            gossip_metrics.average_peer_score = if peer_count > 0.0 {
-                                // Estimate based on connectivity (good peers stay connected)
-                                (peer_count * 2.0 - 5.0).max(-10.0).min(10.0) 
-                            } else {
+
+                            let gossipsub = &swarm.behaviour().gossipsub;
+                            let scores: Vec<f64> = swarm
+                                .connected_peers()
+                                .filter_map(|peer_id| gossipsub.peer_score(peer_id))
+                                .collect();
+                            gossip_metrics.average_peer_score = if scores.is_empty() {
                                 0.0
+                            } else {
+                                scores.iter().sum::<f64>() / scores.len() as f64
                             };
+                            gossip_metrics.lowest_peer_score = scores.iter().copied().fold(0.0, f64::min);
+                            gossip_metrics.mesh_peers = gossipsub.all_mesh_peers().count();
+                            gossip_metrics.mesh_stability = mesh_stability.stability();
+                            gossip_metrics.connections = connections.stats();
+                            gossip_metrics.validation = validator.stats();
+                            bandwidth.sample(tokio::time::Instant::now());
+                            gossip_metrics.bandwidth = bandwidth.report(tokio::time::Instant::now());
+                            gossip_metrics.bytes_received = gossip_metrics.bandwidth.transport.inbound;
+                            gossip_metrics.bytes_sent = gossip_metrics.bandwidth.transport.outbound;
                             
                             let _ = response.send(gossip_metrics.clone());
                         }
+                        NetworkCommand::GetBandwidth { response } => {
+                            bandwidth.sample(tokio::time::Instant::now());
+                            let _ = response.send(bandwidth.report(tokio::time::Instant::now()));
+                        }
+                        NetworkCommand::GetExposure { response } => {
+                            let _ = response.send(gossip_metrics.exposure.clone());
+                        }
+                        NetworkCommand::PublishForFriend { request, response } => {
+                            let result = Self::publish_for_friend(
+                                &mut swarm, &mut validator, &mut exposure, &mut bandwidth, &fork_digest, request,
+                            );
+                            let _ = response.send(result);
+                        }
+                        NetworkCommand::SeenOnGossip { ssz_data, response } => {
+                            let _ = response.send(validator.has_seen(&ssz_data));
+                        }
+                        NetworkCommand::Publish { subnet_id, ssz_data, fanout, response } => {
+                            let topic = attestation_topic(&fork_digest, subnet_id.0);
+                            let topic_hash = topic.hash();
+                            let subscribed = swarm.behaviour().gossipsub.topics().any(|t| *t == topic_hash);
+                            let result = if !subscribed && !fanout {
+                                Err(anyhow::anyhow!("Not subscribed to subnet {} and fanout not requested", subnet_id.0))
+                            } else {
+                                gossip::compress_snappy(&ssz_data).and_then(|data| {
+                                    let len = data.len() as u64;
+                                    swarm.behaviour_mut().gossipsub.publish(topic, data)
+                                        .map(|message_id| (message_id, len))
+                                        .map_err(anyhow::Error::from)
+                                })
+                            };
+                            let result = result.map(|(message_id, len)| {
+                                gossip_metrics.messages_published += 1;
+                                let validator = DecodedAttestation::from_ssz(&ssz_data).ok().and_then(|a| a.attester_index);
+                                let now = tokio::time::Instant::now();
+                                let recipients = if subscribed {
+                                    let mesh_peers: Vec<PeerId> = swarm.behaviour().gossipsub.mesh_peers(&topic_hash).copied().collect();
+                                    let recipients = mesh_peers.len() as u64;
+                                    exposure.record_publish(subnet_id.0, validator, mesh_peers, now);
+                                    recipients
+                                } else {
+                                    let topic_peers = Self::topic_peers(&swarm, &topic_hash);
+                                    let recipients = topic_peers.len().min(gossip::MESH_N) as u64;
+                                    exposure.record_fanout(subnet_id.0, validator, topic_peers, now);
+                                    recipients
+                                };
+                                bandwidth.record(topic_hash.as_str(), Direction::Outbound, len * recipients, now);
+                                debug!("📤 Published attestation on subnet {} ({} bytes{})",
+                                       subnet_id.0, len, if subscribed { "" } else { ", fanout" });
+                                message_id
+                            });
+                            if let Err(e) = &result {
+                                warn!("Failed to publish attestation on subnet {}: {}", subnet_id.0, e);
+                            }
+                            let _ = response.send(result);
+                        }
+                        NetworkCommand::UpdateStatus(status) => {
+                            rpc_state.status = status;
+                        }
+                        NetworkCommand::UpdateChain(chain) => {
+                            if let Err(e) = validator.update_chain(chain) {
+                                warn!("Ignoring chain update from beacon API: {}", e);
+                            }
+                        }
+                        NetworkCommand::UpdatePeerFilter(update) => {
+                            let suspect = match &update {
+                                PeerFilterUpdate::Suspicion { peer_id, .. } => Some(*peer_id),
+                                _ => None,
+                            };
+                            swarm.behaviour_mut().peer_filter.update(update);
+                            // Suspicion lowers the peer's gossipsub score, deprioritising it in meshes and pruning
+                            if let Some(peer_id) = suspect {
+                                let penalty = swarm.behaviour().peer_filter.gossip_penalty(&peer_id);
+                                swarm.behaviour_mut().gossipsub.set_application_score(&peer_id, penalty);
+                            }
+                        }
+                        NetworkCommand::PrepareReshuffle { response, .. } => {
+                            // Identity rotation is handled by the command router
+                            let _ = response.send(Ok(()));
+                        }
                         NetworkCommand::Shutdown => {
                             info!("📡 Shutting down beacon network");
+                            Self::say_goodbye_to_all(&mut swarm, &mut rpc_state).await;
                             break;
                         }
                     }
@@ -369,31 +1539,49 @@ impl BeaconNetworkProvider {
         peers
     }
 
-    /// Calculate current epoch based on system time
-    fn calculate_current_epoch() -> StealthResult<EpochInfo> {
-        const GENESIS_TIME: u64 = 1606824023; // Ethereum mainnet genesis
-        const SECONDS_PER_SLOT: u64 = 12;
-        const SLOTS_PER_EPOCH: u64 = 32;
-
+    /// Calculate current epoch based on system time and the configured genesis
+    fn calculate_current_epoch(genesis_time: u64) -> StealthResult<EpochInfo> {
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
-            .map_err(|e| StealthError::Config(format!("Time error: {}", e)))?
-            .as_secs();
+            .map_err(|e| StealthError::Config(format!("Time error: {}", e)))?;
 
-        if now < GENESIS_TIME {
+        if now.as_secs() < genesis_time {
             return Err(StealthError::Config("Time before genesis".to_string()));
         }
+        Ok(EpochInfo::at(genesis_time, now))
+    }
 
-        let elapsed = now - GENESIS_TIME;
-        let current_slot = elapsed / SECONDS_PER_SLOT;
-        let current_epoch = current_slot / SLOTS_PER_EPOCH;
+    /// Publish an SSZ-encoded attestation on its subnet, see [`NetworkCommand::Publish`]
+    pub async fn publish_attestation(&self, subnet_id: SubnetId, ssz_data: Vec<u8>, fanout: bool) -> Result<MessageId> {
+        self.publisher().publish_attestation(subnet_id, ssz_data, fanout).await
+    }
 
-        Ok(EpochInfo {
-            epoch: current_epoch,
-            slot: current_slot,
-            slots_per_epoch: SLOTS_PER_EPOCH,
-            seconds_per_slot: SECONDS_PER_SLOT,
-        })
+    /// Cloneable handle for publishing from other tasks
+    pub fn publisher(&self) -> AttestationPublisher {
+        AttestationPublisher {
+            command_tx: self.command_tx.clone(),
+        }
+    }
+
+    /// Cloneable handle for updating the peer filter from other tasks
+    pub fn peer_filter(&self) -> PeerFilterHandle {
+        PeerFilterHandle {
+            command_tx: self.command_tx.clone(),
+        }
+    }
+
+    /// Cloneable handle for reading bandwidth from other tasks
+    pub fn bandwidth_monitor(&self) -> BandwidthMonitor {
+        BandwidthMonitor {
+            command_tx: self.command_tx.clone(),
+        }
+    }
+
+    /// Cloneable handle for reading our modelled first-seen exposure from other tasks
+    pub fn exposure_monitor(&self) -> ExposureMonitor {
+        ExposureMonitor {
+            command_tx: self.command_tx.clone(),
+        }
     }
 
     /// Get next network event
@@ -415,29 +1603,116 @@ impl BeaconNetworkProvider {
     pub async fn get_gossip_metrics(&self) -> GossipMetrics {
         let (tx, rx) = oneshot::channel();
         if self.command_tx.send(NetworkCommand::GetGossipMetrics { response: tx }).is_ok() {
-            rx.await.unwrap_or_else(|_| GossipMetrics {
-                average_peer_score: 0.0,
-                connected_peers: 0,
-                active_subnets: 0,
-                messages_published: 0,
-                messages_received: 0,
-                bytes_sent: 0,
-                bytes_received: 0,
-            })
+            rx.await.unwrap_or_default()
         } else {
-            GossipMetrics {
-                average_peer_score: 0.0,
-                connected_peers: 0,
-                active_subnets: 0,
-                messages_published: 0,
-                messages_received: 0,
-                bytes_sent: 0,
-                bytes_received: 0,
-            }
+            GossipMetrics::default()
         }
     }
 }
 
+/// Publishes attestations through a running [`BeaconNetworkProvider`]
+#[derive(Clone)]
+pub struct AttestationPublisher {
+    command_tx: mpsc::UnboundedSender<NetworkCommand>,
+}
+
+impl AttestationPublisher {
+    /// Publish an SSZ-encoded attestation; gossipsub errors such as
+    /// `PublishError::InsufficientPeers` can be recovered with `downcast_ref`
+    pub async fn publish_attestation(&self, subnet_id: SubnetId, ssz_data: Vec<u8>, fanout: bool) -> Result<MessageId> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(NetworkCommand::Publish { subnet_id, ssz_data, fanout, response: tx })
+            .map_err(|_| anyhow::anyhow!("Command channel closed"))?;
+        rx.await.map_err(|_| anyhow::anyhow!("Response channel closed"))?
+    }
+
+    /// Whether the attestation, or another by the same attester for its target
+    /// epoch, already arrived over gossip
+    pub async fn seen_on_gossip(&self, ssz_data: Vec<u8>) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(NetworkCommand::SeenOnGossip { ssz_data, response: tx })
+            .map_err(|_| anyhow::anyhow!("Command channel closed"))?;
+        rx.await.map_err(|_| anyhow::anyhow!("Response channel closed"))
+    }
+}
+
+/// Updates the peer allow and deny lists and sentinel suspicion of a running
+/// [`BeaconNetworkProvider`]; changes carry over to rotated identities
+#[derive(Clone)]
+pub struct PeerFilterHandle {
+    command_tx: mpsc::UnboundedSender<NetworkCommand>,
+}
+
+impl PeerFilterHandle {
+    pub fn update(&self, update: PeerFilterUpdate) -> Result<()> {
+        self.command_tx
+            .send(NetworkCommand::UpdatePeerFilter(update))
+            .map_err(|_| anyhow::anyhow!("Command channel closed"))
+    }
+
+    /// Report how likely `peer_id` is a sentinel, from 0.0 to 1.0
+    pub fn report_suspicion(&self, peer_id: PeerId, score: f64) -> Result<()> {
+        self.update(PeerFilterUpdate::Suspicion { peer_id, score })
+    }
+}
+
+/// Reads bandwidth from a running [`BeaconNetworkProvider`]
+#[derive(Clone)]
+pub struct BandwidthMonitor {
+    command_tx: mpsc::UnboundedSender<NetworkCommand>,
+}
+
+impl BandwidthMonitor {
+    pub async fn report(&self) -> Result<BandwidthReport> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(NetworkCommand::GetBandwidth { response: tx })
+            .map_err(|_| anyhow::anyhow!("Command channel closed"))?;
+        rx.await.map_err(|_| anyhow::anyhow!("Response channel closed"))
+    }
+}
+
+/// Reads the modelled first-seen exposure of a running [`BeaconNetworkProvider`]
+#[derive(Clone)]
+pub struct ExposureMonitor {
+    command_tx: mpsc::UnboundedSender<NetworkCommand>,
+}
+
+impl ExposureMonitor {
+    /// Estimate from the last epoch boundary; resets with each identity rotation
+    pub async fn report(&self) -> Result<ExposureReport> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(NetworkCommand::GetExposure { response: tx })
+            .map_err(|_| anyhow::anyhow!("Command channel closed"))?;
+        rx.await.map_err(|_| anyhow::anyhow!("Response channel closed"))
+    }
+}
+
+impl Drop for BeaconNetworkProvider {
+    fn drop(&mut self) {
+        // Lets the network loop say Goodbye to peers before it exits
+        let _ = self.command_tx.send(NetworkCommand::Shutdown);
+    }
+}
+
+/// Gossip topic of an attestation subnet
+pub(crate) fn attestation_topic(fork_digest: &str, subnet_id: u8) -> IdentTopic {
+    IdentTopic::new(format!("/eth2/{}/beacon_attestation_{}/ssz_snappy", fork_digest, subnet_id))
+}
+
+/// Parse a hex fork digest such as `7a7b8b7f`
+fn parse_fork_digest(fork_digest: &str) -> Option<[u8; 4]> {
+    hex::decode(fork_digest).ok()?.try_into().ok()
+}
+
+/// Parse a `0x` prefixed 32 byte root
+fn parse_root(root: &str) -> Option<[u8; 32]> {
+    hex::decode(root.trim_start_matches("0x")).ok()?.try_into().ok()
+}
+
 #[async_trait::async_trait]
 impl NetworkingProvider for BeaconNetworkProvider {
     async fn subscribe_to_subnet(&self, subnet_id: SubnetId) -> StealthResult<()> {
@@ -482,4 +1757,27 @@ impl NetworkingProvider for BeaconNetworkProvider {
         // Return demo subnets - in reality would query Lighthouse
         Ok(vec![SubnetId::new(0)?, SubnetId::new(1)?])
     }
+
+    async fn bandwidth_report(&self) -> StealthResult<Option<BandwidthReport>> {
+        self.bandwidth_monitor()
+            .report()
+            .await
+            .map(Some)
+            .map_err(|e| StealthError::Network(e.to_string()))
+    }
+
+    async fn prepare_reshuffle(&self, epoch: u64, retained: &[SubnetId]) -> StealthResult<()> {
+        let (tx, rx) = oneshot::channel();
+        self.command_tx
+            .send(NetworkCommand::PrepareReshuffle {
+                epoch,
+                retained: retained.to_vec(),
+                response: tx,
+            })
+            .map_err(|_| StealthError::Network("Command channel closed".to_string()))?;
+
+        rx.await
+            .map_err(|_| StealthError::Network("Response channel closed".to_string()))?
+            .map_err(|e| StealthError::Network(e.to_string()))
+    }
 }
\ No newline at end of file
@@ -0,0 +1,333 @@
+use anyhow::{anyhow, Result};
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Peer count we try to reach and prune back down to
+pub const DEFAULT_TARGET_PEERS: usize = 50;
+/// Peer count above which new connections are turned away
+pub const DEFAULT_MAX_PEERS: usize = 70;
+
+/// Limits and timings for the connection manager
+#[derive(Debug, Clone)]
+pub struct ConnectionManagerConfig {
+    pub target_peers: usize,
+    pub max_peers: usize,
+    /// Mesh peers a subnet keeps before any of them may be pruned
+    pub min_peers_per_subnet: usize,
+    /// Upper bound of the random delay before a scheduled dial
+    pub max_dial_jitter: Duration,
+    /// Redial delay after the first failure, doubled on each further one
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ConnectionManagerConfig {
+    fn default() -> Self {
+        Self {
+            target_peers: DEFAULT_TARGET_PEERS,
+            max_peers: DEFAULT_MAX_PEERS,
+            min_peers_per_subnet: 6,
+            max_dial_jitter: Duration::from_secs(2),
+            initial_backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(300),
+        }
+    }
+}
+
+/// A connected peer as seen when deciding whom to prune
+#[derive(Debug, Clone)]
+pub struct PeerSnapshot {
+    pub peer_id: PeerId,
+    /// Gossipsub score, lowest is pruned first
+    pub score: f64,
+    /// Subnets whose mesh this peer is part of
+    pub mesh_subnets: Vec<u8>,
+    /// Allowlisted, never pruned
+    pub protected: bool,
+}
+
+/// Connection manager state exposed through `GossipMetrics`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionStats {
+    pub target_peers: usize,
+    pub max_peers: usize,
+    /// Dials waiting for their jittered start time
+    pub scheduled_dials: usize,
+    /// Peers we will not dial until their backoff expires
+    pub peers_in_backoff: usize,
+    /// Peers disconnected to get back to the target count
+    pub peers_pruned: u64,
+}
+
+#[derive(Debug)]
+struct ScheduledDial {
+    at: Instant,
+    peer_id: PeerId,
+    address: Multiaddr,
+}
+
+#[derive(Debug)]
+struct Backoff {
+    failures: u32,
+    until: Instant,
+}
+
+/// Decides when to dial, redial and prune peers; driven by the swarm loop
+#[derive(Debug)]
+pub struct ConnectionManager {
+    config: ConnectionManagerConfig,
+    scheduled: Vec<ScheduledDial>,
+    /// Bootstrap peers we reconnect to whenever they drop
+    persistent: HashMap<PeerId, Multiaddr>,
+    backoff: HashMap<PeerId, Backoff>,
+    connected: HashSet<PeerId>,
+    peers_pruned: u64,
+}
+
+impl ConnectionManager {
+    pub fn new(config: ConnectionManagerConfig) -> Self {
+        Self {
+            config,
+            scheduled: Vec::new(),
+            persistent: HashMap::new(),
+            backoff: HashMap::new(),
+            connected: HashSet::new(),
+            peers_pruned: 0,
+        }
+    }
+
+    /// Keep a connection to `address`, which must end in `/p2p/<peer id>`; the first
+    /// dial is scheduled after a random jitter so restarts do not hit peers in lockstep
+    pub fn add_persistent_peer(&mut self, address: Multiaddr) -> Result<PeerId> {
+        let Some(Protocol::P2p(peer_id)) = address.iter().last() else {
+            return Err(anyhow!("Address {} has no /p2p/ peer id", address));
+        };
+        self.persistent.insert(peer_id, address.clone());
+        let at = Instant::now() + self.jitter();
+        self.schedule(at, peer_id, address);
+        Ok(peer_id)
+    }
+
+    /// Whether a discovered peer may be dialled right now
+    pub fn can_dial(&self, peer_id: &PeerId) -> bool {
+        self.connected.len() < self.config.max_peers
+            && !self.connected.contains(peer_id)
+            && !self.is_backing_off(peer_id, Instant::now())
+    }
+
+    /// Scheduled dials whose time has come, while we are below the peer maximum
+    pub fn due_dials(&mut self, now: Instant) -> Vec<(PeerId, Multiaddr)> {
+        let mut due = Vec::new();
+        let mut remaining = Vec::new();
+        for dial in self.scheduled.drain(..) {
+            if dial.at > now || self.connected.len() + due.len() >= self.config.max_peers {
+                remaining.push(dial);
+            } else if !self.connected.contains(&dial.peer_id) {
+                due.push((dial.peer_id, dial.address));
+            }
+        }
+        self.scheduled = remaining;
+        due
+    }
+
+    pub fn on_connected(&mut self, peer_id: PeerId) {
+        self.connected.insert(peer_id);
+        self.backoff.remove(&peer_id);
+        self.scheduled.retain(|dial| dial.peer_id != peer_id);
+    }
+
+    /// Back off from a peer we failed to reach, rescheduling it if it is persistent
+    pub fn on_dial_failure(&mut self, peer_id: PeerId) {
+        let failures = self.backoff.get(&peer_id).map_or(0, |b| b.failures) + 1;
+        let until = Instant::now() + self.backoff_delay(failures);
+        self.backoff.insert(peer_id, Backoff { failures, until });
+        if let Some(address) = self.persistent.get(&peer_id).cloned() {
+            self.schedule(until, peer_id, address);
+        }
+    }
+
+    /// Forget a peer once its last connection closes, redialling persistent peers
+    pub fn on_disconnected(&mut self, peer_id: PeerId) {
+        if !self.connected.remove(&peer_id) {
+            return;
+        }
+        if let Some(address) = self.persistent.get(&peer_id).cloned() {
+            let at = Instant::now() + self.config.initial_backoff + self.jitter();
+            self.schedule(at, peer_id, address);
+        }
+    }
+
+    /// Whether we are short of peers and should run discovery
+    pub fn needs_peers(&self) -> bool {
+        self.connected.len() < self.config.target_peers
+    }
+
+    /// Whether a new connection takes us past the maximum and should be turned away
+    pub fn over_limit(&self) -> bool {
+        self.connected.len() > self.config.max_peers
+    }
+
+    /// Pick the lowest-scored peers to drop until we are back at the target, keeping
+    /// persistent and protected peers and any peer a subnet mesh cannot spare
+    pub fn select_prunes(&mut self, peers: &[PeerSnapshot]) -> Vec<PeerId> {
+        let excess = peers.len().saturating_sub(self.config.target_peers);
+        if excess == 0 {
+            return Vec::new();
+        }
+
+        let mut mesh_counts: HashMap<u8, usize> = HashMap::new();
+        for peer in peers {
+            for subnet in &peer.mesh_subnets {
+                *mesh_counts.entry(*subnet).or_default() += 1;
+            }
+        }
+
+        let mut candidates: Vec<&PeerSnapshot> = peers
+            .iter()
+            .filter(|peer| !peer.protected && !self.persistent.contains_key(&peer.peer_id))
+            .collect();
+        candidates.sort_by(|a, b| a.score.total_cmp(&b.score));
+
+        let mut prunes = Vec::new();
+        for peer in candidates {
+            if prunes.len() == excess {
+                break;
+            }
+            let needed = peer
+                .mesh_subnets
+                .iter()
+                .any(|subnet| mesh_counts[subnet] <= self.config.min_peers_per_subnet);
+            if needed {
+                continue;
+            }
+            for subnet in &peer.mesh_subnets {
+                *mesh_counts.get_mut(subnet).expect("counted above") -= 1;
+            }
+            prunes.push(peer.peer_id);
+        }
+        self.peers_pruned += prunes.len() as u64;
+        prunes
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        let now = Instant::now();
+        ConnectionStats {
+            target_peers: self.config.target_peers,
+            max_peers: self.config.max_peers,
+            scheduled_dials: self.scheduled.len(),
+            peers_in_backoff: self.backoff.values().filter(|b| b.until > now).count(),
+            peers_pruned: self.peers_pruned,
+        }
+    }
+
+    fn schedule(&mut self, at: Instant, peer_id: PeerId, address: Multiaddr) {
+        self.scheduled.retain(|dial| dial.peer_id != peer_id);
+        self.scheduled.push(ScheduledDial { at, peer_id, address });
+    }
+
+    fn is_backing_off(&self, peer_id: &PeerId, now: Instant) -> bool {
+        self.backoff.get(peer_id).is_some_and(|b| b.until > now)
+    }
+
+    /// Exponential backoff with ±20% jitter, capped at `max_backoff`
+    fn backoff_delay(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(16);
+        let delay = self.config.initial_backoff.saturating_mul(1 << exponent).min(self.config.max_backoff);
+        delay.mul_f64(rand::thread_rng().gen_range(0.8..1.2))
+    }
+
+    fn jitter(&self) -> Duration {
+        self.config.max_dial_jitter.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_identity::Keypair;
+
+    fn peer_address() -> (PeerId, Multiaddr) {
+        let peer_id = PeerId::from(Keypair::generate_secp256k1().public());
+        let address = format!("/ip4/127.0.0.1/tcp/9000/p2p/{}", peer_id).parse().unwrap();
+        (peer_id, address)
+    }
+
+    fn snapshot(score: f64, mesh_subnets: Vec<u8>) -> PeerSnapshot {
+        PeerSnapshot {
+            peer_id: PeerId::from(Keypair::generate_ed25519().public()),
+            score,
+            mesh_subnets,
+            protected: false,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_persistent_peers_are_dialled_after_jitter_and_redialled() {
+        let mut manager = ConnectionManager::new(ConnectionManagerConfig::default());
+        let (peer_id, address) = peer_address();
+        assert_eq!(manager.add_persistent_peer(address.clone()).unwrap(), peer_id);
+        assert!(manager.add_persistent_peer("/ip4/127.0.0.1/tcp/9000".parse().unwrap()).is_err());
+
+        let start = Instant::now();
+        assert_eq!(manager.due_dials(start + Duration::from_secs(3)).len(), 1);
+        assert!(manager.due_dials(start + Duration::from_secs(3)).is_empty());
+
+        manager.on_connected(peer_id);
+        assert!(!manager.can_dial(&peer_id));
+        manager.on_disconnected(peer_id);
+        assert_eq!(manager.stats().scheduled_dials, 1);
+        let redial = manager.due_dials(start + Duration::from_secs(10));
+        assert_eq!(redial, vec![(peer_id, address)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dial_failures_back_off_exponentially() {
+        let mut manager = ConnectionManager::new(ConnectionManagerConfig::default());
+        let (peer_id, _) = peer_address();
+        manager.on_dial_failure(peer_id);
+        assert!(!manager.can_dial(&peer_id));
+        assert_eq!(manager.stats().peers_in_backoff, 1);
+
+        // 5s ±20% after the first failure
+        tokio::time::advance(Duration::from_secs(7)).await;
+        assert!(manager.can_dial(&peer_id));
+
+        // 10s ±20% after the second
+        manager.on_dial_failure(peer_id);
+        tokio::time::advance(Duration::from_secs(7)).await;
+        assert!(!manager.can_dial(&peer_id));
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(manager.can_dial(&peer_id));
+
+        assert!(manager.backoff_delay(30) <= Duration::from_secs(360));
+    }
+
+    #[test]
+    fn test_prunes_lowest_scores_but_keeps_subnet_coverage() {
+        let config = ConnectionManagerConfig {
+            target_peers: 3,
+            min_peers_per_subnet: 1,
+            ..Default::default()
+        };
+        let mut manager = ConnectionManager::new(config);
+        let (persistent, address) = peer_address();
+        manager.add_persistent_peer(address).unwrap();
+
+        let peers = vec![
+            // Worst score, but the only mesh peer on subnet 7
+            snapshot(-50.0, vec![7]),
+            snapshot(-10.0, vec![3]),
+            snapshot(-5.0, vec![3]),
+            snapshot(20.0, vec![]),
+            PeerSnapshot { peer_id: persistent, score: -100.0, mesh_subnets: vec![], protected: false },
+        ];
+        let prunes = manager.select_prunes(&peers);
+        assert_eq!(prunes, vec![peers[1].peer_id, peers[3].peer_id]);
+        assert_eq!(manager.stats().peers_pruned, 2);
+
+        assert!(manager.select_prunes(&peers[..3]).is_empty());
+    }
+}
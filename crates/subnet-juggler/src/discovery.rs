@@ -0,0 +1,398 @@
+use anyhow::{anyhow, Result};
+use discv5::enr::{CombinedKey, CombinedPublicKey, EnrPublicKey, NodeId};
+use discv5::{ConfigBuilder, Discv5, Enr, ListenConfig};
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use libp2p_identity as identity;
+use std::collections::HashSet;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// ENR key holding the SSZ `ENRForkID`
+pub const ETH2_ENR_KEY: &str = "eth2";
+/// ENR key holding the SSZ `Bitvector[ATTESTATION_SUBNET_COUNT]` of long-lived subnets
+pub const ATTNETS_ENR_KEY: &str = "attnets";
+/// ENR keys holding the IPv4 and IPv6 QUIC ports
+pub const QUIC_ENR_KEY: &str = "quic";
+pub const QUIC6_ENR_KEY: &str = "quic6";
+/// Number of attestation subnets encoded in `attnets`
+pub const ATTESTATION_SUBNET_COUNT: usize = 64;
+
+/// A peer found through discv5, ready to be handed to the swarm for dialling
+#[derive(Debug, Clone)]
+pub struct DiscoveredPeer {
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+    pub attnets: HashSet<u8>,
+}
+
+/// Outcome of a discovery query, tagged with the subnet it targeted (if any)
+pub type QueryFuture = Pin<Box<dyn Future<Output = (Option<u8>, Vec<DiscoveredPeer>)> + Send>>;
+
+/// Configuration for the discv5 service
+#[derive(Debug, Clone)]
+pub struct DiscoveryConfig {
+    /// UDP port discv5 listens on
+    pub listen_port: u16,
+    /// IP advertised in the local ENR (usually set later from observed addresses)
+    pub enr_address: Option<IpAddr>,
+    /// TCP port advertised in the local ENR for libp2p connections
+    pub enr_tcp_port: Option<u16>,
+    /// UDP port advertised in the local ENR for libp2p QUIC connections
+    pub enr_quic_port: Option<u16>,
+    /// Base64 ENRs (`enr:-...`) of the bootnodes
+    pub boot_enrs: Vec<String>,
+    /// Fork digest of the network we are on, filters out peers from other forks
+    pub fork_digest: [u8; 4],
+    /// Number of peers to look for in each subnet query
+    pub peers_per_subnet_query: usize,
+}
+
+/// discv5 peer discovery with subnet-targeted searches
+pub struct Discovery {
+    discv5: Discv5,
+    fork_digest: [u8; 4],
+    peers_per_subnet_query: usize,
+}
+
+impl Discovery {
+    /// Build the local ENR from the libp2p identity and start the discv5 service
+    pub async fn new(local_key: &identity::Keypair, config: DiscoveryConfig) -> Result<Self> {
+        let enr_key = enr_key_from_keypair(local_key)?;
+
+        let mut builder = Enr::builder();
+        if let Some(ip) = config.enr_address {
+            builder.ip(ip);
+            builder.udp4(config.listen_port);
+            if let Some(tcp) = config.enr_tcp_port {
+                builder.tcp4(tcp);
+            }
+            if let Some(quic) = config.enr_quic_port {
+                builder.add_value(QUIC_ENR_KEY, &quic);
+            }
+        }
+        builder.add_value(ETH2_ENR_KEY, &enr_fork_id(config.fork_digest).to_vec());
+        builder.add_value(ATTNETS_ENR_KEY, &vec![0u8; ATTESTATION_SUBNET_COUNT / 8]);
+        let local_enr = builder
+            .build(&enr_key)
+            .map_err(|e| anyhow!("Failed to build local ENR: {}", e))?;
+
+        let listen_config = ListenConfig::from_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED), config.listen_port);
+        let discv5_config = ConfigBuilder::new(listen_config)
+            .request_timeout(Duration::from_secs(2))
+            .query_timeout(Duration::from_secs(30))
+            .build();
+
+        let mut discv5: Discv5 = Discv5::new(local_enr, enr_key, discv5_config)
+            .map_err(|e| anyhow!("Failed to create discv5 service: {}", e))?;
+
+        for boot_enr in &config.boot_enrs {
+            match boot_enr.parse::<Enr>() {
+                Ok(enr) => {
+                    if let Err(e) = discv5.add_enr(enr) {
+                        warn!("Failed to add boot ENR: {}", e);
+                    }
+                }
+                Err(e) => warn!("Invalid boot ENR {}: {}", boot_enr, e),
+            }
+        }
+
+        discv5
+            .start()
+            .await
+            .map_err(|e| anyhow!("Failed to start discv5: {:?}", e))?;
+
+        info!("🔭 discv5 listening on udp/{} with {} bootnodes", config.listen_port, discv5.table_entries_id().len());
+
+        Ok(Self {
+            discv5,
+            fork_digest: config.fork_digest,
+            peers_per_subnet_query: config.peers_per_subnet_query,
+        })
+    }
+
+    /// Our current ENR
+    pub fn local_enr(&self) -> Enr {
+        self.discv5.local_enr()
+    }
+
+    /// Add a peer's ENR to the routing table
+    pub fn add_enr(&self, enr: Enr) -> Result<()> {
+        self.discv5.add_enr(enr).map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Number of nodes in the routing table
+    pub fn table_size(&self) -> usize {
+        self.discv5.table_entries_id().len()
+    }
+
+    /// Update the `attnets` bitfield advertised in our ENR
+    pub fn update_attnets(&self, subnets: &HashSet<u8>) -> Result<()> {
+        let bitfield = attnets_bitfield(subnets).to_vec();
+        self.discv5
+            .enr_insert(ATTNETS_ENR_KEY, &bitfield)
+            .map_err(|e| anyhow!("Failed to update attnets: {:?}", e))?;
+        Ok(())
+    }
+
+    /// Advertise a confirmed external IP with our libp2p and discv5 ports. A discv5
+    /// port of 0 was picked by the OS; discv5 learns it from its peers' PONGs instead.
+    pub fn set_external_address(&self, ip: IpAddr, tcp_port: u16, quic_port: u16, udp_port: u16) -> Result<()> {
+        self.discv5.update_local_enr_socket(SocketAddr::new(ip, tcp_port), true);
+        if udp_port != 0 {
+            self.discv5.update_local_enr_socket(SocketAddr::new(ip, udp_port), false);
+        }
+        let quic_key = if ip.is_ipv4() { QUIC_ENR_KEY } else { QUIC6_ENR_KEY };
+        self.discv5
+            .enr_insert(quic_key, &quic_port)
+            .map_err(|e| anyhow!("Failed to update QUIC port: {:?}", e))?;
+        Ok(())
+    }
+
+    /// Random walk of the DHT to find any eth2 peers on our fork
+    pub fn find_peers(&self) -> QueryFuture {
+        let fork_digest = self.fork_digest;
+        let query = self.discv5.find_node_predicate(
+            NodeId::random(),
+            Box::new(move |enr: &Enr| enr_matches_fork(enr, fork_digest)),
+            16,
+        );
+        Box::pin(async move { (None, Self::collect(query.await)) })
+    }
+
+    /// Look for peers whose ENR `attnets` include `subnet_id`
+    pub fn find_subnet_peers(&self, subnet_id: u8) -> QueryFuture {
+        let fork_digest = self.fork_digest;
+        let query = self.discv5.find_node_predicate(
+            NodeId::random(),
+            Box::new(move |enr: &Enr| {
+                enr_matches_fork(enr, fork_digest) && enr_attnets(enr).contains(&subnet_id)
+            }),
+            self.peers_per_subnet_query,
+        );
+        Box::pin(async move {
+            let peers = Self::collect(query.await);
+            debug!("🔭 Subnet {} query found {} peers", subnet_id, peers.len());
+            (Some(subnet_id), peers)
+        })
+    }
+
+    fn collect(result: Result<Vec<Enr>, discv5::QueryError>) -> Vec<DiscoveredPeer> {
+        match result {
+            Ok(enrs) => enrs.iter().filter_map(discovered_peer_from_enr).collect(),
+            Err(e) => {
+                debug!("Discovery query failed: {:?}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Convert a libp2p keypair into the key type discv5 signs ENRs with
+pub fn enr_key_from_keypair(keypair: &identity::Keypair) -> Result<CombinedKey> {
+    if let Ok(secp) = keypair.clone().try_into_secp256k1() {
+        let mut secret = secp.secret().to_bytes();
+        return CombinedKey::secp256k1_from_bytes(&mut secret)
+            .map_err(|e| anyhow!("Invalid secp256k1 key: {}", e));
+    }
+    if let Ok(ed) = keypair.clone().try_into_ed25519() {
+        let mut secret = ed.secret().as_ref().to_vec();
+        return CombinedKey::ed25519_from_bytes(&mut secret)
+            .map_err(|e| anyhow!("Invalid ed25519 key: {}", e));
+    }
+    Err(anyhow!("Unsupported key type for discv5"))
+}
+
+/// Derive the libp2p peer id from the public key in an ENR
+pub fn peer_id_from_enr(enr: &Enr) -> Option<PeerId> {
+    let public_key: identity::PublicKey = match enr.public_key() {
+        CombinedPublicKey::Secp256k1(_) => {
+            identity::secp256k1::PublicKey::try_from_bytes(&enr.public_key().encode())
+                .ok()?
+                .into()
+        }
+        CombinedPublicKey::Ed25519(_) => {
+            identity::ed25519::PublicKey::try_from_bytes(&enr.public_key().encode())
+                .ok()?
+                .into()
+        }
+    };
+    Some(PeerId::from(public_key))
+}
+
+/// Dialable libp2p addresses advertised by an ENR
+pub fn multiaddrs_from_enr(enr: &Enr) -> Vec<Multiaddr> {
+    let mut addresses = Vec::new();
+    if let (Some(ip), Some(tcp)) = (enr.ip4(), enr.tcp4()) {
+        addresses.push(Multiaddr::empty().with(Protocol::Ip4(ip)).with(Protocol::Tcp(tcp)));
+    }
+    if let (Some(ip), Some(tcp)) = (enr.ip6(), enr.tcp6()) {
+        addresses.push(Multiaddr::empty().with(Protocol::Ip6(ip)).with(Protocol::Tcp(tcp)));
+    }
+    // QUIC first, Lighthouse peers prefer it
+    if let (Some(ip), Some(Ok(quic))) = (enr.ip6(), enr.get_decodable::<u16>(QUIC6_ENR_KEY)) {
+        addresses.insert(0, Multiaddr::empty().with(Protocol::Ip6(ip)).with(Protocol::Udp(quic)).with(Protocol::QuicV1));
+    }
+    if let (Some(ip), Some(Ok(quic))) = (enr.ip4(), enr.get_decodable::<u16>(QUIC_ENR_KEY)) {
+        addresses.insert(0, Multiaddr::empty().with(Protocol::Ip4(ip)).with(Protocol::Udp(quic)).with(Protocol::QuicV1));
+    }
+    addresses
+}
+
+/// Subnets set in an ENR's `attnets` bitfield
+pub fn enr_attnets(enr: &Enr) -> HashSet<u8> {
+    let mut subnets = HashSet::new();
+    if let Some(bytes) = enr.get(ATTNETS_ENR_KEY) {
+        for (byte_index, byte) in bytes.iter().enumerate().take(ATTESTATION_SUBNET_COUNT / 8) {
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    subnets.insert((byte_index * 8 + bit) as u8);
+                }
+            }
+        }
+    }
+    subnets
+}
+
+/// Encode a subnet set as an SSZ `Bitvector[64]`
+pub fn attnets_bitfield(subnets: &HashSet<u8>) -> [u8; ATTESTATION_SUBNET_COUNT / 8] {
+    let mut bitfield = [0u8; ATTESTATION_SUBNET_COUNT / 8];
+    for subnet in subnets.iter().filter(|s| (**s as usize) < ATTESTATION_SUBNET_COUNT) {
+        bitfield[*subnet as usize / 8] |= 1 << (subnet % 8);
+    }
+    bitfield
+}
+
+/// SSZ `ENRForkID` with no scheduled fork
+fn enr_fork_id(fork_digest: [u8; 4]) -> [u8; 16] {
+    let mut fork_id = [0u8; 16];
+    fork_id[..4].copy_from_slice(&fork_digest);
+    // next_fork_version stays equal to the current one
+    fork_id[4..8].copy_from_slice(&fork_digest);
+    fork_id[8..].copy_from_slice(&u64::MAX.to_le_bytes());
+    fork_id
+}
+
+/// Whether an ENR advertises the same fork digest as ours
+fn enr_matches_fork(enr: &Enr, fork_digest: [u8; 4]) -> bool {
+    enr.get(ETH2_ENR_KEY)
+        .map(|eth2| eth2.len() >= 4 && eth2[..4] == fork_digest)
+        .unwrap_or(false)
+}
+
+fn discovered_peer_from_enr(enr: &Enr) -> Option<DiscoveredPeer> {
+    let addresses = multiaddrs_from_enr(enr);
+    if addresses.is_empty() {
+        return None;
+    }
+    Some(DiscoveredPeer {
+        peer_id: peer_id_from_enr(enr)?,
+        addresses,
+        attnets: enr_attnets(enr),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_FORK_DIGEST: [u8; 4] = [0x7a, 0x7b, 0x8b, 0x7f];
+
+    fn free_udp_port() -> u16 {
+        std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    async fn spawn_node(boot_enrs: Vec<String>, attnets: &[u8]) -> (Discovery, PeerId) {
+        let keypair = identity::Keypair::generate_secp256k1();
+        let peer_id = PeerId::from(keypair.public());
+        let port = free_udp_port();
+        let discovery = Discovery::new(
+            &keypair,
+            DiscoveryConfig {
+                listen_port: port,
+                enr_address: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                enr_tcp_port: Some(port),
+                enr_quic_port: Some(port),
+                boot_enrs,
+                fork_digest: TEST_FORK_DIGEST,
+                peers_per_subnet_query: 8,
+            },
+        )
+        .await
+        .unwrap();
+        discovery.update_attnets(&attnets.iter().copied().collect()).unwrap();
+        (discovery, peer_id)
+    }
+
+    #[test]
+    fn test_attnets_roundtrip() {
+        let subnets: HashSet<u8> = [0, 7, 8, 42, 63].into_iter().collect();
+        let bitfield = attnets_bitfield(&subnets);
+        assert_eq!(bitfield[0], 0b1000_0001);
+        assert_eq!(bitfield[1], 0b0000_0001);
+
+        let keypair = identity::Keypair::generate_secp256k1();
+        let key = enr_key_from_keypair(&keypair).unwrap();
+        let enr = Enr::builder()
+            .add_value(ATTNETS_ENR_KEY, &bitfield.to_vec())
+            .build(&key)
+            .unwrap();
+        assert_eq!(enr_attnets(&enr), subnets);
+        assert_eq!(peer_id_from_enr(&enr), Some(PeerId::from(keypair.public())));
+    }
+
+    #[test]
+    fn test_enr_addresses_prefer_quic() {
+        let keypair = identity::Keypair::generate_secp256k1();
+        let key = enr_key_from_keypair(&keypair).unwrap();
+        let enr = Enr::builder()
+            .ip4(Ipv4Addr::new(203, 0, 113, 7))
+            .tcp4(9000)
+            .add_value(QUIC_ENR_KEY, &9001u16)
+            .build(&key)
+            .unwrap();
+        let addresses: Vec<String> = multiaddrs_from_enr(&enr).iter().map(|a| a.to_string()).collect();
+        assert_eq!(addresses, vec!["/ip4/203.0.113.7/udp/9001/quic-v1", "/ip4/203.0.113.7/tcp/9000"]);
+    }
+
+    #[tokio::test]
+    async fn test_subnet_targeted_discovery() {
+        let (bootnode, _) = spawn_node(Vec::new(), &[]).await;
+        let boot_enrs = vec![bootnode.local_enr().to_base64()];
+
+        let (on_subnet_a, peer_a) = spawn_node(boot_enrs.clone(), &[5, 9]).await;
+        let (on_subnet_b, peer_b) = spawn_node(boot_enrs.clone(), &[5]).await;
+        let (off_subnet, peer_c) = spawn_node(boot_enrs.clone(), &[12]).await;
+
+        // Let the bootnode learn about everyone
+        for node in [&on_subnet_a, &on_subnet_b, &off_subnet] {
+            node.find_peers().await;
+        }
+
+        // Sessions settle asynchronously, so give the query a few attempts
+        let (searcher, _) = spawn_node(boot_enrs, &[]).await;
+        let mut result = searcher.find_subnet_peers(5).await;
+        for _ in 0..10 {
+            let found: HashSet<PeerId> = result.1.iter().map(|p| p.peer_id).collect();
+            if found.contains(&peer_a) && found.contains(&peer_b) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            result = searcher.find_subnet_peers(5).await;
+        }
+        let (subnet, peers) = result;
+
+        let found: HashSet<PeerId> = peers.iter().map(|p| p.peer_id).collect();
+        assert_eq!(subnet, Some(5));
+        assert!(found.contains(&peer_a));
+        assert!(found.contains(&peer_b));
+        assert!(!found.contains(&peer_c));
+        assert!(peers.iter().all(|p| p.attnets.contains(&5) && !p.addresses.is_empty()));
+    }
+}
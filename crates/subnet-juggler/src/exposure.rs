@@ -0,0 +1,262 @@
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::gossip::MESH_N;
+
+/// Time for an attestation's weight in the estimate to halve
+pub const EXPOSURE_HALF_LIFE: Duration = Duration::from_secs(1800);
+
+/// Below this weight a counter has decayed away and is dropped
+const FORGOTTEN: f64 = 0.01;
+
+/// A count that halves every [`EXPOSURE_HALF_LIFE`]
+#[derive(Debug, Clone, Copy)]
+struct Decaying {
+    value: f64,
+    at: Instant,
+}
+
+impl Decaying {
+    fn new(now: Instant) -> Self {
+        Self { value: 0.0, at: now }
+    }
+
+    fn value(&self, now: Instant) -> f64 {
+        let half_lives = now.saturating_duration_since(self.at).as_secs_f64() / EXPOSURE_HALF_LIFE.as_secs_f64();
+        self.value * 0.5f64.powf(half_lives)
+    }
+
+    fn add(&mut self, amount: f64, now: Instant) {
+        self.value = self.value(now) + amount;
+        self.at = now;
+    }
+}
+
+/// Attestations sent in one group, and how many of them each peer likely saw
+/// from us first
+#[derive(Debug, Clone)]
+struct Group {
+    sent: Decaying,
+    attributed: HashMap<PeerId, Decaying>,
+}
+
+impl Group {
+    fn new(now: Instant) -> Self {
+        Self {
+            sent: Decaying::new(now),
+            attributed: HashMap::new(),
+        }
+    }
+
+    fn record(&mut self, recipients: &[PeerId], first_seen: f64, now: Instant) {
+        self.sent.add(1.0, now);
+        for peer_id in recipients {
+            self.attributed
+                .entry(*peer_id)
+                .or_insert_with(|| Decaying::new(now))
+                .add(first_seen, now);
+        }
+    }
+
+    /// Share of the group the single best-placed peer would attribute to us.
+    /// Peers whose count has decayed away are forgotten.
+    fn worst_observer(&mut self, now: Instant) -> f64 {
+        self.attributed.retain(|_, count| count.value(now) >= FORGOTTEN);
+        let sent = self.sent.value(now);
+        if sent < FORGOTTEN {
+            return 0.0;
+        }
+        let most = self.attributed.values().map(|count| count.value(now)).fold(0.0, f64::max);
+        most / sent
+    }
+
+    fn forgotten(&self, now: Instant) -> bool {
+        self.sent.value(now) < FORGOTTEN
+    }
+}
+
+/// Modelled attribution of our attestations to our peer ID
+#[derive(Debug, Clone, Default)]
+pub struct ExposureReport {
+    /// Across everything we sent recently
+    pub overall: f64,
+    /// By attester index, for attestations we published
+    pub per_validator: HashMap<u64, f64>,
+    /// By subnet, published and relayed
+    pub per_subnet: HashMap<u8, f64>,
+    /// Recent attestations, decayed like the estimate
+    pub published: usize,
+    pub relayed: usize,
+}
+
+/// Models the RAINBOW first-seen measurement against ourselves.
+///
+/// This is an estimate from who we sent each attestation to and the mesh size,
+/// not a measurement: gossipsub does not tell us which recipients already had
+/// a message from someone else.
+///
+/// A single observer attributes an attestation to the peer it first received it
+/// from. For a group of attestations, exposure is the share the best-placed peer
+/// would attribute to us: the most any one peer collected, weighted by how likely
+/// it got each one from us first, over the group size. Counts decay with
+/// [`EXPOSURE_HALF_LIFE`], so memory and the estimate's cost follow the number
+/// of (peer, subnet) and (peer, validator) pairs rather than of attestations.
+#[derive(Debug)]
+pub struct ExposureTracker {
+    overall: Group,
+    per_validator: HashMap<u64, Group>,
+    per_subnet: HashMap<u8, Group>,
+    published: Decaying,
+    relayed: Decaying,
+}
+
+impl Default for ExposureTracker {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            overall: Group::new(now),
+            per_validator: HashMap::new(),
+            per_subnet: HashMap::new(),
+            published: Decaying::new(now),
+            relayed: Decaying::new(now),
+        }
+    }
+}
+
+impl ExposureTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// We originated the attestation and sent it to our mesh, so each mesh peer
+    /// saw it from us first
+    pub fn record_publish(&mut self, subnet_id: u8, validator: Option<u64>, mesh_peers: Vec<PeerId>, now: Instant) {
+        self.published.add(1.0, now);
+        self.record(subnet_id, validator, &mesh_peers, 1.0, now);
+    }
+
+    /// We originated the attestation on a subnet we are not in, and gossipsub
+    /// sent it to `MESH_N` fresh random peers among `topic_peers`
+    pub fn record_fanout(&mut self, subnet_id: u8, validator: Option<u64>, topic_peers: Vec<PeerId>, now: Instant) {
+        self.published.add(1.0, now);
+        let first_seen = (MESH_N as f64 / topic_peers.len().max(1) as f64).min(1.0);
+        self.record(subnet_id, validator, &topic_peers, first_seen, now);
+    }
+
+    /// We forwarded someone else's attestation; each recipient also gets it from
+    /// the rest of its mesh, so we are first roughly once in `MESH_N`
+    pub fn record_relay(&mut self, subnet_id: u8, recipients: Vec<PeerId>, now: Instant) {
+        self.relayed.add(1.0, now);
+        self.record(subnet_id, None, &recipients, 1.0 / MESH_N as f64, now);
+    }
+
+    fn record(&mut self, subnet_id: u8, validator: Option<u64>, recipients: &[PeerId], first_seen: f64, now: Instant) {
+        self.overall.record(recipients, first_seen, now);
+        self.per_subnet
+            .entry(subnet_id)
+            .or_insert_with(|| Group::new(now))
+            .record(recipients, first_seen, now);
+        if let Some(validator) = validator {
+            self.per_validator
+                .entry(validator)
+                .or_insert_with(|| Group::new(now))
+                .record(recipients, first_seen, now);
+        }
+    }
+
+    /// Exposure with older attestations weighing less, halving every [`EXPOSURE_HALF_LIFE`]
+    pub fn estimate(&mut self, now: Instant) -> ExposureReport {
+        self.per_validator.retain(|_, group| !group.forgotten(now));
+        self.per_subnet.retain(|_, group| !group.forgotten(now));
+
+        ExposureReport {
+            overall: self.overall.worst_observer(now),
+            per_validator: self
+                .per_validator
+                .iter_mut()
+                .map(|(validator, group)| (*validator, group.worst_observer(now)))
+                .collect(),
+            per_subnet: self
+                .per_subnet
+                .iter_mut()
+                .map(|(subnet_id, group)| (*subnet_id, group.worst_observer(now)))
+                .collect(),
+            published: self.published.value(now).round() as usize,
+            relayed: self.relayed.value(now).round() as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_published_attestations_are_fully_exposed_to_mesh_peers() {
+        let mut tracker = ExposureTracker::new();
+        let now = Instant::now();
+        let mesh: Vec<PeerId> = (0..MESH_N).map(|_| PeerId::random()).collect();
+
+        tracker.record_publish(5, Some(42), mesh.clone(), now);
+        tracker.record_publish(5, Some(42), mesh[..2].to_vec(), now);
+        let report = tracker.estimate(now);
+        assert_eq!(report.per_validator[&42], 1.0);
+        assert_eq!(report.per_subnet[&5], 1.0);
+        assert_eq!(report.published, 2);
+
+        // A disjoint mesh for the next one halves what any single peer saw
+        let other: Vec<PeerId> = (0..MESH_N).map(|_| PeerId::random()).collect();
+        tracker.record_publish(7, Some(42), other, now);
+        tracker.record_publish(7, Some(42), (0..MESH_N).map(|_| PeerId::random()).collect(), now);
+        let report = tracker.estimate(now);
+        assert_eq!(report.per_validator[&42], 0.5);
+        assert_eq!(report.per_subnet[&7], 0.5);
+    }
+
+    #[test]
+    fn test_relays_weigh_less() {
+        let mut tracker = ExposureTracker::new();
+        let now = Instant::now();
+        let peers: Vec<PeerId> = (0..MESH_N * 2).map(|_| PeerId::random()).collect();
+
+        tracker.record_relay(3, peers[..MESH_N].to_vec(), now);
+        let report = tracker.estimate(now);
+        assert!((report.per_subnet[&3] - 1.0 / MESH_N as f64).abs() < 1e-9);
+        assert!(report.per_validator.is_empty());
+        assert_eq!(report.relayed, 1);
+
+        tracker.record_publish(4, Some(9), peers.clone(), now);
+        let report = tracker.estimate(now);
+        assert_eq!(report.per_validator[&9], 1.0);
+        assert_eq!(report.published, 1);
+
+        // Fanout peers are drawn from every topic peer, so each is only likely to be one
+        tracker.record_fanout(6, Some(10), peers, now);
+        let report = tracker.estimate(now);
+        assert!((report.per_validator[&10] - 0.5).abs() < 1e-9);
+        assert_eq!(report.published, 2);
+    }
+
+    #[test]
+    fn test_old_attestations_decay_away() {
+        let mut tracker = ExposureTracker::new();
+        let start = Instant::now();
+        let old = PeerId::random();
+        tracker.record_publish(1, Some(1), vec![old], start);
+
+        // An hour later the old attestation counts a quarter as much as a new one
+        let hour = start + EXPOSURE_HALF_LIFE * 2;
+        tracker.record_publish(1, Some(1), vec![PeerId::random()], hour);
+        let report = tracker.estimate(hour);
+        assert!((report.per_validator[&1] - 1.0 / 1.25).abs() < 1e-9);
+
+        let later = start + EXPOSURE_HALF_LIFE * 10;
+        let report = tracker.estimate(later);
+        assert_eq!(report.overall, 0.0);
+        assert!(report.per_validator.is_empty());
+        assert!(!tracker.overall.attributed.contains_key(&old));
+        assert_eq!(report.published + report.relayed, 0);
+    }
+}
@@ -0,0 +1,74 @@
+use anyhow::{anyhow, Result};
+
+/// Consensus forks in activation order; the active one decides the gossip topic
+/// digest, the blob subnets and the attestation container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Fork {
+    Phase0,
+    Altair,
+    Bellatrix,
+    Capella,
+    Deneb,
+    Electra,
+}
+
+impl Fork {
+    const ALL: [Fork; 6] = [
+        Fork::Phase0,
+        Fork::Altair,
+        Fork::Bellatrix,
+        Fork::Capella,
+        Fork::Deneb,
+        Fork::Electra,
+    ];
+
+    /// Fork at `position` in the beacon API fork schedule, which lists every fork from phase0
+    pub fn from_schedule_position(position: usize) -> Result<Fork> {
+        Self::ALL
+            .get(position)
+            .copied()
+            .ok_or_else(|| anyhow!("Fork {} of the schedule is newer than Electra and not supported", position))
+    }
+
+    /// Fork of a mainnet fork version, whose first byte counts the forks; testnet
+    /// versions follow other schemes and need the beacon API fork schedule
+    pub fn from_mainnet_version(version: [u8; 4]) -> Result<Fork> {
+        match version {
+            [position, 0, 0, 0] => Self::from_schedule_position(position as usize),
+            _ => Err(anyhow!("Fork version {} is not a mainnet one, set beacon_api_url to resolve it", hex::encode(version))),
+        }
+    }
+
+    /// `BLOB_SIDECAR_SUBNET_COUNT`, raised to `BLOB_SIDECAR_SUBNET_COUNT_ELECTRA`
+    pub fn blob_sidecar_subnets(&self) -> u8 {
+        match self {
+            Fork::Phase0 | Fork::Altair | Fork::Bellatrix | Fork::Capella => 0,
+            Fork::Deneb => 6,
+            Fork::Electra => 9,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fork_from_schedule_and_mainnet_version() {
+        assert_eq!(Fork::from_schedule_position(0).unwrap(), Fork::Phase0);
+        assert_eq!(Fork::from_schedule_position(5).unwrap(), Fork::Electra);
+        assert!(Fork::from_schedule_position(6).is_err());
+        assert_eq!(Fork::from_mainnet_version([0x04, 0, 0, 0]).unwrap(), Fork::Deneb);
+        assert_eq!(Fork::from_mainnet_version([0x05, 0, 0, 0]).unwrap(), Fork::Electra);
+        // Hoodi Electra
+        assert!(Fork::from_mainnet_version([0x60, 0x00, 0x09, 0x10]).is_err());
+    }
+
+    #[test]
+    fn test_blob_sidecar_subnets_follow_the_fork() {
+        assert_eq!(Fork::Capella.blob_sidecar_subnets(), 0);
+        assert_eq!(Fork::Deneb.blob_sidecar_subnets(), 6);
+        assert_eq!(Fork::Electra.blob_sidecar_subnets(), 9);
+        assert!(Fork::Electra > Fork::Deneb);
+    }
+}
@@ -0,0 +1,384 @@
+use anyhow::{anyhow, Result};
+use libp2p::gossipsub::{
+    self, MessageAuthenticity, MessageId, PeerScoreParams, PeerScoreThresholds, TopicScoreParams,
+    ValidationMode,
+};
+use sha2::{Digest, Sha256};
+use snap::raw::{decompress_len, Decoder, Encoder};
+use libp2p::gossipsub::TopicHash;
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Domain prepended to message-id preimages when the payload decompresses
+pub const MESSAGE_DOMAIN_VALID_SNAPPY: [u8; 4] = [0x01, 0x00, 0x00, 0x00];
+/// Domain prepended to message-id preimages when the payload is not valid snappy
+pub const MESSAGE_DOMAIN_INVALID_SNAPPY: [u8; 4] = [0x00, 0x00, 0x00, 0x00];
+/// Maximum uncompressed gossip payload (`GOSSIP_MAX_SIZE`, 10 MiB)
+pub const GOSSIP_MAX_SIZE: usize = 10 * 1024 * 1024;
+
+/// Mesh degree `D`
+pub const MESH_N: usize = 8;
+/// Mesh lower bound `D_low`
+pub const MESH_N_LOW: usize = 6;
+/// Mesh upper bound `D_high`
+pub const MESH_N_HIGH: usize = 12;
+/// Peers to emit gossip to `D_lazy`
+pub const GOSSIP_LAZY: usize = 6;
+/// Heartbeat interval (0.7s)
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(700);
+/// Fanout TTL, one heartbeat rather than the spec's 60s, so every fanout
+/// publish goes to a freshly drawn set of `D` topic peers
+pub const FANOUT_TTL: Duration = HEARTBEAT_INTERVAL;
+/// Number of heartbeats kept in the message cache (`mcache_len`)
+pub const HISTORY_LENGTH: usize = 6;
+/// Number of heartbeats gossiped about (`mcache_gossip`)
+pub const HISTORY_GOSSIP: usize = 3;
+/// Heartbeats a message id is remembered for (`seen_ttl` = 550 * heartbeat)
+pub const SEEN_TTL_HEARTBEATS: u32 = 550;
+
+/// Score below which we stop gossiping to a peer
+pub const GOSSIP_THRESHOLD: f64 = -4000.0;
+/// Score below which we stop publishing to a peer
+pub const PUBLISH_THRESHOLD: f64 = -8000.0;
+/// Score below which all RPCs from a peer are ignored
+pub const GRAYLIST_THRESHOLD: f64 = -16000.0;
+/// Score above which peer exchange from a peer is accepted
+pub const ACCEPT_PX_THRESHOLD: f64 = 100.0;
+/// Median mesh score below which we opportunistically graft better peers
+pub const OPPORTUNISTIC_GRAFT_THRESHOLD: f64 = 5.0;
+
+/// Slot duration, also the score decay interval
+const SLOT_DURATION: Duration = Duration::from_secs(12);
+const SLOTS_PER_EPOCH: u32 = 32;
+/// Counters decay until they drop below this fraction
+const DECAY_TO_ZERO: f64 = 0.01;
+/// Highest score a topic's time-in-mesh can contribute
+const MAX_IN_MESH_SCORE: f64 = 10.0;
+/// Highest score a topic's first deliveries can contribute
+const MAX_FIRST_MESSAGE_DELIVERIES_SCORE: f64 = 40.0;
+/// The 64 attestation subnets share the weight of a single topic
+const ATTESTATION_SUBNET_WEIGHT: f64 = 1.0 / 64.0;
+/// Rough mainnet attestations per subnet per slot (~1M validators / 32 slots / 64 subnets)
+const EXPECTED_ATTESTATIONS_PER_SUBNET_SLOT: f64 = 500.0;
+
+/// Compute the consensus-spec message id for a gossip message:
+/// `SHA256(domain + uint_to_bytes(len(topic)) + topic + payload)[:20]`, where the
+/// payload is the snappy-decompressed data if it decompresses and the raw data otherwise.
+pub fn message_id(message: &gossipsub::Message) -> MessageId {
+    MessageId::from(compute_message_id(message.topic.as_str(), &message.data))
+}
+
+/// Message-id preimage hashing, split out so it can be used without a `gossipsub::Message`
+pub fn compute_message_id(topic: &str, data: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    match decompress_snappy(data) {
+        Some(decompressed) => {
+            hasher.update(MESSAGE_DOMAIN_VALID_SNAPPY);
+            hasher.update((topic.len() as u64).to_le_bytes());
+            hasher.update(topic.as_bytes());
+            hasher.update(&decompressed);
+        }
+        None => {
+            hasher.update(MESSAGE_DOMAIN_INVALID_SNAPPY);
+            hasher.update((topic.len() as u64).to_le_bytes());
+            hasher.update(topic.as_bytes());
+            hasher.update(data);
+        }
+    }
+    hasher.finalize()[..20].to_vec()
+}
+
+/// Decompress a raw-snappy gossip payload, refusing anything over `GOSSIP_MAX_SIZE`
+pub fn decompress_snappy(data: &[u8]) -> Option<Vec<u8>> {
+    match decompress_len(data) {
+        Ok(len) if len <= GOSSIP_MAX_SIZE => Decoder::new().decompress_vec(data).ok(),
+        _ => None,
+    }
+}
+
+/// Raw-snappy compress an SSZ payload for publishing
+pub fn compress_snappy(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() > GOSSIP_MAX_SIZE {
+        return Err(anyhow!("Payload of {} bytes exceeds GOSSIP_MAX_SIZE", data.len()));
+    }
+    Encoder::new()
+        .compress_vec(data)
+        .map_err(|e| anyhow!("Snappy compression failed: {}", e))
+}
+
+/// Gossipsub parameters from the consensus p2p spec
+pub fn gossipsub_config() -> Result<gossipsub::Config> {
+    gossipsub::ConfigBuilder::default()
+        .mesh_n(MESH_N)
+        .mesh_n_low(MESH_N_LOW)
+        .mesh_n_high(MESH_N_HIGH)
+        .gossip_lazy(GOSSIP_LAZY)
+        .heartbeat_interval(HEARTBEAT_INTERVAL)
+        .fanout_ttl(FANOUT_TTL)
+        .history_length(HISTORY_LENGTH)
+        .history_gossip(HISTORY_GOSSIP)
+        .duplicate_cache_time(HEARTBEAT_INTERVAL * SEEN_TTL_HEARTBEATS)
+        .max_transmit_size(GOSSIP_MAX_SIZE)
+        // Our own messages go to the mesh (or fanout) like forwarded ones, not to every topic peer
+        .flood_publish(false)
+        .validation_mode(ValidationMode::Anonymous)
+        // Nothing is forwarded until the network loop reports a validation result
+        .validate_messages()
+        .message_id_fn(message_id)
+        .build()
+        .map_err(|e| anyhow!("Invalid gossipsub config: {}", e))
+}
+
+/// Per-tick decay factor that brings a counter to `DECAY_TO_ZERO` after `decay_time`
+fn score_parameter_decay(decay_time: Duration) -> f64 {
+    let ticks = decay_time.as_secs_f64() / SLOT_DURATION.as_secs_f64();
+    DECAY_TO_ZERO.powf(1.0 / ticks)
+}
+
+/// Value a decaying counter converges to when incremented by `rate` every tick
+fn decay_convergence(decay: f64, rate: f64) -> f64 {
+    rate / (1.0 - decay)
+}
+
+/// Highest total positive score a peer can earn across the attestation subnets
+fn max_positive_score() -> f64 {
+    (MAX_IN_MESH_SCORE + MAX_FIRST_MESSAGE_DELIVERIES_SCORE) * ATTESTATION_SUBNET_WEIGHT * 64.0
+}
+
+/// Score thresholds used by consensus clients
+pub fn peer_score_thresholds() -> PeerScoreThresholds {
+    PeerScoreThresholds {
+        gossip_threshold: GOSSIP_THRESHOLD,
+        publish_threshold: PUBLISH_THRESHOLD,
+        graylist_threshold: GRAYLIST_THRESHOLD,
+        accept_px_threshold: ACCEPT_PX_THRESHOLD,
+        opportunistic_graft_threshold: OPPORTUNISTIC_GRAFT_THRESHOLD,
+    }
+}
+
+/// Global score parameters; topic parameters are added per subnet with [`attestation_topic_params`]
+pub fn peer_score_params() -> PeerScoreParams {
+    let epoch = SLOT_DURATION * SLOTS_PER_EPOCH;
+    let behaviour_penalty_threshold = 6.0;
+    let behaviour_penalty_decay = score_parameter_decay(epoch * 10);
+    // A peer misbehaving ten times per epoch converges onto the gossip threshold
+    let target_penalty =
+        decay_convergence(behaviour_penalty_decay, 10.0 / SLOTS_PER_EPOCH as f64) - behaviour_penalty_threshold;
+
+    PeerScoreParams {
+        topic_score_cap: max_positive_score() * 0.5,
+        app_specific_weight: 1.0,
+        ip_colocation_factor_weight: -max_positive_score() * 0.5,
+        ip_colocation_factor_threshold: 8.0,
+        behaviour_penalty_weight: GOSSIP_THRESHOLD / (target_penalty * target_penalty),
+        behaviour_penalty_threshold,
+        behaviour_penalty_decay,
+        decay_interval: SLOT_DURATION,
+        decay_to_zero: DECAY_TO_ZERO,
+        retain_score: epoch * 100,
+        ..Default::default()
+    }
+}
+
+/// Topic score parameters for a `beacon_attestation_{subnet}` topic. Mesh delivery
+/// penalties are left off: juggled subnets come and go faster than their activation window.
+pub fn attestation_topic_params() -> TopicScoreParams {
+    let epoch = SLOT_DURATION * SLOTS_PER_EPOCH;
+    let time_in_mesh_cap = 3600.0 / SLOT_DURATION.as_secs_f64();
+    let first_message_deliveries_decay = score_parameter_decay(epoch);
+    let first_message_deliveries_cap = decay_convergence(
+        first_message_deliveries_decay,
+        2.0 * EXPECTED_ATTESTATIONS_PER_SUBNET_SLOT / MESH_N as f64,
+    );
+
+    TopicScoreParams {
+        topic_weight: ATTESTATION_SUBNET_WEIGHT,
+        time_in_mesh_weight: MAX_IN_MESH_SCORE / time_in_mesh_cap,
+        time_in_mesh_quantum: SLOT_DURATION,
+        time_in_mesh_cap,
+        first_message_deliveries_weight: MAX_FIRST_MESSAGE_DELIVERIES_SCORE / first_message_deliveries_cap,
+        first_message_deliveries_decay,
+        first_message_deliveries_cap,
+        mesh_message_deliveries_weight: 0.0,
+        mesh_failure_penalty_weight: 0.0,
+        invalid_message_deliveries_weight: -max_positive_score() / ATTESTATION_SUBNET_WEIGHT,
+        invalid_message_deliveries_decay: score_parameter_decay(epoch * 50),
+        ..Default::default()
+    }
+}
+
+/// Build a gossipsub behaviour that publishes anonymous, unsigned messages
+/// (`StrictNoSign`), so nothing we publish carries our peer identity.
+pub fn build_gossipsub() -> Result<gossipsub::Behaviour> {
+    let mut behaviour = gossipsub::Behaviour::new(MessageAuthenticity::Anonymous, gossipsub_config()?)
+        .map_err(|e| anyhow!("Failed to create gossipsub behaviour: {}", e))?;
+    behaviour
+        .with_peer_score(peer_score_params(), peer_score_thresholds())
+        .map_err(|e| anyhow!("Invalid peer score parameters: {}", e))?;
+    Ok(behaviour)
+}
+
+/// Subscribe to an attestation subnet topic and give it score parameters
+pub fn subscribe_attestation_topic(gossipsub: &mut gossipsub::Behaviour, topic: &gossipsub::IdentTopic) -> Result<bool> {
+    let subscribed = gossipsub.subscribe(topic)?;
+    gossipsub
+        .set_topic_params(topic.clone(), attestation_topic_params())
+        .map_err(|e| anyhow!("Failed to set topic score parameters: {}", e))?;
+    Ok(subscribed)
+}
+
+/// Share of last sample's mesh peers still grafted now, averaged over topics and smoothed.
+/// Other peers pruning us because of a poor score shows up here as a falling value.
+#[derive(Debug, Clone)]
+pub struct MeshStability {
+    previous: HashMap<TopicHash, HashSet<PeerId>>,
+    stability: f64,
+}
+
+impl MeshStability {
+    /// Weight of the newest sample in the moving average
+    const SMOOTHING: f64 = 0.2;
+
+    pub fn new() -> Self {
+        Self {
+            previous: HashMap::new(),
+            stability: 1.0,
+        }
+    }
+
+    /// Compare current meshes with the last sample; topics we left in between are ignored
+    pub fn sample(&mut self, gossipsub: &gossipsub::Behaviour) {
+        let current: HashMap<TopicHash, HashSet<PeerId>> = gossipsub
+            .topics()
+            .map(|topic| (topic.clone(), gossipsub.mesh_peers(topic).copied().collect()))
+            .collect();
+        self.record(current);
+    }
+
+    fn record(&mut self, current: HashMap<TopicHash, HashSet<PeerId>>) {
+        let retained: Vec<f64> = current
+            .iter()
+            .filter_map(|(topic, peers)| {
+                let previous = self.previous.get(topic).filter(|p| !p.is_empty())?;
+                Some(previous.intersection(peers).count() as f64 / previous.len() as f64)
+            })
+            .collect();
+        if !retained.is_empty() {
+            let sample = retained.iter().sum::<f64>() / retained.len() as f64;
+            self.stability = Self::SMOOTHING * sample + (1.0 - Self::SMOOTHING) * self.stability;
+        }
+        self.previous = current;
+    }
+
+    /// Smoothed fraction of mesh peers retained between samples (1.0 = no churn)
+    pub fn stability(&self) -> f64 {
+        self.stability
+    }
+}
+
+impl Default for MeshStability {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::gossipsub::{IdentTopic, PublishError};
+
+    const TOPIC: &str = "/eth2/7a7b8b7f/beacon_attestation_3/ssz_snappy";
+
+    #[test]
+    fn test_message_id_uses_decompressed_payload() {
+        let payload = vec![42u8; 228];
+        let compressed = Encoder::new().compress_vec(&payload).unwrap();
+
+        let mut preimage = MESSAGE_DOMAIN_VALID_SNAPPY.to_vec();
+        preimage.extend_from_slice(&(TOPIC.len() as u64).to_le_bytes());
+        preimage.extend_from_slice(TOPIC.as_bytes());
+        preimage.extend_from_slice(&payload);
+        let expected = Sha256::digest(&preimage)[..20].to_vec();
+
+        assert_eq!(compute_message_id(TOPIC, &compressed), expected);
+        // Same payload on another topic is a different message
+        assert_ne!(
+            compute_message_id("/eth2/7a7b8b7f/beacon_attestation_4/ssz_snappy", &compressed),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_message_id_invalid_snappy_domain() {
+        let garbage = vec![0xff, 0xff, 0xff, 0xff, 0xff];
+        assert!(decompress_snappy(&garbage).is_none());
+
+        let mut preimage = MESSAGE_DOMAIN_INVALID_SNAPPY.to_vec();
+        preimage.extend_from_slice(&(TOPIC.len() as u64).to_le_bytes());
+        preimage.extend_from_slice(TOPIC.as_bytes());
+        preimage.extend_from_slice(&garbage);
+
+        assert_eq!(compute_message_id(TOPIC, &garbage), Sha256::digest(&preimage)[..20].to_vec());
+    }
+
+    #[test]
+    fn test_spec_gossipsub_config() {
+        let config = gossipsub_config().unwrap();
+        assert_eq!(config.mesh_n(), MESH_N);
+        assert_eq!(config.mesh_n_low(), MESH_N_LOW);
+        assert_eq!(config.mesh_n_high(), MESH_N_HIGH);
+        assert_eq!(config.heartbeat_interval(), Duration::from_millis(700));
+        assert_eq!(config.duplicate_cache_time(), Duration::from_millis(385_000));
+        assert!(matches!(config.validation_mode(), ValidationMode::Anonymous));
+        assert!(!config.flood_publish());
+        assert_eq!(config.fanout_ttl(), config.heartbeat_interval());
+        assert!(config.validate_messages());
+        assert!(build_gossipsub().is_ok());
+    }
+
+    #[test]
+    fn test_publish_without_peers_reports_insufficient_peers() {
+        let mut gossipsub = build_gossipsub().unwrap();
+        let topic = IdentTopic::new(TOPIC);
+        gossipsub.subscribe(&topic).unwrap();
+
+        let data = compress_snappy(&[7u8; 240]).unwrap();
+        assert_eq!(decompress_snappy(&data), Some(vec![7u8; 240]));
+        assert!(matches!(gossipsub.publish(topic, data), Err(PublishError::InsufficientPeers)));
+        assert!(compress_snappy(&vec![0u8; GOSSIP_MAX_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn test_peer_score_params_are_valid() {
+        assert!(peer_score_params().validate().is_ok());
+        assert!(peer_score_thresholds().validate().is_ok());
+        assert!(attestation_topic_params().validate().is_ok());
+
+        // A full hour in the mesh with capped first deliveries is worth the topic's share
+        let topic = attestation_topic_params();
+        let max_topic_score = topic.time_in_mesh_weight * topic.time_in_mesh_cap
+            + topic.first_message_deliveries_weight * topic.first_message_deliveries_cap;
+        assert!((max_topic_score - (MAX_IN_MESH_SCORE + MAX_FIRST_MESSAGE_DELIVERIES_SCORE)).abs() < 1e-9);
+        // Epoch decay takes a counter to 1% in 32 slots
+        assert!((score_parameter_decay(SLOT_DURATION * 32).powi(32) - DECAY_TO_ZERO).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_mesh_stability_tracks_pruning() {
+        let topic = IdentTopic::new(TOPIC).hash();
+        let peers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+        let mut stability = MeshStability::new();
+
+        stability.record([(topic.clone(), peers.iter().copied().collect())].into_iter().collect());
+        assert_eq!(stability.stability(), 1.0);
+
+        // Half of our mesh pruned us
+        stability.record([(topic.clone(), peers[..2].iter().copied().collect())].into_iter().collect());
+        assert!((stability.stability() - 0.9).abs() < 1e-9);
+
+        // Leaving the topic ourselves is not counted as churn
+        stability.record(HashMap::new());
+        assert!((stability.stability() - 0.9).abs() < 1e-9);
+    }
+}
@@ -10,8 +10,31 @@ use tokio::sync::{mpsc, watch};
 use tokio::time::interval;
 use tracing::{debug, error, info, warn};
 
+pub mod addresses;
+pub mod attestation;
+pub mod bandwidth;
 pub mod beacon_network;
-pub use beacon_network::{BeaconNetworkProvider, NetworkEvent, NetworkCommand};
+pub mod connection_manager;
+pub mod discovery;
+pub mod exposure;
+pub mod fork;
+pub mod gossip;
+pub mod node_identity;
+pub mod peer_filter;
+pub mod relay_publisher;
+pub mod rpc;
+pub mod topics;
+pub mod validation;
+pub use attestation::{AttestationFormat, DecodedAttestation};
+pub use bandwidth::{BandwidthReport, ByteCounts};
+pub use beacon_network::{
+    AttestationPublisher, BandwidthMonitor, BeaconNetworkProvider, ExposureMonitor, NetworkCommand, NetworkEvent,
+    PeerFilterHandle,
+};
+pub use connection_manager::{ConnectionManager, ConnectionManagerConfig, ConnectionStats};
+pub use discovery::{DiscoveredPeer, Discovery, DiscoveryConfig};
+pub use exposure::ExposureReport;
+pub use peer_filter::{PeerFilterUpdate, PeerRule};
 
 /// Commands that can be sent to the SubnetJuggler
 #[derive(Debug, Clone)]
@@ -66,6 +89,17 @@ pub trait NetworkingProvider: Send + Sync {
     
     /// Get validator's required subnets for current epoch
     async fn get_validator_subnets(&self, validator_pubkey: &str) -> StealthResult<Vec<SubnetId>>;
+
+    /// Called at each epoch boundary before the extra subnets are reshuffled;
+    /// `retained` are the subnets that stay subscribed across the reshuffle
+    async fn prepare_reshuffle(&self, _epoch: u64, _retained: &[SubnetId]) -> StealthResult<()> {
+        Ok(())
+    }
+
+    /// Measured bandwidth, if the provider tracks it
+    async fn bandwidth_report(&self) -> StealthResult<Option<BandwidthReport>> {
+        Ok(None)
+    }
 }
 
 /// Implementation of NetworkingProvider using reth RPC (deprecated - use SystemClockProvider)
@@ -357,6 +391,12 @@ impl<P: NetworkingProvider> SubnetJuggler<P> {
         self.state.next_reshuffle = Utc::now() + 
             chrono::Duration::seconds(epoch_info.seconds_until_next_epoch() as i64);
 
+        // Let the provider rotate its identity before the new subnets are joined
+        let retained: Vec<SubnetId> = self.state.mandatory_subnets.iter().copied().collect();
+        if let Err(e) = self.provider.prepare_reshuffle(epoch_info.epoch, &retained).await {
+            warn!("Failed to prepare network for reshuffle: {}", e);
+        }
+
         // Reshuffle extra subnets
         let new_subnets = self.reshuffle_extra_subnets().await?;
 
@@ -372,6 +412,16 @@ impl<P: NetworkingProvider> SubnetJuggler<P> {
     async fn reshuffle_extra_subnets(&mut self) -> StealthResult<Vec<SubnetId>> {
         info!("Reshuffling extra subnets for epoch {}", self.state.current_epoch);
 
+        // Log what the outgoing extra subnets cost before leaving them
+        match self.extra_subnet_bandwidth().await {
+            Ok(costs) => {
+                for (subnet, bytes) in costs {
+                    debug!("📶 Extra subnet {} used {} KiB over the last hour", subnet.0, bytes.total() / 1024);
+                }
+            }
+            Err(e) => debug!("Bandwidth report unavailable: {}", e),
+        }
+
         // Unsubscribe from old extra subnets
         let old_extra_subnets: Vec<_> = self.state.extra_subnets.iter().cloned().collect();
         for subnet in &old_extra_subnets {
@@ -478,6 +528,19 @@ impl<P: NetworkingProvider> SubnetJuggler<P> {
     pub fn get_state(&self) -> &SubnetState {
         &self.state
     }
+
+    /// Bytes each current extra subnet used over the last hour, the price of our cover
+    pub async fn extra_subnet_bandwidth(&self) -> StealthResult<HashMap<SubnetId, ByteCounts>> {
+        let Some(report) = self.provider.bandwidth_report().await? else {
+            return Ok(HashMap::new());
+        };
+        Ok(self
+            .state
+            .extra_subnets
+            .iter()
+            .map(|subnet| (*subnet, report.subnet_last_hour(subnet.0)))
+            .collect())
+    }
 }
 
 /// Handle to interact with the SubnetJuggler
@@ -556,6 +619,18 @@ mod tests {
         async fn get_validator_subnets(&self, _validator_pubkey: &str) -> StealthResult<Vec<SubnetId>> {
             Ok(vec![SubnetId::new(0)?, SubnetId::new(1)?])
         }
+
+        async fn bandwidth_report(&self) -> StealthResult<Option<BandwidthReport>> {
+            // 1 KiB per hour for every subscribed subnet
+            let last_hour = self
+                .subscribed_subnets
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|s| (format!("beacon_attestation_{}", s.0), ByteCounts { inbound: 1024, outbound: 0 }))
+                .collect();
+            Ok(Some(BandwidthReport { last_hour, ..Default::default() }))
+        }
     }
 
     #[tokio::test]
@@ -591,4 +666,18 @@ mod tests {
         assert_ne!(initial_extra_subnets, juggler.state.extra_subnets);
         assert_eq!(new_subnets.len(), juggler.config.extra_subnets_per_epoch);
     }
+
+    #[tokio::test]
+    async fn test_extra_subnet_bandwidth() {
+        let config = StealthConfig::default();
+        let provider = MockProvider::new();
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let (mut juggler, _handle) = SubnetJuggler::new(config, provider, shutdown_rx);
+        juggler.initialize().await.unwrap();
+
+        let costs = juggler.extra_subnet_bandwidth().await.unwrap();
+        assert_eq!(costs.len(), juggler.state.extra_subnets.len());
+        assert!(costs.values().all(|bytes| bytes.inbound == 1024));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,133 @@
+use anyhow::{anyhow, Context, Result};
+use libp2p_identity::{secp256k1, Keypair};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use tracing::{info, warn};
+
+/// Key file used when `node_key_path` is not configured
+pub const DEFAULT_NODE_KEY_PATH: &str = "stealth-node.key";
+
+/// Load the secp256k1 node key from `path`, creating it (mode 0600) if it does not exist.
+/// The file holds the raw 32-byte secret, the same layout consensus clients use.
+pub fn load_or_create_keypair(path: &Path) -> Result<Keypair> {
+    if !path.exists() {
+        let keypair = Keypair::generate_secp256k1();
+        write_keypair(path, &keypair)?;
+        info!("🔑 Generated new node key at {}", path.display());
+        return Ok(keypair);
+    }
+
+    enforce_permissions(path)?;
+    let mut bytes = fs::read(path).with_context(|| format!("Failed to read node key {}", path.display()))?;
+    if bytes.len() != 32 {
+        return Err(anyhow!("Node key {} must be 32 bytes, found {}", path.display(), bytes.len()));
+    }
+    let secret = secp256k1::SecretKey::try_from_bytes(&mut bytes)
+        .map_err(|e| anyhow!("Invalid node key {}: {}", path.display(), e))?;
+    info!("🔑 Loaded node key from {}", path.display());
+    Ok(secp256k1::Keypair::from(secret).into())
+}
+
+/// Atomically replace the key file with `keypair`, readable by the owner only
+pub fn write_keypair(path: &Path, keypair: &Keypair) -> Result<()> {
+    let secret = keypair
+        .clone()
+        .try_into_secp256k1()
+        .map_err(|_| anyhow!("Node key must be secp256k1"))?
+        .secret()
+        .to_bytes();
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&tmp_path)
+        .with_context(|| format!("Failed to create node key {}", tmp_path.display()))?;
+    file.write_all(&secret)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Tighten a key file that is readable by group or others back to 0600
+#[cfg(unix)]
+fn enforce_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        warn!("🔑 Node key {} had mode {:o}, restricting to 600", path.display(), mode & 0o777);
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn enforce_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Whether a new identity should take over at the reshuffle for `epoch`
+pub fn rotation_due(epoch: u64, rotation_epochs: Option<u64>) -> bool {
+    // `is_multiple_of(0)` only holds for zero, which is excluded anyway
+    rotation_epochs.is_some_and(|every| epoch > 0 && epoch.is_multiple_of(every))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::PeerId;
+
+    #[test]
+    fn test_key_file_is_created_and_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("network").join("node.key");
+
+        let created = load_or_create_keypair(&path).unwrap();
+        assert!(created.clone().try_into_secp256k1().is_ok());
+        assert_eq!(fs::read(&path).unwrap().len(), 32);
+
+        let loaded = load_or_create_keypair(&path).unwrap();
+        assert_eq!(PeerId::from(created.public()), PeerId::from(loaded.public()));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+            // A key left world-readable is locked down on the next load
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+            load_or_create_keypair(&path).unwrap();
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_rejects_bad_key_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("node.key");
+        fs::write(&path, [1u8; 16]).unwrap();
+        assert!(load_or_create_keypair(&path).is_err());
+
+        // Only secp256k1 identities can be persisted
+        assert!(write_keypair(&path, &Keypair::generate_ed25519()).is_err());
+    }
+
+    #[test]
+    fn test_rotation_schedule() {
+        assert!(!rotation_due(100, None));
+        assert!(!rotation_due(100, Some(0)));
+        assert!(rotation_due(100, Some(10)));
+        assert!(!rotation_due(101, Some(10)));
+        assert!(!rotation_due(0, Some(10)));
+    }
+}
@@ -0,0 +1,457 @@
+use anyhow::{anyhow, Result};
+use ipnet::IpNet;
+use libp2p::{
+    core::{transport::PortUse, Endpoint},
+    multiaddr::Protocol,
+    swarm::{
+        dummy, CloseConnection, ConnectionClosed, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour,
+        THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+use stealth_common::NetworkConfig;
+
+/// Distinct peers allowed behind a single IP
+pub const DEFAULT_MAX_PEERS_PER_IP: usize = 2;
+/// Distinct peers allowed per /24 (IPv4) or /64 (IPv6) block
+pub const DEFAULT_MAX_PEERS_PER_IP_BLOCK: usize = 5;
+/// Suspicion at or above which we stop dialling a peer
+pub const SUSPICION_DIAL_THRESHOLD: f64 = 0.8;
+/// Gossipsub application score of a fully suspicious peer; any negative score
+/// gets a peer pruned from our meshes and ranked first for disconnection
+const SUSPICION_SCORE_PENALTY: f64 = -100.0;
+
+/// A peer ID, IP address or CIDR range in an allow or deny list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerRule {
+    Peer(PeerId),
+    Network(IpNet),
+}
+
+impl PeerRule {
+    fn matches(&self, peer_id: Option<&PeerId>, ip: Option<IpAddr>) -> bool {
+        match self {
+            PeerRule::Peer(rule) => peer_id == Some(rule),
+            PeerRule::Network(net) => ip.is_some_and(|ip| net.contains(&ip)),
+        }
+    }
+}
+
+impl FromStr for PeerRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Ok(peer_id) = s.parse::<PeerId>() {
+            return Ok(PeerRule::Peer(peer_id));
+        }
+        if let Ok(net) = s.parse::<IpNet>() {
+            return Ok(PeerRule::Network(net.trunc()));
+        }
+        s.parse::<IpAddr>()
+            .map(|ip| PeerRule::Network(IpNet::from(ip)))
+            .map_err(|_| anyhow!("Invalid peer rule {:?}, expected a peer ID, IP or CIDR", s))
+    }
+}
+
+/// Runtime change to the peer filter, see [`crate::PeerFilterHandle`]
+#[derive(Debug, Clone)]
+pub enum PeerFilterUpdate {
+    Allow(PeerRule),
+    Deny(PeerRule),
+    /// Drop the rule from both lists
+    Remove(PeerRule),
+    /// Sentinel suspicion from 0.0 (none) to 1.0, as estimated by an analyzer
+    Suspicion { peer_id: PeerId, score: f64 },
+}
+
+/// Which peers we talk to. Allowlisted peers skip the IP caps and suspicion and are
+/// never pruned; denylisted peers are refused and disconnected.
+#[derive(Debug, Clone)]
+pub struct PeerFilterConfig {
+    pub allow: Vec<PeerRule>,
+    pub deny: Vec<PeerRule>,
+    pub max_peers_per_ip: usize,
+    pub max_peers_per_ip_block: usize,
+    pub suspicion: HashMap<PeerId, f64>,
+}
+
+impl Default for PeerFilterConfig {
+    fn default() -> Self {
+        Self {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            max_peers_per_ip: DEFAULT_MAX_PEERS_PER_IP,
+            max_peers_per_ip_block: DEFAULT_MAX_PEERS_PER_IP_BLOCK,
+            suspicion: HashMap::new(),
+        }
+    }
+}
+
+impl PeerFilterConfig {
+    pub fn from_config(network_config: &NetworkConfig) -> Result<Self> {
+        let parse = |rules: &Option<Vec<String>>| -> Result<Vec<PeerRule>> {
+            rules.iter().flatten().map(|rule| rule.parse()).collect()
+        };
+        Ok(Self {
+            allow: parse(&network_config.peer_allowlist)?,
+            deny: parse(&network_config.peer_denylist)?,
+            max_peers_per_ip: network_config.max_peers_per_ip.unwrap_or(DEFAULT_MAX_PEERS_PER_IP),
+            max_peers_per_ip_block: network_config
+                .max_peers_per_ip_block
+                .unwrap_or(DEFAULT_MAX_PEERS_PER_IP_BLOCK),
+            suspicion: HashMap::new(),
+        })
+    }
+
+    pub fn apply(&mut self, update: PeerFilterUpdate) {
+        match update {
+            PeerFilterUpdate::Allow(rule) => {
+                self.deny.retain(|r| *r != rule);
+                if !self.allow.contains(&rule) {
+                    self.allow.push(rule);
+                }
+            }
+            PeerFilterUpdate::Deny(rule) => {
+                self.allow.retain(|r| *r != rule);
+                if !self.deny.contains(&rule) {
+                    self.deny.push(rule);
+                }
+            }
+            PeerFilterUpdate::Remove(rule) => {
+                self.allow.retain(|r| *r != rule);
+                self.deny.retain(|r| *r != rule);
+            }
+            PeerFilterUpdate::Suspicion { peer_id, score } => {
+                let score = score.clamp(0.0, 1.0);
+                if score > 0.0 {
+                    self.suspicion.insert(peer_id, score);
+                } else {
+                    self.suspicion.remove(&peer_id);
+                }
+            }
+        }
+    }
+
+    fn allowlisted(&self, peer_id: Option<&PeerId>, ip: Option<IpAddr>) -> bool {
+        self.allow.iter().any(|rule| rule.matches(peer_id, ip))
+    }
+
+    fn denylisted(&self, peer_id: Option<&PeerId>, ip: Option<IpAddr>) -> bool {
+        self.deny.iter().any(|rule| rule.matches(peer_id, ip))
+    }
+}
+
+/// Why a connection was refused
+#[derive(Debug, thiserror::Error)]
+pub enum Denied {
+    #[error("peer is denylisted")]
+    Denylisted,
+    #[error("too many peers from {0}")]
+    IpLimit(IpAddr),
+    #[error("too many peers from {0}")]
+    IpBlockLimit(IpNet),
+    #[error("peer is suspected of being a sentinel")]
+    Suspicious,
+}
+
+/// Connection gate enforcing the allow and deny lists and the per-IP caps
+#[derive(Debug)]
+pub struct PeerFilter {
+    config: PeerFilterConfig,
+    established: HashMap<ConnectionId, (PeerId, IpAddr)>,
+    to_close: VecDeque<PeerId>,
+    denied: u64,
+}
+
+impl PeerFilter {
+    pub fn new(config: PeerFilterConfig) -> Self {
+        Self {
+            config,
+            established: HashMap::new(),
+            to_close: VecDeque::new(),
+            denied: 0,
+        }
+    }
+
+    /// Apply an update, disconnecting peers it denies
+    pub fn update(&mut self, update: PeerFilterUpdate) {
+        self.config.apply(update);
+        let newly_denied: HashSet<PeerId> = self
+            .established
+            .values()
+            .filter(|(peer_id, ip)| self.refuses(peer_id, *ip))
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        self.to_close.extend(newly_denied);
+    }
+
+    /// Gossipsub application score for `peer_id`, zero unless it is suspected
+    pub fn gossip_penalty(&self, peer_id: &PeerId) -> f64 {
+        SUSPICION_SCORE_PENALTY * self.suspicion(peer_id)
+    }
+
+    /// Allowlisted peers are never pruned
+    pub fn is_protected(&self, peer_id: &PeerId) -> bool {
+        self.config.allowlisted(Some(peer_id), None)
+            || self
+                .established
+                .values()
+                .any(|(peer, ip)| peer == peer_id && self.config.allowlisted(None, Some(*ip)))
+    }
+
+    /// Connections refused so far
+    pub fn denied(&self) -> u64 {
+        self.denied
+    }
+
+    fn suspicion(&self, peer_id: &PeerId) -> f64 {
+        if self.is_protected(peer_id) {
+            return 0.0;
+        }
+        self.config.suspicion.get(peer_id).copied().unwrap_or(0.0)
+    }
+
+    fn refuses(&self, peer_id: &PeerId, ip: IpAddr) -> bool {
+        !self.config.allowlisted(Some(peer_id), Some(ip)) && self.config.denylisted(Some(peer_id), Some(ip))
+    }
+
+    /// Whether `peer_id` may hold a connection from `ip` next to the ones we have
+    fn admit(&self, peer_id: PeerId, ip: Option<IpAddr>) -> Result<(), Denied> {
+        if self.config.allowlisted(Some(&peer_id), ip) {
+            return Ok(());
+        }
+        if self.config.denylisted(Some(&peer_id), ip) {
+            return Err(Denied::Denylisted);
+        }
+        let Some(ip) = ip else {
+            return Ok(());
+        };
+        let block = ip_block(ip);
+        let mut same_ip = HashSet::new();
+        let mut same_block = HashSet::new();
+        for (peer, peer_ip) in self.established.values().filter(|(peer, _)| *peer != peer_id) {
+            if *peer_ip == ip {
+                same_ip.insert(peer);
+            }
+            if block.contains(peer_ip) {
+                same_block.insert(peer);
+            }
+        }
+        if same_ip.len() >= self.config.max_peers_per_ip {
+            return Err(Denied::IpLimit(ip));
+        }
+        if same_block.len() >= self.config.max_peers_per_ip_block {
+            return Err(Denied::IpBlockLimit(block));
+        }
+        Ok(())
+    }
+
+    fn on_established(&mut self, connection_id: ConnectionId, peer_id: PeerId, ip: Option<IpAddr>) {
+        if let Some(ip) = ip {
+            self.established.insert(connection_id, (peer_id, ip));
+        }
+    }
+
+    fn deny(&mut self, reason: Denied) -> ConnectionDenied {
+        self.denied += 1;
+        ConnectionDenied::new(reason)
+    }
+}
+
+impl NetworkBehaviour for PeerFilter {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        let ip = multiaddr_ip(remote_addr);
+        if !self.config.allowlisted(None, ip) && self.config.denylisted(None, ip) {
+            return Err(self.deny(Denied::Denylisted));
+        }
+        Ok(())
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        _: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        let ip = multiaddr_ip(remote_addr);
+        if let Err(reason) = self.admit(peer, ip) {
+            return Err(self.deny(reason));
+        }
+        self.on_established(connection_id, peer, ip);
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        _: &[Multiaddr],
+        _: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        if let Some(peer_id) = maybe_peer {
+            if self.config.denylisted(Some(&peer_id), None) && !self.config.allowlisted(Some(&peer_id), None) {
+                return Err(self.deny(Denied::Denylisted));
+            }
+            if self.suspicion(&peer_id) >= SUSPICION_DIAL_THRESHOLD {
+                return Err(self.deny(Denied::Suspicious));
+            }
+        }
+        Ok(vec![])
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        _: Endpoint,
+        _: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        let ip = multiaddr_ip(addr);
+        if let Err(reason) = self.admit(peer, ip) {
+            return Err(self.deny(reason));
+        }
+        self.on_established(connection_id, peer, ip);
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        if let FromSwarm::ConnectionClosed(ConnectionClosed { connection_id, .. }) = event {
+            self.established.remove(&connection_id);
+        }
+    }
+
+    fn on_connection_handler_event(&mut self, _: PeerId, _: ConnectionId, event: THandlerOutEvent<Self>) {
+        match event {}
+    }
+
+    fn poll(&mut self, _: &mut Context<'_>) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        match self.to_close.pop_front() {
+            Some(peer_id) => Poll::Ready(ToSwarm::CloseConnection {
+                peer_id,
+                connection: CloseConnection::All,
+            }),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// The /24 (IPv4) or /64 (IPv6) block `ip` belongs to
+fn ip_block(ip: IpAddr) -> IpNet {
+    let prefix = if ip.is_ipv4() { 24 } else { 64 };
+    IpNet::new(ip, prefix).expect("prefix fits the address family").trunc()
+}
+
+fn multiaddr_ip(address: &Multiaddr) -> Option<IpAddr> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::Ip4(ip) => Some(IpAddr::V4(ip)),
+        Protocol::Ip6(ip) => Some(IpAddr::V6(ip)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_identity::Keypair;
+
+    fn peer() -> PeerId {
+        PeerId::from(Keypair::generate_secp256k1().public())
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let peer_id = peer();
+        assert_eq!(peer_id.to_string().parse::<PeerRule>().unwrap(), PeerRule::Peer(peer_id));
+        assert_eq!(
+            "10.1.2.3".parse::<PeerRule>().unwrap(),
+            PeerRule::Network("10.1.2.3/32".parse().unwrap())
+        );
+        // Host bits of a CIDR are dropped
+        assert_eq!(
+            " 10.1.2.3/16 ".parse::<PeerRule>().unwrap(),
+            PeerRule::Network("10.1.0.0/16".parse().unwrap())
+        );
+        assert!("2001:db8::/32".parse::<PeerRule>().is_ok());
+        assert!("not-a-peer".parse::<PeerRule>().is_err());
+
+        let config = NetworkConfig {
+            peer_denylist: Some(vec!["192.168.0.0/16".to_string(), "nope".to_string()]),
+            ..stealth_common::StealthConfig::default().network
+        };
+        assert!(PeerFilterConfig::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_ip_caps_and_allowlist() {
+        let mut filter = PeerFilter::new(PeerFilterConfig {
+            max_peers_per_ip: 1,
+            max_peers_per_ip_block: 2,
+            allow: vec!["81.2.69.0/24".parse().unwrap()],
+            ..Default::default()
+        });
+        let first = peer();
+        filter.on_established(ConnectionId::new_unchecked(1), first, ip("5.9.10.1"));
+        // The same peer may open a second connection, e.g. QUIC next to TCP
+        assert!(filter.admit(first, ip("5.9.10.1")).is_ok());
+        assert!(matches!(filter.admit(peer(), ip("5.9.10.1")), Err(Denied::IpLimit(_))));
+
+        filter.on_established(ConnectionId::new_unchecked(2), peer(), ip("5.9.10.2"));
+        assert!(matches!(filter.admit(peer(), ip("5.9.10.3")), Err(Denied::IpBlockLimit(_))));
+        assert!(filter.admit(peer(), ip("5.9.11.3")).is_ok());
+
+        // Allowlisted ranges are not capped
+        for n in 0..4 {
+            filter.on_established(ConnectionId::new_unchecked(10 + n), peer(), ip("81.2.69.160"));
+        }
+        let trusted = peer();
+        assert!(filter.admit(trusted, ip("81.2.69.160")).is_ok());
+        filter.on_established(ConnectionId::new_unchecked(20), trusted, ip("81.2.69.160"));
+        assert!(filter.is_protected(&trusted));
+        assert!(!filter.is_protected(&first));
+    }
+
+    #[test]
+    fn test_runtime_updates() {
+        let mut filter = PeerFilter::new(PeerFilterConfig::default());
+        let sentinel = peer();
+        let other = peer();
+        filter.on_established(ConnectionId::new_unchecked(1), sentinel, ip("5.9.10.1"));
+        filter.on_established(ConnectionId::new_unchecked(2), other, ip("6.9.10.1"));
+
+        filter.update(PeerFilterUpdate::Suspicion { peer_id: sentinel, score: 2.0 });
+        assert_eq!(filter.gossip_penalty(&sentinel), SUSPICION_SCORE_PENALTY);
+        assert_eq!(filter.gossip_penalty(&other), 0.0);
+        assert!(filter.to_close.is_empty());
+
+        // Denying an IP disconnects whoever is behind it
+        filter.update(PeerFilterUpdate::Deny("5.9.10.0/24".parse().unwrap()));
+        assert_eq!(filter.to_close.pop_front(), Some(sentinel));
+        assert!(matches!(filter.admit(peer(), ip("5.9.10.7")), Err(Denied::Denylisted)));
+
+        filter.update(PeerFilterUpdate::Remove("5.9.10.0/24".parse().unwrap()));
+        assert!(filter.admit(peer(), ip("5.9.10.7")).is_ok());
+        filter.update(PeerFilterUpdate::Allow(PeerRule::Peer(sentinel)));
+        assert_eq!(filter.gossip_penalty(&sentinel), 0.0);
+        assert!(filter.to_close.is_empty());
+    }
+}
@@ -0,0 +1,608 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, StreamExt};
+use libp2p::{
+    allow_block_list::{self, AllowedPeers},
+    gossipsub::MessageId,
+    multiaddr::Protocol,
+    request_response::{self, OutboundRequestId, ProtocolSupport, ResponseChannel},
+    swarm::{NetworkBehaviour, SwarmEvent},
+    tcp, yamux, Multiaddr, PeerId, StreamProtocol, Swarm, SwarmBuilder,
+};
+use libp2p_identity as identity;
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+use stealth_common::NetworkConfig;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+use crate::beacon_network::NetworkCommand;
+use crate::gossip;
+
+/// Protocol a relay publisher hands attestations to its friend relays on
+pub const RELAY_PUBLISH_PROTOCOL: StreamProtocol = StreamProtocol::new("/stealth/relay-publish/1");
+/// Largest request we accept, well above any compressed attestation
+const MAX_REQUEST_SIZE: u64 = 2048;
+/// Largest response we accept: a message id or a short reason
+const MAX_RESPONSE_SIZE: u64 = 512;
+/// `result` byte of a published attestation
+const RESULT_PUBLISHED: u8 = 0;
+/// `result` byte of a refused attestation, followed by the reason
+const RESULT_REFUSED: u8 = 1;
+/// A third of a slot; an attestation published later than that is worth little
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(4);
+/// Relay node key file when `relay_node_key_path` is not configured
+pub const DEFAULT_RELAY_NODE_KEY_PATH: &str = "stealth-relay-node.key";
+/// Port relay clients reach us on, over TCP and QUIC
+pub const DEFAULT_RELAY_PORT: u16 = 9100;
+
+/// Attestation a relay publisher asks a friend relay to publish on its own mesh
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishRequest {
+    pub subnet_id: u8,
+    /// Snappy-compressed SSZ, exactly as it goes out on gossip
+    pub data: Vec<u8>,
+}
+
+/// Message id the relay published under, or why it did not
+pub type PublishResponse = Result<Vec<u8>, String>;
+
+/// Request: subnet byte then the gossip payload. Response: a result byte then
+/// the message id, or the reason the relay refused.
+#[derive(Debug, Clone, Default)]
+pub struct RelayPublishCodec;
+
+#[async_trait]
+impl request_response::Codec for RelayPublishCodec {
+    type Protocol = StreamProtocol;
+    type Request = PublishRequest;
+    type Response = PublishResponse;
+
+    async fn read_request<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<PublishRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut bytes = Vec::new();
+        io.take(MAX_REQUEST_SIZE + 1).read_to_end(&mut bytes).await?;
+        if bytes.len() as u64 > MAX_REQUEST_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Publish request too large"));
+        }
+        match bytes.split_first() {
+            Some((subnet_id, data)) => Ok(PublishRequest {
+                subnet_id: *subnet_id,
+                data: data.to_vec(),
+            }),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "Empty publish request")),
+        }
+    }
+
+    async fn read_response<T>(&mut self, _: &StreamProtocol, io: &mut T) -> io::Result<PublishResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut bytes = Vec::new();
+        io.take(MAX_RESPONSE_SIZE).read_to_end(&mut bytes).await?;
+        match bytes.split_first() {
+            Some((&RESULT_PUBLISHED, message_id)) => Ok(Ok(message_id.to_vec())),
+            Some((_, reason)) => Ok(Err(String::from_utf8_lossy(reason).into_owned())),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "Empty publish response")),
+        }
+    }
+
+    async fn write_request<T>(&mut self, _: &StreamProtocol, io: &mut T, req: PublishRequest) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        io.write_all(&[req.subnet_id]).await?;
+        io.write_all(&req.data).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(&mut self, _: &StreamProtocol, io: &mut T, res: PublishResponse) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        match res {
+            Ok(message_id) => {
+                io.write_all(&[RESULT_PUBLISHED]).await?;
+                io.write_all(&message_id).await?;
+            }
+            Err(reason) => {
+                io.write_all(&[RESULT_REFUSED]).await?;
+                io.write_all(reason.as_bytes()).await?;
+            }
+        }
+        io.close().await
+    }
+}
+
+/// Relay publish behaviour; relays answer requests, publishers only send them
+pub fn relay_publish_behaviour(support: ProtocolSupport) -> request_response::Behaviour<RelayPublishCodec> {
+    request_response::Behaviour::with_codec(
+        RelayPublishCodec,
+        [(RELAY_PUBLISH_PROTOCOL, support)],
+        request_response::Config::default().with_request_timeout(PUBLISH_TIMEOUT),
+    )
+}
+
+/// Friends the relay node talks to, on either side of the relay publish protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayPublisherConfig {
+    /// Relays our attestations are published through, each ending in `/p2p/<relay peer id>`
+    pub relays: Vec<Multiaddr>,
+    /// Relay publishers we publish for; nobody else may connect
+    pub clients: Vec<PeerId>,
+    /// Where clients reach us, unused without clients
+    pub listen_addresses: Vec<Multiaddr>,
+}
+
+impl RelayPublisherConfig {
+    /// `None` unless `network.publish_relays` or `network.relay_clients` lists a friend
+    pub fn from_config(network_config: &NetworkConfig) -> Result<Option<Self>> {
+        let relays: Vec<Multiaddr> = network_config
+            .publish_relays
+            .iter()
+            .flatten()
+            .map(|address| parse_relay(address))
+            .collect::<Result<_>>()?;
+        let clients: Vec<PeerId> = network_config
+            .relay_clients
+            .iter()
+            .flatten()
+            .map(|peer_id| peer_id.parse().map_err(|e| anyhow!("Invalid relay client {:?}: {}", peer_id, e)))
+            .collect::<Result<_>>()?;
+        if relays.is_empty() && clients.is_empty() {
+            return Ok(None);
+        }
+        let port = network_config.relay_port.unwrap_or(DEFAULT_RELAY_PORT);
+        Ok(Some(Self {
+            relays,
+            clients,
+            listen_addresses: vec![
+                format!("/ip4/0.0.0.0/tcp/{}", port).parse()?,
+                format!("/ip4/0.0.0.0/udp/{}/quic-v1", port).parse()?,
+            ],
+        }))
+    }
+}
+
+fn parse_relay(address: &str) -> Result<Multiaddr> {
+    let multiaddr: Multiaddr = address
+        .parse()
+        .map_err(|e| anyhow!("Invalid relay address {:?}: {}", address, e))?;
+    match relay_peer_id(&multiaddr) {
+        Some(_) => Ok(multiaddr),
+        None => Err(anyhow!("Relay address {:?} must end with /p2p/<peer id>", address)),
+    }
+}
+
+fn relay_peer_id(relay: &Multiaddr) -> Option<PeerId> {
+    match relay.iter().last() {
+        Some(Protocol::P2p(peer_id)) => Some(peer_id),
+        _ => None,
+    }
+}
+
+/// A publish waiting on a relay, with the relays still left to try
+struct PendingPublish {
+    request: PublishRequest,
+    untried: Vec<PeerId>,
+    response: oneshot::Sender<Result<MessageId>>,
+}
+
+/// A client's request the beacon network is publishing, and where its answer goes
+type Serving = BoxFuture<'static, (ResponseChannel<PublishResponse>, PublishResponse)>;
+
+/// Relay node behaviour: connections from or to anyone but a configured friend are
+/// denied before any protocol runs, and no identify advertises the protocol
+#[derive(NetworkBehaviour)]
+struct RelayNodeBehaviour {
+    friends: allow_block_list::Behaviour<AllowedPeers>,
+    relay_publish: request_response::Behaviour<RelayPublishCodec>,
+}
+
+/// Friend-only swarm under its own persistent identity, separate from the beacon
+/// swarm. It hands our own attestations to friend relays, which publish them on
+/// their mesh so gossip peers see the relay's address, never ours, while the main
+/// swarm stays subscribed as usual for cover. For configured clients it does the
+/// reverse and publishes their attestations through our beacon swarm.
+pub struct RelayPublisher {
+    swarm: Swarm<RelayNodeBehaviour>,
+    relays: Vec<PeerId>,
+    pending: HashMap<OutboundRequestId, PendingPublish>,
+    /// Beacon network commands, which clients' attestations are published through
+    beacon: mpsc::UnboundedSender<NetworkCommand>,
+    serving: FuturesUnordered<Serving>,
+}
+
+impl RelayPublisher {
+    pub fn new(
+        local_key: identity::Keypair,
+        config: RelayPublisherConfig,
+        beacon: mpsc::UnboundedSender<NetworkCommand>,
+    ) -> Result<Self> {
+        let mut friends = allow_block_list::Behaviour::<AllowedPeers>::default();
+        for peer_id in config.clients.iter().copied().chain(config.relays.iter().filter_map(relay_peer_id)) {
+            friends.allow_peer(peer_id);
+        }
+        let support = if config.clients.is_empty() {
+            ProtocolSupport::Outbound
+        } else {
+            ProtocolSupport::Full
+        };
+        // Same noise-secured TCP and QUIC as the beacon swarm
+        let mut swarm = SwarmBuilder::with_existing_identity(local_key)
+            .with_tokio()
+            .with_tcp(tcp::Config::default(), libp2p::noise::Config::new, yamux::Config::default)?
+            .with_quic()
+            .with_behaviour(|_key| RelayNodeBehaviour {
+                friends,
+                relay_publish: relay_publish_behaviour(support),
+            })?
+            .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+            .build();
+        info!("🔁 Relay node peer id: {}", swarm.local_peer_id());
+
+        // Clients dial us; relays are dialled on demand
+        if !config.clients.is_empty() {
+            for address in config.listen_addresses {
+                swarm.listen_on(address)?;
+            }
+        }
+        let mut relays = Vec::new();
+        for relay in config.relays {
+            if let Some(peer_id) = relay_peer_id(&relay) {
+                swarm.add_peer_address(peer_id, relay);
+                relays.push(peer_id);
+            }
+        }
+        Ok(Self {
+            swarm,
+            relays,
+            pending: HashMap::new(),
+            beacon,
+            serving: FuturesUnordered::new(),
+        })
+    }
+
+    /// Spawn the relay node loop, which handles `Publish` and `Shutdown`
+    pub fn spawn(self) -> mpsc::UnboundedSender<NetworkCommand> {
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        tokio::spawn(self.run(command_rx));
+        command_tx
+    }
+
+    async fn run(mut self, mut command_rx: mpsc::UnboundedReceiver<NetworkCommand>) {
+        loop {
+            tokio::select! {
+                command = command_rx.recv() => match command {
+                    Some(NetworkCommand::Publish { subnet_id, ssz_data, response, .. }) => {
+                        match gossip::compress_snappy(&ssz_data) {
+                            Ok(data) => self.publish(PublishRequest { subnet_id: subnet_id.0, data }, response),
+                            Err(e) => {
+                                let _ = response.send(Err(e));
+                            }
+                        }
+                    }
+                    Some(NetworkCommand::Shutdown) | None => {
+                        info!("🔁 Shutting down relay node");
+                        break;
+                    }
+                    Some(_) => {}
+                },
+
+                Some((channel, response)) = self.serving.next(), if !self.serving.is_empty() => {
+                    let _ = self.swarm.behaviour_mut().relay_publish.send_response(channel, response);
+                }
+
+                event = self.swarm.select_next_some() => self.handle_event(event),
+            }
+        }
+    }
+
+    /// Ask the relays in random order until one publishes. Never falls back to
+    /// a direct publish; that would undo the point of relaying.
+    fn publish(&mut self, request: PublishRequest, response: oneshot::Sender<Result<MessageId>>) {
+        let mut untried = self.relays.clone();
+        untried.shuffle(&mut rand::thread_rng());
+        self.try_next(
+            PendingPublish {
+                request,
+                untried,
+                response,
+            },
+            "no relay configured".to_string(),
+        );
+    }
+
+    fn try_next(&mut self, mut pending: PendingPublish, last_error: String) {
+        match pending.untried.pop() {
+            Some(relay) => {
+                let request_id = self.swarm.behaviour_mut().relay_publish.send_request(&relay, pending.request.clone());
+                self.pending.insert(request_id, pending);
+            }
+            None => {
+                let error = anyhow!(
+                    "No relay published the attestation on subnet {}: {}",
+                    pending.request.subnet_id,
+                    last_error
+                );
+                warn!("{}", error);
+                let _ = pending.response.send(Err(error));
+            }
+        }
+    }
+
+    /// Hand a client's attestation to the beacon network, answering once it has been published
+    fn serve(&mut self, client: PeerId, request: PublishRequest, channel: ResponseChannel<PublishResponse>) {
+        debug!("🔁 Relay client {} asked us to publish on subnet {}", client, request.subnet_id);
+        let (tx, rx) = oneshot::channel();
+        let _ = self.beacon.send(NetworkCommand::PublishForFriend { request, response: tx });
+        self.serving.push(Box::pin(async move {
+            let response = rx.await.unwrap_or_else(|_| Err("Beacon network stopped".to_string()));
+            if let Err(reason) = &response {
+                debug!("Refused to publish for relay client {}: {}", client, reason);
+            }
+            (channel, response)
+        }));
+    }
+
+    fn handle_event(&mut self, event: SwarmEvent<RelayNodeBehaviourEvent>) {
+        let event = match event {
+            SwarmEvent::Behaviour(RelayNodeBehaviourEvent::RelayPublish(event)) => event,
+            SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                debug!("🔁 Connected to friend {}", peer_id);
+                return;
+            }
+            _ => return,
+        };
+        match event {
+            request_response::Event::Message {
+                peer,
+                message: request_response::Message::Request { request, channel, .. },
+            } => self.serve(peer, request, channel),
+            request_response::Event::Message {
+                peer,
+                message: request_response::Message::Response { request_id, response },
+            } => {
+                let Some(pending) = self.pending.remove(&request_id) else {
+                    return;
+                };
+                match response {
+                    Ok(message_id) => {
+                        debug!("📤 Relay {} published our attestation on subnet {}", peer, pending.request.subnet_id);
+                        let _ = pending.response.send(Ok(MessageId::new(&message_id)));
+                    }
+                    Err(reason) => {
+                        debug!("Relay {} refused our attestation: {}", peer, reason);
+                        self.try_next(pending, format!("{} refused: {}", peer, reason));
+                    }
+                }
+            }
+            request_response::Event::OutboundFailure { peer, request_id, error } => {
+                if let Some(pending) = self.pending.remove(&request_id) {
+                    debug!("Failed to reach relay {}: {}", peer, error);
+                    self.try_next(pending, format!("{} unreachable: {}", peer, error));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beacon_network::attestation_topic;
+    use libp2p::gossipsub;
+    use stealth_common::SubnetId;
+
+    /// Mainnet Electra
+    const FORK_DIGEST: &str = "ad532ceb";
+
+    #[derive(NetworkBehaviour)]
+    struct RelayNode {
+        relay_publish: request_response::Behaviour<RelayPublishCodec>,
+        gossipsub: gossipsub::Behaviour,
+    }
+
+    async fn next_listen_addr<B: NetworkBehaviour>(swarm: &mut Swarm<B>) -> Multiaddr {
+        loop {
+            if let SwarmEvent::NewListenAddr { address, .. } = swarm.select_next_some().await {
+                return address;
+            }
+        }
+    }
+
+    /// Gossip peer subscribed to `subnet_id`; reports who sent it each message
+    async fn spawn_subscriber(subnet_id: u8) -> (Multiaddr, mpsc::UnboundedReceiver<PeerId>) {
+        let mut gossipsub = gossip::build_gossipsub().unwrap();
+        gossipsub.subscribe(&attestation_topic(FORK_DIGEST, subnet_id)).unwrap();
+        let mut swarm = SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_tcp(tcp::Config::default(), libp2p::noise::Config::new, yamux::Config::default)
+            .unwrap()
+            .with_behaviour(|_key| gossipsub)
+            .unwrap()
+            .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+            .build();
+        swarm.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+        let address = next_listen_addr(&mut swarm).await.with(Protocol::P2p(*swarm.local_peer_id()));
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                if let SwarmEvent::Behaviour(gossipsub::Event::Message { propagation_source, .. }) =
+                    swarm.select_next_some().await
+                {
+                    let _ = tx.send(propagation_source);
+                }
+            }
+        });
+        (address, rx)
+    }
+
+    /// In-process friend relay, not subscribed itself, that publishes what it is
+    /// handed the way a sidecar with `relay_clients` does; dials `peer` if given
+    async fn spawn_relay_node(peer: Option<Multiaddr>) -> (Multiaddr, PeerId) {
+        let mut swarm = SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_tcp(tcp::Config::default(), libp2p::noise::Config::new, yamux::Config::default)
+            .unwrap()
+            .with_behaviour(|_key| RelayNode {
+                relay_publish: relay_publish_behaviour(ProtocolSupport::Inbound),
+                gossipsub: gossip::build_gossipsub().unwrap(),
+            })
+            .unwrap()
+            .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
+            .build();
+        swarm.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+        let relay_id = *swarm.local_peer_id();
+        let address = next_listen_addr(&mut swarm).await.with(Protocol::P2p(relay_id));
+        if let Some(peer) = peer {
+            swarm.dial(peer).unwrap();
+        }
+
+        tokio::spawn(async move {
+            loop {
+                if let SwarmEvent::Behaviour(RelayNodeEvent::RelayPublish(request_response::Event::Message {
+                    message: request_response::Message::Request { request, channel, .. },
+                    ..
+                })) = swarm.select_next_some().await
+                {
+                    let response = swarm
+                        .behaviour_mut()
+                        .gossipsub
+                        .publish(attestation_topic(FORK_DIGEST, request.subnet_id), request.data)
+                        .map(|message_id| message_id.0)
+                        .map_err(|e| e.to_string());
+                    let _ = swarm.behaviour_mut().relay_publish.send_response(channel, response);
+                }
+            }
+        });
+        (address, relay_id)
+    }
+
+    /// Relay node that only publishes through `relays`
+    fn spawn_publisher(local_key: identity::Keypair, relays: Vec<Multiaddr>) -> mpsc::UnboundedSender<NetworkCommand> {
+        let config = RelayPublisherConfig {
+            relays,
+            clients: Vec::new(),
+            listen_addresses: Vec::new(),
+        };
+        RelayPublisher::new(local_key, config, mpsc::unbounded_channel().0).unwrap().spawn()
+    }
+
+    async fn publish(command_tx: &mpsc::UnboundedSender<NetworkCommand>, subnet_id: u8) -> Result<MessageId> {
+        let (tx, rx) = oneshot::channel();
+        command_tx
+            .send(NetworkCommand::Publish {
+                subnet_id: SubnetId(subnet_id),
+                ssz_data: vec![0u8; 236],
+                fanout: true,
+                response: tx,
+            })
+            .unwrap();
+        rx.await.unwrap()
+    }
+
+    #[test]
+    fn test_relay_addresses_need_a_peer_id() {
+        let mut config = stealth_common::StealthConfig::default().network;
+        assert_eq!(RelayPublisherConfig::from_config(&config).unwrap(), None);
+
+        let relay = format!("/ip4/10.0.0.1/tcp/9000/p2p/{}", PeerId::random());
+        config.publish_relays = Some(vec![relay.clone()]);
+        let parsed = RelayPublisherConfig::from_config(&config).unwrap().unwrap();
+        assert_eq!(parsed.relays, vec![relay.parse::<Multiaddr>().unwrap()]);
+
+        config.publish_relays = Some(vec!["/ip4/10.0.0.1/tcp/9000".to_string()]);
+        assert!(RelayPublisherConfig::from_config(&config).is_err());
+
+        // Clients alone bring up the relay node, listening on the relay port
+        let client = PeerId::random();
+        config.publish_relays = None;
+        config.relay_clients = Some(vec![client.to_string()]);
+        let parsed = RelayPublisherConfig::from_config(&config).unwrap().unwrap();
+        assert_eq!(parsed.clients, vec![client]);
+        assert!(parsed.listen_addresses[0].to_string().ends_with(&format!("/tcp/{}", DEFAULT_RELAY_PORT)));
+        config.relay_clients = Some(vec!["10.0.0.1".to_string()]);
+        assert!(RelayPublisherConfig::from_config(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_relays_publish_on_their_own_mesh() {
+        let (subscriber, mut received) = spawn_subscriber(5).await;
+        let (relay, relay_id) = spawn_relay_node(Some(subscriber)).await;
+        let command_tx = spawn_publisher(identity::Keypair::generate_secp256k1(), vec![relay]);
+
+        // The relay refuses until it has learned the subscriber's subscription
+        let mut published = false;
+        for _ in 0..100 {
+            if publish(&command_tx, 5).await.is_ok() {
+                published = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(published, "Relay never published the attestation");
+        let source = tokio::time::timeout(Duration::from_secs(10), received.recv()).await.unwrap().unwrap();
+        assert_eq!(source, relay_id, "The subscriber must only ever hear from the relay");
+        let _ = command_tx.send(NetworkCommand::Shutdown);
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_reported_after_every_relay() {
+        // One relay with nobody to publish to, one that does not exist
+        let (lonely, _) = spawn_relay_node(None).await;
+        let missing = format!("/ip4/127.0.0.1/tcp/1/p2p/{}", PeerId::random()).parse().unwrap();
+        let command_tx = spawn_publisher(identity::Keypair::generate_secp256k1(), vec![lonely, missing]);
+
+        let error = tokio::time::timeout(Duration::from_secs(10), publish(&command_tx, 5))
+            .await
+            .unwrap()
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("No relay published the attestation on subnet 5"), "{}", error);
+        let _ = command_tx.send(NetworkCommand::Shutdown);
+    }
+
+    #[tokio::test]
+    async fn test_relay_node_serves_only_its_clients() {
+        let client_key = identity::Keypair::generate_secp256k1();
+        let (beacon_tx, mut beacon_rx) = mpsc::unbounded_channel();
+        let config = RelayPublisherConfig {
+            relays: Vec::new(),
+            clients: vec![PeerId::from(client_key.public())],
+            listen_addresses: vec!["/ip4/127.0.0.1/tcp/0".parse().unwrap()],
+        };
+        let mut server = RelayPublisher::new(identity::Keypair::generate_secp256k1(), config, beacon_tx).unwrap();
+        let server_id = *server.swarm.local_peer_id();
+        let address = next_listen_addr(&mut server.swarm).await.with(Protocol::P2p(server_id));
+        let server_tx = server.spawn();
+
+        // Stands in for the beacon swarm, which would validate and publish
+        tokio::spawn(async move {
+            while let Some(command) = beacon_rx.recv().await {
+                if let NetworkCommand::PublishForFriend { request, response } = command {
+                    let _ = response.send(Ok(vec![request.subnet_id]));
+                }
+            }
+        });
+
+        let client_tx = spawn_publisher(client_key, vec![address.clone()]);
+        let message_id = tokio::time::timeout(Duration::from_secs(10), publish(&client_tx, 5)).await.unwrap().unwrap();
+        assert_eq!(message_id, MessageId::new(&[5]));
+
+        let stranger_tx = spawn_publisher(identity::Keypair::generate_secp256k1(), vec![address]);
+        let refused = tokio::time::timeout(Duration::from_secs(10), publish(&stranger_tx, 5)).await.unwrap();
+        assert!(refused.is_err(), "Only configured clients may publish through the relay node");
+        for command_tx in [server_tx, client_tx, stranger_tx] {
+            let _ = command_tx.send(NetworkCommand::Shutdown);
+        }
+    }
+}
@@ -0,0 +1,521 @@
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::{
+    request_response::{self, OutboundRequestId, ProtocolSupport, ResponseChannel},
+    swarm::NetworkBehaviour,
+    PeerId,
+};
+use snap::read::FrameDecoder;
+use snap::write::FrameEncoder;
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+
+use crate::discovery::{attnets_bitfield, ATTESTATION_SUBNET_COUNT};
+
+/// SSZ size of a `Status` message
+pub const STATUS_SIZE: usize = 84;
+/// SSZ size of a `MetaData` v2 message (seq_number + attnets + syncnets)
+pub const METADATA_SIZE: usize = 17;
+/// Largest compressed chunk we accept; every message here is well under this
+const MAX_CHUNK_SIZE: u64 = 1024;
+/// `result` byte of a successful response chunk
+const RESULT_SUCCESS: u8 = 0;
+
+/// Goodbye reasons from the consensus p2p spec
+pub const GOODBYE_CLIENT_SHUTDOWN: u64 = 1;
+pub const GOODBYE_IRRELEVANT_NETWORK: u64 = 2;
+pub const GOODBYE_FAULT: u64 = 3;
+pub const GOODBYE_TOO_MANY_PEERS: u64 = 129;
+
+/// Req/resp protocols we speak, each on its own request-response behaviour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcProtocol {
+    Status,
+    Ping,
+    MetaData,
+    Goodbye,
+}
+
+impl AsRef<str> for RpcProtocol {
+    fn as_ref(&self) -> &str {
+        match self {
+            RpcProtocol::Status => "/eth2/beacon_chain/req/status/1/ssz_snappy",
+            RpcProtocol::Ping => "/eth2/beacon_chain/req/ping/1/ssz_snappy",
+            RpcProtocol::MetaData => "/eth2/beacon_chain/req/metadata/2/ssz_snappy",
+            RpcProtocol::Goodbye => "/eth2/beacon_chain/req/goodbye/1/ssz_snappy",
+        }
+    }
+}
+
+/// `Status` handshake message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusMessage {
+    pub fork_digest: [u8; 4],
+    pub finalized_root: [u8; 32],
+    pub finalized_epoch: u64,
+    pub head_root: [u8; 32],
+    pub head_slot: u64,
+}
+
+impl StatusMessage {
+    /// Status of a node that has not synced past genesis, used when no head is configured
+    pub fn genesis(fork_digest: [u8; 4]) -> Self {
+        Self {
+            fork_digest,
+            finalized_root: [0u8; 32],
+            finalized_epoch: 0,
+            head_root: [0u8; 32],
+            head_slot: 0,
+        }
+    }
+
+    pub fn to_ssz(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(STATUS_SIZE);
+        bytes.extend_from_slice(&self.fork_digest);
+        bytes.extend_from_slice(&self.finalized_root);
+        bytes.extend_from_slice(&self.finalized_epoch.to_le_bytes());
+        bytes.extend_from_slice(&self.head_root);
+        bytes.extend_from_slice(&self.head_slot.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_ssz(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() != STATUS_SIZE {
+            return Err(invalid_data(format!("Status must be {} bytes, got {}", STATUS_SIZE, bytes.len())));
+        }
+        Ok(Self {
+            fork_digest: bytes[0..4].try_into().expect("length checked"),
+            finalized_root: bytes[4..36].try_into().expect("length checked"),
+            finalized_epoch: u64::from_le_bytes(bytes[36..44].try_into().expect("length checked")),
+            head_root: bytes[44..76].try_into().expect("length checked"),
+            head_slot: u64::from_le_bytes(bytes[76..84].try_into().expect("length checked")),
+        })
+    }
+}
+
+/// `MetaData` v2: our sequence number and the subnets we advertise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetaData {
+    pub seq_number: u64,
+    pub attnets: [u8; ATTESTATION_SUBNET_COUNT / 8],
+    pub syncnets: u8,
+}
+
+impl MetaData {
+    pub fn new(subnets: &HashSet<u8>) -> Self {
+        Self {
+            seq_number: 0,
+            attnets: attnets_bitfield(subnets),
+            syncnets: 0,
+        }
+    }
+
+    /// Replace the advertised attestation subnets, bumping `seq_number` if they changed
+    pub fn set_attnets(&mut self, subnets: &HashSet<u8>) -> bool {
+        let attnets = attnets_bitfield(subnets);
+        if attnets == self.attnets {
+            return false;
+        }
+        self.attnets = attnets;
+        self.seq_number += 1;
+        true
+    }
+
+    /// Subnets set in the `attnets` bitfield
+    pub fn subnets(&self) -> HashSet<u8> {
+        (0..ATTESTATION_SUBNET_COUNT as u8)
+            .filter(|subnet| self.attnets[*subnet as usize / 8] & (1 << (subnet % 8)) != 0)
+            .collect()
+    }
+
+    pub fn to_ssz(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(METADATA_SIZE);
+        bytes.extend_from_slice(&self.seq_number.to_le_bytes());
+        bytes.extend_from_slice(&self.attnets);
+        bytes.push(self.syncnets);
+        bytes
+    }
+
+    pub fn from_ssz(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() != METADATA_SIZE {
+            return Err(invalid_data(format!("MetaData must be {} bytes, got {}", METADATA_SIZE, bytes.len())));
+        }
+        Ok(Self {
+            seq_number: u64::from_le_bytes(bytes[0..8].try_into().expect("length checked")),
+            attnets: bytes[8..16].try_into().expect("length checked"),
+            // Only the low 4 bits (SYNC_COMMITTEE_SUBNET_COUNT) are meaningful
+            syncnets: bytes[16] & 0x0f,
+        })
+    }
+}
+
+/// Outbound or inbound req/resp request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpcRequest {
+    Status(StatusMessage),
+    Ping(u64),
+    MetaData,
+    Goodbye(u64),
+}
+
+impl RpcRequest {
+    fn protocol(&self) -> RpcProtocol {
+        match self {
+            RpcRequest::Status(_) => RpcProtocol::Status,
+            RpcRequest::Ping(_) => RpcProtocol::Ping,
+            RpcRequest::MetaData => RpcProtocol::MetaData,
+            RpcRequest::Goodbye(_) => RpcProtocol::Goodbye,
+        }
+    }
+}
+
+/// Response to an [`RpcRequest`]; `Goodbye` has no payload on the wire
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpcResponse {
+    Status(StatusMessage),
+    Pong(u64),
+    MetaData(MetaData),
+    Goodbye,
+}
+
+impl RpcResponse {
+    fn protocol(&self) -> RpcProtocol {
+        match self {
+            RpcResponse::Status(_) => RpcProtocol::Status,
+            RpcResponse::Pong(_) => RpcProtocol::Ping,
+            RpcResponse::MetaData(_) => RpcProtocol::MetaData,
+            RpcResponse::Goodbye => RpcProtocol::Goodbye,
+        }
+    }
+}
+
+/// `ssz_snappy` encoding: varint length of the SSZ bytes followed by a snappy frame stream
+#[derive(Debug, Clone, Default)]
+pub struct SszSnappyCodec;
+
+#[async_trait]
+impl request_response::Codec for SszSnappyCodec {
+    type Protocol = RpcProtocol;
+    type Request = RpcRequest;
+    type Response = RpcResponse;
+
+    async fn read_request<T>(&mut self, protocol: &RpcProtocol, io: &mut T) -> io::Result<RpcRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        match protocol {
+            RpcProtocol::Status => {
+                let ssz = read_chunk(io, STATUS_SIZE).await?;
+                Ok(RpcRequest::Status(StatusMessage::from_ssz(&ssz)?))
+            }
+            RpcProtocol::Ping => Ok(RpcRequest::Ping(read_u64_chunk(io).await?)),
+            // MetaData requests carry no body
+            RpcProtocol::MetaData => Ok(RpcRequest::MetaData),
+            RpcProtocol::Goodbye => Ok(RpcRequest::Goodbye(read_u64_chunk(io).await?)),
+        }
+    }
+
+    async fn read_response<T>(&mut self, protocol: &RpcProtocol, io: &mut T) -> io::Result<RpcResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        // Peers are not expected to answer a Goodbye, they just close the stream
+        if *protocol == RpcProtocol::Goodbye {
+            return Ok(RpcResponse::Goodbye);
+        }
+
+        let mut result = [0u8; 1];
+        io.read_exact(&mut result).await?;
+        if result[0] != RESULT_SUCCESS {
+            let mut message = Vec::new();
+            io.take(MAX_CHUNK_SIZE).read_to_end(&mut message).await?;
+            // ErrorMessage is a List[byte, 256]
+            let reason = decode_chunk(&message, 256)
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+                .unwrap_or_default();
+            return Err(io::Error::other(format!("Error response {}: {}", result[0], reason)));
+        }
+
+        match protocol {
+            RpcProtocol::Status => {
+                let ssz = read_chunk(io, STATUS_SIZE).await?;
+                Ok(RpcResponse::Status(StatusMessage::from_ssz(&ssz)?))
+            }
+            RpcProtocol::Ping => Ok(RpcResponse::Pong(read_u64_chunk(io).await?)),
+            RpcProtocol::MetaData => {
+                let ssz = read_chunk(io, METADATA_SIZE).await?;
+                Ok(RpcResponse::MetaData(MetaData::from_ssz(&ssz)?))
+            }
+            RpcProtocol::Goodbye => unreachable!("handled above"),
+        }
+    }
+
+    async fn write_request<T>(&mut self, protocol: &RpcProtocol, io: &mut T, req: RpcRequest) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        if req.protocol() != *protocol {
+            return Err(invalid_data(format!("{:?} request sent on {}", req, protocol.as_ref())));
+        }
+        let ssz = match req {
+            RpcRequest::Status(status) => status.to_ssz(),
+            RpcRequest::Ping(seq_number) => seq_number.to_le_bytes().to_vec(),
+            RpcRequest::MetaData => return io.close().await,
+            RpcRequest::Goodbye(reason) => reason.to_le_bytes().to_vec(),
+        };
+        io.write_all(&encode_chunk(&ssz)?).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(&mut self, protocol: &RpcProtocol, io: &mut T, res: RpcResponse) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        if res.protocol() != *protocol {
+            return Err(invalid_data(format!("{:?} response sent on {}", res, protocol.as_ref())));
+        }
+        let ssz = match res {
+            RpcResponse::Status(status) => status.to_ssz(),
+            RpcResponse::Pong(seq_number) => seq_number.to_le_bytes().to_vec(),
+            RpcResponse::MetaData(metadata) => metadata.to_ssz(),
+            RpcResponse::Goodbye => return io.close().await,
+        };
+        io.write_all(&[RESULT_SUCCESS]).await?;
+        io.write_all(&encode_chunk(&ssz)?).await?;
+        io.close().await
+    }
+}
+
+/// One request-response behaviour per protocol, so a request is only ever
+/// negotiated on the protocol matching its type
+#[derive(NetworkBehaviour)]
+pub struct RpcBehaviour {
+    status: request_response::Behaviour<SszSnappyCodec>,
+    ping: request_response::Behaviour<SszSnappyCodec>,
+    metadata: request_response::Behaviour<SszSnappyCodec>,
+    goodbye: request_response::Behaviour<SszSnappyCodec>,
+}
+
+impl RpcBehaviour {
+    pub fn new() -> Self {
+        let behaviour = |protocol| {
+            request_response::Behaviour::with_codec(
+                SszSnappyCodec,
+                [(protocol, ProtocolSupport::Full)],
+                request_response::Config::default(),
+            )
+        };
+        Self {
+            status: behaviour(RpcProtocol::Status),
+            ping: behaviour(RpcProtocol::Ping),
+            metadata: behaviour(RpcProtocol::MetaData),
+            goodbye: behaviour(RpcProtocol::Goodbye),
+        }
+    }
+
+    /// Send a request on the behaviour for its protocol
+    pub fn send_request(&mut self, peer: &PeerId, request: RpcRequest) -> OutboundRequestId {
+        match request.protocol() {
+            RpcProtocol::Status => self.status.send_request(peer, request),
+            RpcProtocol::Ping => self.ping.send_request(peer, request),
+            RpcProtocol::MetaData => self.metadata.send_request(peer, request),
+            RpcProtocol::Goodbye => self.goodbye.send_request(peer, request),
+        }
+    }
+
+    /// Answer an inbound request; fails if the peer already went away
+    pub fn send_response(&mut self, channel: ResponseChannel<RpcResponse>, response: RpcResponse) -> Result<(), RpcResponse> {
+        match response.protocol() {
+            RpcProtocol::Status => self.status.send_response(channel, response),
+            RpcProtocol::Ping => self.ping.send_response(channel, response),
+            RpcProtocol::MetaData => self.metadata.send_response(channel, response),
+            RpcProtocol::Goodbye => self.goodbye.send_response(channel, response),
+        }
+    }
+}
+
+impl Default for RpcBehaviour {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RpcBehaviourEvent {
+    /// Collapse the per-protocol events, the request/response types already say which one it was
+    pub fn into_inner(self) -> request_response::Event<RpcRequest, RpcResponse> {
+        match self {
+            RpcBehaviourEvent::Status(event)
+            | RpcBehaviourEvent::Ping(event)
+            | RpcBehaviourEvent::Metadata(event)
+            | RpcBehaviourEvent::Goodbye(event) => event,
+        }
+    }
+}
+
+/// Human readable Goodbye reason for logs
+pub fn goodbye_reason(reason: u64) -> &'static str {
+    match reason {
+        GOODBYE_CLIENT_SHUTDOWN => "client shutdown",
+        GOODBYE_IRRELEVANT_NETWORK => "irrelevant network",
+        GOODBYE_FAULT => "fault/error",
+        128 => "unable to verify network",
+        GOODBYE_TOO_MANY_PEERS => "too many peers",
+        250 => "bad score",
+        251 => "banned",
+        _ => "unknown",
+    }
+}
+
+/// Varint-prefixed snappy frame stream for an SSZ payload
+fn encode_chunk(ssz: &[u8]) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut len = ssz.len() as u64;
+    loop {
+        let byte = (len & 0x7f) as u8;
+        len >>= 7;
+        if len == 0 {
+            bytes.push(byte);
+            break;
+        }
+        bytes.push(byte | 0x80);
+    }
+
+    let mut encoder = FrameEncoder::new(bytes);
+    encoder.write_all(ssz)?;
+    encoder.into_inner().map_err(|e| io::Error::other(e.to_string()))
+}
+
+/// Inverse of [`encode_chunk`], refusing payloads longer than `max_len`
+fn decode_chunk(bytes: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    let mut len = 0u64;
+    let mut consumed = 0;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        len |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            consumed = i + 1;
+            break;
+        }
+    }
+    if consumed == 0 {
+        return Err(invalid_data("Invalid length prefix".to_string()));
+    }
+    if len as usize > max_len {
+        return Err(invalid_data(format!("Payload of {} bytes exceeds {}", len, max_len)));
+    }
+
+    let mut ssz = vec![0u8; len as usize];
+    FrameDecoder::new(&bytes[consumed..]).read_exact(&mut ssz)?;
+    Ok(ssz)
+}
+
+async fn read_chunk<T: AsyncRead + Unpin + Send>(io: &mut T, max_len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    io.take(MAX_CHUNK_SIZE).read_to_end(&mut bytes).await?;
+    decode_chunk(&bytes, max_len)
+}
+
+async fn read_u64_chunk<T: AsyncRead + Unpin + Send>(io: &mut T) -> io::Result<u64> {
+    let ssz = read_chunk(io, 8).await?;
+    let bytes: [u8; 8] = ssz
+        .try_into()
+        .map_err(|_| invalid_data("Expected an 8 byte uint64".to_string()))?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::Cursor;
+    use request_response::Codec;
+
+    fn sample_status() -> StatusMessage {
+        StatusMessage {
+            fork_digest: [0x7a, 0x7b, 0x8b, 0x7f],
+            finalized_root: [1u8; 32],
+            finalized_epoch: 300_000,
+            head_root: [2u8; 32],
+            head_slot: 9_600_123,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_request_roundtrip() {
+        let mut codec = SszSnappyCodec;
+        let mut wire = Cursor::new(Vec::new());
+        codec
+            .write_request(&RpcProtocol::Status, &mut wire, RpcRequest::Status(sample_status()))
+            .await
+            .unwrap();
+
+        // varint(84) is a single byte, followed by the snappy stream identifier chunk
+        let bytes = wire.into_inner();
+        assert_eq!(bytes[0], STATUS_SIZE as u8);
+        assert_eq!(bytes[1], 0xff);
+
+        let request = codec
+            .read_request(&RpcProtocol::Status, &mut Cursor::new(bytes))
+            .await
+            .unwrap();
+        assert_eq!(request, RpcRequest::Status(sample_status()));
+    }
+
+    #[tokio::test]
+    async fn test_metadata_response_roundtrip() {
+        let mut metadata = MetaData::new(&[0u8, 1].into_iter().collect());
+        assert!(metadata.set_attnets(&[0u8, 1, 17, 63].into_iter().collect()));
+        assert!(!metadata.set_attnets(&[0u8, 1, 17, 63].into_iter().collect()));
+        assert_eq!(metadata.seq_number, 1);
+
+        let mut codec = SszSnappyCodec;
+        let mut wire = Cursor::new(Vec::new());
+        codec
+            .write_response(&RpcProtocol::MetaData, &mut wire, RpcResponse::MetaData(metadata))
+            .await
+            .unwrap();
+
+        let bytes = wire.into_inner();
+        assert_eq!(bytes[0], RESULT_SUCCESS);
+        let response = codec
+            .read_response(&RpcProtocol::MetaData, &mut Cursor::new(bytes))
+            .await
+            .unwrap();
+        match response {
+            RpcResponse::MetaData(decoded) => {
+                assert_eq!(decoded, metadata);
+                assert_eq!(decoded.subnets(), [0u8, 1, 17, 63].into_iter().collect());
+            }
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_and_error_responses_rejected() {
+        let mut codec = SszSnappyCodec;
+
+        // A ping must not go out on the status protocol
+        let mut wire = Cursor::new(Vec::new());
+        assert!(codec
+            .write_request(&RpcProtocol::Status, &mut wire, RpcRequest::Ping(1))
+            .await
+            .is_err());
+
+        // Non-zero result byte surfaces as an error
+        let mut error = vec![1u8];
+        error.extend(encode_chunk(b"bad request").unwrap());
+        assert!(codec
+            .read_response(&RpcProtocol::Ping, &mut Cursor::new(error))
+            .await
+            .is_err());
+
+        // Goodbye responses are empty
+        let response = codec
+            .read_response(&RpcProtocol::Goodbye, &mut Cursor::new(Vec::new()))
+            .await
+            .unwrap();
+        assert_eq!(response, RpcResponse::Goodbye);
+    }
+}
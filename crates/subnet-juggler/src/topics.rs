@@ -0,0 +1,212 @@
+use anyhow::{anyhow, Result};
+use libp2p::gossipsub::{IdentTopic, MessageAcceptance};
+use std::ops::RangeInclusive;
+
+use crate::fork::Fork;
+use crate::gossip::{self, GOSSIP_MAX_SIZE};
+
+/// Non-attestation gossip topics every beacon node subscribes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CoreTopic {
+    BeaconBlock,
+    BeaconAggregateAndProof,
+    VoluntaryExit,
+    ProposerSlashing,
+    AttesterSlashing,
+    SyncCommitteeContributionAndProof,
+    BlsToExecutionChange,
+    BlobSidecar(u8),
+}
+
+impl CoreTopic {
+    /// The full standard set of `fork`, as subscribed by Lighthouse
+    pub fn all(fork: Fork) -> Vec<CoreTopic> {
+        let mut topics = vec![
+            CoreTopic::BeaconBlock,
+            CoreTopic::BeaconAggregateAndProof,
+            CoreTopic::VoluntaryExit,
+            CoreTopic::ProposerSlashing,
+            CoreTopic::AttesterSlashing,
+            CoreTopic::SyncCommitteeContributionAndProof,
+            CoreTopic::BlsToExecutionChange,
+        ];
+        topics.extend((0..fork.blob_sidecar_subnets()).map(CoreTopic::BlobSidecar));
+        topics
+    }
+
+    /// Topic name between the fork digest and the encoding
+    pub fn name(&self) -> String {
+        match self {
+            CoreTopic::BeaconBlock => "beacon_block".to_string(),
+            CoreTopic::BeaconAggregateAndProof => "beacon_aggregate_and_proof".to_string(),
+            CoreTopic::VoluntaryExit => "voluntary_exit".to_string(),
+            CoreTopic::ProposerSlashing => "proposer_slashing".to_string(),
+            CoreTopic::AttesterSlashing => "attester_slashing".to_string(),
+            CoreTopic::SyncCommitteeContributionAndProof => "sync_committee_contribution_and_proof".to_string(),
+            CoreTopic::BlsToExecutionChange => "bls_to_execution_change".to_string(),
+            CoreTopic::BlobSidecar(subnet) => format!("blob_sidecar_{}", subnet),
+        }
+    }
+
+    pub fn topic(&self, fork_digest: &str) -> IdentTopic {
+        IdentTopic::new(format!("/eth2/{}/{}/ssz_snappy", fork_digest, self.name()))
+    }
+
+    /// Core topic a full gossip topic string refers to, if any
+    pub fn from_topic(topic: &str) -> Option<CoreTopic> {
+        let name = topic.strip_prefix("/eth2/")?.split('/').nth(1)?;
+        if let Some(subnet) = name.strip_prefix("blob_sidecar_") {
+            return subnet.parse().ok().map(CoreTopic::BlobSidecar);
+        }
+        CoreTopic::all(Fork::Phase0).into_iter().find(|core| core.name() == name)
+    }
+
+    /// Bounds of the SSZ-encoded payload (phase0 through Electra containers)
+    pub fn ssz_size(&self) -> RangeInclusive<usize> {
+        match self {
+            // Fixed part of SignedBeaconBlock; bodies are bounded by the gossip limit
+            CoreTopic::BeaconBlock => 100..=GOSSIP_MAX_SIZE,
+            // SignedAggregateAndProof: phase0 minimum, Electra maximum of
+            // 64 committees x 2048 aggregation bits plus the committee bits
+            CoreTopic::BeaconAggregateAndProof => 437..=16_829,
+            CoreTopic::VoluntaryExit => 112..=112,
+            CoreTopic::ProposerSlashing => 416..=416,
+            // Two IndexedAttestations of up to 64 x 2048 indices each (Electra)
+            CoreTopic::AttesterSlashing => 464..=2_097_616,
+            CoreTopic::SyncCommitteeContributionAndProof => 360..=360,
+            CoreTopic::BlsToExecutionChange => 172..=172,
+            CoreTopic::BlobSidecar(_) => 131_928..=131_928,
+        }
+    }
+
+    /// Blocks and blobs make up nearly all core topic traffic
+    pub fn is_heavy(&self) -> bool {
+        matches!(self, CoreTopic::BeaconBlock | CoreTopic::BlobSidecar(_))
+    }
+}
+
+/// What happens to a valid message on a core topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Forwarding {
+    /// Accept it, so gossipsub forwards it to our mesh
+    Forward,
+    /// Ignore it: not forwarded, and the sender is not penalised
+    Drop,
+}
+
+/// How messages on one core topic are validated and forwarded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicPolicy {
+    /// Accepted range of the decompressed payload
+    pub ssz_size: RangeInclusive<usize>,
+    pub forwarding: Forwarding,
+}
+
+impl TopicPolicy {
+    /// Reject what cannot be a valid message, then apply the forwarding policy
+    pub fn validate(&self, data: &[u8]) -> MessageAcceptance {
+        match gossip::decompress_snappy(data) {
+            Some(ssz) if self.ssz_size.contains(&ssz.len()) => match self.forwarding {
+                Forwarding::Forward => MessageAcceptance::Accept,
+                Forwarding::Drop => MessageAcceptance::Ignore,
+            },
+            _ => MessageAcceptance::Reject,
+        }
+    }
+}
+
+/// How `network.cover_topics` is interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverTopics {
+    /// Attestation subnets only
+    Disabled,
+    /// Subscribe to every core topic and forward like a regular node
+    Full,
+    /// Subscribe to every core topic but forward only the light ones; blocks and
+    /// blobs are still received, which is the price of looking like a full node
+    Light,
+}
+
+impl CoverTopics {
+    pub fn from_config(cover_topics: Option<&str>) -> Result<Self> {
+        match cover_topics.map(str::trim) {
+            None | Some("") | Some("off") => Ok(Self::Disabled),
+            Some("full") => Ok(Self::Full),
+            Some("light") => Ok(Self::Light),
+            Some(value) => Err(anyhow!("Invalid cover_topics {:?}, expected \"full\", \"light\" or \"off\"", value)),
+        }
+    }
+
+    /// Core topics of `fork` to subscribe to
+    pub fn topics(&self, fork: Fork) -> Vec<CoreTopic> {
+        match self {
+            CoverTopics::Disabled => Vec::new(),
+            CoverTopics::Full | CoverTopics::Light => CoreTopic::all(fork),
+        }
+    }
+
+    pub fn policy(&self, topic: CoreTopic) -> TopicPolicy {
+        let forwarding = match self {
+            CoverTopics::Light if topic.is_heavy() => Forwarding::Drop,
+            _ => Forwarding::Forward,
+        };
+        TopicPolicy {
+            ssz_size: topic.ssz_size(),
+            forwarding,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_core_topic_names_round_trip() {
+        let topics = CoreTopic::all(Fork::Electra);
+        assert_eq!(topics.len(), 7 + 9);
+        assert_eq!(CoreTopic::all(Fork::Deneb).len(), 7 + 6);
+        for core in &topics {
+            let topic = core.topic("7a7b8b7f");
+            assert_eq!(CoreTopic::from_topic(&topic.to_string()), Some(*core));
+        }
+        assert_eq!(
+            CoreTopic::BlobSidecar(3).topic("7a7b8b7f").to_string(),
+            "/eth2/7a7b8b7f/blob_sidecar_3/ssz_snappy"
+        );
+        assert_eq!(CoreTopic::from_topic("/eth2/7a7b8b7f/beacon_attestation_3/ssz_snappy"), None);
+    }
+
+    #[test]
+    fn test_cover_topics_setting() {
+        assert_eq!(CoverTopics::from_config(None).unwrap(), CoverTopics::Disabled);
+        assert_eq!(CoverTopics::from_config(Some("off")).unwrap(), CoverTopics::Disabled);
+        assert_eq!(CoverTopics::from_config(Some("full")).unwrap(), CoverTopics::Full);
+        assert_eq!(CoverTopics::from_config(Some("light")).unwrap(), CoverTopics::Light);
+        assert!(CoverTopics::from_config(Some("all")).is_err());
+        assert!(CoverTopics::Disabled.topics(Fork::Electra).is_empty());
+        assert_eq!(CoverTopics::Light.topics(Fork::Electra), CoreTopic::all(Fork::Electra));
+    }
+
+    #[test]
+    fn test_policies_validate_and_forward() {
+        let exit = gossip::compress_snappy(&[0u8; 112]).unwrap();
+        let blob = gossip::compress_snappy(&vec![0u8; 131_928]).unwrap();
+        let short = gossip::compress_snappy(&[0u8; 100]).unwrap();
+
+        let full = CoverTopics::Full;
+        assert!(matches!(full.policy(CoreTopic::VoluntaryExit).validate(&exit), MessageAcceptance::Accept));
+        assert!(matches!(full.policy(CoreTopic::BlobSidecar(0)).validate(&blob), MessageAcceptance::Accept));
+        assert!(matches!(full.policy(CoreTopic::VoluntaryExit).validate(&short), MessageAcceptance::Reject));
+        // Electra aggregates carry far more aggregation bits than phase0 ones
+        let aggregate = gossip::compress_snappy(&[0u8; 16_829]).unwrap();
+        assert!(matches!(full.policy(CoreTopic::BeaconAggregateAndProof).validate(&aggregate), MessageAcceptance::Accept));
+        assert!(matches!(full.policy(CoreTopic::VoluntaryExit).validate(&[0xff; 8]), MessageAcceptance::Reject));
+
+        // Light mode still rejects garbage but does not pass blobs on
+        let light = CoverTopics::Light;
+        assert!(matches!(light.policy(CoreTopic::VoluntaryExit).validate(&exit), MessageAcceptance::Accept));
+        assert!(matches!(light.policy(CoreTopic::BlobSidecar(0)).validate(&blob), MessageAcceptance::Ignore));
+        assert!(matches!(light.policy(CoreTopic::BlobSidecar(0)).validate(&exit), MessageAcceptance::Reject));
+    }
+}
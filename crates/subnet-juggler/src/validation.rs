@@ -0,0 +1,580 @@
+use anyhow::{anyhow, Context, Result};
+use libp2p::gossipsub::MessageAcceptance;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+use stealth_common::EpochInfo;
+
+use crate::attestation::{AttestationData, AttestationFormat, Checkpoint, DecodedAttestation, SIGNATURE_SIZE};
+use crate::fork::Fork;
+
+/// Mainnet `genesis_validators_root`, part of every signing domain (the default)
+pub const MAINNET_GENESIS_VALIDATORS_ROOT: [u8; 32] = [
+    0x4b, 0x36, 0x3d, 0xb9, 0x4e, 0x28, 0x61, 0x20, 0xd7, 0x6e, 0xb9, 0x05, 0x34, 0x0f, 0xdd, 0x4e, 0x54, 0xbf,
+    0xe9, 0xf0, 0x6b, 0xf3, 0x3f, 0xf6, 0xcf, 0x5a, 0xd2, 0x7f, 0x51, 0x1b, 0xfe, 0x95,
+];
+/// Mainnet Electra fork version, the default until the beacon API says otherwise
+pub const MAINNET_FORK_VERSION: [u8; 4] = [0x05, 0x00, 0x00, 0x00];
+/// Slots an attestation may be propagated for after its own
+pub const ATTESTATION_PROPAGATION_SLOT_RANGE: u64 = 32;
+/// Clock skew tolerated when checking slots
+pub const MAXIMUM_GOSSIP_CLOCK_DISPARITY: Duration = Duration::from_millis(500);
+/// Upper bound of `committees_per_slot`, reached from ~262k active validators
+pub const MAX_COMMITTEES_PER_SLOT: u64 = 64;
+/// Validators per committee the spec aims for
+pub const TARGET_COMMITTEE_SIZE: u64 = 128;
+/// Active validators on mainnet, enough for the maximum committee count
+pub const MAINNET_ACTIVE_VALIDATORS: u64 = 1_000_000;
+pub const ATTESTATION_SUBNET_COUNT: u64 = 64;
+
+const DOMAIN_BEACON_ATTESTER: [u8; 4] = [0x01, 0x00, 0x00, 0x00];
+const PUBKEY_SIZE: usize = 48;
+
+/// Result of validating a gossip attestation
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    /// Valid, forward it and hand it to the juggler
+    Accept(Box<DecodedAttestation>),
+    /// Invalid, penalise the sender
+    Reject(String),
+    /// Not worth forwarding but not the sender's fault (stale, duplicate, unverifiable)
+    Ignore(String),
+}
+
+impl Outcome {
+    pub fn acceptance(&self) -> MessageAcceptance {
+        match self {
+            Outcome::Accept(_) => MessageAcceptance::Accept,
+            Outcome::Reject(_) => MessageAcceptance::Reject,
+            Outcome::Ignore(_) => MessageAcceptance::Ignore,
+        }
+    }
+}
+
+/// Counts of validation results, exposed through `GossipMetrics`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidationStats {
+    pub accepted: u64,
+    pub rejected: u64,
+    pub ignored: u64,
+}
+
+/// Validator pubkeys by index, saved from the beacon API
+/// (`/eth/v1/beacon/states/head/validators`)
+#[derive(Debug, Clone, Default)]
+pub struct PubkeyCache {
+    pubkeys: HashMap<u64, [u8; PUBKEY_SIZE]>,
+}
+
+#[derive(Deserialize)]
+struct ValidatorsResponse {
+    data: Vec<ValidatorEntry>,
+}
+
+#[derive(Deserialize)]
+struct ValidatorEntry {
+    index: String,
+    validator: ValidatorPubkey,
+}
+
+#[derive(Deserialize)]
+struct ValidatorPubkey {
+    pubkey: String,
+}
+
+impl PubkeyCache {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read validator pubkeys {}", path.display()))?;
+        Self::from_json(&contents)
+    }
+
+    /// Parse a beacon API validators response
+    pub fn from_json(json: &str) -> Result<Self> {
+        let response: ValidatorsResponse = serde_json::from_str(json)?;
+        let mut cache = Self::default();
+        for entry in response.data {
+            let index = entry.index.parse().map_err(|_| anyhow!("Invalid validator index {}", entry.index))?;
+            let bytes = hex::decode(entry.validator.pubkey.trim_start_matches("0x"))?;
+            let pubkey = bytes
+                .try_into()
+                .map_err(|_| anyhow!("Pubkey of validator {} is not {} bytes", index, PUBKEY_SIZE))?;
+            cache.insert(index, pubkey);
+        }
+        Ok(cache)
+    }
+
+    pub fn insert(&mut self, validator_index: u64, pubkey: [u8; PUBKEY_SIZE]) {
+        self.pubkeys.insert(validator_index, pubkey);
+    }
+
+    pub fn get(&self, validator_index: u64) -> Option<&[u8; PUBKEY_SIZE]> {
+        self.pubkeys.get(&validator_index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pubkeys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pubkeys.is_empty()
+    }
+}
+
+/// Chain parameters that change as the chain runs, followed from the beacon API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainState {
+    /// Fork version of the current fork, part of the signing domain
+    pub fork_version: [u8; 4],
+    pub fork: Fork,
+    /// Committees per slot for the current active validator count
+    pub committees_per_slot: u64,
+}
+
+/// Chain parameters the checks depend on
+#[derive(Debug, Clone)]
+pub struct ValidationConfig {
+    pub genesis_time: u64,
+    /// Committees per slot for the current active validator count
+    pub committees_per_slot: u64,
+    /// Verify signatures of attestations whose signer is in the cache; pre-Electra
+    /// bit-list attestations are then ignored, their signer is unknown without the
+    /// committee shuffling
+    pub pubkeys: Option<PubkeyCache>,
+    pub fork_version: [u8; 4],
+    pub fork: Fork,
+    pub genesis_validators_root: [u8; 32],
+}
+
+impl ValidationConfig {
+    /// Digest of the current fork in gossip topics, Status and the ENR
+    pub fn fork_digest(&self) -> [u8; 4] {
+        compute_fork_digest(self.fork_version, self.genesis_validators_root)
+    }
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            genesis_time: EpochInfo::MAINNET_GENESIS_TIME,
+            committees_per_slot: committees_per_slot(MAINNET_ACTIVE_VALIDATORS),
+            pubkeys: None,
+            fork_version: MAINNET_FORK_VERSION,
+            fork: Fork::Electra,
+            genesis_validators_root: MAINNET_GENESIS_VALIDATORS_ROOT,
+        }
+    }
+}
+
+/// Who signed an unaggregated attestation: the index when the container carries it,
+/// else the committee position, which is just as unique within an epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Attester {
+    Index(u64),
+    Position { slot: u64, committee_index: u64, position: usize },
+}
+
+/// Gossip validation of `beacon_attestation_{subnet}` messages per the p2p spec
+#[derive(Debug)]
+pub struct AttestationValidator {
+    config: ValidationConfig,
+    domain: [u8; 32],
+    /// Attesters seen per target epoch
+    seen: HashMap<u64, HashSet<Attester>>,
+    stats: ValidationStats,
+}
+
+impl AttestationValidator {
+    pub fn new(config: ValidationConfig) -> Result<Self> {
+        if config.pubkeys.is_some() && !cfg!(feature = "bls") {
+            return Err(anyhow!("Signature verification needs the `bls` feature"));
+        }
+        check_committees_per_slot(config.committees_per_slot)?;
+        let domain = compute_domain(DOMAIN_BEACON_ATTESTER, config.fork_version, config.genesis_validators_root);
+        Ok(Self {
+            config,
+            domain,
+            seen: HashMap::new(),
+            stats: ValidationStats::default(),
+        })
+    }
+
+    /// Follow a fork or a change in the active validator count
+    pub fn update_chain(&mut self, chain: ChainState) -> Result<()> {
+        check_committees_per_slot(chain.committees_per_slot)?;
+        self.config.fork_version = chain.fork_version;
+        self.config.fork = chain.fork;
+        self.config.committees_per_slot = chain.committees_per_slot;
+        self.domain = compute_domain(DOMAIN_BEACON_ATTESTER, chain.fork_version, self.config.genesis_validators_root);
+        Ok(())
+    }
+
+    /// Validate a message received on `subnet_id` at `now` (time since the Unix epoch)
+    pub fn validate(&mut self, subnet_id: u8, data: &[u8], now: Duration) -> Outcome {
+        let outcome = self.check(subnet_id, data, now);
+        match &outcome {
+            Outcome::Accept(_) => self.stats.accepted += 1,
+            Outcome::Reject(_) => self.stats.rejected += 1,
+            Outcome::Ignore(_) => self.stats.ignored += 1,
+        }
+        outcome
+    }
+
+    pub fn stats(&self) -> ValidationStats {
+        self.stats
+    }
+
+    pub fn genesis_time(&self) -> u64 {
+        self.config.genesis_time
+    }
+
+    fn check(&mut self, subnet_id: u8, data: &[u8], now: Duration) -> Outcome {
+        let attestation = match DecodedAttestation::from_gossip(data) {
+            Ok(attestation) => attestation,
+            Err(e) => return Outcome::Reject(e.to_string()),
+        };
+        let slot = attestation.data.slot;
+
+        // Subnets carry `SingleAttestation`s from Electra on and phase0 `Attestation`s before
+        let expected_format = if self.config.fork >= Fork::Electra {
+            AttestationFormat::Single
+        } else {
+            AttestationFormat::Phase0
+        };
+        if attestation.format != expected_format {
+            return Outcome::Reject(format!("{:?} attestation on a {:?} subnet", attestation.format, self.config.fork));
+        }
+        // From Electra on the committee lives outside the signed data
+        if attestation.format != AttestationFormat::Phase0 && attestation.data.index != 0 {
+            return Outcome::Reject(format!("Electra attestation with data.index {}", attestation.data.index));
+        }
+        if attestation.committee_index >= self.config.committees_per_slot {
+            return Outcome::Reject(format!("Committee index {} out of range", attestation.committee_index));
+        }
+        let expected = compute_subnet_for_attestation(self.config.committees_per_slot, slot, attestation.committee_index);
+        if expected != subnet_id as u64 {
+            return Outcome::Reject(format!("Committee belongs on subnet {}, not {}", expected, subnet_id));
+        }
+        let (earliest, latest) = self.slot_bounds(now);
+        if slot > latest || slot + ATTESTATION_PROPAGATION_SLOT_RANGE < earliest {
+            return Outcome::Ignore(format!("Slot {} outside propagation range", slot));
+        }
+        let target_epoch = attestation.data.target.epoch;
+        if target_epoch != slot / EpochInfo::SLOTS_PER_EPOCH {
+            return Outcome::Reject(format!("Target epoch {} does not match slot {}", target_epoch, slot));
+        }
+
+        let attester = match Self::attester(&attestation) {
+            Ok(attester) => attester,
+            Err(reason) => return Outcome::Reject(reason),
+        };
+        if self.seen.get(&target_epoch).is_some_and(|seen| seen.contains(&attester)) {
+            return Outcome::Ignore(format!("Already seen {:?} for epoch {}", attester, target_epoch));
+        }
+
+        if let Some(pubkeys) = &self.config.pubkeys {
+            let Attester::Index(index) = attester else {
+                return Outcome::Ignore(format!("Signer of {:?} unknown without the committee shuffling", attester));
+            };
+            let Some(pubkey) = pubkeys.get(index) else {
+                return Outcome::Ignore(format!("No pubkey cached for validator {}", index));
+            };
+            let signing_root = compute_signing_root(&attestation.data, self.domain);
+            if !verify_signature(pubkey, &signing_root, &attestation.signature) {
+                return Outcome::Reject(format!("Invalid signature from validator {}", index));
+            }
+        }
+
+        // Only epochs still within the propagation range need remembering
+        let oldest_epoch = earliest.saturating_sub(ATTESTATION_PROPAGATION_SLOT_RANGE) / EpochInfo::SLOTS_PER_EPOCH;
+        self.seen.retain(|epoch, _| *epoch >= oldest_epoch);
+        self.seen.entry(target_epoch).or_default().insert(attester);
+        Outcome::Accept(Box::new(attestation))
+    }
+
+    /// Whether an attestation from the same attester for the same target epoch
+    /// was accepted from gossip; `ssz` is uncompressed, as we publish it
+    pub fn has_seen(&self, ssz: &[u8]) -> bool {
+        let Ok(attestation) = DecodedAttestation::from_ssz(ssz) else {
+            return false;
+        };
+        Self::attester(&attestation).is_ok_and(|attester| {
+            self.seen
+                .get(&attestation.data.target.epoch)
+                .is_some_and(|seen| seen.contains(&attester))
+        })
+    }
+
+    fn attester(attestation: &DecodedAttestation) -> Result<Attester, String> {
+        match attestation.attester_index {
+            Some(index) => Ok(Attester::Index(index)),
+            None => match attestation.attesting_positions()[..] {
+                [position] => Ok(Attester::Position {
+                    slot: attestation.data.slot,
+                    committee_index: attestation.committee_index,
+                    position,
+                }),
+                ref positions => Err(format!("{} attesters, expected one", positions.len())),
+            },
+        }
+    }
+
+    /// Earliest and latest current slot allowing for clock disparity
+    fn slot_bounds(&self, now: Duration) -> (u64, u64) {
+        let slot_at = |time: Duration| time.as_secs().saturating_sub(self.config.genesis_time) / EpochInfo::SECONDS_PER_SLOT;
+        (
+            slot_at(now.saturating_sub(MAXIMUM_GOSSIP_CLOCK_DISPARITY)),
+            slot_at(now + MAXIMUM_GOSSIP_CLOCK_DISPARITY),
+        )
+    }
+}
+
+/// `get_committee_count_per_slot` from the beacon chain spec
+pub fn committees_per_slot(active_validators: u64) -> u64 {
+    (active_validators / EpochInfo::SLOTS_PER_EPOCH / TARGET_COMMITTEE_SIZE).clamp(1, MAX_COMMITTEES_PER_SLOT)
+}
+
+fn check_committees_per_slot(committees_per_slot: u64) -> Result<()> {
+    if !(1..=MAX_COMMITTEES_PER_SLOT).contains(&committees_per_slot) {
+        return Err(anyhow!("committees_per_slot must be 1-{}", MAX_COMMITTEES_PER_SLOT));
+    }
+    Ok(())
+}
+
+/// `compute_subnet_for_attestation` from the validator spec
+pub fn compute_subnet_for_attestation(committees_per_slot: u64, slot: u64, committee_index: u64) -> u64 {
+    let committees_since_epoch_start = committees_per_slot * (slot % EpochInfo::SLOTS_PER_EPOCH);
+    (committees_since_epoch_start + committee_index) % ATTESTATION_SUBNET_COUNT
+}
+
+fn sha256_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn uint_chunk(value: u64) -> [u8; 32] {
+    let mut chunk = [0u8; 32];
+    chunk[..8].copy_from_slice(&value.to_le_bytes());
+    chunk
+}
+
+/// SSZ merkleization of up to eight chunks, padded with zero chunks
+fn merkleize(chunks: &[[u8; 32]]) -> [u8; 32] {
+    let mut layer = chunks.to_vec();
+    layer.resize(chunks.len().next_power_of_two().max(1), [0u8; 32]);
+    while layer.len() > 1 {
+        layer = layer.chunks(2).map(|pair| sha256_pair(&pair[0], &pair[1])).collect();
+    }
+    layer[0]
+}
+
+fn checkpoint_root(checkpoint: &Checkpoint) -> [u8; 32] {
+    merkleize(&[uint_chunk(checkpoint.epoch), checkpoint.root])
+}
+
+/// `hash_tree_root(AttestationData)`
+pub fn attestation_data_root(data: &AttestationData) -> [u8; 32] {
+    merkleize(&[
+        uint_chunk(data.slot),
+        uint_chunk(data.index),
+        data.beacon_block_root,
+        checkpoint_root(&data.source),
+        checkpoint_root(&data.target),
+    ])
+}
+
+/// `compute_domain` from the beacon chain spec
+pub fn compute_domain(domain_type: [u8; 4], fork_version: [u8; 4], genesis_validators_root: [u8; 32]) -> [u8; 32] {
+    let fork_data_root = compute_fork_data_root(fork_version, genesis_validators_root);
+    let mut domain = [0u8; 32];
+    domain[..4].copy_from_slice(&domain_type);
+    domain[4..].copy_from_slice(&fork_data_root[..28]);
+    domain
+}
+
+/// `compute_fork_digest` from the beacon chain spec
+pub fn compute_fork_digest(fork_version: [u8; 4], genesis_validators_root: [u8; 32]) -> [u8; 4] {
+    let mut digest = [0u8; 4];
+    digest.copy_from_slice(&compute_fork_data_root(fork_version, genesis_validators_root)[..4]);
+    digest
+}
+
+/// `hash_tree_root(ForkData)`
+fn compute_fork_data_root(fork_version: [u8; 4], genesis_validators_root: [u8; 32]) -> [u8; 32] {
+    let mut version = [0u8; 32];
+    version[..4].copy_from_slice(&fork_version);
+    merkleize(&[version, genesis_validators_root])
+}
+
+/// `compute_signing_root(attestation.data, domain)`
+pub fn compute_signing_root(data: &AttestationData, domain: [u8; 32]) -> [u8; 32] {
+    merkleize(&[attestation_data_root(data), domain])
+}
+
+#[cfg(feature = "bls")]
+fn verify_signature(pubkey: &[u8; PUBKEY_SIZE], message: &[u8; 32], signature: &[u8; SIGNATURE_SIZE]) -> bool {
+    use blst::min_pk::{PublicKey, Signature};
+    const DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+    let (Ok(pubkey), Ok(signature)) = (PublicKey::from_bytes(pubkey), Signature::from_bytes(signature)) else {
+        return false;
+    };
+    signature.verify(true, message, DST, &[], &pubkey, true) == blst::BLST_ERROR::BLST_SUCCESS
+}
+
+#[cfg(not(feature = "bls"))]
+fn verify_signature(_pubkey: &[u8; PUBKEY_SIZE], _message: &[u8; 32], _signature: &[u8; SIGNATURE_SIZE]) -> bool {
+    // `AttestationValidator::new` refuses a pubkey cache without the feature
+    unreachable!("signature verification requires the bls feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attestation::{ATTESTATION_DATA_SIZE, PHASE0_FIXED_SIZE};
+    use crate::gossip;
+
+    /// Slot 9,600,000 is the first of epoch 300,000
+    const SLOT: u64 = 9_600_000;
+
+    fn slot_time(slot: u64) -> Duration {
+        Duration::from_secs(EpochInfo::MAINNET_GENESIS_TIME + slot * EpochInfo::SECONDS_PER_SLOT + 3)
+    }
+
+    fn single_attestation(committee_index: u64, attester_index: u64, slot: u64, target_epoch: u64) -> Vec<u8> {
+        let mut ssz = Vec::new();
+        ssz.extend_from_slice(&committee_index.to_le_bytes());
+        ssz.extend_from_slice(&attester_index.to_le_bytes());
+        ssz.extend_from_slice(&slot.to_le_bytes());
+        ssz.extend_from_slice(&0u64.to_le_bytes());
+        ssz.extend_from_slice(&[0xaa; 32]);
+        ssz.extend_from_slice(&(target_epoch - 1).to_le_bytes());
+        ssz.extend_from_slice(&[0xbb; 32]);
+        ssz.extend_from_slice(&target_epoch.to_le_bytes());
+        ssz.extend_from_slice(&[0xcc; 32]);
+        assert_eq!(ssz.len(), 16 + ATTESTATION_DATA_SIZE);
+        ssz.extend_from_slice(&[0x11; SIGNATURE_SIZE]);
+        gossip::compress_snappy(&ssz).unwrap()
+    }
+
+    #[test]
+    fn test_spec_checks() {
+        let mut validator = AttestationValidator::new(ValidationConfig::default()).unwrap();
+        let epoch = SLOT / EpochInfo::SLOTS_PER_EPOCH;
+        let now = slot_time(SLOT + 1);
+
+        // Committee 5 in the first slot of an epoch is on subnet 5
+        assert_eq!(compute_subnet_for_attestation(64, SLOT, 5), 5);
+        assert_eq!(compute_subnet_for_attestation(64, SLOT + 1, 5), 5);
+        assert_eq!(compute_subnet_for_attestation(8, SLOT + 1, 5), 13);
+        let valid = single_attestation(5, 42, SLOT, epoch);
+        let valid_ssz = gossip::decompress_snappy(&valid).unwrap();
+        assert!(!validator.has_seen(&valid_ssz));
+        assert!(matches!(validator.validate(5, &valid, now), Outcome::Accept(_)));
+        assert!(validator.has_seen(&valid_ssz));
+        // The same validator again for this target epoch
+        assert!(matches!(validator.validate(5, &valid, now), Outcome::Ignore(_)));
+        // Another validator on the wrong subnet
+        assert!(matches!(validator.validate(6, &single_attestation(5, 43, SLOT, epoch), now), Outcome::Reject(_)));
+        assert!(matches!(validator.validate(5, &[0xff; 16], now), Outcome::Reject(_)));
+        assert!(matches!(validator.validate(5, &single_attestation(64, 43, SLOT, epoch), now), Outcome::Reject(_)));
+        // Target epoch inconsistent with the slot
+        assert!(matches!(validator.validate(5, &single_attestation(5, 43, SLOT, epoch + 1), now), Outcome::Reject(_)));
+        // Electra containers keep data.index at zero
+        let mut indexed = gossip::decompress_snappy(&single_attestation(5, 44, SLOT, epoch)).unwrap();
+        indexed[24..32].copy_from_slice(&5u64.to_le_bytes());
+        let indexed = gossip::compress_snappy(&indexed).unwrap();
+        assert!(matches!(validator.validate(5, &indexed, now), Outcome::Reject(_)));
+
+        let stats = validator.stats();
+        assert_eq!((stats.accepted, stats.rejected, stats.ignored), (1, 5, 1));
+        assert!(matches!(Outcome::Ignore(String::new()).acceptance(), MessageAcceptance::Ignore));
+    }
+
+    #[test]
+    fn test_container_follows_the_fork() {
+        let epoch = SLOT / EpochInfo::SLOTS_PER_EPOCH;
+        let now = slot_time(SLOT + 1);
+        // Committee member 3 of 10 in committee 5
+        let mut phase0 = (PHASE0_FIXED_SIZE as u32).to_le_bytes().to_vec();
+        let single = gossip::decompress_snappy(&single_attestation(5, 42, SLOT, epoch)).unwrap();
+        let mut data = single[16..16 + ATTESTATION_DATA_SIZE].to_vec();
+        data[8..16].copy_from_slice(&5u64.to_le_bytes());
+        phase0.extend_from_slice(&data);
+        phase0.extend_from_slice(&[0x22; SIGNATURE_SIZE]);
+        phase0.extend_from_slice(&[0b0000_1000, 0b0000_0100]);
+        let phase0 = gossip::compress_snappy(&phase0).unwrap();
+
+        // Electra subnets take only SingleAttestations
+        let mut validator = AttestationValidator::new(ValidationConfig::default()).unwrap();
+        assert!(matches!(validator.validate(5, &phase0, now), Outcome::Reject(_)));
+        let deneb = ChainState { fork_version: [0x04, 0, 0, 0], fork: Fork::Deneb, committees_per_slot: 64 };
+        validator.update_chain(deneb).unwrap();
+        assert!(matches!(validator.validate(5, &phase0, now), Outcome::Accept(_)));
+        assert!(matches!(validator.validate(5, &single_attestation(5, 42, SLOT, epoch), now), Outcome::Reject(_)));
+    }
+
+    #[test]
+    fn test_propagation_range() {
+        let mut validator = AttestationValidator::new(ValidationConfig::default()).unwrap();
+        let epoch = SLOT / EpochInfo::SLOTS_PER_EPOCH;
+        let attestation = single_attestation(5, 42, SLOT, epoch);
+
+        // From the future, beyond the clock disparity
+        let early = Duration::from_secs(EpochInfo::MAINNET_GENESIS_TIME + SLOT * EpochInfo::SECONDS_PER_SLOT) - Duration::from_secs(1);
+        assert!(matches!(validator.validate(5, &attestation, early), Outcome::Ignore(_)));
+        // Just inside the disparity
+        let barely = early + Duration::from_millis(600);
+        assert!(matches!(validator.validate(5, &attestation, barely), Outcome::Accept(_)));
+        // Too old: slot + 32 < current slot
+        let stale = single_attestation(5, 43, SLOT, epoch);
+        assert!(matches!(validator.validate(5, &stale, slot_time(SLOT + 33)), Outcome::Ignore(_)));
+        assert!(matches!(validator.validate(5, &stale, slot_time(SLOT + 32)), Outcome::Accept(_)));
+    }
+
+    #[test]
+    fn test_signing_root_and_pubkey_cache() {
+        // Two-leaf merkleization is a single hash
+        let checkpoint = Checkpoint { epoch: 7, root: [0x22; 32] };
+        assert_eq!(checkpoint_root(&checkpoint), sha256_pair(&uint_chunk(7), &[0x22; 32]));
+
+        let domain = compute_domain(DOMAIN_BEACON_ATTESTER, MAINNET_FORK_VERSION, MAINNET_GENESIS_VALIDATORS_ROOT);
+        assert_eq!(domain[..4], DOMAIN_BEACON_ATTESTER);
+        // A new fork changes the domain, and the committee count follows the validator count
+        let mut validator = AttestationValidator::new(ValidationConfig::default()).unwrap();
+        assert_eq!(validator.domain, domain);
+        let deneb = ChainState { fork_version: [0x04, 0, 0, 0], fork: Fork::Deneb, committees_per_slot: 4 };
+        validator.update_chain(deneb).unwrap();
+        assert_ne!(validator.domain, domain);
+        let electra = ChainState { fork_version: MAINNET_FORK_VERSION, fork: Fork::Electra, committees_per_slot: 0 };
+        assert!(validator.update_chain(electra).is_err());
+        // Mainnet topic digests
+        assert_eq!(compute_fork_digest([0x04, 0, 0, 0], MAINNET_GENESIS_VALIDATORS_ROOT), [0x6a, 0x95, 0xa1, 0xa9]);
+        assert_eq!(ValidationConfig::default().fork_digest(), [0xad, 0x53, 0x2c, 0xeb]);
+        assert_eq!(committees_per_slot(MAINNET_ACTIVE_VALIDATORS), MAX_COMMITTEES_PER_SLOT);
+        assert_eq!(committees_per_slot(20_000), 4);
+        assert_eq!(committees_per_slot(64), 1);
+        let data = AttestationData {
+            slot: SLOT,
+            index: 0,
+            beacon_block_root: [0xaa; 32],
+            source: Checkpoint { epoch: 1, root: [0xbb; 32] },
+            target: Checkpoint { epoch: 2, root: [0xcc; 32] },
+        };
+        let root = compute_signing_root(&data, domain);
+        assert_eq!(root, sha256_pair(&attestation_data_root(&data), &domain));
+        assert_ne!(root, compute_signing_root(&AttestationData { slot: SLOT + 1, ..data }, domain));
+
+        let json = format!(
+            r#"{{"data":[{{"index":"12","validator":{{"pubkey":"0x{}"}}}}]}}"#,
+            hex::encode([0x93; PUBKEY_SIZE])
+        );
+        let cache = PubkeyCache::from_json(&json).unwrap();
+        assert_eq!(cache.get(12), Some(&[0x93; PUBKEY_SIZE]));
+        assert!(PubkeyCache::from_json(r#"{"data":[{"index":"1","validator":{"pubkey":"0x00"}}]}"#).is_err());
+    }
+}
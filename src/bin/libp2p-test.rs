@@ -1,12 +1,11 @@
 use anyhow::Result;
 use libp2p::{
-    gossipsub::{self, IdentTopic, MessageId},
+    gossipsub::{self, IdentTopic},
     identify,
     swarm::{SwarmEvent, NetworkBehaviour},
     tcp, yamux, Multiaddr, PeerId, SwarmBuilder,
 };
 use libp2p_identity as identity;
-use sha2::{Digest, Sha256};
 use std::time::Duration;
 use tokio::time::timeout;
 use futures::StreamExt;
@@ -31,25 +30,12 @@ async fn main() -> Result<()> {
     let local_peer_id = PeerId::from(local_key.public());
     info!("Local peer ID: {}", local_peer_id);
 
-    // Configure gossipsub
-    let gossipsub_config = gossipsub::ConfigBuilder::default()
-        .heartbeat_interval(Duration::from_secs(1))
-        .validation_mode(gossipsub::ValidationMode::Permissive)
-        .message_id_fn(|message| {
-            MessageId::from(&Sha256::digest(&message.data)[..20])
-        })
-        .build()
-        .expect("Valid config");
-
-    // Create gossipsub behaviour
-    let mut gossipsub = gossipsub::Behaviour::new(
-        gossipsub::MessageAuthenticity::Signed(local_key.clone()),
-        gossipsub_config,
-    ).expect("Correct configuration");
+    // Create gossipsub behaviour with the same spec parameters as the sidecar
+    let mut gossipsub = subnet_juggler::gossip::build_gossipsub()?;
 
     // Subscribe to a single attestation subnet for testing
     let topic = IdentTopic::new("/eth2/7a7b8b7f/beacon_attestation_0/ssz_snappy");
-    gossipsub.subscribe(&topic)?;
+    subnet_juggler::gossip::subscribe_attestation_topic(&mut gossipsub, &topic)?;
     info!("✅ Subscribed to test topic: {}", topic);
 
     // Create identify behaviour
@@ -113,14 +99,19 @@ async fn main() -> Result<()> {
                 SwarmEvent::Behaviour(event) => {
                     match event {
                         TestBehaviourEvent::Gossipsub(gossipsub::Event::Message { 
-                            propagation_source, message, .. 
+                            propagation_source, message_id, message
                         }) => {
                     messages_received += 1;
                     info!("📨 RECEIVED MESSAGE #{}: {} bytes from peer {} on topic {}",
                         messages_received, message.data.len(), propagation_source, message.topic);
+                    // The shared gossipsub config validates messages, so each one must be reported
+                    if let Err(e) = swarm.behaviour_mut().gossipsub.report_message_validation_result(
+                        &message_id, &propagation_source, gossipsub::MessageAcceptance::Accept) {
+                        warn!("⚠️  Failed to report validation result: {}", e);
+                    }
                 }
                         TestBehaviourEvent::Identify(identify::Event::Received { 
-                            peer_id, info 
+                            peer_id, info, ..
                         }) => {
                             info!("🆔 Identified peer {}: agent={}, protocols={:?}", 
                                 peer_id, info.agent_version, info.protocols);
@@ -10,24 +10,20 @@ use tracing::{info, warn, error, debug};
 use rand::Rng;
 
 // Import our stealth sidecar components
-use subnet_juggler::{SubnetJuggler, SubnetJugglerHandle, SubnetCommand, SubnetEvent, NetworkingProvider};
+use subnet_juggler::{SubnetJuggler, SubnetJugglerHandle, SubnetCommand, SubnetEvent, NetworkingProvider, DecodedAttestation};
 use friend_relay::{FriendRelay, NwakuProvider, RelayCommand, RelayEvent};
 use stealth_common::{StealthConfig, SubnetId, FriendNodeConfig, WakuConfig, MetricsConfig, NetworkConfig, EpochInfo, StealthResult, StealthError};
 use stealth_metrics::{StealthMetricsCollector, MetricsServer, start_system_metrics_updater};
 
 // Add libp2p imports for real gossipsub
 use libp2p::{
-    gossipsub::{self, MessageId, IdentTopic},
+    gossipsub::{self, IdentTopic},
     swarm::SwarmEvent,
     identify, noise, tcp, yamux, Multiaddr, PeerId, SwarmBuilder,
 };
 use libp2p_identity as identity;
-use sha2::{Sha256, Digest};
 use futures::StreamExt;
 
-// SSZ and Ethereum consensus imports  
-use snap::raw::Decoder as SnapDecoder;
-
 /// System clock-based provider using only public RPC data
 pub struct SystemClockProvider {
     genesis_time: SystemTime,
@@ -96,6 +92,8 @@ struct RealAttestation {
     source_peer: PeerId,
     timestamp: Instant,
     raw_data: Vec<u8>,
+    /// Decoded contents, `None` for simulated observations
+    attestation: Option<DecodedAttestation>,
 }
 
 /// Realistic demo that connects to actual reth RPC endpoints
@@ -200,8 +198,13 @@ impl RainbowAnalyzer {
             // Only record if this is the first time we see this validator on this subnet
             if first_seen.is_empty() {
                 first_seen.push((attestation.source_peer, attestation.timestamp));
-                info!("🎯 RAINBOW: Validator {} first seen on non-backbone subnet {} from peer {}",
-                    validator_index, subnet_id, attestation.source_peer);
+                match &attestation.attestation {
+                    Some(decoded) => info!("🎯 RAINBOW: Validator {} first seen on non-backbone subnet {} for slot {} (committee {}, head {}) from peer {}",
+                        validator_index, subnet_id, decoded.data.slot, decoded.committee_index,
+                        hex::encode(&decoded.data.beacon_block_root[..4]), attestation.source_peer),
+                    None => info!("🎯 RAINBOW: Validator {} first seen on non-backbone subnet {} from peer {}",
+                        validator_index, subnet_id, attestation.source_peer),
+                }
                 
                 // Update confidence score
                 self.update_confidence_score(validator_index);
@@ -262,6 +265,7 @@ impl RainbowAnalyzer {
             source_peer: PeerId::random(),
             timestamp: Instant::now(),
             raw_data: vec![],
+            attestation: None,
         };
         self.observe_real_attestation(&attestation);
     }
@@ -353,23 +357,8 @@ impl AttestationNetwork {
         let local_peer_id = PeerId::from(local_key.public());
         info!("Local peer id: {local_peer_id}");
 
-        // Set up gossipsub config for Ethereum mainnet
-        let gossipsub_config = gossipsub::ConfigBuilder::default()
-            .heartbeat_interval(Duration::from_secs(1))
-            .validation_mode(gossipsub::ValidationMode::Permissive) // Accept all for observation
-            .message_id_fn(|message| {
-                MessageId::from(
-                    &Sha256::digest(&message.data)[..20]
-                )
-            })
-            .build()
-            .expect("Valid config");
-
-        // Create gossipsub behaviour
-        let mut gossipsub = gossipsub::Behaviour::new(
-            gossipsub::MessageAuthenticity::Signed(local_key.clone()),
-            gossipsub_config,
-        ).expect("Correct configuration");
+        // Anonymous gossipsub with spec parameters, shared with the sidecar
+        let mut gossipsub = subnet_juggler::gossip::build_gossipsub()?;
 
         // Subscribe to all 64 attestation subnets with proper Ethereum format
         info!("📡 Subscribing to all 64 attestation subnets...");
@@ -378,7 +367,7 @@ impl AttestationNetwork {
         
         for subnet_id in 0..64 {
             let topic = IdentTopic::new(format!("/eth2/{}/beacon_attestation_{}/ssz_snappy", fork_digest, subnet_id));
-            gossipsub.subscribe(&topic)?;
+            subnet_juggler::gossip::subscribe_attestation_topic(&mut gossipsub, &topic)?;
             debug!("Subscribed to {}", topic);
         }
 
@@ -452,10 +441,17 @@ impl AttestationNetwork {
                 SwarmEvent::Behaviour(AttestationNetworkBehaviourEvent::Gossipsub(
                     gossipsub::Event::Message {
                         propagation_source: peer_id,
-                        message_id: _,
+                        message_id,
                         message,
                     }
                 )) => {
+                    // The shared config validates manually; the observer forwards everything
+                    let _ = swarm.behaviour_mut().gossipsub.report_message_validation_result(
+                        &message_id,
+                        &peer_id,
+                        gossipsub::MessageAcceptance::Accept,
+                    );
+
                     // Parse attestation from gossipsub message
                     messages_received += 1;
                     bytes_received += message.data.len() as u64;
@@ -476,7 +472,7 @@ impl AttestationNetwork {
                     }
                 }
                 SwarmEvent::Behaviour(AttestationNetworkBehaviourEvent::Identify(
-                    identify::Event::Received { peer_id, info }
+                    identify::Event::Received { peer_id, info, .. }
                 )) => {
                     debug!("🆔 Identified peer {}: {}", peer_id, info.protocol_version);
                 }
@@ -530,12 +526,20 @@ impl AttestationNetwork {
         };
 
         // Decode SSZ+Snappy compressed attestation data
-        let validator_index = Self::extract_validator_index_from_ssz(&message.data)
-            .unwrap_or_else(|| {
-                // Fallback: Generate deterministic validator index from message hash for demo
-                let hash = Sha256::digest(&message.data);
-                u64::from_le_bytes(hash[0..8].try_into().unwrap_or([0; 8])) % 1000
-            });
+        let attestation = match DecodedAttestation::from_gossip(&message.data) {
+            Ok(attestation) => attestation,
+            Err(e) => {
+                debug!("Failed to decode attestation: {}", e);
+                return None;
+            }
+        };
+
+        // Only SingleAttestation names its validator; older formats need the committee shuffling
+        let Some(validator_index) = attestation.attester_index else {
+            debug!("📨 {:?} attestation for slot {} committee {} has no attester index",
+                attestation.format, attestation.data.slot, attestation.committee_index);
+            return None;
+        };
 
         Some(RealAttestation {
             validator_index,
@@ -543,6 +547,7 @@ impl AttestationNetwork {
             source_peer,
             timestamp: Instant::now(),
             raw_data: message.data.clone(),
+            attestation: Some(attestation),
         })
     }
 
@@ -558,27 +563,6 @@ impl AttestationNetwork {
         // Non-blocking check for latest stats
         self.stats_rx.try_recv().ok()
     }
-
-    fn extract_validator_index_from_ssz(data: &[u8]) -> Option<u64> {
-        // Attempt to decompress Snappy data first
-        let decompressed = match SnapDecoder::new().decompress_vec(data) {
-            Ok(data) => data,
-            Err(_) => {
-                // If snappy decompression fails, try raw data
-                data.to_vec()
-            }
-        };
-
-        // For demo purposes, extract validator info from decompressed data
-        // Real implementation would use proper SSZ decoding
-        if decompressed.len() >= 8 {
-            // Try to extract slot info from the beginning of SSZ data
-            let slot = u64::from_le_bytes(decompressed[0..8].try_into().ok()?);
-            Some(slot % 1000) // Modulo to limit validator range for demo
-        } else {
-            None
-        }
-    }
 }
 
 /// Real reth integration using HTTP RPC
@@ -727,17 +711,30 @@ impl DemoState {
                     peer_id: "friend_1".to_string(),
                     multiaddr: "/ip4/127.0.0.1/tcp/60001".parse().unwrap(),
                     public_key: "0x1234".to_string(),
+                    key_fingerprint: None,
                 },
                 FriendNodeConfig {
                     peer_id: "friend_2".to_string(), 
                     multiaddr: "/ip4/127.0.0.1/tcp/60002".parse().unwrap(),
                     public_key: "0x5678".to_string(),
+                    key_fingerprint: None,
                 },
             ],
+            min_anonymity_set: None,
+            anonymity_fallback: None,
             waku_config: WakuConfig {
                 nwaku_rpc_url: "http://localhost:8545".to_string(),
+                nwaku_rest_url: None,
                 rln_contract_address: None,
                 rate_limit_per_epoch: 100,
+                relay_key_path: None,
+                stem_probability: None,
+                embargo_secs: None,
+                mix_delay: None,
+                mix_window_ms: None,
+                cover_traffic: None,
+                heartbeat_secs: None,
+                native_rln: None,
             },
             metrics: MetricsConfig {
                 enabled: true,
@@ -746,11 +743,34 @@ impl DemoState {
             },
             network: NetworkConfig {
                 listen_port: 9000,
+                quic_port: None,
                 external_ip: None,
                 bootstrap_peers: Some(vec![
                     "/ip4/4.157.240.54/tcp/9000/p2p/16Uiu2HAm5a1z45GYvdBZgGh8b5jB6jm1YcgP5TdhqfqmpVsM6gFV".to_string(),
                     "/ip4/4.196.214.4/tcp/9000/p2p/16Uiu2HAm5CQgaLeFXLFpn7YbYfKXGTGgJBP1vKKg5gLJKPKe2VKb".to_string(),
                 ]),
+                discovery_port: None,
+                boot_enrs: None,
+                beacon_api_url: None,
+                chain_head: None,
+                node_key_path: None,
+                identity_rotation_epochs: None,
+                target_peers: None,
+                max_peers: None,
+                cover_topics: None,
+                peer_allowlist: None,
+                peer_denylist: None,
+                max_peers_per_ip: None,
+                max_peers_per_ip_block: None,
+                genesis_time: None,
+                active_validators: None,
+                fork_version: None,
+                genesis_validators_root: None,
+                validator_pubkeys_path: None,
+                publish_relays: None,
+                relay_clients: None,
+                relay_port: None,
+                relay_node_key_path: None,
             },
         };
         
@@ -895,6 +915,7 @@ impl DemoState {
                 source_peer: PeerId::random(),
                 timestamp: Instant::now(),
                 raw_data: vec![0u8; rng.gen_range(300..800)], // Realistic size
+                attestation: None,
             };
             
             debug!("📊 Simulated attestation: validator {} on subnet {} (stealth: {})",
@@ -931,7 +952,9 @@ impl DemoState {
             // Use real friend relay to forward the attestation
             if let Some(friend_relay) = &self.friend_relay_handle {
                 let start_time = std::time::Instant::now();
-                if let Err(e) = friend_relay.relay_attestation(data.as_bytes().to_vec(), subnet_id) {
+                let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+                let slot = EpochInfo::at(EpochInfo::MAINNET_GENESIS_TIME, now).slot;
+                if let Err(e) = friend_relay.relay_attestation(data.as_bytes().to_vec(), subnet_id, slot) {
                     warn!("Failed to relay attestation through friends: {}", e);
                 } else {
                     let latency = start_time.elapsed().as_secs_f64();
@@ -9,9 +9,10 @@ use tokio::time::sleep;
 use tracing::{info, warn, error, debug};
 
 // Import our privacy sidecar components
-use subnet_juggler::{SubnetJuggler, SubnetJugglerHandle, SubnetCommand, BeaconNetworkProvider};
-use friend_relay::{FriendRelay, NwakuProvider, RelayCommand};
-use stealth_common::StealthConfig;
+use subnet_juggler::{SubnetJuggler, SubnetJugglerHandle, SubnetCommand, BeaconNetworkProvider, BandwidthReport, AttestationPublisher};
+use friend_relay::{nwaku_provider, BeaconPublisher, DropReason, FriendRelay, RelayCommand, RelayEvent};
+use friend_relay::crypto::{RelayIdentity, DEFAULT_RELAY_KEY_PATH};
+use stealth_common::{utils, EpochInfo, StealthConfig, StealthError, StealthResult, SubnetId};
 use stealth_metrics::{StealthMetricsCollector, MetricsServer, start_system_metrics_updater};
 
 /// Main lighthouse-privacy-sidecar binary
@@ -53,6 +54,25 @@ enum SidecarCommand {
     Shutdown,
 }
 
+/// Publishes attestations friends relay through us, allowing subnets we are
+/// not subscribed to, since we rarely are to theirs
+struct GossipPublisher(AttestationPublisher);
+
+#[async_trait::async_trait]
+impl BeaconPublisher for GossipPublisher {
+    async fn publish_attestation(&self, subnet_id: u8, attestation_data: Vec<u8>) -> StealthResult<()> {
+        self.0
+            .publish_attestation(SubnetId(subnet_id), attestation_data, true)
+            .await
+            .map(|_| ())
+            .map_err(|e| StealthError::Network(e.to_string()))
+    }
+
+    async fn seen_on_gossip(&self, _subnet_id: u8, attestation_data: &[u8]) -> bool {
+        self.0.seen_on_gossip(attestation_data.to_vec()).await.unwrap_or(false)
+    }
+}
+
 /// Main sidecar state management
 struct PrivacySidecar {
     config: StealthConfig,
@@ -122,13 +142,51 @@ impl PrivacySidecar {
             return Ok(());
         }
 
+        utils::validate_config(&self.config)
+            .map_err(|e| anyhow::anyhow!("Refusing to enable stealth mode: {}", e))?;
+
         info!("🛡️  ENABLING STEALTH MODE");
         self.stealth_enabled = true;
         
         // Initialize real beacon network provider
-        let beacon_network = BeaconNetworkProvider::new(bootstrap_peers).await
+        let beacon_network = BeaconNetworkProvider::new(bootstrap_peers, &self.config.network).await
             .map_err(|e| anyhow::anyhow!("Failed to initialize beacon network: {}", e))?;
         
+        // Export per-topic bandwidth measured by the network layer
+        if let Some(metrics) = self.metrics_collector.clone() {
+            let monitor = beacon_network.bandwidth_monitor();
+            tokio::spawn(async move {
+                let mut previous = BandwidthReport::default();
+                loop {
+                    sleep(Duration::from_secs(10)).await;
+                    let Ok(report) = monitor.report().await else {
+                        break;
+                    };
+                    for (topic, direction, bytes) in report.increments_since(&previous) {
+                        metrics.record_topic_bandwidth(bytes, direction.as_str(), &topic);
+                    }
+                    previous = report;
+                }
+            });
+        }
+
+        // Export our modelled first-seen exposure, re-estimated by the network layer every epoch
+        if let Some(metrics) = self.metrics_collector.clone() {
+            let monitor = beacon_network.exposure_monitor();
+            tokio::spawn(async move {
+                loop {
+                    sleep(Duration::from_secs(60)).await;
+                    let Ok(report) = monitor.report().await else {
+                        break;
+                    };
+                    metrics.update_modelled_exposure(&report.per_validator, &report.per_subnet);
+                }
+            });
+        }
+
+        // Attestations friends relay through us go out on the beacon gossip network
+        let gossip_publisher = GossipPublisher(beacon_network.publisher());
+
         // Start real subnet juggler with beacon network provider
         let (shutdown_tx, shutdown_rx) = watch::channel(false);
         
@@ -148,14 +206,57 @@ impl PrivacySidecar {
         });
         
         // Start real friend relay
-        let waku_provider = NwakuProvider::new(&self.config.waku_config);
+        let waku_provider = nwaku_provider(&self.config)?;
         let (shutdown_tx2, shutdown_rx2) = watch::channel(false);
             
-        let (mut friend_relay, friend_relay_handle) = FriendRelay::new(
+        let (friend_relay, mut friend_relay_handle) = FriendRelay::new(
             self.config.clone(),
             waku_provider,
             shutdown_rx2,
         );
+        let relay_key_path = self.config.waku_config.relay_key_path.as_deref().unwrap_or(DEFAULT_RELAY_KEY_PATH);
+        let relay_identity = RelayIdentity::load_or_create(std::path::Path::new(relay_key_path))?;
+        info!("🔐 Friend relay public key {} (fingerprint {})", relay_identity.public_key_hex(), relay_identity.fingerprint());
+        let mut friend_relay = friend_relay
+            .with_identity(relay_identity)
+            .with_publisher(Arc::new(gossip_publisher));
+        
+        // Count what we send friends and what they send us, and which of them are live
+        if let (Some(metrics), Some(mut events)) = (self.metrics_collector.clone(), friend_relay_handle.take_events()) {
+            tokio::spawn(async move {
+                let mut live_friends = std::collections::HashSet::new();
+                while let Some(event) = events.recv().await {
+                    match event {
+                        RelayEvent::FriendMessageSent { friend_id, success } => {
+                            metrics.record_friend_message_sent(&friend_id, success);
+                        }
+                        RelayEvent::MessageReceived { from_friend, .. } => {
+                            metrics.record_rln_proof_verified(true);
+                            metrics.record_friend_message_received(&from_friend);
+                        }
+                        RelayEvent::MessageDropped { reason: DropReason::InvalidProof, .. } => {
+                            metrics.record_rln_proof_verified(false);
+                        }
+                        RelayEvent::RateLimitExceeded { .. } => metrics.record_rate_limit_violation(),
+                        RelayEvent::FriendHeartbeat { friend_id, rtt, loss_rate } => {
+                            metrics.record_friend_heartbeat(&friend_id, rtt.as_secs_f64(), loss_rate);
+                        }
+                        RelayEvent::AnonymityFallback { decision, .. } => {
+                            metrics.record_anonymity_fallback(decision.as_str());
+                        }
+                        RelayEvent::FriendConnected(friend_id) => {
+                            live_friends.insert(friend_id);
+                            metrics.update_peer_connections("friend", live_friends.len() as i64);
+                        }
+                        RelayEvent::FriendDisconnected(friend_id) => {
+                            live_friends.remove(&friend_id);
+                            metrics.update_peer_connections("friend", live_friends.len() as i64);
+                        }
+                        _ => {}
+                    }
+                }
+            });
+        }
         
         self.friend_relay_handle = Some(friend_relay_handle);
         
@@ -212,6 +313,14 @@ impl PrivacySidecar {
         Ok(())
     }
 
+    /// Slot on the configured chain's clock
+    fn current_slot(&self) -> u64 {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        EpochInfo::at(self.config.genesis_time(), now).slot
+    }
+
     async fn get_status(&self) -> Value {
         let peer_count = if let Some(network) = &self.beacon_network {
             network.get_peer_count().await
@@ -219,18 +328,7 @@ impl PrivacySidecar {
             0
         };
 
-        // Calculate current epoch using Ethereum constants
-        let genesis_time = 1606824023u64; // Ethereum mainnet genesis
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::SystemTime::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-        
-        let current_slot = if now > genesis_time {
-            (now - genesis_time) / 12
-        } else {
-            0
-        };
+        let current_slot = self.current_slot();
         let current_epoch = current_slot / 32;
 
         json!({
@@ -254,7 +352,7 @@ impl PrivacySidecar {
             // Use real friend relay to forward the attestation
             if let Some(friend_relay) = &self.friend_relay_handle {
                 let start_time = Instant::now();
-                if let Err(e) = friend_relay.relay_attestation(data.as_bytes().to_vec(), subnet_id) {
+                if let Err(e) = friend_relay.relay_attestation(data.as_bytes().to_vec(), subnet_id, self.current_slot()) {
                     warn!("Failed to relay attestation through friends: {}", e);
                 } else {
                     let latency = start_time.elapsed().as_secs_f64();
@@ -297,6 +395,7 @@ async fn run_sidecar(args: Args) -> Result<()> {
     
     // Load configuration
     let config = load_config(&args.config).await?;
+
     
     // Use bootstrap peers from config if not provided via CLI
     let bootstrap_peers = if args.bootstrap_peers.is_empty() {